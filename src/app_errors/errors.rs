@@ -42,6 +42,14 @@ pub enum AppError {
     NotFound,
     ParseError(String),
     InternalError,
+    PreconditionFailed,     // optimistic concurrency: the document changed since the client read it (If-Match mismatch)
+    PreconditionRequired,   // optimistic concurrency: If-Match is required but was not sent
+    Forbidden(String),      // the caller is known but not allowed to do this (e.g. not the owner of the booking)
+    PayloadTooLarge(String),        // an upload over the size limit
+    UnsupportedMediaType(String),   // an upload of a type that is not accepted (sniffed from its bytes)
+    Conflict(String),               // the request cannot apply to the current state (e.g. a walk report submitted twice)
+    Unauthorized(String),           // no valid credentials: missing, expired or revoked token, wrong password
+    BadGateway(String),             // an upstream server failed or answered unexpectedly (e.g. the OpenID Connect provider)
 }
 
 // Implementing the Display trait to allow the control how your error appears when printed or logged
@@ -55,6 +63,14 @@ impl fmt::Display for AppError {
             AppError::NotFound => write!(f, "Item not found"),
             AppError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             AppError::InternalError => write!(f, "Internal server error"),
+            AppError::PreconditionFailed => write!(f, "Precondition failed: the item was modified by someone else, read it again and retry"),
+            AppError::PreconditionRequired => write!(f, "Precondition required: send an If-Match header with the item's ETag"),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            AppError::PayloadTooLarge(msg) => write!(f, "Payload too large: {}", msg),
+            AppError::UnsupportedMediaType(msg) => write!(f, "Unsupported media type: {}", msg),
+            AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::BadGateway(msg) => write!(f, "Bad gateway: {}", msg),
         }
     }
 }
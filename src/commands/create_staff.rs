@@ -0,0 +1,34 @@
+use std::io::BufRead;
+
+use crate::{app_errors::errors::AppError,
+            models::auth_model::Role,
+            services::{auth, db::AppDatabase}};
+
+// ============================================================================
+// Staff account (dispatcher or admin)
+// ============================================================================
+// Usage:   echo "<password>" | cargo run -- create-staff <dispatcher|admin> <email>
+//
+// Staff accounts cannot be created through the API (see services/policy.rs):
+//  - the login is the email, the account has no owner / sitter record behind it
+//  - the password is read from the first line of stdin, so that it does not end up in the shell history
+//  - its password can be changed later with `set-password <dispatcher|admin> <id>`
+
+pub async fn run(db: &AppDatabase, args: &[String]) -> Result<(), AppError> {
+    let usage = || AppError::ParseError("usage: create-staff <dispatcher|admin> <email>  (password on stdin)".to_string());
+
+    let role = match args.first().map(String::as_str) {
+        Some("dispatcher") => Role::Dispatcher,
+        Some("admin") => Role::Admin,
+        _ => return Err(usage()),
+    };
+    let email = args.get(1).ok_or_else(usage)?;
+
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    let credential = auth::create_staff(db, role, email, password).await?;
+    println!("{} account created for {}: {}", role.as_str(), credential.login, credential.principal_id.to_hex());
+    Ok(())
+}
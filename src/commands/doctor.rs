@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use futures::StreamExt;
+use log::info;
+
+use crate::{app_errors::errors::AppError,
+            models::{booking_model::{Booking, BookingUpdateRequest}, dog_model::{Dog, DogUpdateRequest}, owner_model::Owner, sitter_model::Sitter},
+            services::{bookings, db::AppDatabase, dogs, integrity, listing::ListSelection, owners, sitters, versioning::VersionCheck}};
+
+// ============================================================================
+// Data consistency doctor
+// ============================================================================
+// Usage:   cargo run -- doctor          -> dry-run: scan and print the report, change nothing
+//          cargo run -- doctor --fix    -> scan, print the report AND apply the fixes
+//
+// MongoDB has no foreign keys, so nothing stops a dog from outliving its owner.
+// The doctor scans the 'owner', 'dog', 'booking' and 'sitter' collections (through AppDatabase)
+// and looks for:
+//  - duplicated owners (same email)         -> fix: re-link their dogs/bookings to the oldest owner, delete the others
+//  - duplicated sitters (same email)        -> fix: delete all but the oldest sitter
+//  - orphaned dogs (owner missing)          -> fix: delete the dog
+//  - bookings whose owner was deleted       -> fix: archive the booking (moved to 'booking_archive')
+//  - overlapping bookings for the same owner -> fix: archive the later booking
+// Documents that cannot be deserialised are reported too (see GET /admin/integrity/{resource}), never touched.
+
+// recorded as updated_by on the documents the doctor re-links
+const DOCTOR_ACTOR: &str = "doctor";
+
+// What the doctor does about an issue when run with --fix
+#[derive(Debug)]
+enum Fix {
+    DeleteDog(ObjectId),
+    DeleteSitter(ObjectId),
+    ArchiveBooking(ObjectId, String),           // booking id, reason
+    RelinkOwner { from: ObjectId, to: ObjectId }, // re-link dogs/bookings, then delete 'from'
+    None,                                        // report only
+}
+
+#[derive(Debug)]
+struct Issue {
+    kind: &'static str,
+    description: String,
+    fix: Fix,
+}
+
+// Run the doctor; 'apply_fixes' is false by default (dry-run)
+pub async fn run(db: &AppDatabase, apply_fixes: bool) -> Result<(), AppError> {
+    info!("Doctor: scanning collections (mode: {})", if apply_fixes { "fix" } else { "dry-run" });
+
+    let owners = integrity::scan_collection(db.get_owners_collection(), ListSelection::all()).await?;
+    let dogs = integrity::scan_collection(db.get_dogs_collection(), ListSelection::all()).await?;
+    let bookings = integrity::scan_collection(db.get_bookings_collection(), ListSelection::all()).await?;
+    let sitters = integrity::scan_collection(db.get_sitters_collection(), ListSelection::all()).await?;
+
+    let mut issues = Vec::<Issue>::new();
+
+    for (resource, corrupt) in [("owner", &owners.corrupt), ("dog", &dogs.corrupt), ("booking", &bookings.corrupt), ("sitter", &sitters.corrupt)] {
+        for entry in corrupt {
+            issues.push(Issue {
+                kind: "corrupt document",
+                description: format!("{} {}: {}", resource, entry._id, entry.error),
+                fix: Fix::None,
+            });
+        }
+    }
+
+    // duplicates first: when fixing, re-linked dogs/bookings must not be reported as orphans
+    let relinked = check_duplicated_owners(&owners.valid, &mut issues);
+    check_duplicated_sitters(&sitters.valid, &mut issues);
+
+    let known_owners: HashSet<ObjectId> = owners.valid.iter().map(|owner| owner._id).collect();
+    check_orphaned_dogs(&dogs.valid, &known_owners, &relinked, &mut issues);
+    let archived = check_orphaned_bookings(&bookings.valid, &known_owners, &relinked, &mut issues);
+    check_overlapping_bookings(&bookings.valid, &relinked, &archived, &mut issues);
+
+    print_report(&issues, owners.valid.len(), dogs.valid.len(), bookings.valid.len(), sitters.valid.len());
+
+    if !apply_fixes {
+        if issues.iter().any(|issue| !matches!(issue.fix, Fix::None)) {
+            println!("Dry-run: nothing was changed. Run `doctor --fix` to apply the fixes above.");
+        }
+        return Ok(());
+    }
+
+    let mut applied = 0;
+    for issue in &issues {
+        if apply_fix(db, &issue.fix).await? {
+            applied += 1;
+        }
+    }
+    println!("Applied {} fix(es).", applied);
+    Ok(())
+}
+
+// Owners sharing the same email (case-insensitive): the oldest one (smallest ObjectId) is kept
+// returns the re-link map duplicate -> kept owner
+fn check_duplicated_owners(owners: &[Owner], issues: &mut Vec<Issue>) -> HashMap<ObjectId, ObjectId> {
+    let mut relinked = HashMap::new();
+
+    for group in group_by_email(owners.iter().map(|owner| (owner._id, owner.email.as_str()))) {
+        let kept = group[0];
+        for duplicate in &group[1..] {
+            issues.push(Issue {
+                kind: "duplicated owner",
+                description: format!("owner {} has the same email as owner {}", duplicate.to_hex(), kept.to_hex()),
+                fix: Fix::RelinkOwner { from: *duplicate, to: kept },
+            });
+            relinked.insert(*duplicate, kept);
+        }
+    }
+    relinked
+}
+
+// Sitters sharing the same email (case-insensitive): the oldest one is kept, nothing references sitters
+fn check_duplicated_sitters(sitters: &[Sitter], issues: &mut Vec<Issue>) {
+    for group in group_by_email(sitters.iter().map(|sitter| (sitter._id, sitter.email.as_str()))) {
+        for duplicate in &group[1..] {
+            issues.push(Issue {
+                kind: "duplicated sitter",
+                description: format!("sitter {} has the same email as sitter {}", duplicate.to_hex(), group[0].to_hex()),
+                fix: Fix::DeleteSitter(*duplicate),
+            });
+        }
+    }
+}
+
+// groups of ids sharing the same (trimmed, lowercased) email, oldest id first
+fn group_by_email<'a>(entries: impl Iterator<Item = (ObjectId, &'a str)>) -> Vec<Vec<ObjectId>> {
+    let mut by_email = HashMap::<String, Vec<ObjectId>>::new();
+    for (id, email) in entries {
+        by_email.entry(email.trim().to_lowercase()).or_default().push(id);
+    }
+
+    let mut groups: Vec<Vec<ObjectId>> = by_email.into_values().filter(|ids| ids.len() > 1).collect();
+    for ids in groups.iter_mut() {
+        ids.sort();
+    }
+    groups.sort();
+    groups
+}
+
+// the owner an entry will point to once the duplicates are re-linked
+fn resolve_owner(owner: ObjectId, relinked: &HashMap<ObjectId, ObjectId>) -> ObjectId {
+    relinked.get(&owner).copied().unwrap_or(owner)
+}
+
+fn check_orphaned_dogs(dogs: &[Dog], known_owners: &HashSet<ObjectId>, relinked: &HashMap<ObjectId, ObjectId>, issues: &mut Vec<Issue>) {
+    for dog in dogs {
+        if !known_owners.contains(&resolve_owner(dog.owner, relinked)) {
+            issues.push(Issue {
+                kind: "orphaned dog",
+                description: format!("dog {} ({}) belongs to missing owner {}", dog._id.to_hex(), dog.name, dog.owner.to_hex()),
+                fix: Fix::DeleteDog(dog._id),
+            });
+        }
+    }
+}
+
+// returns the ids of the bookings that will be archived
+fn check_orphaned_bookings(bookings: &[Booking], known_owners: &HashSet<ObjectId>, relinked: &HashMap<ObjectId, ObjectId>, issues: &mut Vec<Issue>) -> HashSet<ObjectId> {
+    let mut archived = HashSet::new();
+    for booking in bookings {
+        if !known_owners.contains(&resolve_owner(booking.owner, relinked)) {
+            issues.push(Issue {
+                kind: "orphaned booking",
+                description: format!("booking {} belongs to deleted owner {}", booking._id.to_hex(), booking.owner.to_hex()),
+                fix: Fix::ArchiveBooking(booking._id, "owner deleted".to_string()),
+            });
+            archived.insert(booking._id);
+        }
+    }
+    archived
+}
+
+// Non-cancelled bookings of the same owner whose time slots overlap: the later one is archived
+fn check_overlapping_bookings(bookings: &[Booking], relinked: &HashMap<ObjectId, ObjectId>, archived: &HashSet<ObjectId>, issues: &mut Vec<Issue>) {
+    let mut by_owner = HashMap::<ObjectId, Vec<&Booking>>::new();
+    for booking in bookings.iter().filter(|booking| !booking.cancelled && !archived.contains(&booking._id)) {
+        by_owner.entry(resolve_owner(booking.owner, relinked)).or_default().push(booking);
+    }
+
+    for (owner, mut owner_bookings) in by_owner {
+        owner_bookings.sort_by_key(|booking| booking.start_time);
+
+        // end of the latest booking kept so far
+        let mut kept_until: Option<(ObjectId, i64)> = None;
+        for booking in owner_bookings {
+            let start = booking.start_time.timestamp_millis();
+            let end = start + i64::from(booking.duration_minutes) * 60_000;
+
+            match kept_until {
+                Some((kept_id, kept_end)) if start < kept_end => {
+                    issues.push(Issue {
+                        kind: "overlapping booking",
+                        description: format!("booking {} of owner {} overlaps booking {}", booking._id.to_hex(), owner.to_hex(), kept_id.to_hex()),
+                        fix: Fix::ArchiveBooking(booking._id, format!("overlaps booking {}", kept_id.to_hex())),
+                    });
+                }
+                _ => kept_until = Some((booking._id, end)),
+            }
+        }
+    }
+}
+
+fn print_report(issues: &[Issue], owners: usize, dogs: usize, bookings: usize, sitters: usize) {
+    println!("Doctor report");
+    println!("  scanned: {} owner(s), {} dog(s), {} booking(s), {} sitter(s)", owners, dogs, bookings, sitters);
+
+    if issues.is_empty() {
+        println!("  no issues found");
+        return;
+    }
+    println!("  {} issue(s) found:", issues.len());
+    for issue in issues {
+        let action = match &issue.fix {
+            Fix::DeleteDog(_) => "delete dog".to_string(),
+            Fix::DeleteSitter(_) => "delete sitter".to_string(),
+            Fix::ArchiveBooking(_, _) => "archive booking".to_string(),
+            Fix::RelinkOwner { to, .. } => format!("re-link dogs and bookings to owner {}, delete duplicate", to.to_hex()),
+            Fix::None => "none (manual)".to_string(),
+        };
+        println!("  - [{}] {} -> fix: {}", issue.kind, issue.description, action);
+    }
+}
+
+// returns true when something was changed
+// deletes, archives and re-links go through the services: audit log, credentials, sessions and attachments follow
+async fn apply_fix(db: &AppDatabase, fix: &Fix) -> Result<bool, AppError> {
+    match fix {
+        Fix::DeleteDog(dog_id) => deleted(dogs::delete_dog(db, &dog_id.to_hex(), &VersionCheck::Any, &doc! {}, DOCTOR_ACTOR).await),
+        Fix::DeleteSitter(sitter_id) => deleted(sitters::delete_sitter(db, &sitter_id.to_hex(), &VersionCheck::Any, &doc! {}, DOCTOR_ACTOR).await),
+        Fix::ArchiveBooking(booking_id, reason) => archive_booking(db, booking_id, reason).await,
+        Fix::RelinkOwner { from, to } => {
+            let mut cursor = db.get_dogs_collection().find(doc! { "owner": from }).await?;
+            while let Some(dog) = cursor.next().await {
+                let dog_update = DogUpdateRequest { owner: Some(to.to_hex()), name: None, age: None, breed: None };
+                match dogs::update_dog(db, &dog?._id.to_hex(), dog_update, &VersionCheck::Any, &doc! {}, DOCTOR_ACTOR).await {
+                    Ok(_) | Err(AppError::NotFound) => {},   // or deleted in the meantime
+                    Err(app_error) => return Err(app_error),
+                }
+            }
+            // bookings are event-sourced: one OwnerChanged event per booking (see services/booking_events.rs)
+            let mut cursor = db.get_bookings_collection().find(doc! { "owner": from }).await?;
+            while let Some(booking) = cursor.next().await {
+                let booking_update = BookingUpdateRequest { owner: Some(to.to_hex()), start_time: None, duration_minutes: None, cancelled: None, sitter: None, dogs: None };
+                match bookings::update_booking(db, &booking?._id.to_hex(), booking_update, &VersionCheck::Any, &doc! {}, DOCTOR_ACTOR).await {
+                    Ok(_) | Err(AppError::NotFound) => {},
+                    Err(app_error) => return Err(app_error),
+                }
+            }
+            deleted(owners::delete_owner(db, &from.to_hex(), &VersionCheck::Any, &doc! {}, DOCTOR_ACTOR).await)
+        }
+        Fix::None => Ok(false),
+    }
+}
+
+// a document already gone is nothing to fix
+fn deleted(result: Result<String, AppError>) -> Result<bool, AppError> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(AppError::NotFound) => Ok(false),
+        Err(app_error) => Err(app_error),
+    }
+}
+
+// Copy the raw booking document into 'booking_archive' (with the reason), then delete it from 'booking'
+// (through the bookings service: a Deleted event keeps the stream consistent with its projection, and it is audited)
+async fn archive_booking(db: &AppDatabase, booking_id: &ObjectId, reason: &str) -> Result<bool, AppError> {
+    let raw_bookings = db.get_bookings_collection().clone_with_type::<Document>();
+
+    let mut archived_booking = match raw_bookings.find_one(doc! { "_id": booking_id }).await? {
+        Some(document) => document,
+        None => return Ok(false),
+    };
+    archived_booking.insert("archived_at", DateTime::now());
+    archived_booking.insert("archive_reason", reason);
+
+    let booking_archive = db.get_booking_archive_collection();
+    booking_archive.insert_one(archived_booking).await?;
+    let result = bookings::delete_booking(db, &booking_id.to_hex(), &VersionCheck::Any, &doc! {}, DOCTOR_ACTOR).await;
+    // deleted in the meantime: not archived by the doctor
+    if matches!(result, Err(AppError::NotFound)) {
+        booking_archive.delete_one(doc! { "_id": booking_id }).await?;
+    }
+    deleted(result)
+}
@@ -0,0 +1,223 @@
+use std::{collections::HashMap, net::TcpListener, sync::Mutex};
+
+use actix_web::{dev::Server, http::header, web, App, HttpResponse, HttpServer};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use log::info;
+use reqwest::Url;
+use ring::{rand::SystemRandom, signature::{Ed25519KeyPair, KeyPair}};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{app_errors::errors::AppError, config, services::auth};
+
+// ============================================================================
+// Mock OpenID Connect identity provider (local tests of the single sign-on, see services/oidc.rs)
+// ============================================================================
+// Usage:   cargo run -- mock-idp          (MOCK_IDP_PORT, default 9000)
+// then start the server with:
+//   OIDC_ISSUER=http://localhost:9000  OIDC_CLIENT_ID=walker  OIDC_REDIRECT_URI=http://localhost:8080/auth/oidc/callback
+//
+// A real provider as far as the server can tell: discovery document, JWKS, authorization code flow with PKCE,
+// ID tokens signed with an Ed25519 key generated at startup. There are no passwords:
+//   GET /authorize?..                               shows a form asking for the email and the roles
+//   GET /authorize?..&login_hint=<email>&roles=..   logs that user in at once (roles: comma separated, e.g. dispatcher)
+// The scenario of src/test_http_requests/test_api_oidc_requests.http runs the whole flow against it.
+
+const DEFAULT_PORT: u16 = 9000;
+const ID_TOKEN_TTL_S: i64 = 300;
+
+struct MockIdp {
+    issuer: String,
+    key_id: String,
+    key_pkcs8: Vec<u8>,
+    public_key: Vec<u8>,
+    codes: Mutex<HashMap<String, IssuedCode>>,   // authorization codes waiting for the token request, used once
+}
+
+struct IssuedCode {
+    client_id: String,
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: Option<String>,
+    email: String,
+    roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizeQuery {
+    client_id: String,
+    redirect_uri: String,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    scope: Option<String>,
+    login_hint: Option<String>,
+    roles: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    client_id: String,
+    code_verifier: String,
+}
+
+pub async fn run() -> Result<(), AppError> {
+    let port = config::env_number("MOCK_IDP_PORT", DEFAULT_PORT);
+    let listener = TcpListener::bind(("localhost", port))?;
+    serve(listener, format!("http://localhost:{}", port))?.await?;
+    Ok(())
+}
+
+// The identity provider on 'listener', with a new signing key; also started in-process by the tests of services/oidc.rs
+pub fn serve(listener: TcpListener, issuer: String) -> Result<Server, AppError> {
+    let key_pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| AppError::InternalError)?
+        .as_ref()
+        .to_vec();
+    let public_key = Ed25519KeyPair::from_pkcs8(&key_pkcs8)
+        .map_err(|_| AppError::InternalError)?
+        .public_key()
+        .as_ref()
+        .to_vec();
+    let idp = web::Data::new(MockIdp {
+        issuer,
+        key_id: auth::random_hex(8),
+        key_pkcs8,
+        public_key,
+        codes: Mutex::new(HashMap::new()),
+    });
+
+    info!("Mock identity provider at {} (discovery: {}/.well-known/openid-configuration)", idp.issuer, idp.issuer);
+    let server = HttpServer::new(move || App::new()
+        .app_data(idp.clone())
+        .route("/.well-known/openid-configuration", web::get().to(discovery))
+        .route("/jwks", web::get().to(jwks))
+        .route("/authorize", web::get().to(authorize))
+        .route("/token", web::post().to(token))
+        )
+        .listen(listener)?
+        .run();
+    Ok(server)
+}
+
+async fn discovery(idp: web::Data<MockIdp>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["EdDSA"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+async fn jwks(idp: web::Data<MockIdp>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "keys": [{ "kty": "OKP", "crv": "Ed25519", "use": "sig", "alg": "EdDSA", "kid": idp.key_id, "x": BASE64_URL.encode(&idp.public_key) }],
+    }))
+}
+
+// without login_hint: a form for the user to pick who logs in, submitted to this same URL
+async fn authorize(idp: web::Data<MockIdp>, query: web::Query<AuthorizeQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    let code_challenge = match (query.code_challenge, query.code_challenge_method.as_deref()) {
+        (Some(code_challenge), Some("S256")) => code_challenge,
+        _ => return HttpResponse::BadRequest().body("PKCE is required: code_challenge with code_challenge_method=S256"),
+    };
+    if !query.scope.as_deref().unwrap_or_default().split(' ').any(|scope| scope == "openid") {
+        return HttpResponse::BadRequest().body("the openid scope is required");
+    }
+
+    let email = match query.login_hint {
+        Some(email) => email,
+        None => {
+            let hidden = [
+                ("client_id", query.client_id.as_str()), ("redirect_uri", query.redirect_uri.as_str()),
+                ("state", query.state.as_str()), ("nonce", query.nonce.as_deref().unwrap_or_default()),
+                ("code_challenge", code_challenge.as_str()), ("code_challenge_method", "S256"),
+                ("scope", query.scope.as_deref().unwrap_or_default()),
+            ]
+                .iter()
+                .map(|(name, value)| format!("<input type=\"hidden\" name=\"{}\" value=\"{}\">", name, escape(value)))
+                .collect::<String>();
+            return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(format!(
+                "<!DOCTYPE html><html><body><h1>Mock identity provider</h1><form method=\"get\" action=\"/authorize\">{}\
+                 <p><label>Email <input name=\"login_hint\" type=\"email\" required></label></p>\
+                 <p><label>Roles <input name=\"roles\" placeholder=\"dispatcher, admin, or empty\"></label></p>\
+                 <p><button type=\"submit\">Log in</button></p></form></body></html>",
+                hidden,
+            ));
+        },
+    };
+
+    let code = auth::random_hex(16);
+    let issued = IssuedCode {
+        client_id: query.client_id,
+        redirect_uri: query.redirect_uri.clone(),
+        code_challenge,
+        nonce: query.nonce,
+        email: email.trim().to_string(),
+        roles: query.roles.unwrap_or_default().split(',').map(str::trim).filter(|role| !role.is_empty()).map(str::to_string).collect(),
+    };
+    idp.codes.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(code.clone(), issued);
+
+    match Url::parse_with_params(&query.redirect_uri, &[("code", code.as_str()), ("state", query.state.as_str())]) {
+        Ok(location) => HttpResponse::Found().insert_header((header::LOCATION, location.to_string())).finish(),
+        Err(_) => HttpResponse::BadRequest().body("invalid redirect_uri"),
+    }
+}
+
+// the code exchange: the PKCE verifier must match the challenge of the authorization request
+async fn token(idp: web::Data<MockIdp>, form: web::Form<TokenForm>) -> HttpResponse {
+    let form = form.into_inner();
+    let invalid_grant = |description: &str| HttpResponse::BadRequest().json(json!({ "error": "invalid_grant", "error_description": description }));
+    if form.grant_type != "authorization_code" {
+        return HttpResponse::BadRequest().json(json!({ "error": "unsupported_grant_type" }));
+    }
+    let issued = match idp.codes.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&form.code) {
+        Some(issued) => issued,
+        None => return invalid_grant("unknown or already used code"),
+    };
+    if issued.client_id != form.client_id || issued.redirect_uri != form.redirect_uri {
+        return invalid_grant("client_id or redirect_uri differ from the authorization request");
+    }
+    if BASE64_URL.encode(Sha256::digest(form.code_verifier.as_bytes())) != issued.code_challenge {
+        return invalid_grant("the code_verifier does not match the code_challenge");
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": idp.issuer,
+        "sub": format!("mock|{}", issued.email.to_lowercase()),
+        "aud": issued.client_id,
+        "iat": now,
+        "exp": now + ID_TOKEN_TTL_S,
+        "nonce": issued.nonce,
+        "email": issued.email,
+        "email_verified": true,
+        "roles": issued.roles,
+    });
+    let mut id_token_header = Header::new(Algorithm::EdDSA);
+    id_token_header.kid = Some(idp.key_id.clone());
+    match jsonwebtoken::encode(&id_token_header, &claims, &EncodingKey::from_ed_der(&idp.key_pkcs8)) {
+        Ok(id_token) => HttpResponse::Ok().json(json!({
+            "access_token": auth::random_hex(16),
+            "token_type": "Bearer",
+            "expires_in": ID_TOKEN_TTL_S,
+            "id_token": id_token,
+        })),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,6 @@
+pub mod create_staff;
+pub mod doctor;
+pub mod mock_idp;
+pub mod reencrypt_owners;
+pub mod replay;
+pub mod set_password;
@@ -0,0 +1,82 @@
+use bson::{doc, Bson, Document};
+use futures::StreamExt;
+use log::{info, warn};
+
+use crate::{app_errors::errors::AppError,
+            models::owner_model::Owner,
+            services::{db::{self, AppDatabase}, field_encryption}};
+
+// ============================================================================
+// Owner personal data re-encryption
+// ============================================================================
+// Usage:   cargo run -- reencrypt-owners
+//
+// Brings every 'owner' document to the current keys (see services/field_encryption.rs):
+//  - plaintext email / phone / address / home_access (written before the encryption) -> encrypted
+//  - values encrypted with an older key                                            -> encrypted with the first key
+//  - missing blind indexes (email_index, phone_index)                              -> computed
+// Run it after enabling the encryption, and after putting a new key first in the keyring (the old key can be
+// removed once it reports nothing left to do). The version of the owners is not changed: their content is the same.
+// An owner updated while it runs is left for the next run; safe to run several times.
+
+const ENCRYPTED_FIELDS: [&str; 4] = ["email", "phone", "address", "home_access"];
+
+pub async fn run(db: &AppDatabase) -> Result<(), AppError> {
+    let owners = db.get_owners_collection();
+    let mut cursor = owners.clone_with_type::<Document>().find(doc! {}).await?;
+
+    let (mut scanned, mut rewritten, mut changed, mut failed) = (0, 0, 0, 0);
+    while let Some(raw_owner) = cursor.next().await {
+        let raw_owner = raw_owner?;
+        scanned += 1;
+        if !is_outdated(&raw_owner) {
+            continue;
+        }
+
+        // read through the model: decrypted with any key of the keyring, written back with the first one
+        let mut owner = match bson::from_document::<Owner>(raw_owner.clone()) {
+            Ok(owner) => owner,
+            Err(e) => {
+                warn!("Owner {} not re-encrypted: {}", document_id(&raw_owner), e);
+                failed += 1;
+                continue;
+            }
+        };
+        owner.email_index = Some(field_encryption::email_index(&owner.email).map_err(AppError::DatabaseError)?);
+        owner.phone_index = Some(field_encryption::phone_index(&owner.phone).map_err(AppError::DatabaseError)?);
+
+        // only the state that was read: a concurrent update wins, the owner is done by the next run
+        let filter = doc! { "_id": owner._id, "version": raw_owner.get("version").cloned().unwrap_or(Bson::Null) };
+        match owners.replace_one(filter, &owner).await {
+            Ok(result) if result.matched_count == 0 => changed += 1,
+            Ok(_) => rewritten += 1,
+            Err(e) if db::is_duplicate_key(&e) => {
+                warn!("Owner {} not re-encrypted: another owner has the same email (see `doctor --fix`)", owner._id.to_hex());
+                failed += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    info!("Re-encryption: {} owner(s) scanned, {} rewritten, {} changed meanwhile, {} failed", scanned, rewritten, changed, failed);
+    println!("Scanned {} owner(s): {} rewritten, {} changed meanwhile (run again), {} not rewritten (see the log)", scanned, rewritten, changed, failed);
+    Ok(())
+}
+
+// a field not encrypted with the first key, or a missing blind index
+fn is_outdated(raw_owner: &Document) -> bool {
+    let stale_field = ENCRYPTED_FIELDS
+        .iter()
+        .filter_map(|field| raw_owner.get_str(field).ok())
+        .any(field_encryption::needs_reencryption);
+    let missing_index = ["email_index", "phone_index"].iter().any(|index| raw_owner.get_str(index).is_err());
+    stale_field || missing_index
+}
+
+fn document_id(raw_document: &Document) -> String {
+    match raw_document.get("_id") {
+        Some(Bson::ObjectId(oid)) => oid.to_hex(),
+        Some(other) => other.to_string(),
+        None => "<missing _id>".to_string(),
+    }
+}
@@ -0,0 +1,53 @@
+use bson::{doc, oid::ObjectId};
+use log::info;
+
+use crate::{app_errors::errors::AppError,
+            services::{booking_events, db::AppDatabase}};
+
+// ============================================================================
+// Booking projections replay
+// ============================================================================
+// Usage:   cargo run -- replay-bookings
+//
+// Rebuilds every 'booking' document from its event stream in 'booking_events' (see services/booking_events.rs):
+//  - a stream ending with Deleted          -> the projection is removed
+//  - any other stream                      -> the projection is replaced by the replayed state (upsert)
+// Bookings without a stream (written before event sourcing) are left as they are, their stream starts
+// with a BookingImported snapshot on their next write.
+// Safe to run several times: the result only depends on the events.
+
+pub async fn run(db: &AppDatabase) -> Result<(), AppError> {
+    let booking_ids = db.get_booking_events_collection()
+        .distinct("booking_id", doc! {})
+        .await?;
+
+    let (mut rebuilt, mut removed) = (0, 0);
+    for booking_id in booking_ids.iter().filter_map(|id| id.as_object_id()) {
+        if replay_booking(db, booking_id).await? {
+            rebuilt += 1;
+        } else {
+            removed += 1;
+        }
+    }
+
+    info!("Replay: {} booking stream(s), {} projection(s) rebuilt, {} removed", booking_ids.len(), rebuilt, removed);
+    println!("Replayed {} booking stream(s): {} projection(s) rebuilt, {} removed (deleted bookings)", booking_ids.len(), rebuilt, removed);
+    Ok(())
+}
+
+// returns true when the booking still exists after its last event
+async fn replay_booking(db: &AppDatabase, booking_id: ObjectId) -> Result<bool, AppError> {
+    let events = booking_events::load_stream(db, booking_id).await?;
+    let bookings = db.get_bookings_collection();
+
+    match booking_events::replay(&events) {
+        Some(booking) => {
+            bookings.replace_one(doc! { "_id": booking_id }, &booking).upsert(true).await?;
+            Ok(true)
+        }
+        None => {
+            bookings.delete_one(doc! { "_id": booking_id }).await?;
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::BufRead;
+
+use bson::oid::ObjectId;
+
+use crate::{app_errors::errors::AppError,
+            models::auth_model::Role,
+            services::{auth, db::AppDatabase}};
+
+// ============================================================================
+// Password of an existing account
+// ============================================================================
+// Usage:   echo "<password>" | cargo run -- set-password <owner|sitter|dispatcher|admin> <id>
+//
+// For accounts created without a password, or to reset a forgotten one (see services/auth.rs):
+//  - the login is the current email of the owner / sitter (staff accounts keep theirs, see create_staff.rs)
+//  - the password is read from the first line of stdin, so that it does not end up in the shell history
+//  - every session of the account ends (refresh tokens revoked)
+
+pub async fn run(db: &AppDatabase, args: &[String]) -> Result<(), AppError> {
+    let usage = || AppError::ParseError("usage: set-password <owner|sitter|dispatcher|admin> <id>  (password on stdin)".to_string());
+
+    let role = match args.first().map(String::as_str) {
+        Some("owner") => Role::Owner,
+        Some("sitter") => Role::Sitter,
+        Some("dispatcher") => Role::Dispatcher,
+        Some("admin") => Role::Admin,
+        _ => return Err(usage()),
+    };
+    let principal_id = args.get(1).ok_or_else(usage)?;
+    let principal_id = ObjectId::parse_str(principal_id).map_err(|_| AppError::InvalidId)?;
+
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    auth::set_password(db, role, principal_id, password).await?;
+    println!("Password set for {} {}, its sessions were ended", role.as_str(), principal_id.to_hex());
+    Ok(())
+}
@@ -0,0 +1,588 @@
+use std::{env, fmt, str::FromStr, time::Duration};
+use log::{info, warn};
+use rand::RngCore;
+
+// ============================================================================
+// Application configuration (HTTP layer)
+// ============================================================================
+// Read once at startup from environment variables (or the .env file, see dotenv in main.rs)
+// and shared with the handlers as web::Data<AppConfig>.
+// Database related settings (MONGODB_URI, LENIENT_READS) are read by services::db::AppDatabase.
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    // REQUIRE_IF_MATCH (default: true)
+    // when true, PUT and DELETE on a resource must send an `If-Match` header with the ETag
+    // previously received, otherwise the request is rejected with 428 Precondition Required
+    pub require_if_match: bool,
+    // WALK_POSITION_MIN_INTERVAL_MS (default: 2000)
+    // a sitter publishing live walk positions faster than that gets 'throttled' answers (see routes/walk_routes.rs)
+    pub walk_position_interval: Duration,
+    // PHOTO_MAX_BYTES (default: 10 MiB), DOCUMENT_MAX_BYTES (default: 20 MiB)
+    // size limit of each uploaded file, larger files are rejected with 413 (see routes/attachment_routes.rs)
+    pub max_photo_bytes: usize,
+    pub max_document_bytes: usize,
+}
+
+impl AppConfig {
+    pub fn from_env() -> Self {
+        let config = AppConfig {
+            require_if_match: env_flag("REQUIRE_IF_MATCH", true),
+            walk_position_interval: Duration::from_millis(env_number("WALK_POSITION_MIN_INTERVAL_MS", 2000)),
+            max_photo_bytes: env_number("PHOTO_MAX_BYTES", 10 * 1024 * 1024),
+            max_document_bytes: env_number("DOCUMENT_MAX_BYTES", 20 * 1024 * 1024),
+        };
+        info!("Configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// boolean environment variable: "true"/"1" or "false"/"0", anything else (or unset) gives the default
+pub fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name).as_deref() {
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") => false,
+        _ => default,
+    }
+}
+
+// ============================================================================
+// Outbox dispatcher configuration (see services/outbox.rs), also used by the webhook deliveries (services/webhooks.rs)
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct DispatcherConfig {
+    // OUTBOX_SINKS (default: "log,in_process,webhook_subscriptions"), comma separated:
+    // log | webhook (fixed URL) | in_process | webhook_subscriptions (POST /webhooks, see services/webhooks.rs)
+    pub sinks: Vec<String>,
+    // OUTBOX_WEBHOOK_URL: where the 'webhook' sink POSTs the messages (the sink is disabled without it)
+    pub webhook_url: Option<String>,
+    // OUTBOX_POLL_INTERVAL_MS (default: 1000): pause when no message is due
+    pub poll_interval: Duration,
+    // OUTBOX_MAX_ATTEMPTS (default: 10): failed rounds before a message is dead-lettered
+    pub max_attempts: i32,
+    // OUTBOX_RETRY_BASE_MS (default: 1000): first retry delay, doubled on each attempt (at most 1 hour)
+    pub retry_base: Duration,
+    // WEBHOOK_TIMEOUT_MS (default: 10000): a webhook receiver slower than that is a failed attempt
+    pub webhook_timeout: Duration,
+}
+
+impl DispatcherConfig {
+    pub fn from_env() -> Self {
+        let config = DispatcherConfig {
+            sinks: env::var("OUTBOX_SINKS")
+                .unwrap_or_else(|_| "log,in_process,webhook_subscriptions".to_string())
+                .split(',')
+                .map(|sink| sink.trim().to_string())
+                .filter(|sink| !sink.is_empty())
+                .collect(),
+            webhook_url: env::var("OUTBOX_WEBHOOK_URL").ok().filter(|url| !url.is_empty()),
+            poll_interval: Duration::from_millis(env_number("OUTBOX_POLL_INTERVAL_MS", 1000)),
+            max_attempts: env_number("OUTBOX_MAX_ATTEMPTS", 10),
+            retry_base: Duration::from_millis(env_number("OUTBOX_RETRY_BASE_MS", 1000)),
+            webhook_timeout: Duration::from_millis(env_number("WEBHOOK_TIMEOUT_MS", 10000)),
+        };
+        info!("Outbox dispatcher configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// numeric environment variable, the default when unset or not a number
+pub fn env_number<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+// ============================================================================
+// Authentication configuration (see services/auth.rs and routes/actor.rs)
+// ============================================================================
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    // JWT_SECRET: HMAC key of the access tokens (at least 32 bytes)
+    // when unset, a random key is generated at startup: tokens do not survive a restart and cannot be shared by several instances
+    pub jwt_secret: Vec<u8>,
+    // JWT_ISSUER (default: "breizh-app-walker"): 'iss' claim, tokens of another issuer are refused
+    pub issuer: String,
+    // ACCESS_TOKEN_TTL_S (default: 900): lifetime of the access tokens
+    pub access_token_ttl: Duration,
+    // REFRESH_TOKEN_TTL_S (default: 30 days): lifetime of a refresh token, renewed on every refresh
+    pub refresh_token_ttl: Duration,
+}
+
+const MIN_JWT_SECRET_BYTES: usize = 32;
+
+impl AuthConfig {
+    pub fn from_env() -> Self {
+        let jwt_secret = match env::var("JWT_SECRET") {
+            Ok(secret) if secret.len() >= MIN_JWT_SECRET_BYTES => secret.into_bytes(),
+            other => {
+                if other.is_ok() {
+                    warn!("JWT_SECRET is shorter than {} bytes, ignored", MIN_JWT_SECRET_BYTES);
+                }
+                warn!("No JWT_SECRET: using a random key, access tokens will be invalid after a restart");
+                let mut secret = vec![0u8; MIN_JWT_SECRET_BYTES];
+                rand::thread_rng().fill_bytes(&mut secret);
+                secret
+            }
+        };
+        let config = AuthConfig {
+            jwt_secret,
+            issuer: env::var("JWT_ISSUER").unwrap_or_else(|_| "breizh-app-walker".to_string()),
+            access_token_ttl: Duration::from_secs(env_number("ACCESS_TOKEN_TTL_S", 900)),
+            refresh_token_ttl: Duration::from_secs(env_number("REFRESH_TOKEN_TTL_S", 30 * 24 * 3600)),
+        };
+        info!("Authentication configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// the secret is never logged
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("jwt_secret", &"<redacted>")
+            .field("issuer", &self.issuer)
+            .field("access_token_ttl", &self.access_token_ttl)
+            .field("refresh_token_ttl", &self.refresh_token_ttl)
+            .finish()
+    }
+}
+
+// ============================================================================
+// OpenID Connect login (single sign-on, see services/oidc.rs)
+// ============================================================================
+// Disabled (GET /auth/oidc/* answer 404) unless OIDC_ISSUER, OIDC_CLIENT_ID and OIDC_REDIRECT_URI are set.
+
+#[derive(Clone)]
+pub struct OidcConfig {
+    // OIDC_ISSUER: the identity provider, its discovery document is {issuer}/.well-known/openid-configuration
+    pub issuer: String,
+    // OIDC_CLIENT_ID, OIDC_CLIENT_SECRET (optional: public clients only rely on PKCE)
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    // OIDC_REDIRECT_URI: GET /auth/oidc/callback of this server, as registered at the identity provider
+    pub redirect_uri: String,
+    // OIDC_SCOPES (default: "openid email profile")
+    pub scopes: String,
+    // OIDC_ROLES_CLAIM (default: "roles"): claim of the ID token with the groups / roles of the user (string or array)
+    pub roles_claim: String,
+    // OIDC_ROLE_MAPPING (default: "admin=admin,dispatcher=dispatcher"): <value of the claim>=<dispatcher|admin>, comma separated
+    // users without a mapped value log in as the owner or sitter that has their (verified) email
+    pub role_mapping: Vec<(String, String)>,
+    // OIDC_METADATA_TTL_S (default: 3600): how long the discovery document and the signing keys (JWKS) are cached
+    pub metadata_ttl: Duration,
+    // OIDC_LOGIN_TTL_S (default: 600): time to complete the login at the identity provider
+    pub login_ttl: Duration,
+}
+
+impl OidcConfig {
+    pub fn from_env() -> Option<Self> {
+        let setting = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let (issuer, client_id, redirect_uri) = match (setting("OIDC_ISSUER"), setting("OIDC_CLIENT_ID"), setting("OIDC_REDIRECT_URI")) {
+            (Some(issuer), Some(client_id), Some(redirect_uri)) => (issuer.trim_end_matches('/').to_string(), client_id, redirect_uri),
+            (None, None, None) => {
+                info!("OpenID Connect login disabled (no OIDC_ISSUER)");
+                return None;
+            },
+            _ => {
+                warn!("OpenID Connect login disabled: OIDC_ISSUER, OIDC_CLIENT_ID and OIDC_REDIRECT_URI are all required");
+                return None;
+            },
+        };
+        let config = OidcConfig {
+            issuer,
+            client_id,
+            client_secret: setting("OIDC_CLIENT_SECRET"),
+            redirect_uri,
+            scopes: setting("OIDC_SCOPES").unwrap_or_else(|| "openid email profile".to_string()),
+            roles_claim: setting("OIDC_ROLES_CLAIM").unwrap_or_else(|| "roles".to_string()),
+            role_mapping: setting("OIDC_ROLE_MAPPING")
+                .unwrap_or_else(|| "admin=admin,dispatcher=dispatcher".to_string())
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(value, role)| (value.trim().to_string(), role.trim().to_string()))
+                .collect(),
+            metadata_ttl: Duration::from_secs(env_number("OIDC_METADATA_TTL_S", 3600)),
+            login_ttl: Duration::from_secs(env_number("OIDC_LOGIN_TTL_S", 600)),
+        };
+        info!("OpenID Connect configuration loaded: {:?}", config);
+        Some(config)
+    }
+}
+
+// the client secret is never logged
+impl fmt::Debug for OidcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcConfig")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "<redacted>"))
+            .field("redirect_uri", &self.redirect_uri)
+            .field("scopes", &self.scopes)
+            .field("roles_claim", &self.roles_claim)
+            .field("role_mapping", &self.role_mapping)
+            .field("metadata_ttl", &self.metadata_ttl)
+            .field("login_ttl", &self.login_ttl)
+            .finish()
+    }
+}
+
+// ============================================================================
+// Emails: verification of the account emails and password resets (see services/mailer.rs and services/account_emails.rs)
+// ============================================================================
+
+#[derive(Clone)]
+pub struct MailConfig {
+    // MAIL_TRANSPORT (default: "log"): log (emails written to the log) | file (one .eml file per email) | smtp
+    pub transport: String,
+    // MAIL_FROM (default: "Breizh Walker <no-reply@breizh-walker.net>")
+    pub from: String,
+    // MAIL_DIR (default: "mails"): directory of the 'file' transport
+    pub dir: String,
+    // SMTP_HOST, SMTP_PORT (default: 587), SMTP_TLS (default: "starttls"): starttls | tls (implicit, port 465) | none (local relay only)
+    // SMTP_USERNAME, SMTP_PASSWORD: AUTH PLAIN when set (refused over an unencrypted connection)
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: String,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    // SMTP_TIMEOUT_MS (default: 15000): a slower SMTP server is a failed delivery
+    pub smtp_timeout: Duration,
+    // APP_PUBLIC_URL (default: "http://localhost:8080"): base of the links sent by email (GET /auth/verify-email?token=..)
+    pub public_url: String,
+    // PASSWORD_RESET_URL (default: "{APP_PUBLIC_URL}/auth/password-reset"): page of the client app receiving ?token=..
+    // it asks for the new password and sends both to POST /auth/password-reset/confirm
+    pub password_reset_url: String,
+    // EMAIL_VERIFICATION_TTL_S (default: 48 hours), PASSWORD_RESET_TTL_S (default: 1 hour): lifetime of the tokens sent
+    pub verification_ttl: Duration,
+    pub password_reset_ttl: Duration,
+}
+
+impl MailConfig {
+    pub fn from_env() -> Self {
+        let setting = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let public_url = setting("APP_PUBLIC_URL").unwrap_or_else(|| "http://localhost:8080".to_string()).trim_end_matches('/').to_string();
+        let config = MailConfig {
+            transport: setting("MAIL_TRANSPORT").unwrap_or_else(|| "log".to_string()),
+            from: setting("MAIL_FROM").unwrap_or_else(|| "Breizh Walker <no-reply@breizh-walker.net>".to_string()),
+            dir: setting("MAIL_DIR").unwrap_or_else(|| "mails".to_string()),
+            smtp_host: setting("SMTP_HOST").unwrap_or_else(|| "localhost".to_string()),
+            smtp_port: env_number("SMTP_PORT", 587),
+            smtp_tls: setting("SMTP_TLS").unwrap_or_else(|| "starttls".to_string()),
+            smtp_username: setting("SMTP_USERNAME"),
+            smtp_password: setting("SMTP_PASSWORD"),
+            smtp_timeout: Duration::from_millis(env_number("SMTP_TIMEOUT_MS", 15000)),
+            password_reset_url: setting("PASSWORD_RESET_URL").unwrap_or_else(|| format!("{}/auth/password-reset", public_url)),
+            public_url,
+            verification_ttl: Duration::from_secs(env_number("EMAIL_VERIFICATION_TTL_S", 48 * 3600)),
+            password_reset_ttl: Duration::from_secs(env_number("PASSWORD_RESET_TTL_S", 3600)),
+        };
+        info!("Mail configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// the SMTP password is never logged
+impl fmt::Debug for MailConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MailConfig")
+            .field("transport", &self.transport)
+            .field("from", &self.from)
+            .field("dir", &self.dir)
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("smtp_tls", &self.smtp_tls)
+            .field("smtp_username", &self.smtp_username)
+            .field("smtp_password", &self.smtp_password.as_ref().map(|_| "<redacted>"))
+            .field("smtp_timeout", &self.smtp_timeout)
+            .field("public_url", &self.public_url)
+            .field("password_reset_url", &self.password_reset_url)
+            .field("verification_ttl", &self.verification_ttl)
+            .field("password_reset_ttl", &self.password_reset_ttl)
+            .finish()
+    }
+}
+
+// ============================================================================
+// Rate limiting (see services/rate_limiter.rs and routes/rate_limit.rs)
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    // RATE_LIMIT_ENABLED (default: true)
+    pub enabled: bool,
+    // RATE_LIMIT_DEFAULT (default: "300/60"): <requests>/<seconds> of each client over the routes without their own limit
+    pub default_limit: RateLimit,
+    // RATE_LIMIT_ROUTES (default: "POST /auth/login=10/60,POST /auth/password-reset=5/300,POST /bookings=30/60")
+    // <METHOD> <route>=<requests>/<seconds>, comma separated; the route as declared on the handler (e.g. GET /owners/{id}),
+    // METHOD * for every method. Each of these routes has its own bucket per client.
+    pub routes: Vec<RouteLimit>,
+    // RATE_LIMIT_STORE (default: "memory"): memory (each instance counts alone) | mongodb (shared by every instance, 'rate_limits' collection)
+    pub store: String,
+    // RATE_LIMIT_TRUST_PROXY (default: false): anonymous clients identified by X-Forwarded-For / Forwarded
+    // instead of the peer address, only behind a reverse proxy that sets them (clients could pick their own address otherwise)
+    pub trust_proxy: bool,
+}
+
+// token bucket: 'requests' at once, then refilled at requests / period
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    // "<requests>/<seconds>", e.g. "30/60"
+    fn parse(value: &str) -> Option<RateLimit> {
+        let (requests, seconds) = value.trim().split_once('/')?;
+        let requests = requests.trim().parse::<u32>().ok().filter(|requests| *requests > 0)?;
+        let seconds = seconds.trim().parse::<u64>().ok().filter(|seconds| *seconds > 0)?;
+        Some(RateLimit { requests, period: Duration::from_secs(seconds) })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteLimit {
+    pub method: String,     // upper case, or "*"
+    pub route: String,
+    pub limit: RateLimit,
+}
+
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        let setting = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let default_limit = setting("RATE_LIMIT_DEFAULT")
+            .and_then(|value| RateLimit::parse(&value).or_else(|| {
+                warn!("Invalid RATE_LIMIT_DEFAULT '{}' (expected <requests>/<seconds>), using 300/60", value);
+                None
+            }))
+            .unwrap_or(RateLimit { requests: 300, period: Duration::from_secs(60) });
+        let routes = setting("RATE_LIMIT_ROUTES")
+            .unwrap_or_else(|| "POST /auth/login=10/60,POST /auth/password-reset=5/300,POST /bookings=30/60".to_string())
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .filter_map(|entry| {
+                let parsed = entry.rsplit_once('=').and_then(|(route, limit)| {
+                    let (method, route) = route.trim().split_once(' ')?;
+                    Some(RouteLimit { method: method.to_uppercase(), route: route.trim().to_string(), limit: RateLimit::parse(limit)? })
+                });
+                if parsed.is_none() {
+                    warn!("Invalid RATE_LIMIT_ROUTES entry '{}' (expected <METHOD> <route>=<requests>/<seconds>), ignored", entry.trim());
+                }
+                parsed
+            })
+            .collect();
+        let config = RateLimitConfig {
+            enabled: env_flag("RATE_LIMIT_ENABLED", true),
+            default_limit,
+            routes,
+            store: setting("RATE_LIMIT_STORE").unwrap_or_else(|| "memory".to_string()),
+            trust_proxy: env_flag("RATE_LIMIT_TRUST_PROXY", false),
+        };
+        info!("Rate limit configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// ============================================================================
+// HTTP security: CORS, security headers and JSON body limits (see routes/http_security.rs)
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct HttpSecurityConfig {
+    // CORS_ALLOWED_ORIGINS (default: none): web apps on other origins allowed to call the API, comma separated
+    // (e.g. "https://app.breizh-walker.net,http://localhost:5173"), "*" for any origin (never with credentials)
+    pub cors_allowed_origins: Vec<String>,
+    // CORS_ALLOWED_METHODS (default: "GET,POST,PUT,DELETE")
+    pub cors_allowed_methods: Vec<String>,
+    // CORS_ALLOWED_HEADERS (default: "Authorization,Content-Type,If-Match,If-None-Match")
+    pub cors_allowed_headers: Vec<String>,
+    // CORS_ALLOW_CREDENTIALS (default: false): cookies and HTTP authentication sent cross-origin
+    pub cors_allow_credentials: bool,
+    // CORS_MAX_AGE_S (default: 3600): how long browsers keep the answer of a preflight request
+    pub cors_max_age: usize,
+    // HSTS_MAX_AGE_S (default: 31536000, one year; 0: no Strict-Transport-Security header), HSTS_INCLUDE_SUBDOMAINS (default: false)
+    // browsers only take it into account over HTTPS
+    pub hsts_max_age: u64,
+    pub hsts_include_subdomains: bool,
+    // FRAME_OPTIONS (default: "DENY"): DENY | SAMEORIGIN | none (no X-Frame-Options header)
+    pub frame_options: Option<String>,
+    // JSON_MAX_BYTES (default: 64 KiB): largest JSON body accepted, larger ones get 413
+    pub json_max_bytes: usize,
+    // JSON_ROUTE_LIMITS (default: "POST /bookings/{id}/track=10485760"): <METHOD> <route>=<bytes>, comma separated,
+    // the route as declared on the handler; routes with larger (or smaller) JSON bodies than JSON_MAX_BYTES
+    pub json_route_limits: Vec<(String, String, usize)>,
+}
+
+impl HttpSecurityConfig {
+    pub fn from_env() -> Self {
+        let setting = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let list = |name: &str, default: &str| -> Vec<String> {
+            setting(name)
+                .unwrap_or_else(|| default.to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+
+        // an origin is "<scheme>://<host>[:<port>]", exactly as browsers send it in the Origin header
+        let cors_allowed_origins = list("CORS_ALLOWED_ORIGINS", "")
+            .into_iter()
+            .map(|origin| origin.trim_end_matches('/').to_string())
+            .filter(|origin| {
+                let valid = origin == "*" || reqwest::Url::parse(origin).map(|url| url.has_host() && url.path() == "/").unwrap_or(false);
+                if !valid {
+                    warn!("Invalid CORS_ALLOWED_ORIGINS entry '{}' (expected <scheme>://<host>[:<port>]), ignored", origin);
+                }
+                valid
+            })
+            .collect::<Vec<String>>();
+        let mut cors_allow_credentials = env_flag("CORS_ALLOW_CREDENTIALS", false);
+        if cors_allow_credentials && cors_allowed_origins.iter().any(|origin| origin == "*") {
+            warn!("CORS_ALLOW_CREDENTIALS ignored: credentials are never allowed for any origin (\"*\")");
+            cors_allow_credentials = false;
+        }
+
+        let json_route_limits = list("JSON_ROUTE_LIMITS", "POST /bookings/{id}/track=10485760")
+            .into_iter()
+            .filter_map(|entry| {
+                let parsed = entry.rsplit_once('=').and_then(|(route, bytes)| {
+                    let (method, route) = route.trim().split_once(' ')?;
+                    Some((method.to_uppercase(), route.trim().to_string(), bytes.trim().parse::<usize>().ok()?))
+                });
+                if parsed.is_none() {
+                    warn!("Invalid JSON_ROUTE_LIMITS entry '{}' (expected <METHOD> <route>=<bytes>), ignored", entry);
+                }
+                parsed
+            })
+            .collect();
+
+        let config = HttpSecurityConfig {
+            cors_allowed_origins,
+            cors_allowed_methods: list("CORS_ALLOWED_METHODS", "GET,POST,PUT,DELETE").into_iter().map(|method| method.to_uppercase()).collect(),
+            cors_allowed_headers: list("CORS_ALLOWED_HEADERS", "Authorization,Content-Type,If-Match,If-None-Match"),
+            cors_allow_credentials,
+            cors_max_age: env_number("CORS_MAX_AGE_S", 3600),
+            hsts_max_age: env_number("HSTS_MAX_AGE_S", 365 * 24 * 3600),
+            hsts_include_subdomains: env_flag("HSTS_INCLUDE_SUBDOMAINS", false),
+            frame_options: match setting("FRAME_OPTIONS").map(|value| value.to_uppercase()).as_deref() {
+                None | Some("DENY") => Some("DENY".to_string()),
+                Some("SAMEORIGIN") => Some("SAMEORIGIN".to_string()),
+                Some("NONE") => None,
+                Some(other) => {
+                    warn!("Invalid FRAME_OPTIONS '{}' (DENY, SAMEORIGIN or none), using DENY", other);
+                    Some("DENY".to_string())
+                },
+            },
+            json_max_bytes: env_number("JSON_MAX_BYTES", 64 * 1024),
+            json_route_limits,
+        };
+        info!("HTTP security configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// ============================================================================
+// HTTPS (see services/tls.rs)
+// ============================================================================
+// Disabled (plain HTTP on port 8080) unless TLS_CERT_PATH and TLS_KEY_PATH are set.
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    // TLS_CERT_PATH: PEM certificate chain, the certificate of the server first
+    // TLS_KEY_PATH: PEM private key (PKCS#8, PKCS#1 or SEC1), never logged
+    pub cert_path: String,
+    pub key_path: String,
+    // TLS_PORT (default: 8443)
+    pub port: u16,
+    // TLS_KEEP_HTTP (default: false): also serve plain HTTP on port 8080 (e.g. behind a load balancer that checks it)
+    pub keep_http: bool,
+    // TLS_CLIENT_CA_PATH (optional): PEM certificates of the authorities signing the client certificates (mutual TLS)
+    pub client_ca_path: Option<String>,
+    // TLS_CLIENT_AUTH (default: "optional"): optional (clients without a certificate are accepted, partners send theirs)
+    // | required (every client needs a certificate of TLS_CLIENT_CA_PATH)
+    pub client_auth_required: bool,
+    // TLS_RELOAD_INTERVAL_S (default: 30; 0: only on SIGHUP): how often the files are checked for changes
+    pub reload_interval: Duration,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Option<Self> {
+        let setting = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let (cert_path, key_path) = match (setting("TLS_CERT_PATH"), setting("TLS_KEY_PATH")) {
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            (None, None) => {
+                info!("HTTPS disabled (no TLS_CERT_PATH)");
+                return None;
+            },
+            _ => {
+                warn!("HTTPS disabled: TLS_CERT_PATH and TLS_KEY_PATH are both required");
+                return None;
+            },
+        };
+        let client_auth_required = match setting("TLS_CLIENT_AUTH").map(|value| value.to_lowercase()).as_deref() {
+            None | Some("optional") => false,
+            Some("required") => true,
+            Some(other) => {
+                warn!("Invalid TLS_CLIENT_AUTH '{}' (optional or required), using optional", other);
+                false
+            },
+        };
+        let config = TlsConfig {
+            cert_path,
+            key_path,
+            port: env_number("TLS_PORT", 8443),
+            keep_http: env_flag("TLS_KEEP_HTTP", false),
+            client_ca_path: setting("TLS_CLIENT_CA_PATH"),
+            client_auth_required,
+            reload_interval: Duration::from_secs(env_number("TLS_RELOAD_INTERVAL_S", 30)),
+        };
+        if config.client_auth_required && config.client_ca_path.is_none() {
+            warn!("TLS_CLIENT_AUTH=required without TLS_CLIENT_CA_PATH: client certificates are not checked");
+        }
+        info!("HTTPS configuration loaded: {:?}", config);
+        Some(config)
+    }
+}
+
+// ============================================================================
+// Field encryption of the owner personal data (see services/field_encryption.rs)
+// ============================================================================
+// Read by services::db::AppDatabase::init: the server (and the commands) do not start with invalid keys.
+
+#[derive(Clone)]
+pub struct FieldEncryptionConfig {
+    // FIELD_ENCRYPTION_KEYS (optional): "<key id>:<base64 of 32 bytes>,..." the first key encrypts, every key decrypts
+    // FIELD_BLIND_INDEX_KEY (required with FIELD_ENCRYPTION_KEYS): base64 of 32 bytes, keys the email / phone lookups
+    pub keys: Option<String>,
+    pub blind_index_key: Option<String>,
+    // FIELD_KEYRING_FILE (default: "field_keyring.txt"): the keys when FIELD_ENCRYPTION_KEYS is unset,
+    // created with new keys when missing (keep it out of the repository and back it up)
+    pub keyring_file: String,
+}
+
+impl FieldEncryptionConfig {
+    pub fn from_env() -> Self {
+        let setting = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let config = FieldEncryptionConfig {
+            keys: setting("FIELD_ENCRYPTION_KEYS"),
+            blind_index_key: setting("FIELD_BLIND_INDEX_KEY"),
+            keyring_file: setting("FIELD_KEYRING_FILE").unwrap_or_else(|| "field_keyring.txt".to_string()),
+        };
+        info!("Field encryption configuration loaded: {:?}", config);
+        config
+    }
+}
+
+// the keys are never logged
+impl fmt::Debug for FieldEncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldEncryptionConfig")
+            .field("keys", &self.keys.as_ref().map(|_| "<redacted>"))
+            .field("blind_index_key", &self.blind_index_key.as_ref().map(|_| "<redacted>"))
+            .field("keyring_file", &self.keyring_file)
+            .finish()
+    }
+}
@@ -1,5 +1,6 @@
 use actix_web::HttpResponse;
 use serde::Serialize;
+use crate::app_errors::errors::AppError;
 
 // Successful messages with/without data
 #[derive(Debug, Serialize)]
@@ -87,6 +88,115 @@ impl ErrorJsonApiResponse {
         )
     }
 
+    // 412: If-Match did not match the current version of the item (optimistic concurrency)
+    #[allow(dead_code)]
+    pub fn precondition_failed(err: &str) -> HttpResponse {
+        HttpResponse::PreconditionFailed().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 428: If-Match is required (see AppConfig::require_if_match) but missing
+    #[allow(dead_code)]
+    pub fn precondition_required(err: &str) -> HttpResponse {
+        HttpResponse::PreconditionRequired().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 401: the caller is not authenticated (see routes/actor.rs), the client should log in again
+    #[allow(dead_code)]
+    pub fn unauthorized(err: &str) -> HttpResponse {
+        HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Bearer"))
+            .json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 403: the caller is not allowed to access this item
+    #[allow(dead_code)]
+    pub fn forbidden(err: &str) -> HttpResponse {
+        HttpResponse::Forbidden().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 409: the request conflicts with the current state of the item
+    #[allow(dead_code)]
+    pub fn conflict(err: &str) -> HttpResponse {
+        HttpResponse::Conflict().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 413: the request body is larger than accepted
+    #[allow(dead_code)]
+    pub fn payload_too_large(err: &str) -> HttpResponse {
+        HttpResponse::PayloadTooLarge().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 415: the type of the uploaded content is not accepted
+    #[allow(dead_code)]
+    pub fn unsupported_media_type(err: &str) -> HttpResponse {
+        HttpResponse::UnsupportedMediaType().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 429: the client sent more requests than its rate limit allows (see routes/rate_limit.rs)
+    #[allow(dead_code)]
+    pub fn too_many_requests(err: &str) -> HttpResponse {
+        HttpResponse::TooManyRequests().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // 502: an upstream server (e.g. the identity provider) failed or answered unexpectedly
+    #[allow(dead_code)]
+    pub fn bad_gateway(err: &str) -> HttpResponse {
+        HttpResponse::BadGateway().json(
+            ErrorJsonApiResponse{
+                error: err.to_string(),
+            }
+        )
+    }
+
+    // errors of update/delete operations: missing item, version conflict, missing If-Match, missing credentials, refused access or upload, anything else is a 500
+    #[allow(dead_code)]
+    pub fn from_write_error(app_error: &AppError) -> HttpResponse {
+        match app_error {
+            AppError::NotFound => ErrorJsonApiResponse::not_found(&app_error.to_string()),
+            AppError::PreconditionFailed => ErrorJsonApiResponse::precondition_failed(&app_error.to_string()),
+            AppError::PreconditionRequired => ErrorJsonApiResponse::precondition_required(&app_error.to_string()),
+            AppError::Unauthorized(_) => ErrorJsonApiResponse::unauthorized(&app_error.to_string()),
+            AppError::Forbidden(_) => ErrorJsonApiResponse::forbidden(&app_error.to_string()),
+            AppError::Conflict(_) => ErrorJsonApiResponse::conflict(&app_error.to_string()),
+            AppError::PayloadTooLarge(_) => ErrorJsonApiResponse::payload_too_large(&app_error.to_string()),
+            AppError::UnsupportedMediaType(_) => ErrorJsonApiResponse::unsupported_media_type(&app_error.to_string()),
+            AppError::BadGateway(_) => ErrorJsonApiResponse::bad_gateway(&app_error.to_string()),
+            _ => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn from_db_error(err: mongodb::error::Error) -> HttpResponse {
         ErrorJsonApiResponse::internal_server_error(&err.to_string())
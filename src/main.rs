@@ -2,11 +2,28 @@ use log::{ info,  LevelFilter};  // A lightweight logging facade for Rust
 use env_logger::{Builder, Target};  // Builder to configure logging programatically; Target to choose output
 
 
-use actix_web::{get, App, web, HttpResponse, HttpServer, Responder};
-use routes::{booking_routes::{create_booking, delete_booking, list_booking, list_bookings, update_booking}, 
-                 dog_routes::{create_dog, delete_dog, list_dog, list_dogs, update_dog}, 
-                 owner_routes::{create_owner, delete_owner, list_owner, list_owners, update_owner}, 
-                 sitter_routes::{create_sitter, delete_sitter, list_sitter, list_sitters, update_sitter}};
+use actix_web::{get, middleware, App, web, HttpResponse, HttpServer, Responder};
+use routes::{admin_routes::integrity_report,
+                 api_key_auth::api_key_auth,
+                 api_key_routes::{create_api_key, list_api_key, list_api_keys, revoke_api_key},
+                 attachment_routes::{delete_booking_photo, delete_dog_photo, delete_sitter_document,
+                                     download_booking_photo, download_booking_photo_thumbnail, download_dog_photo,
+                                     download_dog_photo_thumbnail, download_sitter_document, download_sitter_document_thumbnail,
+                                     list_booking_photos, list_dog_photos, list_sitter_documents,
+                                     upload_booking_photos, upload_dog_photos, upload_sitter_documents},
+                 auth_routes::{confirm_password_reset, login, logout, oidc_callback, oidc_login, refresh, request_password_reset,
+                              resend_verification_email, verify_email, verify_email_link},
+                 audit_routes::{query_audit_log, resource_history},
+                 booking_routes::{create_booking, delete_booking, list_booking, list_bookings, stream_bookings, update_booking}, 
+                 http_security::{self, json_limit},
+                 dog_routes::{create_dog, delete_dog, dog_stats, list_dog, list_dogs, update_dog}, 
+                 outbox_routes::{list_outbox_messages, retry_outbox_message},
+                 rate_limit::rate_limit,
+                 owner_routes::{create_owner, delete_owner, erase_owner, export_owner, list_owner, list_owners, update_owner}, 
+                 report_routes::{booking_report, shared_report_page, submit_booking_report},
+                 sitter_routes::{create_sitter, delete_sitter, list_sitter, list_sitters, update_sitter},
+                 walk_routes::{booking_track, upload_booking_track, walk_socket},
+                 webhook_routes::{create_webhook, delete_webhook, list_webhook, list_webhook_deliveries, list_webhooks, redeliver_webhook}};
 
 
 mod services;
@@ -14,12 +31,18 @@ mod models;
 mod routes;
 mod json_response;
 mod app_errors;
+mod commands;
+mod config;
 
 
 //  use environment variables defined in a .env file with the help of the dotenv crate. 
 //  This is commonly used in combination with the standard std::env module to access environment variables at runtime.
 use dotenv::dotenv;
 //use std::env;
+use std::sync::Arc;
+
+// domain events kept for slow in-process subscribers before they start losing the oldest ones
+const IN_PROCESS_EVENTS_CAPACITY: usize = 1024;
 
 #[get("/")]
 async fn hello() -> impl Responder{ 
@@ -40,29 +63,148 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok(); // Load variables from `.env` into the environment
     set_logger();  // set and init logger
 
+    // Subcommands: `cargo run -- doctor [--fix]` runs the data consistency doctor instead of the server
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("doctor") {
+        let db = services::db::AppDatabase::init().await;
+        let apply_fixes = args.iter().any(|arg| arg == "--fix");
+        return commands::doctor::run(&db, apply_fixes)
+            .await
+            .map_err(|app_error| std::io::Error::other(app_error.to_string()));
+    }
+    // `cargo run -- set-password <owner|sitter|dispatcher|admin> <id>` sets the password of an account (read from stdin)
+    if args.get(1).map(String::as_str) == Some("set-password") {
+        let db = services::db::AppDatabase::init().await;
+        return commands::set_password::run(&db, &args[2..])
+            .await
+            .map_err(|app_error| std::io::Error::other(app_error.to_string()));
+    }
+    // `cargo run -- create-staff <dispatcher|admin> <email>` creates a staff account (password read from stdin)
+    if args.get(1).map(String::as_str) == Some("create-staff") {
+        let db = services::db::AppDatabase::init().await;
+        return commands::create_staff::run(&db, &args[2..])
+            .await
+            .map_err(|app_error| std::io::Error::other(app_error.to_string()));
+    }
+    // `cargo run -- mock-idp` runs a local OpenID Connect identity provider to try the single sign-on
+    if args.get(1).map(String::as_str) == Some("mock-idp") {
+        return commands::mock_idp::run()
+            .await
+            .map_err(|app_error| std::io::Error::other(app_error.to_string()));
+    }
+    // `cargo run -- replay-bookings` rebuilds the booking projections from their events
+    if args.get(1).map(String::as_str) == Some("replay-bookings") {
+        let db = services::db::AppDatabase::init().await;
+        return commands::replay::run(&db)
+            .await
+            .map_err(|app_error| std::io::Error::other(app_error.to_string()));
+    }
+    // `cargo run -- reencrypt-owners` encrypts the owner personal data with the current key (see services/field_encryption.rs)
+    if args.get(1).map(String::as_str) == Some("reencrypt-owners") {
+        let db = services::db::AppDatabase::init().await;
+        return commands::reencrypt_owners::run(&db)
+            .await
+            .map_err(|app_error| std::io::Error::other(app_error.to_string()));
+    }
+
 
     let address = "localhost";
     let port = 8080;
+    // HTTPS with TLS_CERT_PATH / TLS_KEY_PATH, certificates reloaded on SIGHUP or when they change (see services/tls.rs)
+    let tls = match config::TlsConfig::from_env() {
+        Some(tls_config) => Some(services::tls::TlsState::load(tls_config).map_err(std::io::Error::other)?),
+        None => None,
+    };
 
     let db = services::db::AppDatabase::init().await;
     let db_data = web::Data::new(db);        // type: web::Data<service::db::AppDatabase>
+    let config_data = web::Data::new(config::AppConfig::from_env());   // type: web::Data<config::AppConfig>
+    let auth_config_data = web::Data::new(config::AuthConfig::from_env());   // read by the Principal extractor (see routes/actor.rs)
+    // single sign-on, disabled without OIDC_ISSUER (see services/oidc.rs)
+    let oidc_data = web::Data::new(services::oidc::OidcProvider::new(config::OidcConfig::from_env()));
+    // email verification and password reset links, sent through the mailer of MAIL_TRANSPORT (see services/account_emails.rs)
+    let mail_config = config::MailConfig::from_env();
+    let mailer = services::mailer::build_mailer(&mail_config);
+    let account_emails_data = web::Data::new(services::account_emails::AccountEmails::new(mail_config, mailer));
+    // CORS, security headers and JSON body limits (see routes/http_security.rs)
+    let http_security_data = web::Data::new(config::HttpSecurityConfig::from_env());
+    // token buckets per client, kept in memory or in MongoDB (see services/rate_limiter.rs)
+    let rate_limiter_data = web::Data::new(services::rate_limiter::RateLimiter::new(config::RateLimitConfig::from_env(), db_data.clone().into_inner()));
 
-    info!("Starting server at {} , port: {}", address, port);
-    HttpServer::new(move || App::new()
+    // Outbox dispatcher: delivers the domain events of the 'outbox' collection to the sinks (see services/outbox.rs)
+    // the in-process sink is shared with the handlers, they can subscribe to the domain events
+    let in_process_sink = Arc::new(services::outbox_sinks::InProcessSink::new(IN_PROCESS_EVENTS_CAPACITY));
+    let dispatcher_config = config::DispatcherConfig::from_env();
+    let sinks = services::outbox::build_sinks(db_data.clone().into_inner(), &dispatcher_config, in_process_sink.clone());
+    actix_web::rt::spawn(services::outbox::run_dispatcher(db_data.clone().into_inner(), sinks, dispatcher_config.clone()));
+    // Live booking changes for GET /bookings/stream (see services/booking_stream.rs)
+    actix_web::rt::spawn(services::booking_stream::run_change_stream_feed(db_data.clone().into_inner()));
+    // Webhook deliveries: signed POSTs to the partner subscriptions, with retries (see services/webhooks.rs)
+    actix_web::rt::spawn(services::webhooks::run_delivery_worker(db_data.clone().into_inner(), dispatcher_config));
+    let in_process_data = web::Data::from(in_process_sink);   // type: web::Data<services::outbox_sinks::InProcessSink>
+    // live walk positions, relayed from the sitters to the owners (see routes/walk_routes.rs)
+    let walk_hub_data = web::Data::new(services::walk_tracking::WalkHub::new());
+
+    let server = HttpServer::new(move || App::new()
         .app_data(db_data.clone())     // register it here 
+        .app_data(config_data.clone())
+        .app_data(auth_config_data.clone())
+        .app_data(in_process_data.clone())
+        .app_data(walk_hub_data.clone())
+        .app_data(oidc_data.clone())
+        .app_data(account_emails_data.clone())
+        .app_data(rate_limiter_data.clone())
+        .app_data(http_security_data.clone())
+        // the Json extractor accepts up to the largest route limit (JSON_ROUTE_LIMITS): only safe because json_limit
+        // below wraps the whole App, every JSON route included, and applies the limit of each route first
+        .app_data(http_security::json_config(&http_security_data))
+        .wrap(middleware::from_fn(json_limit))     // 413 for JSON bodies over JSON_MAX_BYTES / JSON_ROUTE_LIMITS
+        .wrap(middleware::from_fn(rate_limit))     // 429 over the limits of RATE_LIMIT_* (see routes/rate_limit.rs), runs after the API key check
+        .wrap(middleware::from_fn(api_key_auth))   // `Authorization: ApiKey <key>` of partner systems (see routes/api_key_auth.rs)
+        .wrap(http_security::security_headers(&http_security_data))
+        .wrap(http_security::cors(&http_security_data))   // outermost: preflight requests and every error get the CORS headers
+        .service(login)
+        .service(refresh)
+        .service(logout)
+        .service(oidc_login)
+        .service(oidc_callback)
+        .service(verify_email_link)
+        .service(verify_email)
+        .service(resend_verification_email)
+        .service(request_password_reset)
+        .service(confirm_password_reset)
         .service(create_owner)
         .service(list_owners)
         .service(list_owner)
         .service(update_owner)
         .service(delete_owner)
+        .service(export_owner)
+        .service(erase_owner)
         .service(create_dog)
         .service(list_dogs)
         .service(list_dog)
+        .service(dog_stats)
+        .service(upload_dog_photos)
+        .service(list_dog_photos)
+        .service(download_dog_photo)
+        .service(download_dog_photo_thumbnail)
+        .service(delete_dog_photo)
         .service(update_dog)
         .service(delete_dog)
         .service(create_booking)
         .service(list_bookings)
+        .service(stream_bookings)     // before list_booking: /bookings/stream is not /bookings/{id}
         .service(list_booking)
+        .service(walk_socket)
+        .service(booking_track)
+        .service(upload_booking_track)
+        .service(upload_booking_photos)
+        .service(list_booking_photos)
+        .service(download_booking_photo)
+        .service(download_booking_photo_thumbnail)
+        .service(delete_booking_photo)
+        .service(submit_booking_report)
+        .service(booking_report)
         .service(update_booking)
         .service(delete_booking)
         .service(create_sitter)
@@ -70,9 +212,47 @@ async fn main() -> std::io::Result<()> {
         .service(list_sitter)
         .service(update_sitter)
         .service(delete_sitter)
+        .service(upload_sitter_documents)
+        .service(list_sitter_documents)
+        .service(download_sitter_document)
+        .service(download_sitter_document_thumbnail)
+        .service(delete_sitter_document)
+        .service(shared_report_page)
+        .service(integrity_report)
+        .service(query_audit_log)
+        .service(create_webhook)
+        .service(list_webhooks)
+        .service(list_webhook)
+        .service(list_webhook_deliveries)
+        .service(redeliver_webhook)
+        .service(delete_webhook)
+        .service(list_outbox_messages)
+        .service(retry_outbox_message)
+        .service(resource_history)
+        .service(create_api_key)
+        .service(list_api_keys)
+        .service(list_api_key)
+        .service(revoke_api_key)
         )
-        .bind((address, port))?
-        .run()
-        .await
+        .on_connect(services::tls::on_connect);   // the client certificate of mutual TLS connections
+
+    let server = match &tls {
+        Some(tls) => {
+            actix_web::rt::spawn(services::tls::run_reloader(tls.clone()));
+            info!("Starting HTTPS server at {} , port: {}", address, tls.port());
+            let server = server.bind_rustls_0_23((address, tls.port()), tls.server_config())?;
+            if tls.keep_http() {
+                info!("Also serving plain HTTP at {} , port: {}", address, port);
+                server.bind((address, port))?
+            } else {
+                server
+            }
+        },
+        None => {
+            info!("Starting server at {} , port: {}", address, port);
+            server.bind((address, port))?
+        },
+    };
+    server.run().await
 
 }
@@ -0,0 +1,109 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// API keys: server-to-server access for partner systems (no human login)
+// ============================================================================
+// Admins create them (POST /admin/api-keys) with the resources and actions they grant, and an optional expiry.
+// The partner sends `Authorization: ApiKey bwk_<prefix>_<secret>` (see routes/api_key_auth.rs):
+//  - the key is only shown once, on creation; only its SHA-256 is stored
+//  - the prefix identifies the key (listings, logs) without revealing it
+//  - a request with the key acts as an 'integration' principal, limited to its grants (see services/policy.rs)
+//  - a key bound to a client certificate only works over mutual TLS with that certificate (see services/tls.rs)
+
+// ApiKey: Represents the data stored in MongoDB ('api_keys' collection)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub _id: ObjectId,
+    pub name: String,                   // e.g. "partner booking portal"
+    pub prefix: String,                 // 8 hex characters, unique, also the start of the key
+    pub key_hash: String,               // SHA-256 (hex) of the whole key
+    pub scopes: Vec<String>,            // grants, e.g. ["bookings:write", "dogs:read"]
+    pub expires_at: Option<DateTime>,   // None: valid until revoked
+    pub last_used_at: Option<DateTime>, // updated at most once a minute
+    pub revoked_at: Option<DateTime>,
+    #[serde(default)]
+    pub client_certificate_sha256: Option<String>,   // SHA-256 (hex) of the client certificate the key is bound to
+    pub created_at: DateTime,
+    pub created_by: String,
+}
+
+// MongoDB validator for the 'api_keys' collection (see models/schema.rs)
+impl CollectionSchema for ApiKey {
+    const COLLECTION_NAME: &'static str = "api_keys";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "name", "prefix", "key_hash", "scopes", "created_at", "created_by"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "name": schema::string_min_length(1),
+                "prefix": schema::string_min_length(8),
+                "key_hash": schema::string_min_length(64),
+                "scopes": { "bsonType": "array", "minItems": 1, "items": { "bsonType": "string" } },
+                "expires_at": schema::nullable("date"),
+                "last_used_at": schema::nullable("date"),
+                "revoked_at": schema::nullable("date"),
+                "client_certificate_sha256": schema::nullable("string"),
+                "created_at": schema::field("date"),
+                "created_by": schema::field("string"),
+            },
+        )
+    }
+}
+
+// ApiKeyRequest: Used when receiving data from clients (POST /admin/api-keys)
+// { "name": "partner portal", "scopes": ["bookings:write", "dogs:read"], "expires_at": "2027-01-01T00:00:00Z",
+//   "client_certificate_sha256": "AB:CD:..." }
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ApiKeyRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,     // RFC3339, in the future
+    pub client_certificate_sha256: Option<String>,   // hex, ':' separators allowed (openssl x509 -noout -fingerprint -sha256)
+}
+
+// ApiKeyResponse: Used to send clean, flattened JSON to clients (without the hash)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyResponse {
+    pub _id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,     // RFC3339 strings
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+    pub client_certificate_sha256: Option<String>,
+    pub created_at: String,
+    pub created_by: String,
+}
+
+// use From as it is a safe mapping (from database 'ApiKey' struct → response 'ApiKeyResponse' struct)
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            _id: api_key._id.to_hex(),
+            name: api_key.name,
+            prefix: api_key.prefix,
+            scopes: api_key.scopes,
+            expires_at: api_key.expires_at.map(|date| date.to_chrono().to_rfc3339()),
+            last_used_at: api_key.last_used_at.map(|date| date.to_chrono().to_rfc3339()),
+            revoked_at: api_key.revoked_at.map(|date| date.to_chrono().to_rfc3339()),
+            client_certificate_sha256: api_key.client_certificate_sha256,
+            created_at: api_key.created_at.to_chrono().to_rfc3339(),
+            created_by: api_key.created_by,
+        }
+    }
+}
+
+// CreatedApiKeyResponse: answer of POST /admin/api-keys, the only time the key itself is sent
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedApiKeyResponse {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+}
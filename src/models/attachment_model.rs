@@ -0,0 +1,105 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Attachments: photos and documents uploaded for a dog, a booking or a sitter
+// ============================================================================
+// The bytes are stored in MongoDB GridFS (bucket 'uploads': 'uploads.files' + 'uploads.chunks'),
+// this document describes the upload and links it to its resource (see services/attachments.rs).
+//
+//   POST /dogs/{id}/photos            photos of the dog
+//   POST /bookings/{id}/photos        photos of the walk, by the sitter of the booking
+//   POST /sitters/{id}/documents      documents of the sitter (certificates, insurance, ...)
+
+// Attachment: Represents the data stored in MongoDB ('attachments' collection)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub _id: ObjectId,
+    pub resource: String,              // dogs | bookings | sitters
+    pub resource_id: ObjectId,
+    pub kind: String,                  // photo | document
+    pub filename: String,              // as sent by the client
+    pub content_type: String,          // sniffed from the bytes, never the one declared by the client
+    pub length: i64,                   // bytes
+    pub sha256: String,                // hex, also the ETag of the download
+    pub file_id: ObjectId,             // GridFS file
+    pub thumbnail_id: Option<ObjectId>,   // GridFS file of the JPEG thumbnail (images only)
+    pub width: Option<i64>,            // pixels (images only)
+    pub height: Option<i64>,
+    pub uploaded_at: DateTime,
+    pub uploaded_by: String,           // actor (see routes/actor.rs)
+}
+
+// MongoDB validator for the 'attachments' collection (see models/schema.rs)
+impl CollectionSchema for Attachment {
+    const COLLECTION_NAME: &'static str = "attachments";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "resource", "resource_id", "kind", "filename", "content_type", "length", "sha256", "file_id", "uploaded_at", "uploaded_by"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "resource": { "enum": ["dogs", "bookings", "sitters"] },
+                "resource_id": schema::field("objectId"),
+                "kind": { "enum": ["photo", "document"] },
+                "filename": schema::field("string"),
+                "content_type": schema::field("string"),
+                "length": schema::integer_range(0, i64::MAX),
+                "sha256": schema::string_min_length(64),
+                "file_id": schema::field("objectId"),
+                "thumbnail_id": schema::nullable("objectId"),
+                "width": schema::nullable_integer_range(0, i64::MAX),
+                "height": schema::nullable_integer_range(0, i64::MAX),
+                "uploaded_at": schema::field("date"),
+                "uploaded_by": schema::field("string"),
+            },
+        )
+    }
+}
+
+// AttachmentResponse: Used to send clean, flattened JSON to clients, with the download links
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachmentResponse {
+    pub _id: String,
+    pub resource: String,
+    pub resource_id: String,
+    pub kind: String,
+    pub filename: String,
+    pub content_type: String,
+    pub length: i64,
+    pub sha256: String,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub url: String,                      // e.g. /dogs/{id}/photos/{attachment id}
+    pub thumbnail_url: Option<String>,    // e.g. /dogs/{id}/photos/{attachment id}/thumbnail
+    pub uploaded_at: String,              // RFC3339 string
+    pub uploaded_by: String,
+}
+
+// use From as it is a safe mapping (from database 'Attachment' struct → response 'AttachmentResponse' struct)
+impl From<Attachment> for AttachmentResponse {
+    fn from(attachment: Attachment) -> Self {
+        let collection = match attachment.kind.as_str() {
+            "document" => "documents",
+            _ => "photos",
+        };
+        let url = format!("/{}/{}/{}/{}", attachment.resource, attachment.resource_id.to_hex(), collection, attachment._id.to_hex());
+        Self {
+            _id: attachment._id.to_hex(),
+            resource: attachment.resource,
+            resource_id: attachment.resource_id.to_hex(),
+            kind: attachment.kind,
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            length: attachment.length,
+            sha256: attachment.sha256,
+            width: attachment.width,
+            height: attachment.height,
+            thumbnail_url: attachment.thumbnail_id.map(|_| format!("{}/thumbnail", url)),
+            url,
+            uploaded_at: attachment.uploaded_at.to_chrono().to_rfc3339(),
+            uploaded_by: attachment.uploaded_by,
+        }
+    }
+}
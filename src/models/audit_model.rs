@@ -0,0 +1,82 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Audit log: one append-only entry per create / update / delete
+// ============================================================================
+// Written by services::audit, never updated nor deleted by the application, except an erasure
+// (POST /owners/{id}/erase) removing the personal values of the owner from its entries.
+
+// AuditEntry: Represents the data stored in MongoDB ('audit_log' collection)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub _id: ObjectId,
+    pub resource: String,        // owners, dogs, sitters, bookings (same names as the URL paths)
+    pub resource_id: ObjectId,   // _id of the document that changed
+    pub action: String,          // create, update, delete; export, erase (personal data requests, see services/privacy.rs)
+    pub actor: String,           // who did it (see routes/actor.rs)
+    pub timestamp: DateTime,
+    pub changes: Document,       // { field: { before: .., after: .. } } for the fields written by the operation
+                                 // (export, erase: the reason and what was sent or removed)
+}
+
+// MongoDB validator for the 'audit_log' collection (see models/schema.rs)
+impl CollectionSchema for AuditEntry {
+    const COLLECTION_NAME: &'static str = "audit_log";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "resource", "resource_id", "action", "actor", "timestamp", "changes"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "resource": schema::field("string"),
+                "resource_id": schema::field("objectId"),
+                "action": { "enum": ["create", "update", "delete", "export", "erase"] },
+                "actor": schema::field("string"),
+                "timestamp": schema::field("date"),
+                "changes": schema::field("object"),
+            },
+        )
+    }
+}
+
+// AuditEntryResponse: Used to send clean, flattened JSON to clients
+// the before/after values are sent as relaxed extended JSON (ObjectIds as {"$oid": ..}, dates as {"$date": ..})
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntryResponse {
+    pub _id: String,
+    pub resource: String,
+    pub resource_id: String,
+    pub action: String,
+    pub actor: String,
+    pub timestamp: String,       // RFC3339 string
+    pub changes: serde_json::Value,
+}
+
+// use From as it is a safe mapping (from database 'AuditEntry' struct → response 'AuditEntryResponse' struct)
+impl From<AuditEntry> for AuditEntryResponse {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            _id: entry._id.to_hex(),
+            resource: entry.resource,
+            resource_id: entry.resource_id.to_hex(),
+            action: entry.action,
+            actor: entry.actor,
+            timestamp: entry.timestamp.to_chrono().to_rfc3339(),
+            changes: mongodb::bson::Bson::Document(entry.changes).into_relaxed_extjson(),
+        }
+    }
+}
+
+// AuditQuery: query string of GET /admin/audit, all parameters optional
+// e.g. GET /admin/audit?actor=dispatcher-1&from=2025-05-01T00:00:00Z&to=2025-05-31T23:59:59Z&resource=bookings
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub resource: Option<String>,
+    pub action: Option<String>,
+    pub from: Option<String>,    // RFC3339, inclusive
+    pub to: Option<String>,      // RFC3339, inclusive
+    pub limit: Option<i64>,      // default 100, at most 1000
+}
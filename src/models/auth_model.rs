@@ -0,0 +1,197 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Authentication: credentials, refresh tokens and revoked access tokens
+// ============================================================================
+// Owners, sitters and staff (dispatchers, admins) log in with their email and password (POST /auth/login, see services/auth.rs):
+//  - the access token is a short-lived JWT (HS256), sent as `Authorization: Bearer <token>`
+//  - the refresh token is random, stored hashed, and replaced by a new one on each use (POST /auth/refresh)
+//  - POST /auth/logout revokes the access token (until it expires) and the refresh tokens of the session
+// The password never leaves 'credentials', which is not exposed by any route.
+
+// Role: the kind of account behind a token, what it may see and change is decided in services/policy.rs
+//  - owner:      its own owner record, its dogs and its bookings
+//  - sitter:     its own sitter record and the bookings it is assigned to (with their owners and dogs)
+//  - dispatcher: reads everything, creates and assigns bookings (staff account, no owner/sitter record)
+//  - admin:      everything, including the audit log, the outbox and the webhooks (staff account)
+//  - integration: a partner system calling with an API key, limited to the grants of the key (see models/api_key_model.rs)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Owner,
+    Sitter,
+    Dispatcher,
+    Admin,
+    Integration,
+}
+
+impl Role {
+    // the roles of the accounts logging in with a password (an integration only has API keys)
+    pub const ACCOUNT_ROLES: [&'static str; 4] = ["owner", "sitter", "dispatcher", "admin"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Sitter => "sitter",
+            Role::Dispatcher => "dispatcher",
+            Role::Admin => "admin",
+            Role::Integration => "integration",
+        }
+    }
+
+    // staff accounts are not owners or sitters, their principal_id only identifies the account
+    pub fn is_staff(&self) -> bool {
+        matches!(self, Role::Dispatcher | Role::Admin)
+    }
+}
+
+// Principal: the authenticated account performing a request (from its access token or API key, see routes/actor.rs)
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub id: ObjectId,           // owner or sitter ID (account ID for staff, key ID for an integration)
+    pub role: Role,
+    pub token_id: String,       // jti of the access token, see POST /auth/logout (prefix of an API key)
+    pub expires_at: i64,        // seconds since epoch (i64::MAX for an API key without expiry)
+    pub grants: Vec<String>,    // "<resource>:<read|write>" of an API key, empty for the other roles
+}
+
+// Credential: Represents the data stored in MongoDB ('credentials' collection), one per account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credential {
+    pub _id: ObjectId,
+    pub principal_id: ObjectId,     // the owner or sitter (a new ID for staff accounts)
+    pub role: Role,
+    pub login: String,              // email given at sign-up (or to create-staff), lowercase, unique
+    pub password_hash: String,      // Argon2id, PHC string (algorithm, parameters and salt included)
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+// MongoDB validator for the 'credentials' collection (see models/schema.rs)
+impl CollectionSchema for Credential {
+    const COLLECTION_NAME: &'static str = "credentials";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "principal_id", "role", "login", "password_hash", "created_at", "updated_at"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "principal_id": schema::field("objectId"),
+                "role": { "enum": Role::ACCOUNT_ROLES.to_vec() },
+                "login": schema::string_min_length(3),
+                "password_hash": schema::string_min_length(1),
+                "created_at": schema::field("date"),
+                "updated_at": schema::field("date"),
+            },
+        )
+    }
+}
+
+// RefreshToken: Represents the data stored in MongoDB ('refresh_tokens' collection)
+// Every login starts a family; each refresh marks the token as rotated and adds the next one to the family.
+// A rotated token presented again means it leaked: the whole family is revoked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub _id: ObjectId,
+    pub token_hash: String,         // SHA-256 (hex) of the token, the token itself is only known by the client
+    pub family_id: ObjectId,
+    pub principal_id: ObjectId,
+    pub role: Role,
+    pub issued_at: DateTime,
+    pub expires_at: DateTime,       // removed by MongoDB after that (TTL index)
+    pub rotated_at: Option<DateTime>,
+    pub revoked_at: Option<DateTime>,
+}
+
+impl CollectionSchema for RefreshToken {
+    const COLLECTION_NAME: &'static str = "refresh_tokens";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "token_hash", "family_id", "principal_id", "role", "issued_at", "expires_at"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "token_hash": schema::string_min_length(64),
+                "family_id": schema::field("objectId"),
+                "principal_id": schema::field("objectId"),
+                "role": { "enum": Role::ACCOUNT_ROLES.to_vec() },
+                "issued_at": schema::field("date"),
+                "expires_at": schema::field("date"),
+                "rotated_at": schema::nullable("date"),
+                "revoked_at": schema::nullable("date"),
+            },
+        )
+    }
+}
+
+// RevokedToken: an access token revoked before its expiry (logout), kept until it expires (TTL index)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokedToken {
+    pub _id: String,                // jti of the access token
+    pub principal_id: ObjectId,
+    pub expires_at: DateTime,
+}
+
+impl CollectionSchema for RevokedToken {
+    const COLLECTION_NAME: &'static str = "revoked_tokens";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "principal_id", "expires_at"],
+            doc! {
+                "_id": schema::field("string"),
+                "principal_id": schema::field("objectId"),
+                "expires_at": schema::field("date"),
+            },
+        )
+    }
+}
+
+// AccessClaims: the payload of the access token (JWT)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessClaims {
+    pub sub: String,                // principal ID (hex)
+    pub role: Role,
+    pub jti: String,                // token ID, for revocation
+    pub iss: String,
+    pub iat: i64,                   // seconds since epoch
+    pub exp: i64,
+}
+
+// LoginRequest: POST /auth/login  { "email": "...", "password": "..." }
+#[derive(Debug, Deserialize, Validate)]
+pub struct LoginRequest {
+    #[validate(length(min = 1, max = 320))]
+    pub email: String,
+    #[validate(length(min = 1, max = 128))]
+    pub password: String,
+}
+
+// RefreshRequest: POST /auth/refresh  { "refresh_token": "..." }
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+// LogoutRequest: POST /auth/logout  { "refresh_token": "..." } ends that session, { "all": true } every session
+#[derive(Debug, Default, Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub all: bool,
+}
+
+// TokenResponse: answer of login and refresh
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,         // "Bearer"
+    pub expires_in: i64,            // seconds
+    pub refresh_token: String,
+    pub refresh_expires_in: i64,    // seconds
+    pub principal_id: String,
+    pub role: Role,
+}
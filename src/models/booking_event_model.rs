@@ -0,0 +1,126 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::schema::{self, CollectionSchema};
+use crate::models::track_model::WalkStats;
+
+// ============================================================================
+// Booking events (event sourcing)
+// ============================================================================
+//
+// The history of a booking is a stream of events, stored in the 'booking_events' collection.
+// The 'booking' collection only holds a projection: the current state obtained by applying
+// the events of the stream in order (see services/booking_events.rs).
+//
+//   BookingRequested -> Rescheduled -> SitterAssigned -> Cancelled -> ...
+//     sequence 1         sequence 2     sequence 3       sequence 4
+//
+// The sequence of the last event is the version of the booking (ETag, If-Match).
+
+// domain event types published in the outbox, see BookingEventKind::domain_event_type
+pub const DOMAIN_EVENT_TYPES: [&str; 10] = [
+    "booking.created", "booking.rescheduled", "booking.owner_changed", "booking.sitter_assigned",
+    "booking.dogs_changed", "booking.cancelled", "booking.reinstated", "booking.walk_recorded",
+    "booking.report_submitted", "booking.deleted",
+];
+
+// BookingEventKind: what happened to the booking, stored with a "type" tag, e.g. { "type": "Cancelled" }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BookingEventKind {
+    // a new booking
+    BookingRequested {
+        owner: ObjectId,
+        start_time: DateTime,
+        duration_minutes: u8,
+        sitter: Option<ObjectId>,
+        #[serde(default)]
+        dogs: Vec<ObjectId>,   // events written before dogs were linked have none
+    },
+    // snapshot of a booking written before event sourcing, starts its stream
+    BookingImported {
+        owner: ObjectId,
+        start_time: DateTime,
+        duration_minutes: u8,
+        cancelled: bool,
+        sitter: Option<ObjectId>,
+        #[serde(default)]
+        dogs: Vec<ObjectId>,
+        created_at: Option<DateTime>,
+        created_by: Option<String>,
+    },
+    Rescheduled { start_time: DateTime, duration_minutes: u8 },
+    OwnerChanged { owner: ObjectId },
+    SitterAssigned { sitter: ObjectId },
+    DogsChanged { dogs: Vec<ObjectId> },
+    Cancelled,
+    Reinstated,   // a cancelled booking made active again
+    WalkRecorded { walk: WalkStats },   // track of the walk uploaded (again), see services/track_upload.rs
+    ReportSubmitted { report_id: ObjectId },   // walk report of the sitter, see services/walk_reports.rs
+    Deleted,      // ends the stream, the projection is removed
+}
+
+impl BookingEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BookingEventKind::BookingRequested { .. } => "BookingRequested",
+            BookingEventKind::BookingImported { .. } => "BookingImported",
+            BookingEventKind::Rescheduled { .. } => "Rescheduled",
+            BookingEventKind::OwnerChanged { .. } => "OwnerChanged",
+            BookingEventKind::SitterAssigned { .. } => "SitterAssigned",
+            BookingEventKind::DogsChanged { .. } => "DogsChanged",
+            BookingEventKind::Cancelled => "Cancelled",
+            BookingEventKind::Reinstated => "Reinstated",
+            BookingEventKind::WalkRecorded { .. } => "WalkRecorded",
+            BookingEventKind::ReportSubmitted { .. } => "ReportSubmitted",
+            BookingEventKind::Deleted => "Deleted",
+        }
+    }
+
+    // type of the domain event published in the outbox (see services/outbox.rs), None for internal events
+    pub fn domain_event_type(&self) -> Option<&'static str> {
+        match self {
+            BookingEventKind::BookingRequested { .. } => Some("booking.created"),
+            BookingEventKind::BookingImported { .. } => None,
+            BookingEventKind::Rescheduled { .. } => Some("booking.rescheduled"),
+            BookingEventKind::OwnerChanged { .. } => Some("booking.owner_changed"),
+            BookingEventKind::SitterAssigned { .. } => Some("booking.sitter_assigned"),
+            BookingEventKind::DogsChanged { .. } => Some("booking.dogs_changed"),
+            BookingEventKind::Cancelled => Some("booking.cancelled"),
+            BookingEventKind::Reinstated => Some("booking.reinstated"),
+            BookingEventKind::WalkRecorded { .. } => Some("booking.walk_recorded"),
+            BookingEventKind::ReportSubmitted { .. } => Some("booking.report_submitted"),
+            BookingEventKind::Deleted => Some("booking.deleted"),
+        }
+    }
+}
+
+// BookingEvent: Represents the data stored in MongoDB ('booking_events' collection)
+// (booking_id, sequence) is unique: two writers cannot append the same sequence, the second one fails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookingEvent {
+    pub _id: ObjectId,
+    pub booking_id: ObjectId,
+    pub sequence: i64,            // position in the stream of the booking, 1 for BookingRequested
+    pub event: BookingEventKind,
+    pub actor: String,            // who caused it (see routes/actor.rs)
+    pub timestamp: DateTime,
+}
+
+// MongoDB validator for the 'booking_events' collection (see models/schema.rs)
+impl CollectionSchema for BookingEvent {
+    const COLLECTION_NAME: &'static str = "booking_events";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "booking_id", "sequence", "event", "actor", "timestamp"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "booking_id": schema::field("objectId"),
+                "sequence": schema::integer_range(0, i64::MAX),
+                "event": schema::object_schema(&["type"], doc! { "type": schema::field("string") }),
+                "actor": schema::field("string"),
+                "timestamp": schema::field("date"),
+            },
+        )
+    }
+}
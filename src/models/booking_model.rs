@@ -1,19 +1,67 @@
-use mongodb::bson::{oid::ObjectId, DateTime};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
 use validator::Validate; // a mature crate that works with serde for fields validation
 use serde::{Deserialize, Serialize};   
 // Serialize, Deserialize: Needed for Actix (for sending/receiving JSON) and MongoDB (bson conversion).
 use chrono::Utc;
 use std::time::SystemTime;
+use crate::models::schema::{self, CollectionSchema};
+use crate::models::track_model::{WalkStats, WalkStatsResponse};
 
 
 // Separating database and API input schemas: backend pattern design
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Booking {
     pub _id: ObjectId,          // MongoDB needs an "_id" field, a unique identifier, ObjectId: Special ID format used by MongoDB
     pub owner: ObjectId,        // The ID of the user who made the booking
     pub start_time: DateTime,   // When the booking starts, DateTime (MongoDB BSON version): This is different from chrono::DateTime. MongoDB uses its own date format internally
     pub duration_minutes: u8,   // How long it lasts (in minutes)
     pub cancelled: bool,        // If the booking was cancelled
+    #[serde(default)]
+    pub sitter: Option<ObjectId>,   // The sitter (walker) assigned to the booking, if any
+    #[serde(default)]
+    pub dogs: Vec<ObjectId>,        // The dogs walked, empty: all the dogs of the owner
+    #[serde(default)]
+    pub walk: Option<WalkStats>,    // What actually happened, from the uploaded track (see services/track_upload.rs)
+    #[serde(default)]
+    pub report_id: Option<ObjectId>,   // The walk report of the sitter, once submitted (see services/walk_reports.rs)
+    #[serde(default)]
+    pub version: i64,           // optimistic concurrency: incremented on every update (see services/versioning.rs)
+    #[serde(default)]
+    pub created_at: Option<DateTime>,   // set by the services layer on create (None for documents written before)
+    #[serde(default)]
+    pub updated_at: Option<DateTime>,   // set by the services layer on create and on every update
+    #[serde(default)]
+    pub created_by: Option<String>,     // actor who created the document (see routes/actor.rs)
+    #[serde(default)]
+    pub updated_by: Option<String>,     // actor of the last create/update
+}
+
+// MongoDB validator for the 'booking' collection, mirrors the Booking struct above (see models/schema.rs)
+// e.g. a string `duration_minutes` is rejected here instead of breaking every read_bookings() afterwards
+impl CollectionSchema for Booking {
+    const COLLECTION_NAME: &'static str = "booking";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "owner", "start_time", "duration_minutes", "cancelled"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "owner": schema::field("objectId"),
+                "start_time": schema::field("date"),
+                "duration_minutes": schema::integer_range(0, u8::MAX as i64),
+                "cancelled": schema::field("bool"),
+                "sitter": schema::nullable("objectId"),
+                "dogs": { "bsonType": "array", "items": { "bsonType": "objectId" } },
+                "walk": schema::nullable("object"),
+                "report_id": schema::nullable("objectId"),
+                "version": schema::integer_range(0, i64::MAX),
+                "created_at": schema::nullable("date"),
+                "updated_at": schema::nullable("date"),
+                "created_by": schema::nullable("string"),
+                "updated_by": schema::nullable("string"),
+            },
+        )
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -21,7 +69,10 @@ pub struct BookingRequest {
     pub owner: String,           // Client sends owner ID as string
     pub start_time: String,      // Client sends start time as a string (RFC3339 datetime string)
     pub duration_minutes: u8,    // Client sends duration
-
+    #[serde(default)]
+    pub sitter: Option<String>,  // Client may directly assign a sitter (ID as string)
+    #[serde(default)]
+    pub dogs: Vec<String>,       // The dogs to walk (IDs as strings), empty: all the dogs of the owner
 }
 
 // use TryFrom for 'validated' or 'fallible' mappings (like request → domain struct)
@@ -37,10 +88,25 @@ impl TryFrom<BookingRequest> for Booking{
 	// and convert it to mongodb::bson::DateTime via DateTime::from_chrono()
     Ok(Self{
             _id: ObjectId::new(),  // Create a new _id for MongoDB
-            owner: ObjectId::parse_str(&booking_request.owner).expect("Failed to parse owner!"),    // Parse owner string to an ObjectId
+            owner: ObjectId::parse_str(&booking_request.owner).map_err(|err| format!("Failed to parse owner: {}", err))?,    // Parse owner string to an ObjectId
             start_time: DateTime::from(chrono_datatime),  
             duration_minutes: booking_request.duration_minutes,
             cancelled: false,
+            sitter: match booking_request.sitter {
+                Some(sitter) => Some(ObjectId::parse_str(&sitter).map_err(|err| format!("Failed to parse sitter: {}", err))?),
+                None => None,
+            },
+            dogs: booking_request.dogs
+                .iter()
+                .map(|dog| ObjectId::parse_str(dog).map_err(|err| format!("Failed to parse dog: {}", err)))
+                .collect::<Result<Vec<ObjectId>, String>>()?,
+            walk: None,   // set by POST /bookings/{id}/track
+            report_id: None,   // set by POST /bookings/{id}/report
+            version: 1,
+            created_at: None,   // timestamps are set by services::bookings::create_booking
+            updated_at: None,
+            created_by: None,
+            updated_by: None,
     })
     }
 
@@ -55,6 +121,16 @@ pub struct BookingResponse {
     pub start_time: String,   // RFC3339 string
     pub duration_minutes: u8, 
     pub cancelled: bool,      
+    pub sitter: Option<String>,
+    pub dogs: Vec<String>,
+    pub walk: Option<WalkStatsResponse>,   // None until the track of the walk is uploaded
+    pub report: Option<String>,            // link to the walk report, None until the sitter submits it
+    pub version: i64,         // also sent as ETag, send it back in If-Match to update/delete
+    // read-only metadata, managed by the server (RFC3339 datetimes)
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
 }
 
 // use From as it is a safe mapping (from database 'Booking' struct → response 'BookingResponse' struct)
@@ -67,6 +143,15 @@ impl From<Booking> for BookingResponse {
             start_time: booking.start_time.to_chrono().to_rfc3339(),  
             duration_minutes: booking.duration_minutes ,
             cancelled: booking.cancelled,
+            sitter: booking.sitter.map(|sitter| sitter.to_hex()),
+            dogs: booking.dogs.iter().map(|dog| dog.to_hex()).collect(),
+            walk: booking.walk.map(WalkStatsResponse::from),
+            report: booking.report_id.map(|_| format!("/bookings/{}/report", booking._id.to_hex())),
+            version: booking.version,
+            created_at: booking.created_at.map(|date| date.to_chrono().to_rfc3339()),
+            updated_at: booking.updated_at.map(|date| date.to_chrono().to_rfc3339()),
+            created_by: booking.created_by,
+            updated_by: booking.updated_by,
         }
     }
 }
@@ -80,5 +165,15 @@ pub struct BookingUpdateRequest{
     pub start_time:       Option<String>,   // RFC3339 string
     pub duration_minutes: Option<u8>, 
     pub cancelled:        Option<bool>,
+    pub sitter:           Option<String>,   // assign (or re-assign) a sitter
+    pub dogs:             Option<Vec<String>>,   // replaces the dogs to walk
 }
 // BookingUpdateResponse, we can create a new struct here for consistency reasons but BookingResponse seems to have the same effect. 
+
+// BookingStreamQuery: query string of GET /bookings/stream, all parameters optional
+// e.g. GET /bookings/stream?owner=6814c4958aef1b781ca7e9e2
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BookingStreamQuery {
+    pub owner: Option<String>,           // only the bookings of this owner (ID as string)
+    pub last_event_id: Option<String>,   // resume point, for clients that cannot send the Last-Event-ID header
+}
@@ -1,6 +1,7 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
 use serde::{Deserialize, Serialize}; 
 use validator::Validate;   
+use crate::models::schema::{self, CollectionSchema};
 // Serialize, Deserialize: Needed for Actix (for sending/receiving JSON) and MongoDB (bson conversion).
 
 
@@ -12,6 +13,39 @@ pub struct Dog {
     pub name:  String,
     pub age:   Option<u8>,
     pub breed: Option<String>,
+    #[serde(default)]
+    pub version: i64,         // optimistic concurrency: incremented on every update (see services/versioning.rs)
+    #[serde(default)]
+    pub created_at: Option<DateTime>,   // set by the services layer on create (None for documents written before)
+    #[serde(default)]
+    pub updated_at: Option<DateTime>,   // set by the services layer on create and on every update
+    #[serde(default)]
+    pub created_by: Option<String>,     // actor who created the document (see routes/actor.rs)
+    #[serde(default)]
+    pub updated_by: Option<String>,     // actor of the last create/update
+}
+
+// MongoDB validator for the 'dog' collection, mirrors the Dog struct above (see models/schema.rs)
+impl CollectionSchema for Dog {
+    const COLLECTION_NAME: &'static str = "dog";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "owner", "name"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "owner": schema::field("objectId"),
+                "name": schema::field("string"),
+                "age": schema::nullable_integer_range(0, u8::MAX as i64),
+                "breed": schema::nullable("string"),
+                "version": schema::integer_range(0, i64::MAX),
+                "created_at": schema::nullable("date"),
+                "updated_at": schema::nullable("date"),
+                "created_by": schema::nullable("string"),
+                "updated_by": schema::nullable("string"),
+            },
+        )
+    }
 }
 
 
@@ -37,6 +71,11 @@ impl TryFrom<DogRequest> for Dog{
                 name: item.name,
                 age: item.age,
                 breed: item.breed,
+                version: 1,
+                created_at: None,   // timestamps are set by services::dogs::create_dog
+                updated_at: None,
+                created_by: None,
+                updated_by: None,
         })
     }
 }
@@ -49,6 +88,12 @@ pub struct DogResponse {
     pub name:  String,
     pub age:   Option<u8>,
     pub breed: Option<String>,
+    pub version: i64,       // also sent as ETag, send it back in If-Match to update/delete
+    // read-only metadata, managed by the server (RFC3339 datetimes)
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
 }
 
 // use From as it is a 'safe mapping' (from database 'Dog' struct → response 'DogResponse' struct)
@@ -60,7 +105,12 @@ impl From<Dog> for DogResponse {
             owner: dog.owner.to_hex(), 
             name: dog.name, 
             age: dog.age, 
-            breed: dog.breed
+            breed: dog.breed,
+            version: dog.version,
+            created_at: dog.created_at.map(|date| date.to_chrono().to_rfc3339()),
+            updated_at: dog.updated_at.map(|date| date.to_chrono().to_rfc3339()),
+            created_by: dog.created_by,
+            updated_by: dog.updated_by,
         }
     }
 }
@@ -77,3 +127,15 @@ pub struct DogUpdateRequest{
     pub breed: Option<String>,
 }
 // DogUpdateResponse, we can create a new struct here for consistency reasons but DogResponse seems to have the same effect. 
+
+// DogWalkStats: walk totals of a dog (GET /dogs/{id}/stats), over the bookings with a recorded walk
+// (uploaded track, see services/track_upload.rs); cancelled bookings are not counted
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DogWalkStats {
+    pub dog_id: String,
+    pub walks: i64,
+    pub distance_m: f64,
+    pub moving_time_s: i64,
+    pub average_pace_s_per_km: Option<f64>,   // over the walks with timestamps
+    pub last_walk_at: Option<String>,         // RFC3339 string, start of the most recent walk
+}
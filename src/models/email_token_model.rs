@@ -0,0 +1,93 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use crate::models::{auth_model::Role, schema::{self, CollectionSchema}};
+
+// ============================================================================
+// Email tokens: email verification and password reset (see services/account_emails.rs)
+// ============================================================================
+// The token sent by email is a JWT (HS256, key of the access tokens) whose audience is its purpose:
+// it cannot be used as an access token, nor for the other purpose. It expires, and it is used once:
+// its ID (jti) is kept in 'email_tokens' and marked as used by the first request presenting it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    EmailVerification,
+    PasswordReset,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::EmailVerification => "email_verification",
+            TokenPurpose::PasswordReset => "password_reset",
+        }
+    }
+}
+
+// EmailToken: Represents the data stored in MongoDB ('email_tokens' collection), removed by MongoDB once expired
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailToken {
+    pub _id: String,                // jti of the token
+    pub purpose: TokenPurpose,
+    pub principal_id: ObjectId,
+    pub role: Role,
+    pub email: String,              // the address the token was sent to
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub used_at: Option<DateTime>,
+}
+
+impl CollectionSchema for EmailToken {
+    const COLLECTION_NAME: &'static str = "email_tokens";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "purpose", "principal_id", "role", "email", "created_at", "expires_at"],
+            doc! {
+                "_id": schema::string_min_length(1),
+                "purpose": { "enum": ["email_verification", "password_reset"] },
+                "principal_id": schema::field("objectId"),
+                "role": { "enum": Role::ACCOUNT_ROLES.to_vec() },
+                "email": schema::string_min_length(3),
+                "created_at": schema::field("date"),
+                "expires_at": schema::field("date"),
+                "used_at": schema::nullable("date"),
+            },
+        )
+    }
+}
+
+// EmailTokenClaims: the payload of the token sent by email
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailTokenClaims {
+    pub sub: String,                // principal ID (hex)
+    pub role: Role,
+    pub email: String,
+    pub jti: String,
+    pub aud: String,                // the purpose, see TokenPurpose::as_str
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+// EmailTokenRequest: POST /auth/verify-email  { "token": "..." }  (also GET /auth/verify-email?token=.., the link of the email)
+#[derive(Debug, Deserialize)]
+pub struct EmailTokenRequest {
+    pub token: String,
+}
+
+// PasswordResetRequest: POST /auth/password-reset  { "email": "..." }
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(length(min = 3, max = 320))]
+    pub email: String,
+}
+
+// PasswordResetConfirmRequest: POST /auth/password-reset/confirm  { "token": "...", "password": "..." }
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    pub password: String,
+}
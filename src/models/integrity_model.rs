@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+// ============================================================================
+// Integrity report: documents that exist in a collection but cannot be
+// deserialised into our domain structs (Owner, Dog, Sitter, Booking)
+// ============================================================================
+// These are response-only structs (there is nothing to store), they are built by
+// services::integrity and sent back by GET /admin/integrity/{resource}
+
+// CorruptDocument: one document that failed to deserialise
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CorruptDocument {
+    pub _id: String,      // hex ObjectId (or the raw _id value if it is not an ObjectId)
+    pub error: String,    // the deserialisation error, e.g. "invalid type: string \"60\", expected u8"
+}
+
+// IntegrityReport: result of scanning a whole collection
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub resource: String,                 // owners, dogs, sitters or bookings
+    pub collection: String,               // name of the MongoDB collection scanned
+    pub scanned: usize,                   // number of documents read
+    pub valid: usize,                     // number of documents deserialised successfully
+    pub corrupt: Vec<CorruptDocument>,    // the ones that failed
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+// ListQuery: query string accepted by the list endpoints (GET /owners, /dogs, /sitters, /bookings)
+// All parameters are optional, e.g. GET /bookings?created_by=dispatcher-1&updated_after=2025-05-01T00:00:00Z&sort=-updated_at
+// - sort: one of created_at, updated_at, created_by, updated_by ('-' prefix for descending order)
+// - created_by / updated_by: exact actor match
+// - created_after / created_before / updated_after / updated_before: RFC3339 datetimes (inclusive bounds)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListQuery {
+    pub sort: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub updated_after: Option<String>,
+    pub updated_before: Option<String>,
+}
@@ -1,4 +1,22 @@
 pub mod booking_model;
 pub mod owner_model;
 pub mod dog_model;
-pub mod sitter_model;
\ No newline at end of file
+pub mod sitter_model;
+pub mod schema;
+pub mod integrity_model;
+
+pub mod list_query_model;
+pub mod audit_model;
+pub mod booking_event_model;
+pub mod outbox_model;
+
+pub mod webhook_model;
+pub mod track_model;
+pub mod attachment_model;
+pub mod walk_report_model;
+pub mod auth_model;
+pub mod api_key_model;
+pub mod oidc_model;
+pub mod email_token_model;
+pub mod rate_limit_model;
+pub mod privacy_model;
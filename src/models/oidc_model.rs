@@ -0,0 +1,107 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::{auth_model::Role, schema::{self, CollectionSchema}};
+
+// ============================================================================
+// OpenID Connect login (authorization code flow with PKCE, see services/oidc.rs)
+// ============================================================================
+//  1. GET /auth/oidc/login      -> 302 to the identity provider, with a state, a nonce and a PKCE challenge
+//  2. the user logs in there    -> 302 back to GET /auth/oidc/callback?code=..&state=..
+//  3. the code is exchanged (with the PKCE verifier) for an ID token, whose claims give the role:
+//     dispatcher / admin from the roles claim, otherwise the owner or sitter with the same email
+//  4. the answer is the usual TokenResponse (access + refresh token, see models/auth_model.rs)
+
+// OidcLogin: a login started at step 1, used once at step 3 ('oidc_logins' collection, removed by MongoDB once expired)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcLogin {
+    pub _id: String,                // the 'state' sent to the identity provider
+    pub nonce: String,              // must come back in the ID token
+    pub code_verifier: String,      // PKCE: its SHA-256 was sent as code_challenge
+    pub expires_at: DateTime,
+}
+
+impl CollectionSchema for OidcLogin {
+    const COLLECTION_NAME: &'static str = "oidc_logins";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "nonce", "code_verifier", "expires_at"],
+            doc! {
+                "_id": schema::string_min_length(32),
+                "nonce": schema::string_min_length(32),
+                "code_verifier": schema::string_min_length(43),
+                "expires_at": schema::field("date"),
+            },
+        )
+    }
+}
+
+// ExternalIdentity: a user of the identity provider linked to an account ('external_identities' collection)
+// (issuer, subject) is unique: the link survives a change of email at the identity provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalIdentity {
+    pub _id: ObjectId,
+    pub issuer: String,
+    pub subject: String,            // 'sub' claim
+    pub email: String,              // at the last login
+    pub principal_id: ObjectId,     // owner, sitter, or staff account ID
+    pub role: Role,                 // at the last login
+    pub created_at: DateTime,
+    pub last_login_at: DateTime,
+}
+
+impl CollectionSchema for ExternalIdentity {
+    const COLLECTION_NAME: &'static str = "external_identities";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "issuer", "subject", "email", "principal_id", "role", "created_at", "last_login_at"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "issuer": schema::string_min_length(1),
+                "subject": schema::string_min_length(1),
+                "email": schema::field("string"),
+                "principal_id": schema::field("objectId"),
+                "role": { "enum": Role::ACCOUNT_ROLES.to_vec() },
+                "created_at": schema::field("date"),
+                "last_login_at": schema::field("date"),
+            },
+        )
+    }
+}
+
+// OidcCallbackQuery: GET /auth/oidc/callback?code=..&state=..  (or ?error=..&error_description=..)
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+// ProviderMetadata: the fields of the discovery document we use ({issuer}/.well-known/openid-configuration)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+// TokenEndpointResponse: answer of the identity provider to the code exchange
+#[derive(Debug, Deserialize)]
+pub struct TokenEndpointResponse {
+    pub id_token: String,
+}
+
+// IdTokenClaims: the claims of the ID token we use (signature, iss, aud and exp are checked by jsonwebtoken)
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,   // the roles claim is configurable (OIDC_ROLES_CLAIM)
+}
@@ -0,0 +1,125 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Transactional outbox: domain events waiting to be delivered
+// ============================================================================
+// A message is written in the same MongoDB transaction as the booking change it describes
+// (see services/booking_events.rs), then delivered to the sinks by the dispatcher (see services/outbox.rs).
+// Delivery is at least once: a sink may receive the same message twice, 'event_id' lets it deduplicate.
+//
+//   pending --(all sinks ok)--> delivered
+//      |  \--(a sink failed)--> pending again, retried later (exponential backoff)
+//      \-----(too many attempts)--> dead_letter (kept for inspection, can be re-queued)
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    Pending,
+    Delivered,
+    DeadLetter,
+}
+
+impl OutboxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Delivered => "delivered",
+            OutboxStatus::DeadLetter => "dead_letter",
+        }
+    }
+}
+
+// OutboxMessage: Represents the data stored in MongoDB ('outbox' collection)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub _id: ObjectId,                 // also the event id sent to the sinks
+    pub event_type: String,            // e.g. booking.created, booking.cancelled
+    pub aggregate: String,             // bookings
+    pub aggregate_id: ObjectId,        // _id of the booking
+    pub payload: Document,             // what the sinks receive (the booking after the change, its previous owner / sitter, ...)
+    pub actor: String,                 // who caused it (see routes/actor.rs)
+    pub created_at: DateTime,
+    pub status: OutboxStatus,
+    pub attempts: i32,                 // failed delivery rounds so far
+    pub next_attempt_at: DateTime,     // not retried before that time
+    pub locked_until: Option<DateTime>,    // claimed by a dispatcher until then (several instances may run)
+    pub delivered_to: Vec<String>,     // sinks that already received it, not called again on retry
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime>,
+}
+
+// MongoDB validator for the 'outbox' collection (see models/schema.rs)
+impl CollectionSchema for OutboxMessage {
+    const COLLECTION_NAME: &'static str = "outbox";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "event_type", "aggregate", "aggregate_id", "payload", "actor", "created_at", "status", "attempts", "next_attempt_at", "delivered_to"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "event_type": schema::string_min_length(1),
+                "aggregate": schema::field("string"),
+                "aggregate_id": schema::field("objectId"),
+                "payload": schema::field("object"),
+                "actor": schema::field("string"),
+                "created_at": schema::field("date"),
+                "status": { "enum": ["pending", "delivered", "dead_letter"] },
+                "attempts": schema::integer_range(0, i32::MAX as i64),
+                "next_attempt_at": schema::field("date"),
+                "locked_until": schema::nullable("date"),
+                "delivered_to": { "bsonType": "array", "items": { "bsonType": "string" } },
+                "last_error": schema::nullable("string"),
+                "delivered_at": schema::nullable("date"),
+            },
+        )
+    }
+}
+
+// OutboxMessageResponse: Used to send clean, flattened JSON to clients (admin endpoints)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutboxMessageResponse {
+    pub _id: String,
+    pub event_type: String,
+    pub aggregate: String,
+    pub aggregate_id: String,
+    pub payload: serde_json::Value,   // relaxed extended JSON
+    pub actor: String,
+    pub created_at: String,           // RFC3339 string
+    pub status: OutboxStatus,
+    pub attempts: i32,
+    pub next_attempt_at: String,
+    pub delivered_to: Vec<String>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<String>,
+}
+
+// use From as it is a safe mapping (from database 'OutboxMessage' struct → response 'OutboxMessageResponse' struct)
+impl From<OutboxMessage> for OutboxMessageResponse {
+    fn from(message: OutboxMessage) -> Self {
+        Self {
+            _id: message._id.to_hex(),
+            event_type: message.event_type,
+            aggregate: message.aggregate,
+            aggregate_id: message.aggregate_id.to_hex(),
+            payload: mongodb::bson::Bson::Document(message.payload).into_relaxed_extjson(),
+            actor: message.actor,
+            created_at: message.created_at.to_chrono().to_rfc3339(),
+            status: message.status,
+            attempts: message.attempts,
+            next_attempt_at: message.next_attempt_at.to_chrono().to_rfc3339(),
+            delivered_to: message.delivered_to,
+            last_error: message.last_error,
+            delivered_at: message.delivered_at.map(|date| date.to_chrono().to_rfc3339()),
+        }
+    }
+}
+
+// OutboxQuery: query string of GET /admin/outbox
+// e.g. GET /admin/outbox?status=dead_letter
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OutboxQuery {
+    pub status: Option<OutboxStatus>,
+    pub limit: Option<i64>,      // default 100, at most 1000
+}
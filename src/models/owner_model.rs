@@ -1,6 +1,8 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
 use serde::{Deserialize, Serialize};  
 use validator::{Validate};
+use crate::models::schema::{self, CollectionSchema};
+use crate::services::field_encryption;
 // Serialize, Deserialize: Needed for Actix (for sending/receiving JSON) and MongoDB (bson conversion).
 
 // Notes on Data Struct Separation: Domain vs API Layer
@@ -55,13 +57,66 @@ use validator::{Validate};
 // Analogy: what your system stores (includes ID, timestamps, metadata, etc.)
 // note that we are using the crate 'validator' to validate the fields here. (https://github.com/Keats/validator)
 // Contains ObjectId, Might expose Mongo types, Used internally / for DB
+// email, phone, address and home_access are encrypted in MongoDB, and plaintext here (see services/field_encryption.rs)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Owner {
     pub _id: ObjectId,        
     pub name: String,
+    #[serde(with = "field_encryption::encrypted")]
     pub email: String,
+    #[serde(default)]
+    pub email_index: Option<String>,    // blind index of the email: lookups and uniqueness (None for documents written before)
+    #[serde(with = "field_encryption::encrypted")]
     pub phone: String,
+    #[serde(default)]
+    pub phone_index: Option<String>,    // blind index of the phone number
+    #[serde(with = "field_encryption::encrypted")]
     pub address: String,
+    #[serde(default, with = "field_encryption::encrypted_option")]
+    pub home_access: Option<String>,    // how the sitter gets in: key box code, gate, alarm, ...
+    #[serde(default)]
+    pub email_verified_at: Option<DateTime>,   // set when a link sent to 'email' is followed, reset when the email changes (see services/account_emails.rs)
+    #[serde(default)]
+    pub erased_at: Option<DateTime>,    // personal data erased (POST /owners/{id}/erase, see services/privacy.rs), the document only keeps the bookings linked
+    #[serde(default)]
+    pub version: i64,         // optimistic concurrency: incremented on every update (see services/versioning.rs)
+    #[serde(default)]
+    pub created_at: Option<DateTime>,   // set by the services layer on create (None for documents written before)
+    #[serde(default)]
+    pub updated_at: Option<DateTime>,   // set by the services layer on create and on every update
+    #[serde(default)]
+    pub created_by: Option<String>,     // actor who created the document (see routes/actor.rs)
+    #[serde(default)]
+    pub updated_by: Option<String>,     // actor of the last create/update
+}
+
+// MongoDB validator for the 'owner' collection, mirrors the Owner struct above
+// (and the rules of OwnerRequest below), see models/schema.rs
+impl CollectionSchema for Owner {
+    const COLLECTION_NAME: &'static str = "owner";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "name", "email", "phone", "address"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "name": schema::string_min_length(1),
+                "email": schema::field("string"),
+                "email_index": schema::nullable("string"),
+                "phone": schema::string_min_length(7),
+                "phone_index": schema::nullable("string"),
+                "address": schema::string_min_length(5),
+                "home_access": schema::nullable("string"),
+                "email_verified_at": schema::nullable("date"),
+                "erased_at": schema::nullable("date"),
+                "version": schema::integer_range(0, i64::MAX),
+                "created_at": schema::nullable("date"),
+                "updated_at": schema::nullable("date"),
+                "created_by": schema::nullable("string"),
+                "updated_by": schema::nullable("string"),
+            },
+        )
+    }
 }
 
 // OwnerRequest: Represents the incoming data from the client (e.g. from an HTTP POST/PUT body):
@@ -77,6 +132,11 @@ pub struct OwnerRequest {
     pub phone: String,
     #[validate(length(min = 5))]
     pub address: String,
+    #[serde(default)]
+    pub home_access: Option<String>,
+    // sign-up: with a password the owner can log in (POST /auth/login), it is stored hashed in 'credentials' only
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
 }
 
 // use TryFrom for 'validated' or 'fallible' mappings (like request → domain struct)
@@ -88,9 +148,19 @@ impl TryFrom<OwnerRequest> for Owner{
         Ok(Self{
             _id: ObjectId::new(),  // Create a new _id for MongoDB
             name: item.name,
+            email_index: Some(field_encryption::email_index(&item.email)?),
             email: item.email,
+            phone_index: Some(field_encryption::phone_index(&item.phone)?),
             phone: item.phone,
             address: item.address,  
+            home_access: item.home_access,
+            email_verified_at: None,
+            erased_at: None,
+            version: 1,
+            created_at: None,   // timestamps are set by services::owners::create_owner
+            updated_at: None,
+            created_by: None,
+            updated_by: None,
         })
     }
 }
@@ -104,6 +174,16 @@ pub struct OwnerResponse {
     pub email: String,
     pub phone: String,
     pub address: String,
+    pub home_access: Option<String>,
+    pub email_verified: bool,               // the email was confirmed by following the link sent to it
+    pub email_verified_at: Option<String>,
+    pub erased_at: Option<String>,          // the personal data was erased (GDPR)
+    pub version: i64,       // also sent as ETag, send it back in If-Match to update/delete
+    // read-only metadata, managed by the server (RFC3339 datetimes)
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
 }
 
 // use From as it is a safe mapping (from database 'Owner' struct → response 'OwnerResponse' struct)
@@ -115,11 +195,29 @@ impl From<Owner> for OwnerResponse {
             email: owner.email,
             phone: owner.phone,
             address: owner.address,
+            home_access: owner.home_access,
+            email_verified: owner.email_verified_at.is_some(),
+            email_verified_at: owner.email_verified_at.map(|date| date.to_chrono().to_rfc3339()),
+            erased_at: owner.erased_at.map(|date| date.to_chrono().to_rfc3339()),
+            version: owner.version,
+            created_at: owner.created_at.map(|date| date.to_chrono().to_rfc3339()),
+            updated_at: owner.updated_at.map(|date| date.to_chrono().to_rfc3339()),
+            created_by: owner.created_by,
+            updated_by: owner.updated_by,
         }
     }
 }
 
 
+// OwnerLookupQuery: GET /owners?email=...&phone=... finds owners by exact email (any case) or phone number
+// (any spacing), through the blind indexes of these encrypted fields. Sent along the ListQuery parameters.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OwnerLookupQuery {
+    pub email: Option<String>,
+    pub phone: Option<String>,
+}
+
+
 // Updates: for updates we use specific structs for:
 // - clarity: each struct clearly expresses its purpose 
 // - avoid Accidental Overwrites
@@ -130,6 +228,7 @@ pub struct OwnerUpdateRequest{
     pub email: Option<String>,
     pub phone: Option<String>,
     pub address: Option<String>,
+    pub home_access: Option<String>,
 }
 // OwnerUpdateResponse, we can create a new struct here for consistency reasons but OwnerResponse seems to have the same effect. 
 
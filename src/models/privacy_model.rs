@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::{attachment_model::AttachmentResponse, audit_model::AuditEntryResponse, booking_model::BookingResponse,
+                    dog_model::DogResponse, owner_model::OwnerResponse, track_model::TrackPointResponse,
+                    walk_report_model::WalkReportResponse};
+
+// ============================================================================
+// Personal data requests of the owners (GDPR, see services/privacy.rs)
+// ============================================================================
+// GET /owners/{id}/export   subject access: everything stored about the owner, as one JSON document
+// POST /owners/{id}/erase   right to erasure: the personal data is removed, the bookings are kept anonymous
+
+// version of the export document, changed when its fields change in an incompatible way
+pub const EXPORT_FORMAT: &str = "breizh-walker-owner-export/1";
+
+// OwnerExport: the archive sent by GET /owners/{id}/export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OwnerExport {
+    pub format: String,                            // EXPORT_FORMAT
+    pub exported_at: String,                       // RFC3339 string
+    pub owner: OwnerResponse,
+    pub account: AccountExport,
+    pub dogs: Vec<DogExport>,
+    pub bookings: Vec<BookingExport>,
+    pub archived_bookings: Vec<serde_json::Value>, // bookings archived by the doctor (relaxed extended JSON)
+    pub history: Vec<AuditEntryResponse>,          // audit log of the owner, its dogs and its bookings, oldest first
+}
+
+// how the owner logs in (never the password hash)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AccountExport {
+    pub login: Option<String>,                     // email of the password login, if any
+    pub login_created_at: Option<String>,          // RFC3339 string
+    pub external_identities: Vec<ExternalIdentityExport>,
+}
+
+// a single sign-on account linked to the owner (see services/oidc.rs)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExternalIdentityExport {
+    pub issuer: String,
+    pub subject: String,
+    pub email: String,
+    pub created_at: String,                        // RFC3339 string
+    pub last_login_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DogExport {
+    pub dog: DogResponse,
+    pub photos: Vec<AttachmentResponse>,           // metadata and download links, not the files
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookingExport {
+    pub booking: BookingResponse,
+    pub report: Option<WalkReportResponse>,
+    pub photos: Vec<AttachmentResponse>,
+    pub track: Vec<TrackPointResponse>,            // every position stored: live and uploaded
+}
+
+// EraseRequest: body of POST /owners/{id}/erase, kept in the audit record of the erasure
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct EraseRequest {
+    #[validate(length(min = 1, max = 500))]
+    pub reason: String,                            // e.g. "erasure request of 2025-06-02, ticket 4512"
+}
+
+// ErasureResponse: what POST /owners/{id}/erase removed and kept
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ErasureResponse {
+    pub owner_id: String,
+    pub erased_at: String,                         // RFC3339 string
+    pub dogs_anonymised: u64,
+    pub bookings_kept: u64,                        // anonymous: dates, durations, sitters and walk statistics
+    pub walk_reports_cleared: u64,                 // notes and photos removed, the checklist is kept
+    pub track_points_deleted: u64,
+    pub attachments_deleted: u64,
+    pub external_identities_deleted: u64,
+    pub audit_entries_redacted: u64,
+}
@@ -0,0 +1,36 @@
+use mongodb::bson::{doc, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Rate limit buckets shared by the instances (RATE_LIMIT_STORE=mongodb, see services/rate_limiter.rs)
+// ============================================================================
+// One token bucket per client and route: refilled with the clock of MongoDB ($$NOW) so that instances
+// with different clocks agree, removed by MongoDB once it would be full again (same as no bucket).
+
+// RateLimitBucket: Represents the data stored in MongoDB ('rate_limits' collection)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitBucket {
+    pub _id: String,                // "<client>|<route>", e.g. "user:66f0..|POST /bookings"
+    pub tokens: f64,                // requests left, refilled continuously
+    pub refilled_at: DateTime,
+    pub expires_at: DateTime,       // the bucket is full again by then
+    pub allowed: bool,              // whether the last request took a token
+}
+
+impl CollectionSchema for RateLimitBucket {
+    const COLLECTION_NAME: &'static str = "rate_limits";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "tokens", "refilled_at", "expires_at"],
+            doc! {
+                "_id": schema::string_min_length(1),
+                "tokens": { "bsonType": ["double", "int", "long"], "minimum": 0 },
+                "refilled_at": schema::field("date"),
+                "expires_at": schema::field("date"),
+                "allowed": schema::field("bool"),
+            },
+        )
+    }
+}
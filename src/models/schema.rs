@@ -0,0 +1,64 @@
+use mongodb::bson::{doc, Bson, Document};
+
+// ============================================================================
+// MongoDB collection validators ($jsonSchema)
+// ============================================================================
+//
+// Each domain struct (Owner, Dog, Sitter, Booking) describes, next to its own
+// definition, the BSON shape it expects to find in MongoDB. The schema is applied
+// to the collection on startup (see services::db::AppDatabase::init), so a document
+// that would later fail to deserialise (e.g. a string `duration_minutes`) is
+// rejected by the database itself, whoever tries to write it.
+//
+// REF: https://www.mongodb.com/docs/manual/core/schema-validation/specify-json-schema/
+//
+// Note: Mongo's $jsonSchema uses `bsonType` instead of JSON Schema's `type`,
+//       which is what lets us require `objectId` or `date` fields.
+
+pub trait CollectionSchema {
+    // name of the MongoDB collection the struct is stored in
+    const COLLECTION_NAME: &'static str;
+
+    // the $jsonSchema document describing one stored entry
+    fn json_schema() -> Document;
+
+    // the full validator, as expected by `collMod` / `createCollection`
+    fn validator() -> Document {
+        doc! { "$jsonSchema": Self::json_schema() }
+    }
+}
+
+// Helpers to keep the per-model schemas short and readable
+// object schema: list of required fields + their property definitions
+pub fn object_schema(required: &[&str], properties: Document) -> Document {
+    doc! {
+        "bsonType": "object",
+        "required": required.iter().map(|field| Bson::String(field.to_string())).collect::<Vec<Bson>>(),
+        "properties": properties,
+    }
+}
+
+// a field of a single BSON type (e.g. "string", "objectId", "date", "bool")
+pub fn field(bson_type: &str) -> Document {
+    doc! { "bsonType": bson_type }
+}
+
+// a field that may be null (Rust `Option<T>`, serialized as null by serde when None)
+pub fn nullable(bson_type: &str) -> Document {
+    doc! { "bsonType": [bson_type, "null"] }
+}
+
+// a non-empty string (mirrors `#[validate(length(min = ..))]` on the request structs)
+pub fn string_min_length(min_length: i32) -> Document {
+    doc! { "bsonType": "string", "minLength": min_length }
+}
+
+// an integer within a range (Rust unsigned ints are stored as int32 or int64 by the bson serializer)
+pub fn integer_range(minimum: i64, maximum: i64) -> Document {
+    doc! { "bsonType": ["int", "long"], "minimum": minimum, "maximum": maximum }
+}
+
+// same as integer_range() but allowing null (Rust `Option<u8>`, ...)
+pub fn nullable_integer_range(minimum: i64, maximum: i64) -> Document {
+    doc! { "bsonType": ["int", "long", "null"], "minimum": minimum, "maximum": maximum }
+}
@@ -1,5 +1,6 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
 use serde::{Deserialize, Serialize};  
+use crate::models::schema::{self, CollectionSchema};
 
 // ============================================================================
 // Separating database and API input schemas: common pattern in backend design (see above):
@@ -18,7 +19,44 @@ pub struct Sitter {
     pub email: String,
     pub phone: String,
     pub address: String,
+    #[serde(default)]
+    pub email_verified_at: Option<DateTime>,   // set when a link sent to 'email' is followed, reset when the email changes (see services/account_emails.rs)
+    #[serde(default)]
+    pub version: i64,         // optimistic concurrency: incremented on every update (see services/versioning.rs)
+    #[serde(default)]
+    pub created_at: Option<DateTime>,   // set by the services layer on create (None for documents written before)
+    #[serde(default)]
+    pub updated_at: Option<DateTime>,   // set by the services layer on create and on every update
+    #[serde(default)]
+    pub created_by: Option<String>,     // actor who created the document (see routes/actor.rs)
+    #[serde(default)]
+    pub updated_by: Option<String>,     // actor of the last create/update
+}
+
+// MongoDB validator for the 'sitter' collection, mirrors the Sitter struct above (see models/schema.rs)
+impl CollectionSchema for Sitter {
+    const COLLECTION_NAME: &'static str = "sitter";
 
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "firstname", "lastname", "gender", "email", "phone", "address"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "firstname": schema::field("string"),
+                "lastname": schema::field("string"),
+                "gender": schema::field("string"),
+                "email": schema::field("string"),
+                "phone": schema::field("string"),
+                "address": schema::field("string"),
+                "email_verified_at": schema::nullable("date"),
+                "version": schema::integer_range(0, i64::MAX),
+                "created_at": schema::nullable("date"),
+                "updated_at": schema::nullable("date"),
+                "created_by": schema::nullable("string"),
+                "updated_by": schema::nullable("string"),
+            },
+        )
+    }
 }
 
 // -> maybe Endorsements: personal recommendations from friends, family, clients, coworkers, and other community members that help Pet Caregivers build credibility and trust with clients
@@ -34,6 +72,9 @@ pub struct SitterRequest {
     pub email: String,
     pub phone: String,
     pub address: String,
+    // sign-up: with a password the sitter can log in (POST /auth/login), it is stored hashed in 'credentials' only
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
 }
 
 // use TryFrom for 'validated' or 'fallible' mappings (like request → domain struct)
@@ -48,6 +89,12 @@ impl TryFrom<SitterRequest> for Sitter{
             email: request.email,
             phone: request.phone,
             address: request.address,  
+            email_verified_at: None,
+            version: 1,
+            created_at: None,   // timestamps are set by services::sitters::create_sitter
+            updated_at: None,
+            created_by: None,
+            updated_by: None,
         })
     }
 }
@@ -63,6 +110,14 @@ pub struct SitterResponse {
     pub email: String,
     pub phone: String,
     pub address: String,
+    pub email_verified: bool,               // the email was confirmed by following the link sent to it
+    pub email_verified_at: Option<String>,
+    pub version: i64,       // also sent as ETag, send it back in If-Match to update/delete
+    // read-only metadata, managed by the server (RFC3339 datetimes)
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
 }
 
 // use From as it is a safe mapping (from database 'Sitter' struct → response 'SitterResponse' struct)
@@ -76,6 +131,13 @@ impl From<Sitter> for SitterResponse {
             email: sitter.email,
             phone: sitter.phone,
             address: sitter.address,
+            email_verified: sitter.email_verified_at.is_some(),
+            email_verified_at: sitter.email_verified_at.map(|date| date.to_chrono().to_rfc3339()),
+            version: sitter.version,
+            created_at: sitter.created_at.map(|date| date.to_chrono().to_rfc3339()),
+            updated_at: sitter.updated_at.map(|date| date.to_chrono().to_rfc3339()),
+            created_by: sitter.created_by,
+            updated_by: sitter.updated_by,
         }
     }
 }
@@ -0,0 +1,126 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Walk tracks: the GPS positions of a booking's walk
+// ============================================================================
+// One document per position, published live by the sitter over the walk WebSocket
+// (see routes/walk_routes.rs) and kept to display the track afterwards.
+// After the walk, the sitter may upload the recorded track (GPX or GeoJSON, POST /bookings/{id}/track):
+// its simplified points replace the live ones as the track of the booking (source "upload").
+
+// TrackPoint: Represents the data stored in MongoDB ('track_points' collection)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackPoint {
+    pub _id: ObjectId,
+    pub booking_id: ObjectId,
+    pub lat: f64,                   // WGS84 degrees, -90..90
+    pub lon: f64,                   // WGS84 degrees, -180..180
+    pub accuracy_m: Option<f64>,    // as reported by the device, in meters
+    pub recorded_at: DateTime,      // when the device took the position
+    pub received_at: DateTime,      // when the server stored it
+    pub source: String,             // live | upload
+    pub recorded_by: String,        // who sent it (the sitter)
+    #[serde(default)]
+    pub upload_id: Option<ObjectId>,   // the upload the point belongs to (source "upload")
+}
+
+// MongoDB validator for the 'track_points' collection (see models/schema.rs)
+impl CollectionSchema for TrackPoint {
+    const COLLECTION_NAME: &'static str = "track_points";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "booking_id", "lat", "lon", "recorded_at", "received_at", "source", "recorded_by"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "booking_id": schema::field("objectId"),
+                "lat": { "bsonType": "double", "minimum": -90, "maximum": 90 },
+                "lon": { "bsonType": "double", "minimum": -180, "maximum": 180 },
+                "accuracy_m": { "bsonType": ["double", "null"], "minimum": 0 },
+                "recorded_at": schema::field("date"),
+                "received_at": schema::field("date"),
+                "source": schema::field("string"),
+                "recorded_by": schema::field("string"),
+                "upload_id": schema::nullable("objectId"),
+            },
+        )
+    }
+}
+
+// PositionMessage: what the sitter's client sends on the walk WebSocket (one JSON text message per position)
+// { "lat": 48.1113, "lon": -1.6800, "accuracy": 8.5, "timestamp": "2025-05-05T15:31:10Z" }
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PositionMessage {
+    pub lat: f64,
+    pub lon: f64,
+    pub accuracy: Option<f64>,       // meters
+    pub timestamp: Option<String>,   // RFC3339, the reception time when missing
+}
+
+// TrackPointResponse: Used to send clean, flattened JSON to clients (also the live WebSocket frames)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrackPointResponse {
+    pub booking_id: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub accuracy: Option<f64>,
+    pub recorded_at: String,    // RFC3339 string
+}
+
+// use From as it is a safe mapping (from database 'TrackPoint' struct → response 'TrackPointResponse' struct)
+impl From<TrackPoint> for TrackPointResponse {
+    fn from(point: TrackPoint) -> Self {
+        Self {
+            booking_id: point.booking_id.to_hex(),
+            lat: point.lat,
+            lon: point.lon,
+            accuracy: point.accuracy_m,
+            recorded_at: point.recorded_at.to_chrono().to_rfc3339(),
+        }
+    }
+}
+
+// WalkStats: summary of a walk, computed from the track uploaded after the walk (GPX or GeoJSON)
+// stored on the booking, see services/track_upload.rs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalkStats {
+    pub distance_m: f64,
+    pub moving_time_s: Option<i64>,           // time spent moving, None when the track has no timestamps
+    pub average_pace_s_per_km: Option<f64>,   // moving time per kilometer
+    pub started_at: Option<DateTime>,         // first and last timestamps of the track
+    pub ended_at: Option<DateTime>,
+    pub points_received: i64,                 // points in the uploaded file
+    pub points_stored: i64,                   // points kept after simplification (in 'track_points')
+    pub format: String,                       // gpx | geojson
+}
+
+// WalkStatsResponse: Used to send clean, flattened JSON to clients (part of BookingResponse)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalkStatsResponse {
+    pub distance_m: f64,
+    pub moving_time_s: Option<i64>,
+    pub average_pace_s_per_km: Option<f64>,
+    pub started_at: Option<String>,   // RFC3339 string
+    pub ended_at: Option<String>,     // RFC3339 string
+    pub points_received: i64,
+    pub points_stored: i64,
+    pub format: String,
+}
+
+// use From as it is a safe mapping (from database 'WalkStats' struct → response 'WalkStatsResponse' struct)
+impl From<WalkStats> for WalkStatsResponse {
+    fn from(stats: WalkStats) -> Self {
+        Self {
+            distance_m: stats.distance_m,
+            moving_time_s: stats.moving_time_s,
+            average_pace_s_per_km: stats.average_pace_s_per_km,
+            started_at: stats.started_at.map(|date| date.to_chrono().to_rfc3339()),
+            ended_at: stats.ended_at.map(|date| date.to_chrono().to_rfc3339()),
+            points_received: stats.points_received,
+            points_stored: stats.points_stored,
+            format: stats.format,
+        }
+    }
+}
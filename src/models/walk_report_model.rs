@@ -0,0 +1,128 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Walk reports: the summary the sitter writes for the owner after the walk
+// ============================================================================
+// One report per booking, submitted once by the sitter once the walk is over (see services/walk_reports.rs).
+// The owner reads it with GET /bookings/{id}/report, or shares the HTML page /reports/{share_token}.
+
+// WalkMood: how the dog was during the walk
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalkMood {
+    Happy,
+    Calm,
+    Playful,
+    Tired,
+    Anxious,
+    Reactive,
+}
+
+impl WalkMood {
+    pub const ALL: [&'static str; 6] = ["happy", "calm", "playful", "tired", "anxious", "reactive"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WalkMood::Happy => "happy",
+            WalkMood::Calm => "calm",
+            WalkMood::Playful => "playful",
+            WalkMood::Tired => "tired",
+            WalkMood::Anxious => "anxious",
+            WalkMood::Reactive => "reactive",
+        }
+    }
+}
+
+// WalkReport: Represents the data stored in MongoDB ('walk_reports' collection), booking_id is unique
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkReport {
+    pub _id: ObjectId,
+    pub booking_id: ObjectId,
+    pub pee: bool,
+    pub poo: bool,
+    pub water_given: bool,
+    pub mood: WalkMood,
+    pub notes: Option<String>,
+    pub photos: Vec<ObjectId>,     // photos of the booking (attachments, see POST /bookings/{id}/photos)
+    pub share_token: String,       // random, the capability of the public HTML page
+    pub submitted_at: DateTime,
+    pub submitted_by: String,      // the sitter (see routes/actor.rs)
+}
+
+// MongoDB validator for the 'walk_reports' collection (see models/schema.rs)
+impl CollectionSchema for WalkReport {
+    const COLLECTION_NAME: &'static str = "walk_reports";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "booking_id", "pee", "poo", "water_given", "mood", "photos", "share_token", "submitted_at", "submitted_by"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "booking_id": schema::field("objectId"),
+                "pee": schema::field("bool"),
+                "poo": schema::field("bool"),
+                "water_given": schema::field("bool"),
+                "mood": { "enum": WalkMood::ALL.to_vec() },
+                "notes": schema::nullable("string"),
+                "photos": { "bsonType": "array", "items": { "bsonType": "objectId" } },
+                "share_token": schema::string_min_length(32),
+                "submitted_at": schema::field("date"),
+                "submitted_by": schema::field("string"),
+            },
+        )
+    }
+}
+
+// WalkReportRequest: what the sitter sends (POST /bookings/{id}/report)
+// { "pee": true, "poo": false, "water_given": true, "mood": "happy", "notes": "...", "photos": ["<attachment id>"] }
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct WalkReportRequest {
+    pub pee: bool,
+    pub poo: bool,
+    pub water_given: bool,
+    pub mood: WalkMood,
+    #[validate(length(max = 2000))]
+    pub notes: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 20))]
+    pub photos: Vec<String>,        // IDs of photos already uploaded to the booking
+}
+
+// WalkReportResponse: Used to send clean, flattened JSON to clients
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalkReportResponse {
+    pub _id: String,
+    pub booking_id: String,
+    pub pee: bool,
+    pub poo: bool,
+    pub water_given: bool,
+    pub mood: WalkMood,
+    pub notes: Option<String>,
+    pub photos: Vec<String>,      // download links of the photos, e.g. /bookings/{id}/photos/{attachment id}
+    pub share_url: String,        // public HTML page, e.g. /reports/{share_token}
+    pub submitted_at: String,     // RFC3339 string
+    pub submitted_by: String,
+}
+
+// use From as it is a safe mapping (from database 'WalkReport' struct → response 'WalkReportResponse' struct)
+impl From<WalkReport> for WalkReportResponse {
+    fn from(report: WalkReport) -> Self {
+        let booking_id = report.booking_id.to_hex();
+        Self {
+            _id: report._id.to_hex(),
+            photos: report.photos.iter().map(|photo| format!("/bookings/{}/photos/{}", booking_id, photo.to_hex())).collect(),
+            booking_id,
+            pee: report.pee,
+            poo: report.poo,
+            water_given: report.water_given,
+            mood: report.mood,
+            notes: report.notes,
+            share_url: format!("/reports/{}", report.share_token),
+            submitted_at: report.submitted_at.to_chrono().to_rfc3339(),
+            submitted_by: report.submitted_by,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use crate::models::schema::{self, CollectionSchema};
+
+// ============================================================================
+// Outbound webhooks: subscriptions and deliveries
+// ============================================================================
+// A partner registers a URL, the event types it wants and a shared secret (POST /webhooks).
+// Each matching outbox message (see models/outbox_model.rs) becomes one WebhookDelivery per subscription,
+// POSTed with an HMAC-SHA256 signature and retried with exponential backoff (see services/webhooks.rs).
+
+// subscribe to every event type
+pub const ALL_EVENTS: &str = "*";
+
+// WebhookSubscription: Represents the data stored in MongoDB ('webhook_subscriptions' collection)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSubscription {
+    pub _id: ObjectId,
+    pub url: String,
+    pub event_types: Vec<String>,   // e.g. ["booking.created", "booking.cancelled"], or ["*"]
+    pub secret: String,             // HMAC key, never sent back to clients
+    pub active: bool,
+    pub created_at: DateTime,
+    pub created_by: String,
+}
+
+// MongoDB validator for the 'webhook_subscriptions' collection (see models/schema.rs)
+impl CollectionSchema for WebhookSubscription {
+    const COLLECTION_NAME: &'static str = "webhook_subscriptions";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "url", "event_types", "secret", "active", "created_at", "created_by"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "url": schema::string_min_length(1),
+                "event_types": { "bsonType": "array", "minItems": 1, "items": { "bsonType": "string" } },
+                "secret": schema::string_min_length(16),
+                "active": schema::field("bool"),
+                "created_at": schema::field("date"),
+                "created_by": schema::field("string"),
+            },
+        )
+    }
+}
+
+// WebhookRequest: Used when receiving data from clients (POST /webhooks)
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct WebhookRequest {
+    #[validate(url)]
+    pub url: String,
+    #[validate(length(min = 1))]
+    pub event_types: Vec<String>,
+    #[validate(length(min = 16, max = 256))]
+    pub secret: String,
+}
+
+// WebhookSubscriptionResponse: Used to send clean, flattened JSON to clients (without the secret)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookSubscriptionResponse {
+    pub _id: String,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub active: bool,
+    pub created_at: String,      // RFC3339 string
+    pub created_by: String,
+}
+
+// use From as it is a safe mapping (from database 'WebhookSubscription' struct → response 'WebhookSubscriptionResponse' struct)
+impl From<WebhookSubscription> for WebhookSubscriptionResponse {
+    fn from(subscription: WebhookSubscription) -> Self {
+        Self {
+            _id: subscription._id.to_hex(),
+            url: subscription.url,
+            event_types: subscription.event_types,
+            active: subscription.active,
+            created_at: subscription.created_at.to_chrono().to_rfc3339(),
+            created_by: subscription.created_by,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,     // waiting for its (next) attempt
+    Delivered,   // the receiver answered 2xx
+    Failed,      // gave up after max attempts (can be redelivered by hand)
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+// DeliveryAttempt: one HTTP call to the receiver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    pub at: DateTime,
+    pub status_code: Option<i32>,   // None when no answer (connection refused, timeout, ...)
+    pub error: Option<String>,
+    pub duration_ms: i64,
+}
+
+// WebhookDelivery: Represents the data stored in MongoDB ('webhook_deliveries' collection)
+// (subscription_id, event_id) is unique: an outbox message delivered twice to the webhooks sink is fanned out once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub _id: ObjectId,                 // also sent as Webhook-Id, receivers can deduplicate with it
+    pub subscription_id: ObjectId,
+    pub event_id: ObjectId,            // _id of the outbox message
+    pub event_type: String,
+    pub body: String,                  // exact JSON body, signed and sent on every attempt
+    pub status: DeliveryStatus,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub failed_attempts: i32,          // since the last (re)delivery request, drives the backoff
+    pub next_attempt_at: DateTime,
+    pub locked_until: Option<DateTime>,
+    pub created_at: DateTime,
+    pub delivered_at: Option<DateTime>,
+}
+
+// MongoDB validator for the 'webhook_deliveries' collection (see models/schema.rs)
+impl CollectionSchema for WebhookDelivery {
+    const COLLECTION_NAME: &'static str = "webhook_deliveries";
+
+    fn json_schema() -> Document {
+        schema::object_schema(
+            &["_id", "subscription_id", "event_id", "event_type", "body", "status", "attempts", "failed_attempts", "next_attempt_at", "created_at"],
+            doc! {
+                "_id": schema::field("objectId"),
+                "subscription_id": schema::field("objectId"),
+                "event_id": schema::field("objectId"),
+                "event_type": schema::field("string"),
+                "body": schema::field("string"),
+                "status": { "enum": ["pending", "delivered", "failed"] },
+                "attempts": schema::field("array"),
+                "failed_attempts": schema::integer_range(0, i32::MAX as i64),
+                "next_attempt_at": schema::field("date"),
+                "locked_until": schema::nullable("date"),
+                "created_at": schema::field("date"),
+                "delivered_at": schema::nullable("date"),
+            },
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliveryAttemptResponse {
+    pub at: String,              // RFC3339 string
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+}
+
+// WebhookDeliveryResponse: Used to send clean, flattened JSON to clients
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDeliveryResponse {
+    pub _id: String,
+    pub subscription_id: String,
+    pub event_id: String,
+    pub event_type: String,
+    pub status: DeliveryStatus,
+    pub attempts: Vec<DeliveryAttemptResponse>,
+    pub next_attempt_at: String,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            _id: delivery._id.to_hex(),
+            subscription_id: delivery.subscription_id.to_hex(),
+            event_id: delivery.event_id.to_hex(),
+            event_type: delivery.event_type,
+            status: delivery.status,
+            attempts: delivery.attempts.into_iter().map(|attempt| DeliveryAttemptResponse {
+                at: attempt.at.to_chrono().to_rfc3339(),
+                status_code: attempt.status_code,
+                error: attempt.error,
+                duration_ms: attempt.duration_ms,
+            }).collect(),
+            next_attempt_at: delivery.next_attempt_at.to_chrono().to_rfc3339(),
+            created_at: delivery.created_at.to_chrono().to_rfc3339(),
+            delivered_at: delivery.delivered_at.map(|date| date.to_chrono().to_rfc3339()),
+        }
+    }
+}
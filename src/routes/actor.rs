@@ -0,0 +1,103 @@
+use actix_web::{dev::Payload, error::InternalError, http::header, web, FromRequest, HttpMessage, HttpRequest};
+use bson::oid::ObjectId;
+use futures::future::LocalBoxFuture;
+
+use crate::{app_errors::errors::AppError, config::AuthConfig,
+            json_response::api_responses::ErrorJsonApiResponse,
+            services::{auth, db::AppDatabase}};
+
+// -----------------------------------
+// Principal: the authenticated account performing the request (owner, sitter, dispatcher or admin)
+// Taken from `Authorization: Bearer <access token>` (see POST /auth/login and services/auth.rs):
+// add `principal: Principal` to a handler's parameters and the request is refused with 401
+// when the token is missing, invalid, expired or revoked. What its role allows is in services/policy.rs.
+// The token is checked once per request, the principal is then kept in the request extensions.
+// Partner systems send `Authorization: ApiKey <key>` instead, checked by the middleware of routes/api_key_auth.rs
+// which puts their 'integration' principal in the extensions before the handler runs.
+pub use crate::models::auth_model::Principal;
+
+impl Principal {
+    // check an access token received another way (e.g. the query string of a WebSocket)
+    pub async fn from_token(db: &AppDatabase, config: &AuthConfig, token: &str) -> Result<Principal, AppError> {
+        let claims = auth::verify_access_token(db, config, token).await?;
+        Ok(Principal {
+            id: ObjectId::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized("invalid access token".to_string()))?,
+            role: claims.role,
+            token_id: claims.jti,
+            expires_at: claims.exp,
+            grants: Vec::new(),
+        })
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let request = request.clone();
+        Box::pin(async move {
+            if let Some(principal) = request.extensions().get::<Principal>() {
+                return Ok(principal.clone());
+            }
+
+            let token = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(str::trim)
+                .filter(|token| !token.is_empty());
+            let (db, config) = match (request.app_data::<web::Data<AppDatabase>>(), request.app_data::<web::Data<AuthConfig>>()) {
+                (Some(db), Some(config)) => (db, config),
+                _ => return Err(unauthorized(&AppError::InternalError)),
+            };
+            let principal = match token {
+                Some(token) => Principal::from_token(db, config, token).await.map_err(|app_error| unauthorized(&app_error))?,
+                None => return Err(unauthorized(&AppError::Unauthorized("send an access token: Authorization: Bearer <token> (POST /auth/login)".to_string()))),
+            };
+
+            request.extensions_mut().insert(principal.clone());
+            Ok(principal)
+        })
+    }
+}
+
+// the JSON error of the API, with its status code (401, or 500 when the revocation list cannot be read)
+pub fn unauthorized(app_error: &AppError) -> actix_web::Error {
+    let response = match app_error {
+        AppError::Unauthorized(_) => ErrorJsonApiResponse::unauthorized(&app_error.to_string()),
+        _ => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    };
+    InternalError::from_response(app_error.to_string(), response).into()
+}
+
+// -----------------------------------
+// Actor: who is performing the request
+// Recorded as created_by / updated_by on every create and update (see services::*).
+// The ID of the authenticated principal (see Principal above), so a handler taking `actor: Actor`
+// also requires authentication. Sign-up routes take `Option<Actor>`: "anonymous" without a token.
+pub const ANONYMOUS: &str = "anonymous";
+
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+impl Actor {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Extractor: add `actor: Actor` to a handler's parameters
+impl FromRequest for Actor {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let principal = Principal::from_request(request, payload);
+        Box::pin(async move {
+            let principal = principal.await?;
+            Ok(Actor(principal.id.to_hex()))
+        })
+    }
+}
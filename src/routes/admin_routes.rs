@@ -0,0 +1,30 @@
+use actix_web::{web, HttpResponse};
+use log::info;
+
+use crate::{app_errors::errors::AppError, routes::actor::Principal,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::auth_model::Role,
+            services::{db::AppDatabase, integrity, policy}};
+
+// -----------------------------------
+// ADMIN
+// Integrity report -> receive GET method on /admin/integrity/{resource}
+// resource: owners | dogs | sitters | bookings
+// Lists the ids and deserialisation errors of the documents that cannot be read back into our models
+#[actix_web::get("/admin/integrity/{resource}")]
+pub async fn integrity_report(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let resource = path.into_inner();
+    info!("Integrity report of {} requested by admin {}", resource, principal.id.to_hex());
+
+    match integrity::integrity_report(&db, &resource).await {
+        Ok(report) => JsonApiResponse::success(report),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&format!("Unknown resource: {}", resource)),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
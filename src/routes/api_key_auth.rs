@@ -0,0 +1,51 @@
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, http::header, middleware::Next, web, HttpMessage};
+
+use crate::{app_errors::errors::AppError, routes::actor,
+            services::{api_keys, db::AppDatabase, tls::ClientCertificate}};
+
+// -----------------------------------
+// API key authentication (middleware, wrapped around every route in main.rs)
+// `Authorization: ApiKey <key>`: the key is checked before the handler runs and its 'integration' Principal
+// is put in the request extensions, where the Principal / Actor extractors find it (see routes/actor.rs).
+// An unknown, expired or revoked key is refused with 401 whatever the route, so is a key bound to a client
+// certificate sent over a connection without it (mutual TLS, see services/tls.rs).
+// Requests without an API key go through untouched (access tokens are checked by the extractors).
+pub async fn api_key_auth(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let key = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(sent_key)
+        .map(str::to_string);
+
+    if let Some(key) = key {
+        let db = request
+            .app_data::<web::Data<AppDatabase>>()
+            .cloned()
+            .ok_or_else(|| actor::unauthorized(&AppError::InternalError))?;
+        let client_certificate = request.conn_data::<ClientCertificate>().cloned();
+        let principal = api_keys::verify_key(&db, &key, client_certificate.as_ref()).await.map_err(|app_error| actor::unauthorized(&app_error))?;
+        request.extensions_mut().insert(principal);
+    }
+
+    next.call(request).await
+}
+
+// the key of an `Authorization: ApiKey <key>` header, None for the other schemes (e.g. Bearer access tokens)
+fn sent_key(authorization: &str) -> Option<&str> {
+    authorization.strip_prefix("ApiKey ").map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_api_key_scheme_is_read() {
+        assert_eq!(sent_key("ApiKey bwk_0a1b2c3d_secret"), Some("bwk_0a1b2c3d_secret"));
+        assert_eq!(sent_key("ApiKey  bwk_0a1b2c3d_secret "), Some("bwk_0a1b2c3d_secret"));
+        assert_eq!(sent_key("ApiKey "), Some(""));   // refused by verify_key, never taken as no key
+        assert_eq!(sent_key("Bearer eyJhbGciOiJIUzI1NiJ9.e30.sig"), None);
+        assert_eq!(sent_key("apikey bwk_0a1b2c3d_secret"), None);
+    }
+}
@@ -0,0 +1,96 @@
+use actix_web::{web::{self, Json}, HttpResponse};
+use crate::{app_errors::errors::AppError, routes::actor::{Actor, Principal},
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::api_key_model::{ApiKeyRequest, ApiKeyResponse, CreatedApiKeyResponse},
+            models::auth_model::Role,
+            services::{api_keys, db::AppDatabase, policy}};
+
+// -----------------------------------
+// CREATE
+// New API key -> receive POST method on /admin/api-keys + a Json ApiKeyRequest obj
+// { "name": "partner portal", "scopes": ["bookings:write", "dogs:read"], "expires_at": "2027-01-01T00:00:00Z" }
+// the answer holds the key: it is not stored and cannot be read again
+#[actix_web::post("/admin/api-keys")]
+pub async fn create_api_key(db: web::Data<AppDatabase>, principal: Principal, actor: Actor, request: Result<Json<ApiKeyRequest>, actix_web::Error>) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    // Validate Request
+    let key_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(e) => {
+            println!("JSON error: {:?}", e);
+            return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types.");
+        }
+    };
+
+    match api_keys::create_key(&db, key_req, actor.as_str()).await {
+        Ok((api_key, key)) => JsonApiResponse::success(CreatedApiKeyResponse { key, api_key: ApiKeyResponse::from(api_key) }),
+        Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// READS
+// List ALL API keys -> receive GET method on /admin/api-keys  (prefix, scopes, expiry, last use; never the key)
+#[actix_web::get("/admin/api-keys")]
+pub async fn list_api_keys(db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    match api_keys::read_keys(&db).await {
+        Ok(keys) => {
+            let key_responses = keys.into_iter().map(ApiKeyResponse::from).collect::<Vec<ApiKeyResponse>>();
+            JsonApiResponse::success(key_responses)
+        },
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// List specific API key -> receive GET method on /admin/api-keys/{id}
+#[actix_web::get("/admin/api-keys/{id}")]
+pub async fn list_api_key(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let key_id = path.into_inner();
+
+    match api_keys::read_key(&db, &key_id).await {
+        Ok(api_key) => JsonApiResponse::success(ApiKeyResponse::from(api_key)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&AppError::NotFound.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// REVOCATION
+// Revoke an API key -> receive DELETE method on /admin/api-keys/{id}
+// the key is refused from now on, it stays listed (with revoked_at) for the audit trail
+#[actix_web::delete("/admin/api-keys/{id}")]
+pub async fn revoke_api_key(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal, actor: Actor) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let key_id = path.into_inner();
+
+    match api_keys::revoke_key(&db, &key_id, actor.as_str()).await {
+        Ok(api_key) => JsonApiResponse::success(ApiKeyResponse::from(api_key)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&AppError::NotFound.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
@@ -0,0 +1,321 @@
+use actix_multipart::Multipart;
+use actix_web::{http::header::{self, ContentDisposition, DispositionParam, DispositionType}, web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use std::str::FromStr;
+
+use crate::{app_errors::errors::AppError, config::AppConfig, routes::actor::{Actor, Principal},
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::attachment_model::AttachmentResponse,
+            services::{attachments::{self, AttachmentKind, AttachmentTarget, UploadedFile}, db::AppDatabase, dogs, sitters,
+                       policy::{self, Access, Resource},
+                       walk_tracking::{self, WalkRole}}};
+
+// Photos and documents -> multipart/form-data uploads, stored in GridFS (see services/attachments.rs)
+//   POST   /{resource}/{id}/{photos|documents}                       upload one or more files (any field with a filename)
+//   GET    /{resource}/{id}/{photos|documents}                       list them, with their download links
+//   GET    /{resource}/{id}/{photos|documents}/{file_id}             download (Range: bytes=... for a part, 206)
+//   GET    /{resource}/{id}/{photos|documents}/{file_id}/thumbnail   JPEG thumbnail, for images
+//   DELETE /{resource}/{id}/{photos|documents}/{file_id}
+// Dog photos and sitter documents follow the scopes of dogs and sitter documents (see services/policy.rs),
+// booking photos the walk rules: the sitter of the booking uploads and deletes, owner, sitter and staff read (logged in).
+
+const MAX_FILES_PER_UPLOAD: usize = 10;
+
+// -----------------------------------
+// Dog photos
+#[actix_web::post("/dogs/{id}/photos")]
+pub async fn upload_dog_photos(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, principal: Principal, actor: Actor, multipart: Multipart) -> HttpResponse {
+    match dog_target(&db, &path.into_inner(), &principal, Access::Write).await {
+        Ok(target) => upload(&db, target, multipart, config.max_photo_bytes, &actor).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/dogs/{id}/photos")]
+pub async fn list_dog_photos(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+    match dog_target(&db, &path.into_inner(), &principal, Access::Read).await {
+        Ok(target) => list(&db, target).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/dogs/{id}/photos/{file_id}")]
+pub async fn download_dog_photo(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
+    let (dog_id, file_id) = path.into_inner();
+    match dog_target(&db, &dog_id, &principal, Access::Read).await {
+        Ok(target) => download(&db, &http_request, target, &file_id, false).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/dogs/{id}/photos/{file_id}/thumbnail")]
+pub async fn download_dog_photo_thumbnail(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
+    let (dog_id, file_id) = path.into_inner();
+    match dog_target(&db, &dog_id, &principal, Access::Read).await {
+        Ok(target) => download(&db, &http_request, target, &file_id, true).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::delete("/dogs/{id}/photos/{file_id}")]
+pub async fn delete_dog_photo(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, principal: Principal, actor: Actor) -> HttpResponse {
+    let (dog_id, file_id) = path.into_inner();
+    match dog_target(&db, &dog_id, &principal, Access::Write).await {
+        Ok(target) => delete(&db, target, &file_id, &actor).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+// -----------------------------------
+// Booking photos (walk photos)
+#[actix_web::post("/bookings/{id}/photos")]
+pub async fn upload_booking_photos(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, principal: Principal, actor: Actor, multipart: Multipart) -> HttpResponse {
+    match booking_target(&db, &path.into_inner(), &principal, true).await {
+        Ok(target) => upload(&db, target, multipart, config.max_photo_bytes, &actor).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/bookings/{id}/photos")]
+pub async fn list_booking_photos(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+    match booking_target(&db, &path.into_inner(), &principal, false).await {
+        Ok(target) => list(&db, target).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/bookings/{id}/photos/{file_id}")]
+pub async fn download_booking_photo(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
+    let (booking_id, file_id) = path.into_inner();
+    match booking_target(&db, &booking_id, &principal, false).await {
+        Ok(target) => download(&db, &http_request, target, &file_id, false).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/bookings/{id}/photos/{file_id}/thumbnail")]
+pub async fn download_booking_photo_thumbnail(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
+    let (booking_id, file_id) = path.into_inner();
+    match booking_target(&db, &booking_id, &principal, false).await {
+        Ok(target) => download(&db, &http_request, target, &file_id, true).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::delete("/bookings/{id}/photos/{file_id}")]
+pub async fn delete_booking_photo(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, principal: Principal, actor: Actor) -> HttpResponse {
+    let (booking_id, file_id) = path.into_inner();
+    match booking_target(&db, &booking_id, &principal, true).await {
+        Ok(target) => delete(&db, target, &file_id, &actor).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+// -----------------------------------
+// Sitter documents
+#[actix_web::post("/sitters/{id}/documents")]
+pub async fn upload_sitter_documents(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, principal: Principal, actor: Actor, multipart: Multipart) -> HttpResponse {
+    match sitter_target(&db, &path.into_inner(), &principal, Access::Write).await {
+        Ok(target) => upload(&db, target, multipart, config.max_document_bytes, &actor).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/sitters/{id}/documents")]
+pub async fn list_sitter_documents(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+    match sitter_target(&db, &path.into_inner(), &principal, Access::Read).await {
+        Ok(target) => list(&db, target).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/sitters/{id}/documents/{file_id}")]
+pub async fn download_sitter_document(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
+    let (sitter_id, file_id) = path.into_inner();
+    match sitter_target(&db, &sitter_id, &principal, Access::Read).await {
+        Ok(target) => download(&db, &http_request, target, &file_id, false).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::get("/sitters/{id}/documents/{file_id}/thumbnail")]
+pub async fn download_sitter_document_thumbnail(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
+    let (sitter_id, file_id) = path.into_inner();
+    match sitter_target(&db, &sitter_id, &principal, Access::Read).await {
+        Ok(target) => download(&db, &http_request, target, &file_id, true).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+#[actix_web::delete("/sitters/{id}/documents/{file_id}")]
+pub async fn delete_sitter_document(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, principal: Principal, actor: Actor) -> HttpResponse {
+    let (sitter_id, file_id) = path.into_inner();
+    match sitter_target(&db, &sitter_id, &principal, Access::Write).await {
+        Ok(target) => delete(&db, target, &file_id, &actor).await,
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+// -----------------------------------
+// Targets: the resource must exist and be in the principal's scope (see services/policy.rs)
+
+async fn dog_target(db: &AppDatabase, dog_id: &str, principal: &Principal, access: Access) -> Result<AttachmentTarget, AppError> {
+    let scope = policy::scope(db, principal, Resource::Dogs, access).await?;
+    let dog = dogs::read_dog(db, dog_id, &scope).await?;
+    Ok(AttachmentTarget { resource: "dogs", resource_id: dog._id, kind: AttachmentKind::Photo })
+}
+
+async fn sitter_target(db: &AppDatabase, sitter_id: &str, principal: &Principal, access: Access) -> Result<AttachmentTarget, AppError> {
+    let scope = policy::scope(db, principal, Resource::SitterDocuments, access).await?;
+    let sitter = sitters::read_sitter(db, sitter_id, &scope).await?;
+    Ok(AttachmentTarget { resource: "sitters", resource_id: sitter._id, kind: AttachmentKind::Document })
+}
+
+// 'write': upload or delete, only for the sitter of the booking
+async fn booking_target(db: &AppDatabase, booking_id: &str, principal: &Principal, write: bool) -> Result<AttachmentTarget, AppError> {
+    let (booking, role) = walk_tracking::authorise(db, booking_id, principal).await?;
+    if write && role != WalkRole::Sitter {
+        return Err(AppError::Forbidden("only the sitter of the booking can add or remove its photos".to_string()));
+    }
+    Ok(AttachmentTarget { resource: "bookings", resource_id: booking._id, kind: AttachmentKind::Photo })
+}
+
+fn error_response(app_error: &AppError) -> HttpResponse {
+    match app_error {
+        AppError::InvalidId | AppError::ParseError(_) => ErrorJsonApiResponse::bad_request(&app_error.to_string()),
+        _ => ErrorJsonApiResponse::from_write_error(app_error),
+    }
+}
+
+// -----------------------------------
+// Handlers shared by the resources
+
+async fn upload(db: &AppDatabase, target: AttachmentTarget, multipart: Multipart, max_bytes: usize, actor: &Actor) -> HttpResponse {
+    let files = match read_files(multipart, max_bytes).await {
+        Ok(files) => files,
+        Err(app_error) => return error_response(&app_error),
+    };
+
+    match attachments::store_attachments(db, target, files, actor.as_str()).await {
+        Ok(stored) => {
+            let attachment_responses = stored.into_iter().map(AttachmentResponse::from).collect::<Vec<AttachmentResponse>>();
+            JsonApiResponse::success(attachment_responses)
+        },
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+// Files of the multipart body, each one at most 'max_bytes' (checked while reading, not after)
+async fn read_files(mut multipart: Multipart, max_bytes: usize) -> Result<Vec<UploadedFile>, AppError> {
+    let mut files = Vec::new();
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(|e| AppError::ParseError(format!("Invalid multipart body: {}", e)))?;
+
+        // form fields without a filename are not files: read and ignored
+        let filename = field.content_disposition().and_then(|disposition| disposition.get_filename()).map(str::to_string);
+        if filename.is_some() && files.len() == MAX_FILES_PER_UPLOAD {
+            return Err(AppError::PayloadTooLarge(format!("at most {} files per request", MAX_FILES_PER_UPLOAD)));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| AppError::ParseError(format!("Invalid multipart body: {}", e)))?;
+            if bytes.len() + chunk.len() > max_bytes {
+                let name = filename.as_deref().unwrap_or("a form field");
+                return Err(AppError::PayloadTooLarge(format!("'{}' is larger than {} bytes", name, max_bytes)));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        if let Some(filename) = filename {
+            files.push(UploadedFile { filename, bytes });
+        }
+    }
+    Ok(files)
+}
+
+async fn list(db: &AppDatabase, target: AttachmentTarget) -> HttpResponse {
+    match attachments::read_attachments(db, target).await {
+        Ok(found) => {
+            let attachment_responses = found.into_iter().map(AttachmentResponse::from).collect::<Vec<AttachmentResponse>>();
+            JsonApiResponse::success(attachment_responses)
+        },
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+async fn delete(db: &AppDatabase, target: AttachmentTarget, attachment_id: &str, actor: &Actor) -> HttpResponse {
+    match attachments::delete_attachment(db, target, attachment_id, actor.as_str()).await {
+        Ok(id) => JsonApiResponse::with_message(&format!("Attachment Deleted: {}", id)),
+        Err(app_error) => error_response(&app_error),
+    }
+}
+
+// Streamed download of the file (or its thumbnail)
+// - ETag: the SHA-256 of the file, If-None-Match answers 304
+// - Range: one byte range (bytes=0-1023, bytes=1024-, bytes=-500) answers 206 with that part, 416 when outside the file;
+//   several ranges are not supported, the whole file is sent
+async fn download(db: &AppDatabase, http_request: &HttpRequest, target: AttachmentTarget, attachment_id: &str, thumbnail: bool) -> HttpResponse {
+    let attachment = match attachments::read_attachment(db, target, attachment_id).await {
+        Ok(attachment) => attachment,
+        Err(app_error) => return error_response(&app_error),
+    };
+
+    let (file_id, content_type, etag, filename) = match (thumbnail, attachment.thumbnail_id) {
+        (false, _) => (attachment.file_id, attachment.content_type.clone(), format!("\"{}\"", attachment.sha256), attachment.filename.clone()),
+        (true, Some(thumbnail_id)) => (thumbnail_id, "image/jpeg".to_string(), format!("\"{}-thumbnail\"", attachment.sha256), format!("thumbnail-{}.jpg", attachment._id.to_hex())),
+        (true, None) => return ErrorJsonApiResponse::not_found("This attachment has no thumbnail"),
+    };
+    let length = match thumbnail {
+        false => attachment.length as u64,
+        true => match attachments::file_length(db, file_id).await {
+            Ok(length) => length,
+            Err(app_error) => return error_response(&app_error),
+        },
+    };
+
+    let if_none_match = http_request.headers().get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|value| value.split(',').any(|candidate| candidate.trim() == etag || candidate.trim() == "*")) {
+        return HttpResponse::NotModified().insert_header((header::ETAG, etag)).finish();
+    }
+
+    // a single satisfiable range, or the whole file
+    let requested = http_request.headers().get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| header::Range::from_str(value).ok());
+    let range = match requested {
+        Some(header::Range::Bytes(specs)) if specs.len() == 1 => match specs[0].to_satisfiable_range(length) {
+            Some(range) => Some(range),
+            None => {
+                return HttpResponse::RangeNotSatisfiable()
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", length)))
+                    .json(ErrorJsonApiResponse { error: format!("Range not satisfiable, the file has {} bytes", length) });
+            }
+        },
+        _ => None,
+    };
+
+    let mut response = match range {
+        Some(_) => HttpResponse::PartialContent(),
+        None => HttpResponse::Ok(),
+    };
+    response
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header(ContentDisposition { disposition: DispositionType::Inline, parameters: vec![DispositionParam::Filename(filename)] });
+
+    if length == 0 {
+        return response.finish();
+    }
+    let (first, last) = range.unwrap_or((0, length - 1));
+    if range.is_some() {
+        response.insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", first, last, length)));
+    }
+
+    match attachments::read_file_range(db, file_id, first, last).await {
+        Ok(bytes) => response.no_chunking(last - first + 1).streaming(bytes),
+        Err(app_error) => error_response(&app_error),
+    }
+}
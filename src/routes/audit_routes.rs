@@ -0,0 +1,60 @@
+use actix_web::{web, HttpResponse};
+use crate::{app_errors::errors::AppError, routes::actor::Principal,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::audit_model::{AuditEntryResponse, AuditQuery},
+            models::auth_model::Role,
+            services::{audit, db::AppDatabase, policy}};
+
+// -----------------------------------
+// READS
+// History of a document -> receive GET method on /{resource}/{id}/history
+// resource: owners | dogs | sitters | bookings, oldest change first
+// e.g. "who cancelled this booking and when?" -> GET /bookings/{id}/history
+#[actix_web::get("/{resource}/{id}/history")]
+pub async fn resource_history(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // staff only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Dispatcher, Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let (resource, resource_id) = path.into_inner();
+
+    match audit::read_history(&db, &resource, &resource_id).await {
+        Ok(entries) => {
+            let entry_responses = entries.into_iter().map(AuditEntryResponse::from).collect::<Vec<AuditEntryResponse>>();
+            JsonApiResponse::success(entry_responses)
+        },
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&format!("Unknown resource: {}", resource)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// ADMIN
+// Query the audit log -> receive GET method on /admin/audit?actor=..&from=..&to=..&resource=..&action=..&limit=..
+// most recent first
+#[actix_web::get("/admin/audit")]
+pub async fn query_audit_log(db: web::Data<AppDatabase>, query: Result<web::Query<AuditQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    // Validate query string
+    let audit_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+
+    match audit::query_audit_log(&db, &audit_query).await {
+        Ok(entries) => {
+            let entry_responses = entries.into_iter().map(AuditEntryResponse::from).collect::<Vec<AuditEntryResponse>>();
+            JsonApiResponse::success(entry_responses)
+        },
+        Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
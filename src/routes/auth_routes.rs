@@ -0,0 +1,178 @@
+use actix_web::{http::header, web, HttpResponse};
+use log::info;
+
+use crate::{app_errors::errors::AppError, config::AuthConfig, routes::actor::Principal,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::auth_model::{LoginRequest, LogoutRequest, RefreshRequest, Role},
+            models::email_token_model::{EmailTokenRequest, PasswordResetConfirmRequest, PasswordResetRequest},
+            models::oidc_model::OidcCallbackQuery,
+            services::{account_emails::{self, AccountEmails}, auth, db::AppDatabase, oidc::OidcProvider}};
+
+
+// -----------------------------------
+// LOGIN
+// Log in -> receive POST method on /auth/login  with Json data { "email": "...", "password": "..." }
+// answers with an access token (Authorization: Bearer <access_token> on the other routes) and a refresh token
+#[actix_web::post("/auth/login")]
+pub async fn login(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, request: Result<web::Json<LoginRequest>, actix_web::Error>) -> HttpResponse {
+
+    let login_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types."),
+    };
+
+    match auth::login(&db, &config, login_req).await {
+        Ok(tokens) => JsonApiResponse::success(tokens),
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// -----------------------------------
+// REFRESH
+// New tokens -> receive POST method on /auth/refresh  with Json data { "refresh_token": "..." }
+// the refresh token sent cannot be used again: keep the new one (sending it twice ends the session)
+#[actix_web::post("/auth/refresh")]
+pub async fn refresh(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, request: Result<web::Json<RefreshRequest>, actix_web::Error>) -> HttpResponse {
+
+    let refresh_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types."),
+    };
+
+    match auth::refresh(&db, &config, &refresh_req.refresh_token).await {
+        Ok(tokens) => JsonApiResponse::success(tokens),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// -----------------------------------
+// LOGOUT
+// Log out -> receive POST method on /auth/logout  (authenticated) with optional Json data
+// { "refresh_token": "..." } also ends that session, { "all": true } ends every session of the account
+#[actix_web::post("/auth/logout")]
+pub async fn logout(db: web::Data<AppDatabase>, principal: Principal, request: Option<web::Json<LogoutRequest>>) -> HttpResponse {
+
+    // an API key has no session, admins revoke it (DELETE /admin/api-keys/{id})
+    if principal.role == Role::Integration {
+        return ErrorJsonApiResponse::bad_request("API keys cannot log out, an admin revokes them");
+    }
+
+    let logout_req = request.map(web::Json::into_inner).unwrap_or_default();
+    match auth::logout(&db, principal.id, &principal.token_id, principal.expires_at, logout_req).await {
+        Ok(()) => {
+            info!("{} {} logged out", principal.role.as_str(), principal.id.to_hex());
+            JsonApiResponse::with_message("Logged out")
+        },
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// -----------------------------------
+// SINGLE SIGN-ON (OpenID Connect, see services/oidc.rs)
+// Start -> receive GET method on /auth/oidc/login: 302 to the identity provider (404 when OIDC_* is not configured)
+#[actix_web::get("/auth/oidc/login")]
+pub async fn oidc_login(db: web::Data<AppDatabase>, oidc: web::Data<OidcProvider>) -> HttpResponse {
+
+    match oidc.start_login(&db).await {
+        Ok(url) => HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .finish(),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found("OpenID Connect login is not configured"),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// Callback -> receive GET method on /auth/oidc/callback?code=..&state=..  (the identity provider redirects there)
+// answers like POST /auth/login: access token + refresh token of the linked account
+#[actix_web::get("/auth/oidc/callback")]
+pub async fn oidc_callback(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, oidc: web::Data<OidcProvider>, query: Result<web::Query<OidcCallbackQuery>, actix_web::Error>) -> HttpResponse {
+
+    let callback_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+
+    match oidc.complete_login(&db, &config, callback_query).await {
+        Ok(tokens) => JsonApiResponse::success(tokens),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found("OpenID Connect login is not configured"),
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// -----------------------------------
+// EMAIL VERIFICATION (see services/account_emails.rs)
+// Verify -> receive GET method on /auth/verify-email?token=..  (the link of the email sent on sign-up and email changes)
+// or POST method on /auth/verify-email  with Json data { "token": "..." }
+#[actix_web::get("/auth/verify-email")]
+pub async fn verify_email_link(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, query: Result<web::Query<EmailTokenRequest>, actix_web::Error>) -> HttpResponse {
+
+    match query {
+        Ok(valid_query) => verify_email_token(&db, &config, &valid_query.token).await,
+        Err(_) => ErrorJsonApiResponse::bad_request("Invalid query string parameters: token is required."),
+    }
+}
+
+#[actix_web::post("/auth/verify-email")]
+pub async fn verify_email(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, request: Result<web::Json<EmailTokenRequest>, actix_web::Error>) -> HttpResponse {
+
+    match request {
+        Ok(valid_json) => verify_email_token(&db, &config, &valid_json.token).await,
+        Err(_) => ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types."),
+    }
+}
+
+async fn verify_email_token(db: &AppDatabase, config: &AuthConfig, token: &str) -> HttpResponse {
+    match account_emails::verify_email(db, config, token).await {
+        Ok(_) => JsonApiResponse::with_message("Email verified"),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// New link -> receive POST method on /auth/verify-email/resend  (authenticated owner or sitter, email not verified yet)
+#[actix_web::post("/auth/verify-email/resend")]
+pub async fn resend_verification_email(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, emails: web::Data<AccountEmails>, principal: Principal) -> HttpResponse {
+
+    match emails.resend_verification(&db, &config, &principal).await {
+        Ok(()) => JsonApiResponse::with_message("Verification email sent"),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// -----------------------------------
+// PASSWORD RESET (see services/account_emails.rs)
+// Ask -> receive POST method on /auth/password-reset  with Json data { "email": "..." }
+// always the same answer: whether an account uses the email is not disclosed
+#[actix_web::post("/auth/password-reset")]
+pub async fn request_password_reset(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, emails: web::Data<AccountEmails>, request: Result<web::Json<PasswordResetRequest>, actix_web::Error>) -> HttpResponse {
+
+    let reset_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types."),
+    };
+
+    match emails.request_password_reset(&db, &config, reset_req).await {
+        Ok(()) => JsonApiResponse::with_message("If an account uses this email, a link to reset its password was sent to it"),
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// Confirm -> receive POST method on /auth/password-reset/confirm  with Json data { "token": "...", "password": "..." }
+// every session of the account ends, log in again with the new password
+#[actix_web::post("/auth/password-reset/confirm")]
+pub async fn confirm_password_reset(db: web::Data<AppDatabase>, config: web::Data<AuthConfig>, request: Result<web::Json<PasswordResetConfirmRequest>, actix_web::Error>) -> HttpResponse {
+
+    let confirm_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types."),
+    };
+
+    match account_emails::confirm_password_reset(&db, &config, confirm_req).await {
+        Ok(()) => JsonApiResponse::with_message("Password changed, log in again"),
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
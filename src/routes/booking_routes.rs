@@ -1,6 +1,12 @@
-use actix_web::{web::{self, Json}, HttpResponse};
-use crate::{json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse}, 
-            models::booking_model::{Booking, BookingRequest, BookingResponse,BookingUpdateRequest}, services::bookings};
+use actix_web::{web::{self, Json}, HttpRequest, HttpResponse};
+use crate::{app_errors::errors::AppError, config::AppConfig, routes::{actor::{Actor, Principal}, conditional},
+            models::list_query_model::ListQuery,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse}, 
+            models::auth_model::Role,
+            models::booking_model::{Booking, BookingRequest, BookingResponse, BookingStreamQuery, BookingUpdateRequest},
+            services::{bookings, policy::{self, Access, Resource}}};
+use crate::services::booking_stream::{self, StreamFilter};
+use mongodb::bson::oid::ObjectId;
 
 //use mongodb::AppDatabase; 
 use crate::services::db::AppDatabase;  //  ← again, use the actual type
@@ -12,6 +18,8 @@ use crate::services::db::AppDatabase;  //  ← again, use the actual type
 #[actix_web::post("/bookings")]
 pub async fn create_booking(
     db: web::Data<AppDatabase>, 
+    principal: Principal, 
+    actor: Actor, 
     request: Result<web::Json<BookingRequest>, 
     actix_web::Error> ) -> HttpResponse {
     println!("Creating new Booking");
@@ -31,13 +39,20 @@ pub async fn create_booking(
         Ok(booking) => booking,
         Err(_e) => return ErrorJsonApiResponse::bad_request("Invalid Booking: Error converting BookingRequest to Booking."),
     };
+    // owners book for themselves and their dogs, the sitter is assigned by a dispatcher (see services/policy.rs)
+    if let Err(app_error) = policy::check_booking_fields(&db, &principal, Some(validated_booking.owner), validated_booking.sitter, &validated_booking.dogs).await {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
     // Note: by validating the convertion before we avoid using this 'expect()' method here below
     // because Booking structure is already validated and an error is propagated if errors happen.
     // we use the validated_booking next, instead of  // Booking::try_from(booking_req ).expect("Error converting BookingRequest to Booking.")
 
-    match bookings::create_booking(&db, validated_booking).await {
+    match bookings::create_booking(&db, validated_booking, actor.as_str()).await {
         //Ok(booking) => HttpResponse::Ok().json(BookingResponse::from(booking)), 
-        Ok(inserted_booking) => JsonApiResponse::success(BookingResponse::from(inserted_booking)),
+        Ok(inserted_booking) => {
+            let version = inserted_booking.version;
+            conditional::with_etag(JsonApiResponse::success(BookingResponse::from(inserted_booking)), version)
+        },
         //Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
         Err(error) => ErrorJsonApiResponse::internal_server_error(&error.to_string()),
     }
@@ -47,29 +62,101 @@ pub async fn create_booking(
 // READS
 // LIST Bookings  -> receive GET method on /bookings
 #[actix_web::get("/bookings")]
-pub async fn list_bookings(db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn list_bookings(db: web::Data<AppDatabase>, http_request: HttpRequest, query: Result<web::Query<ListQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
     println!("Reading all Bookings");
 
-    match bookings::read_bookings(&db).await {
+    // Validate query string (filters and sort order, see ListQuery)
+    let list_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Bookings, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match bookings::read_bookings(&db, &list_query, &scope).await {
         Ok(booking) => {
-            let booking_responses = booking.into_iter().map(|x| BookingResponse::from(x)).collect::<Vec<BookingResponse>>();
+            let booking_responses = booking.into_iter().map(BookingResponse::from).collect::<Vec<BookingResponse>>();
             //HttpResponse::Ok().json(booking_responses)
-            JsonApiResponse::success(booking_responses)
+            // strong ETag from the content: 304 Not Modified while the list does not change
+            let validators = conditional::CacheValidators::for_content(&booking_responses);
+            conditional::respond(&http_request, &validators, || JsonApiResponse::success(booking_responses))
         },
         //Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// Live changes -> receive GET method on /bookings/stream?owner=..  (Server-Sent Events, text/event-stream)
+// events: created | updated | deleted; reconnecting clients send Last-Event-ID (or ?last_event_id=) to get what they missed
+// owners only receive their bookings and sitters the bookings assigned to them, whatever ?owner= says
+// note: registered before /bookings/{id} in main.rs, otherwise "stream" would be taken for an id
+#[actix_web::get("/bookings/stream")]
+pub async fn stream_bookings(db: web::Data<AppDatabase>, http_request: HttpRequest, query: Result<web::Query<BookingStreamQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
+
+    // Validate query string
+    let stream_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+    let owner = match stream_query.owner.as_deref().map(ObjectId::parse_str).transpose() {
+        Ok(owner) => owner,
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid owner ID."),
+    };
+    // same scope as GET /bookings (see services/policy.rs)
+    if let Err(app_error) = policy::scope(&db, &principal, Resource::Bookings, Access::Read).await {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+    let (owner, sitter) = match principal.role {
+        Role::Owner => (Some(principal.id), None),
+        Role::Sitter => (owner, Some(principal.id)),
+        Role::Dispatcher | Role::Admin | Role::Integration => (owner, None),
+    };
+
+    // the header sent by EventSource on reconnection wins over the query string
+    let last_event_id = http_request
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or(stream_query.last_event_id);
+    let last_event_id = match last_event_id.as_deref().map(ObjectId::parse_str).transpose() {
+        Ok(last_event_id) => last_event_id,
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid Last-Event-ID."),
+    };
+
+    match booking_stream::booking_stream(db.into_inner(), StreamFilter { owner, sitter, last_event_id }).await {
+        Ok(event_stream) => HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .insert_header(("X-Accel-Buffering", "no"))   // no proxy buffering (nginx)
+            .streaming(event_stream),
         Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
     }
 }
 
 // List spcific Booking -> receive GET method on /bookings/{id} 
 #[actix_web::get("/bookings/{id}")]
-pub async fn list_booking(path: web::Path<String>, db: web::Data<AppDatabase> ) -> HttpResponse {
+pub async fn list_booking(path: web::Path<String>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
    
     // id received must be String because it is a Hexadecimal string
     let booking_id = path.into_inner();
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Bookings, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
 
-    match bookings::read_booking(&db, &booking_id).await {
-        Ok(booking ) =>  JsonApiResponse::success(BookingResponse::from(booking)),
+    match bookings::read_booking(&db, &booking_id, &scope).await {
+        Ok(booking ) =>  {
+            // ETag (version) + Last-Modified (updated_at): 304 Not Modified while the booking does not change
+            let validators = conditional::CacheValidators::for_item(booking.version, booking.updated_at.or(booking.created_at));
+            conditional::respond(&http_request, &validators, || JsonApiResponse::success(BookingResponse::from(booking)))
+        },
         Err(app_error) => ErrorJsonApiResponse::not_found(&app_error.to_string()),
     }
 }
@@ -79,7 +166,7 @@ pub async fn list_booking(path: web::Path<String>, db: web::Data<AppDatabase> )
 // Update specific Booking -> receive PUT method on /bookings/{id}  + a Json data representing a BookingUpdateRequest Object
 
 #[actix_web::put("/bookings/{id}")]
-pub async fn update_booking(path: web::Path<String>, db: web::Data<AppDatabase>, request: Result< Json<BookingUpdateRequest>, actix_web::Error> ) -> HttpResponse {
+pub async fn update_booking(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor, request: Result< Json<BookingUpdateRequest>, actix_web::Error> ) -> HttpResponse {
 
      // Validating request
      let booking_update = match request {
@@ -92,16 +179,40 @@ pub async fn update_booking(path: web::Path<String>, db: web::Data<AppDatabase>,
         && booking_update.start_time.is_none()
          && booking_update.duration_minutes.is_none()
         && booking_update.cancelled.is_none()
+        && booking_update.sitter.is_none()
+        && booking_update.dogs.is_none()
      {
          return ErrorJsonApiResponse::bad_request("No fields provided to update.");
      }
 
     let booking_id = path.into_inner();
     
+    // Optimistic concurrency: the update only applies to the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // owners cannot move their bookings to another owner, assign the sitter or add dogs of others
+    // (invalid IDs are refused by the service)
+    let owner = booking_update.owner.as_deref().and_then(|owner| ObjectId::parse_str(owner).ok());
+    let sitter = booking_update.sitter.as_deref().and_then(|sitter| ObjectId::parse_str(sitter).ok());
+    let dogs = booking_update.dogs.iter().flatten().filter_map(|dog| ObjectId::parse_str(dog).ok()).collect::<Vec<ObjectId>>();
+    if let Err(app_error) = policy::check_booking_fields(&db, &principal, owner, sitter, &dogs).await {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Bookings, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
      // Invoking database layer 
-    match bookings::update_booking(&db, &booking_id, booking_update).await {
-        Ok(id) => JsonApiResponse::with_message(&format!("Booking Update Sucessful: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    match bookings::update_booking(&db, &booking_id, booking_update, &version_check, &scope, actor.as_str()).await {
+        Ok(updated_booking) => conditional::with_etag(
+            JsonApiResponse::with_message(&format!("Booking Update Sucessful: {}", updated_booking._id.to_hex())),
+            updated_booking.version),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
 
@@ -109,13 +220,25 @@ pub async fn update_booking(path: web::Path<String>, db: web::Data<AppDatabase>,
 // DELETION
 // Delete specific Booking -> receive DELETE method on /bookings/{id}
 #[actix_web::delete("/bookings/{id}")]
-pub async fn delete_booking(path: web::Path<String>, db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn delete_booking(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor) -> HttpResponse {
 
     let booking_id = path.into_inner();
     println!("Deleting id {:?}", booking_id);
 
-    match bookings::delete_booking(&db, &booking_id).await {
+    // Optimistic concurrency: only delete the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Bookings, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match bookings::delete_booking(&db, &booking_id, &version_check, &scope, actor.as_str()).await {
         Ok(id) => JsonApiResponse::with_message(&format!("Booking Deleted: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
\ No newline at end of file
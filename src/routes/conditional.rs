@@ -0,0 +1,131 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{http::header::{self, Header, HeaderValue, HttpDate}, HttpRequest, HttpResponse};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{app_errors::errors::AppError, config::AppConfig, services::versioning::VersionCheck};
+
+// -----------------------------------
+// Conditional requests (HTTP preconditions)
+// ETags are derived from the document version: version 3 -> ETag: "v3"
+// - responses carrying a single item send its ETag
+// - PUT/DELETE send it back in `If-Match`, so the update only applies to the version the client has seen
+
+// ETag value for a given document version
+pub fn etag(version: i64) -> String {
+    format!("\"v{}\"", version)
+}
+
+// Add the ETag header of the item's version to a response
+pub fn with_etag(mut response: HttpResponse, version: i64) -> HttpResponse {
+    if let Ok(value) = HeaderValue::from_str(&etag(version)) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+// Read the If-Match header of an update/delete request
+// - "*"              -> any version
+// - "\"v3\", \"v4\"" -> one of those versions (weak ETags W/"..." never match, If-Match uses strong comparison)
+// - missing          -> any version, unless AppConfig::require_if_match (then PreconditionRequired)
+pub fn if_match(request: &HttpRequest, config: &AppConfig) -> Result<VersionCheck, AppError> {
+    let value = match request.headers().get(header::IF_MATCH) {
+        Some(value) => value.to_str().map_err(|_| AppError::PreconditionFailed)?,
+        None if config.require_if_match => return Err(AppError::PreconditionRequired),
+        None => return Ok(VersionCheck::Any),
+    };
+
+    if value.trim() == "*" {
+        return Ok(VersionCheck::Any);
+    }
+
+    let versions: Vec<i64> = value
+        .split(',')
+        .filter_map(|tag| tag.trim().strip_prefix("\"v")?.strip_suffix('"')?.parse().ok())
+        .collect();
+
+    // none of the tags can match a current version
+    if versions.is_empty() {
+        return Err(AppError::PreconditionFailed);
+    }
+    Ok(VersionCheck::OneOf(versions))
+}
+
+
+// -----------------------------------
+// Conditional GET (caching validators)
+// Polling clients send back what they received (If-None-Match: <ETag>, If-Modified-Since: <Last-Modified>)
+// and get a body-less 304 Not Modified while nothing changed.
+// - single item: ETag from its version (same value as above, usable in If-Match), Last-Modified from updated_at
+// - lists: ETag computed from the content itself (SHA-256 of the JSON), no Last-Modified because
+//   a deleted item would not move the most recent updated_at of the remaining ones
+
+pub struct CacheValidators {
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl CacheValidators {
+    // validators of a single stored item
+    pub fn for_item(version: i64, updated_at: Option<bson::DateTime>) -> Self {
+        CacheValidators {
+            etag: etag(version),
+            last_modified: updated_at.map(|date| date.to_system_time()),
+        }
+    }
+
+    // validators of a computed content (e.g. a list of items): strong ETag from its JSON representation
+    pub fn for_content<T: Serialize>(data: &T) -> Self {
+        let json = serde_json::to_vec(data).unwrap_or_default();
+        CacheValidators {
+            etag: format!("\"{:x}\"", Sha256::digest(&json)),
+            last_modified: None,
+        }
+    }
+}
+
+// Answer 304 Not Modified if the client's copy is still current, otherwise build the full response
+// (build is only called when needed) and add the validators to it
+pub fn respond(request: &HttpRequest, validators: &CacheValidators, build: impl FnOnce() -> HttpResponse) -> HttpResponse {
+    let mut response = if is_not_modified(request, validators) {
+        HttpResponse::NotModified().finish()
+    } else {
+        build()
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&validators.etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    if let Some(last_modified) = validators.last_modified {
+        if let Ok(value) = HeaderValue::from_str(&HttpDate::from(last_modified).to_string()) {
+            response.headers_mut().insert(header::LAST_MODIFIED, value);
+        }
+    }
+    response
+}
+
+// RFC 9110 (13.2.2): If-None-Match wins over If-Modified-Since when both are sent
+fn is_not_modified(request: &HttpRequest, validators: &CacheValidators) -> bool {
+    if let Some(value) = request.headers().get(header::IF_NONE_MATCH) {
+        let Ok(value) = value.to_str() else { return false };
+        // weak comparison: W/"v3" matches "v3"
+        return value
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == validators.etag);
+    }
+
+    match (validators.last_modified, header::IfModifiedSince::parse(request)) {
+        // HTTP dates have a one second precision
+        (Some(last_modified), Ok(header::IfModifiedSince(since))) => truncate_to_seconds(last_modified) <= SystemTime::from(since),
+        _ => false,
+    }
+}
+
+fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => UNIX_EPOCH + Duration::from_secs(elapsed.as_secs()),
+        Err(_) => time,
+    }
+}
@@ -1,14 +1,17 @@
-use actix_web::{web::{self, Json}, HttpResponse};
-use crate::{json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+use actix_web::{web::{self, Json}, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use crate::{app_errors::errors::AppError, config::AppConfig, routes::{actor::{Actor, Principal}, conditional},
+            models::list_query_model::ListQuery,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
              models::dog_model::{Dog, DogRequest, DogResponse, DogUpdateRequest}, services::db::AppDatabase};
-use crate::services::dogs;
+use crate::services::{dogs, policy::{self, Access, Resource}};
  // ← again, use the actual typee;
 
 // -----------------------------------
 // CREATE 
 // Create Dog -> receive POST method on /dogs with Json data representing a DogRequest Object
 #[actix_web::post("/dogs")]
-pub async fn create_dog(db: web::Data<AppDatabase>, request: Result<web::Json<DogRequest>, actix_web::Error> ) -> HttpResponse {
+pub async fn create_dog(db: web::Data<AppDatabase>, principal: Principal, actor: Actor, request: Result<web::Json<DogRequest>, actix_web::Error> ) -> HttpResponse {
     
     //let dog_req = request.into_inner();  // request data is of type web::Json<MyStruct>,  Json<OwnerRequest> in this case, into_inner() unwraps into inner 'T' value
     // Validate Request
@@ -27,8 +30,16 @@ pub async fn create_dog(db: web::Data<AppDatabase>, request: Result<web::Json<Do
         Err(_e) => return ErrorJsonApiResponse::bad_request("Invalid Dog: Error converting DogRequest to Dog."),
         };
 
-    match dogs::create_dog(&db, validated_dog).await {
-        Ok(created_dog) => JsonApiResponse::success(DogResponse::from(created_dog)),
+    // owners register their own dogs, admins anybody's (see services/policy.rs)
+    if let Err(app_error) = policy::check_dog_owner(&principal, Some(validated_dog.owner)) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    match dogs::create_dog(&db, validated_dog, actor.as_str()).await {
+        Ok(created_dog) => {
+            let version = created_dog.version;
+            conditional::with_etag(JsonApiResponse::success(DogResponse::from(created_dog)), version)
+        },
         Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
     }
 
@@ -40,37 +51,81 @@ pub async fn create_dog(db: web::Data<AppDatabase>, request: Result<web::Json<Do
 // READS
 // LIST All Dogs  -> receive GET method on /dogs
 #[actix_web::get("/dogs")]
-pub async fn list_dogs(db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn list_dogs(db: web::Data<AppDatabase>, http_request: HttpRequest, query: Result<web::Query<ListQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
+
+    // Validate query string (filters and sort order, see ListQuery)
+    let list_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Dogs, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
 
-    match dogs::read_dogs(&db).await {
+    match dogs::read_dogs(&db, &list_query, &scope).await {
         Ok(dog_vec) => {
             let dogs_responses = dog_vec.into_iter().map(|x| {DogResponse::from(x) }).collect::<Vec<DogResponse>>();
-            HttpResponse::Ok().json(dogs_responses)
+            // strong ETag from the content: 304 Not Modified while the list does not change
+            let validators = conditional::CacheValidators::for_content(&dogs_responses);
+            conditional::respond(&http_request, &validators, || HttpResponse::Ok().json(dogs_responses))
         },
         //Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
         Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
     }
 }
 
 // List a spcific Dog -> receive GET method on /dogs/{id} 
 #[actix_web::get("/dogs/{id}")]
-pub async fn list_dog(path: web::Path<String>, db: web::Data<AppDatabase> ) -> HttpResponse {
+pub async fn list_dog(path: web::Path<String>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
     
     // id received must be String because it is a Hexadecimal string
     let dog_id = path.into_inner();
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Dogs, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
     
-    match dogs::read_dog(&db, &dog_id).await {
-        Ok(dog) => JsonApiResponse::success(DogResponse::from(dog)),
+    match dogs::read_dog(&db, &dog_id, &scope).await {
+        Ok(dog) => {
+            // ETag (version) + Last-Modified (updated_at): 304 Not Modified while the dog does not change
+            let validators = conditional::CacheValidators::for_item(dog.version, dog.updated_at.or(dog.created_at));
+            conditional::respond(&http_request, &validators, || JsonApiResponse::success(DogResponse::from(dog)))
+        },
         Err(app_error) => ErrorJsonApiResponse::not_found(&app_error.to_string()),
     }
 
 }
 
+// Walk totals of a Dog -> receive GET method on /dogs/{id}/stats
+// distance, moving time and average pace over the walks recorded on its bookings (see POST /bookings/{id}/track)
+#[actix_web::get("/dogs/{id}/stats")]
+pub async fn dog_stats(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    let dog_id = path.into_inner();
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Dogs, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match dogs::read_dog_stats(&db, &dog_id, &scope).await {
+        Ok(stats) => JsonApiResponse::success(stats),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&AppError::NotFound.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
 // -----------------------------------
 // UPDATES
 // Update specific Dog -> receive PUT method on /dogs/{id} + a Json data representing a DogUpdateRequest Object
 #[actix_web::put("/dogs/{id}")]
-pub async fn update_dog(path: web::Path<String>, db: web::Data<AppDatabase>, request: Result<Json<DogUpdateRequest>, actix_web::Error> ) -> HttpResponse {
+pub async fn update_dog(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor, request: Result<Json<DogUpdateRequest>, actix_web::Error> ) -> HttpResponse {
 
     // Validating request
     let dog_update = match request {
@@ -90,9 +145,27 @@ pub async fn update_dog(path: web::Path<String>, db: web::Data<AppDatabase>, req
 
 
     //println!("Updating Dog id {:?}", dog_id);
-    match dogs::update_dog(&db, &dog_id, dog_update).await {
-        Ok(id) => JsonApiResponse::with_message(&format!("Dog Update Sucessful: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    // Optimistic concurrency: the update only applies to the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // owners cannot give their dogs to another owner (an invalid owner ID is refused by the service)
+    if let Err(app_error) = policy::check_dog_owner(&principal, dog_update.owner.as_deref().and_then(|owner| ObjectId::parse_str(owner).ok())) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Dogs, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match dogs::update_dog(&db, &dog_id, dog_update, &version_check, &scope, actor.as_str()).await {
+        Ok(updated_dog) => conditional::with_etag(
+            JsonApiResponse::with_message(&format!("Dog Update Sucessful: {}", updated_dog._id.to_hex())),
+            updated_dog.version),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
 
@@ -100,13 +173,25 @@ pub async fn update_dog(path: web::Path<String>, db: web::Data<AppDatabase>, req
 // DELETION
 // Delete specific Dog -> receive DELETE method on /dogs/{id}
 #[actix_web::delete("/dogs/{id}")]
-pub async fn delete_dog(path: web::Path<String>, db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn delete_dog(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor) -> HttpResponse {
 
     let dog_id = path.into_inner();
     println!("Deleting Dog id {:?}", dog_id);
 
-    match dogs::delete_dog(&db, &dog_id).await {
+    // Optimistic concurrency: only delete the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Dogs, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match dogs::delete_dog(&db, &dog_id, &version_check, &scope, actor.as_str()).await {
         Ok(id) => JsonApiResponse::with_message(&format!("Dog Deleted: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
\ No newline at end of file
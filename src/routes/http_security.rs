@@ -0,0 +1,118 @@
+use actix_cors::Cors;
+use actix_web::{body::{BoxBody, MessageBody}, dev::{Payload, ServiceRequest, ServiceResponse}, web::BytesMut,
+                http::{header::{self, HeaderName}, Method}, middleware::{DefaultHeaders, Next}, web, HttpMessage};
+use futures::StreamExt;
+use log::warn;
+
+use crate::{config::HttpSecurityConfig, json_response::api_responses::ErrorJsonApiResponse};
+
+// -----------------------------------
+// HTTP security (wrapped around every route in main.rs)
+//  - cors():              which web apps on other origins may call the API (CORS_*), the outermost layer so that
+//                         preflight requests are answered before anything else and errors (401, 429) carry the CORS headers
+//  - security_headers():  Strict-Transport-Security, X-Content-Type-Options, X-Frame-Options, Referrer-Policy on every response
+//  - json_limit():        JSON bodies over JSON_MAX_BYTES (or the limit of their route) get 413 before reaching the handler
+// The settings are in config.rs (HttpSecurityConfig).
+
+// the headers a web app may read on cross-origin responses, besides the CORS-safelisted ones
+const EXPOSED_HEADERS: [&str; 6] = ["etag", "retry-after", "ratelimit-policy", "ratelimit-limit", "ratelimit-remaining", "ratelimit-reset"];
+
+pub fn cors(config: &HttpSecurityConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(config.cors_allowed_methods.iter().filter_map(|method| {
+            let parsed = Method::from_bytes(method.as_bytes()).ok();
+            if parsed.is_none() {
+                warn!("Invalid CORS_ALLOWED_METHODS entry '{}', ignored", method);
+            }
+            parsed
+        }))
+        .allowed_headers(config.cors_allowed_headers.iter().filter_map(|name| {
+            let parsed = HeaderName::from_bytes(name.as_bytes()).ok();
+            if parsed.is_none() {
+                warn!("Invalid CORS_ALLOWED_HEADERS entry '{}', ignored", name);
+            }
+            parsed
+        }))
+        .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+        .max_age(config.cors_max_age);
+    for origin in &config.cors_allowed_origins {
+        cors = match origin.as_str() {
+            "*" => cors.allow_any_origin(),
+            origin => cors.allowed_origin(origin),
+        };
+    }
+    if config.cors_allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+// set only when the handler did not (e.g. the shared report page keeps its own headers)
+pub fn security_headers(config: &HttpSecurityConfig) -> DefaultHeaders {
+    let mut headers = DefaultHeaders::new()
+        .add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        // the links of shared walk reports carry their token: never sent to other sites
+        .add((header::REFERRER_POLICY, "no-referrer"));
+    if config.hsts_max_age > 0 {
+        let subdomains = if config.hsts_include_subdomains { "; includeSubDomains" } else { "" };
+        headers = headers.add((header::STRICT_TRANSPORT_SECURITY, format!("max-age={}{}", config.hsts_max_age, subdomains)));
+    }
+    if let Some(frame_options) = &config.frame_options {
+        headers = headers.add((header::X_FRAME_OPTIONS, frame_options.as_str()));
+    }
+    headers
+}
+
+// the largest JSON body of any route: the Json extractor must not refuse what json_limit() accepts
+pub fn json_config(config: &HttpSecurityConfig) -> web::JsonConfig {
+    let largest = config.json_route_limits.iter().map(|(_, _, bytes)| *bytes).fold(config.json_max_bytes, usize::max);
+    web::JsonConfig::default().limit(largest)
+}
+
+// The handlers read their JSON as Result<Json<..>> and would answer a body cut by a limit with 400:
+// the size is checked here instead, on Content-Length when sent, else by reading the body before the handler.
+pub async fn json_limit(mut request: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let config = match request.app_data::<web::Data<HttpSecurityConfig>>() {
+        Some(config) if is_json(&request) => config.clone(),
+        _ => return Ok(next.call(request).await?.map_into_boxed_body()),
+    };
+
+    let route = request.match_pattern().unwrap_or_else(|| request.path().to_string());
+    let limit = config.json_route_limits
+        .iter()
+        .find(|(method, limit_route, _)| *limit_route == route && (method == "*" || method == request.method().as_str()))
+        .map(|(_, _, bytes)| *bytes)
+        .unwrap_or(config.json_max_bytes);
+    let too_large = || ErrorJsonApiResponse::payload_too_large(&format!("The JSON body is larger than {} bytes", limit));
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > limit) {
+        return Ok(request.into_response(too_large()));
+    }
+
+    // chunked bodies: read up to the limit before the handler runs, so a body too large never reaches it
+    let mut payload = request.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limit {
+            return Ok(request.into_response(too_large()));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    request.set_payload(Payload::from(body.freeze()));
+
+    Ok(next.call(request).await?.map_into_boxed_body())
+}
+
+// application/json, or a JSON based type (e.g. application/geo+json)
+fn is_json(request: &ServiceRequest) -> bool {
+    match request.mime_type() {
+        Ok(Some(mime)) => mime.subtype() == "json" || mime.suffix().is_some_and(|suffix| suffix == "json"),
+        _ => false,
+    }
+}
@@ -1,4 +1,18 @@
 pub mod booking_routes;
 pub mod owner_routes;
 pub mod dog_routes;
-pub mod sitter_routes;
\ No newline at end of file
+pub mod sitter_routes;
+pub mod admin_routes;
+pub mod conditional;
+pub mod actor;
+pub mod audit_routes;
+pub mod outbox_routes;
+pub mod webhook_routes;
+pub mod walk_routes;
+pub mod attachment_routes;
+pub mod report_routes;
+pub mod auth_routes;
+pub mod api_key_auth;
+pub mod api_key_routes;
+pub mod rate_limit;
+pub mod http_security;
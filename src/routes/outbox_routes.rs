@@ -0,0 +1,53 @@
+use actix_web::{web, HttpResponse};
+use crate::{app_errors::errors::AppError, routes::actor::Principal,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::outbox_model::{OutboxMessageResponse, OutboxQuery},
+            models::auth_model::Role,
+            services::{db::AppDatabase, outbox, policy}};
+
+// -----------------------------------
+// ADMIN
+// List outbox messages -> receive GET method on /admin/outbox?status=..&limit=..
+// status: pending | delivered | dead_letter, most recent first
+#[actix_web::get("/admin/outbox")]
+pub async fn list_outbox_messages(db: web::Data<AppDatabase>, query: Result<web::Query<OutboxQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    // Validate query string
+    let outbox_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+
+    match outbox::read_messages(&db, &outbox_query).await {
+        Ok(messages) => {
+            let message_responses = messages.into_iter().map(OutboxMessageResponse::from).collect::<Vec<OutboxMessageResponse>>();
+            JsonApiResponse::success(message_responses)
+        },
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// Re-queue a dead-lettered message -> receive POST method on /admin/outbox/{id}/retry
+// the dispatcher picks it up again on its next round, with a fresh attempt counter
+#[actix_web::post("/admin/outbox/{id}/retry")]
+pub async fn retry_outbox_message(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let message_id = path.into_inner();
+
+    match outbox::requeue(&db, &message_id).await {
+        Ok(message) => JsonApiResponse::success(OutboxMessageResponse::from(message)),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&format!("No dead-lettered outbox message: {}", message_id)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
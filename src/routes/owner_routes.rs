@@ -1,8 +1,11 @@
-use actix_web::{delete, get, post, put, web::{self, Json}, HttpResponse};
+use actix_web::{delete, get, http::header, post, put, web::{self, Json}, HttpRequest, HttpResponse};
 
-use crate::{json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse}, 
-            models::owner_model::{Owner, OwnerRequest, OwnerResponse, OwnerUpdateRequest}, };
-use crate::services::owners;
+use crate::{app_errors::errors::AppError, config::{AppConfig, AuthConfig}, routes::{actor::{Actor, Principal, ANONYMOUS}, conditional},
+            models::list_query_model::ListQuery,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse}, 
+            models::{auth_model::Role, owner_model::{Owner, OwnerLookupQuery, OwnerRequest, OwnerResponse, OwnerUpdateRequest},
+                     privacy_model::EraseRequest}, };
+use crate::services::{account_emails::AccountEmails, owners, policy::{self, Access, Resource}, privacy};
 
 use crate::services::db::AppDatabase;// ← again, use the actual type
 
@@ -50,13 +53,19 @@ use crate::services::db::AppDatabase;// ← again, use the actual type
 #[post("/owners")]
 pub async fn create_owner(
         db: web::Data<AppDatabase>,   // ← must match exac
+        auth_config: web::Data<AuthConfig>,
+        emails: web::Data<AccountEmails>,   // the link to verify the email (see services/account_emails.rs)
+        principal: Option<Principal>,   // who creates it: nobody for a sign-up, else an admin (see services/policy.rs)
         request: Result<Json<OwnerRequest>, 
         actix_web::Error> ) -> HttpResponse {
     // Json is wrapped by a Result to allow validating the request locally here. 
     println!("CREATE ROUTER");
+    if let Err(app_error) = policy::check_sign_up(principal.as_ref(), Resource::Owners) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
     //let owner_req = request.into_inner();  // request data is of type web::Json<MyStruct>,  Json<OwnerRequest> in this case, into_inner() unwraps into inner 'T' value
     // Validate Request
-    let owner_req = match request {
+    let mut owner_req = match request {
         Ok(valid_json) => valid_json.into_inner(),
         Err(e) => {
             println!("JSON error: {:?}", e);
@@ -64,6 +73,9 @@ pub async fn create_owner(
         }
     };  // request data is of type web::Json<MyStruct>,  Json<OwnerRequest> in this case, into_inner() unwraps into inner 'T' value
     
+    // the password goes to 'credentials' (see services/auth.rs), never to the owner document
+    let password = owner_req.password.take();
+
     // Convert OwnerRequest to Owner and Validate Convertion
     let validated_owner = match Owner::try_from(owner_req) {
         Ok(owner) => owner,
@@ -71,9 +83,16 @@ pub async fn create_owner(
     };
 
     println!("CREATE ROUTER: calling create_owner...");
-    match owners::create_owner(&db, validated_owner).await
+    let actor = principal.map(|principal| principal.id.to_hex()).unwrap_or_else(|| ANONYMOUS.to_string());
+    match owners::create_owner(&db, validated_owner, password, &actor).await
     {   // returns an OwnerResponse
-        Ok(inserted_owner) => JsonApiResponse::success(OwnerResponse::from(inserted_owner)),
+        Ok(inserted_owner) => {
+            emails.send_verification_or_log(&db, &auth_config, Role::Owner, inserted_owner._id, &inserted_owner.email).await;
+            let version = inserted_owner.version;
+            conditional::with_etag(JsonApiResponse::success(OwnerResponse::from(inserted_owner)), version)
+        },
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(AppError::Conflict(message)) => ErrorJsonApiResponse::conflict(&message),
         Err(error) => ErrorJsonApiResponse::internal_server_error(&error.to_string()),
     }
 
@@ -82,31 +101,53 @@ pub async fn create_owner(
 // READS
 // List ALL Owners -> receive GET method on /owners
 #[get("/owners")]
-pub async fn list_owners(db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn list_owners(db: web::Data<AppDatabase>, http_request: HttpRequest, query: Result<web::Query<ListQuery>, actix_web::Error>,
+        lookup: Result<web::Query<OwnerLookupQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
+
+    // Validate query string (filters and sort order, see ListQuery; email / phone lookup, see OwnerLookupQuery)
+    let (list_query, lookup) = match (query, lookup) {
+        (Ok(valid_query), Ok(valid_lookup)) => (valid_query.into_inner(), valid_lookup.into_inner()),
+        _ => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Owners, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
 
-    match owners::read_owners(&db).await {
+    match owners::read_owners(&db, &list_query, &lookup, &scope).await {
         Ok(vec_owner) => {
             // map the Vec<Owner> received from the database handler 'read_owners' into a vector of OwnerResponse, to avoid exposing mongodb objects
-            let owner_responses = vec_owner.into_iter().map(|x| OwnerResponse::from(x)).collect::<Vec<OwnerResponse>>();
+            let owner_responses = vec_owner.into_iter().map(OwnerResponse::from).collect::<Vec<OwnerResponse>>();
             //HttpResponse::Ok().json(owner_responses)
-            JsonApiResponse::success(owner_responses)
+            // strong ETag from the content: 304 Not Modified while the list does not change
+            let validators = conditional::CacheValidators::for_content(&owner_responses);
+            conditional::respond(&http_request, &validators, || JsonApiResponse::success(owner_responses))
         },
        // Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+       Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
     }
 }
 
 // List specific Owner -> receive GET method on /owners/{id}
 #[get("/owners/{id}")]
-pub async fn list_owner(path: web::Path<String>, db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn list_owner(path: web::Path<String>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
     
     // id received must be String because it is a Hexadecimal string
     let id_str = path.into_inner();
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Owners, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
   
-    match owners::read_owner(&db, &id_str).await {
+    match owners::read_owner(&db, &id_str, &scope).await {
         Ok(owner) =>  {
            // HttpResponse::Ok().json(OwnerResponse::from(owner))
-           JsonApiResponse::success(OwnerResponse::from(owner))
+           // ETag (version) + Last-Modified (updated_at): 304 Not Modified while the owner does not change
+           let validators = conditional::CacheValidators::for_item(owner.version, owner.updated_at.or(owner.created_at));
+           conditional::respond(&http_request, &validators, || JsonApiResponse::success(OwnerResponse::from(owner)))
         },
         Err(app_error) => ErrorJsonApiResponse::not_found(&app_error.to_string()),
        // Err(e) => HttpResponse::NotFound().body("{}"),
@@ -116,7 +157,8 @@ pub async fn list_owner(path: web::Path<String>, db: web::Data<AppDatabase>) ->
 // UPDATES
 // Update specific Owner -> receive PUT method on /owners/{id} + a Json data representing a OwnerUpdateRequest Object
 #[put("/owners/{id}")]
-pub async fn update_owner(path: web::Path<String>, db: web::Data<AppDatabase>, request: Result<Json<OwnerUpdateRequest>, actix_web::Error>, ) -> HttpResponse {
+#[allow(clippy::too_many_arguments)]   // one extractor per piece of shared state
+pub async fn update_owner(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, auth_config: web::Data<AuthConfig>, emails: web::Data<AccountEmails>, http_request: HttpRequest, principal: Principal, actor: Actor, request: Result<Json<OwnerUpdateRequest>, actix_web::Error>, ) -> HttpResponse {
     // initially the request wasnt a Result, but I wrapped it into a Result in order to validate it here
    
     // Validating request
@@ -129,6 +171,7 @@ pub async fn update_owner(path: web::Path<String>, db: web::Data<AppDatabase>, r
         && owner_update.email.is_none()
         && owner_update.phone.is_none()
         && owner_update.address.is_none()
+        && owner_update.home_access.is_none()
     {
         return ErrorJsonApiResponse::bad_request("No fields provided to update.");
     }
@@ -136,25 +179,103 @@ pub async fn update_owner(path: web::Path<String>, db: web::Data<AppDatabase>, r
     let owner_id = path.into_inner();
     println!("Updating id {:?}", owner_id);
 
+    // Optimistic concurrency: the update only applies to the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Owners, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
     // Invoking database layer 
-    match owners::update_owner(&db, &owner_id, owner_update).await {
-        Ok(id) => JsonApiResponse::with_message(&format!("Owner Update Sucessful: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    match owners::update_owner(&db, &owner_id, owner_update, &version_check, &scope, actor.as_str()).await {
+        Ok(updated_owner) => {
+            // a new email is not verified yet: a link goes to it
+            if updated_owner.email_verified_at.is_none() {
+                emails.send_verification_or_log(&db, &auth_config, Role::Owner, updated_owner._id, &updated_owner.email).await;
+            }
+            conditional::with_etag(
+                JsonApiResponse::with_message(&format!("Owner Update Sucessful: {}", updated_owner._id.to_hex())),
+                updated_owner.version)
+        },
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
 // -----------------------------------
 // DELETION
 // Delete specific Owner -> receive DELETE method on /owners/{id}
 #[delete("/owners/{id}")]
-pub async fn delete_owner(path: web::Path<String>, db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn delete_owner(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor) -> HttpResponse {
 
     let owner_id = path.into_inner();
     println!("Deleting id {:?}", owner_id);
 
-    match owners::delete_owner(&db, &owner_id).await {
+    // Optimistic concurrency: only delete the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Owners, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match owners::delete_owner(&db, &owner_id, &version_check, &scope, actor.as_str()).await {
         Ok(id) => JsonApiResponse::with_message(&format!("Owner Deleted: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+// -----------------------------------
+// PERSONAL DATA (GDPR, see services/privacy.rs)
+// Export everything stored about an Owner -> receive GET method on /owners/{id}/export
+// admins, and the owner itself; sent as a JSON file to download, recorded in the audit log
+#[get("/owners/{id}/export")]
+pub async fn export_owner(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal, actor: Actor) -> HttpResponse {
+
+    // not the sitters: they only see the owners of their bookings (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin, Role::Owner]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+    let scope = match policy::scope(&db, &principal, Resource::Owners, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    let owner_id = path.into_inner();
+    match privacy::export_owner(&db, &owner_id, &scope, actor.as_str()).await {
+        Ok(export) => HttpResponse::Ok()
+            .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"owner-{}-export.json\"", export.owner._id)))
+            .insert_header((header::CACHE_CONTROL, "no-store"))   // personal data: never kept by a cache
+            .json(export),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
 
+// Erase the personal data of an Owner -> receive POST method on /owners/{id}/erase + a Json EraseRequest ({ "reason": ... })
+// admins only: the bookings are kept anonymous, the reason goes to the audit log (409 if already erased)
+#[post("/owners/{id}/erase")]
+pub async fn erase_owner(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal, actor: Actor, request: Result<Json<EraseRequest>, actix_web::Error>) -> HttpResponse {
 
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+    let erase_request = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid input: a \"reason\" for the erasure is required."),
+    };
+
+    let owner_id = path.into_inner();
+    match privacy::erase_owner(&db, &owner_id, erase_request, actor.as_str()).await {
+        Ok(erasure) => JsonApiResponse::success(erasure),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::{body::{BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, http::header::{self, HeaderName, HeaderValue},
+                middleware::Next, web, HttpMessage};
+
+use crate::{config::{AuthConfig, RateLimit},
+            json_response::api_responses::ErrorJsonApiResponse,
+            models::auth_model::Principal,
+            services::{auth, rate_limiter::{Decision, RateLimiter}}};
+
+// -----------------------------------
+// Rate limiting (middleware, wrapped around every route in main.rs, inside the API key authentication)
+// Who is counted: the API key (its 'integration' principal, see routes/api_key_auth.rs), else the account of
+// a valid access token (signature only: a forged token cannot use the bucket of someone else), else the IP address.
+// Every response carries the state of the bucket of the request:
+//   RateLimit-Policy: 30;w=60     30 requests per 60 seconds
+//   RateLimit-Limit / RateLimit-Remaining / RateLimit-Reset (seconds until the bucket is full again)
+// and a request over the limit gets 429 { "error": ... } with Retry-After (seconds) instead of reaching its handler.
+// The limits and where the buckets are kept are in config.rs (RATE_LIMIT_*) and services/rate_limiter.rs.
+pub async fn rate_limit(request: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let limiter = match request.app_data::<web::Data<RateLimiter>>() {
+        Some(limiter) if limiter.enabled() => limiter.clone(),
+        _ => return Ok(next.call(request).await?.map_into_boxed_body()),
+    };
+
+    let client = client_key(&request, &limiter);
+    // the route as declared on the handler: /owners/{id} is one bucket, not one per owner
+    let route = request.match_pattern().unwrap_or_else(|| request.path().to_string());
+    let method = request.method().as_str().to_string();
+    let (limit, decision) = match limiter.check(&client, &method, &route).await {
+        Some(checked) => checked,
+        None => return Ok(next.call(request).await?.map_into_boxed_body()),
+    };
+
+    if !decision.allowed {
+        let retry_after = seconds(decision.retry_after.as_secs_f64());
+        let mut response = ErrorJsonApiResponse::too_many_requests(&format!("Too many requests: retry in {} s", retry_after));
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        set_headers(response.headers_mut(), &limit, &decision);
+        return Ok(request.into_response(response));
+    }
+
+    let mut response = next.call(request).await?.map_into_boxed_body();
+    set_headers(response.headers_mut(), &limit, &decision);
+    Ok(response)
+}
+
+fn client_key(request: &ServiceRequest, limiter: &RateLimiter) -> String {
+    if let Some(principal) = request.extensions().get::<Principal>() {
+        return format!("key:{}", principal.token_id);
+    }
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    if let (Some(token), Some(config)) = (bearer, request.app_data::<web::Data<AuthConfig>>()) {
+        if let Ok(claims) = auth::decode_access_token(config, token) {
+            return format!("user:{}", claims.sub);
+        }
+    }
+
+    let address = if limiter.trust_proxy() {
+        request.connection_info().realip_remote_addr().map(str::to_string)
+    } else {
+        request.peer_addr().map(|address| address.ip().to_string())
+    };
+    format!("ip:{}", address.unwrap_or_else(|| "unknown".to_string()))
+}
+
+fn set_headers(headers: &mut header::HeaderMap, limit: &RateLimit, decision: &Decision) {
+    let policy = format!("{};w={}", limit.requests, limit.period.as_secs());
+    let values = [
+        ("ratelimit-policy", HeaderValue::from_str(&policy).ok()),
+        ("ratelimit-limit", Some(HeaderValue::from(limit.requests))),
+        ("ratelimit-remaining", Some(HeaderValue::from(decision.remaining))),
+        ("ratelimit-reset", Some(HeaderValue::from(seconds(decision.reset.as_secs_f64())))),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+// whole seconds, rounded up: waiting the announced time is always enough
+fn seconds(value: f64) -> u64 {
+    value.ceil() as u64
+}
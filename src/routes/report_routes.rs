@@ -0,0 +1,98 @@
+use actix_web::{http::header, web, HttpResponse};
+
+use crate::{app_errors::errors::AppError, routes::actor::{Actor, Principal},
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::walk_report_model::{WalkReportRequest, WalkReportResponse},
+            services::{db::AppDatabase, walk_reports, walk_tracking::{self, WalkRole}}};
+
+// The shared page embeds its photos and styles: nothing else may be loaded from it
+const REPORT_PAGE_CSP: &str = "default-src 'none'; img-src data:; style-src 'unsafe-inline'";
+
+
+// -----------------------------------
+// CREATE
+// Walk report -> receive POST method on /bookings/{id}/report  with Json data representing a WalkReportRequest Object
+// only the sitter of the booking (logged in), once the walk is over and only once (409 otherwise)
+#[actix_web::post("/bookings/{id}/report")]
+pub async fn submit_booking_report(
+    path: web::Path<String>,
+    db: web::Data<AppDatabase>,
+    principal: Principal,
+    actor: Actor,
+    request: Result<web::Json<WalkReportRequest>, actix_web::Error>) -> HttpResponse {
+
+    let booking_id = path.into_inner();
+
+    let report_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(e) => {
+            println!("JSON error: {:?}", e);
+            return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types.");
+        }
+    };
+
+    let (booking, role) = match walk_tracking::authorise(&db, &booking_id, &principal).await {
+        Ok(authorised) => authorised,
+        Err(AppError::InvalidId) => return ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+    if role != WalkRole::Sitter {
+        return ErrorJsonApiResponse::forbidden(&AppError::Forbidden("only the sitter of the booking can submit its report".to_string()).to_string());
+    }
+
+    match walk_reports::submit_report(&db, &booking, report_req, actor.as_str()).await {
+        Ok(report) => JsonApiResponse::success(WalkReportResponse::from(report)),
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
+
+// -----------------------------------
+// READ
+// Walk report of a booking -> receive GET method on /bookings/{id}/report
+// the owner or the sitter of the booking, dispatchers and admins (logged in); the response carries the share_url of the HTML page
+#[actix_web::get("/bookings/{id}/report")]
+pub async fn booking_report(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    let booking_id = path.into_inner();
+
+    let (booking, _role) = match walk_tracking::authorise(&db, &booking_id, &principal).await {
+        Ok(authorised) => authorised,
+        Err(AppError::InvalidId) => return ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match walk_reports::read_report(&db, &booking).await {
+        Ok(report) => JsonApiResponse::success(WalkReportResponse::from(report)),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found("No report was submitted for this booking yet"),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// SHARE
+// Walk report as a standalone HTML page -> receive GET method on /reports/{share_token}
+// no actor: the token is the capability, whoever has the link can read the page (not indexed, no referrer)
+#[actix_web::get("/reports/{token}")]
+pub async fn shared_report_page(path: web::Path<String>, db: web::Data<AppDatabase>) -> HttpResponse {
+
+    let share_token = path.into_inner();
+
+    let (report, booking) = match walk_reports::read_shared_report(&db, &share_token).await {
+        Ok(shared) => shared,
+        Err(AppError::NotFound) => return ErrorJsonApiResponse::not_found("Report not found"),
+        Err(app_error) => return ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    };
+
+    match walk_reports::render_report_page(&db, &report, &booking).await {
+        Ok(page) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CONTENT_SECURITY_POLICY, REPORT_PAGE_CSP))
+            .insert_header((header::REFERRER_POLICY, "no-referrer"))
+            .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+            .insert_header(("X-Robots-Tag", "noindex"))
+            .insert_header((header::CACHE_CONTROL, "private, no-cache"))
+            .body(page),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
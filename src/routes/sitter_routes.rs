@@ -1,8 +1,10 @@
-use actix_web::{delete, get, post, put, web::{self, Data, Json}, HttpResponse};
-use crate::{json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse}, 
-            models::sitter_model::{Sitter, SitterRequest, SitterResponse, SitterUpdateRequest}, 
+use actix_web::{delete, get, post, put, web::{self, Data, Json}, HttpRequest, HttpResponse};
+use crate::{app_errors::errors::AppError, config::{AppConfig, AuthConfig}, routes::{actor::{Actor, Principal, ANONYMOUS}, conditional},
+            models::list_query_model::ListQuery,
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse}, 
+            models::{auth_model::Role, sitter_model::{Sitter, SitterRequest, SitterResponse, SitterUpdateRequest}}, 
             services::db::AppDatabase};   // ← again, use the actual type
-use crate::services::sitters;
+use crate::services::{account_emails::AccountEmails, policy::{self, Access, Resource}, sitters};
 
 // -----------------------------------
 // CREATE 
@@ -10,12 +12,19 @@ use crate::services::sitters;
 #[post("/sitters")]
 pub async fn create_sitter(
     db: Data<AppDatabase>, 
+    auth_config: Data<AuthConfig>,
+    emails: Data<AccountEmails>,   // the link to verify the email (see services/account_emails.rs)
+    principal: Option<Principal>,   // nobody for a sign-up, else an admin (see services/policy.rs)
     request: Result<Json<SitterRequest>, 
     actix_web::Error> ) -> HttpResponse {
     
+    if let Err(app_error) = policy::check_sign_up(principal.as_ref(), Resource::Sitters) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
     // let sitter_req = request.into_inner();  // request data is of type web::Json<MyStruct>,  Json<SitterRequest> in this case, into_inner() unwraps into inner 'T' value
     // Validate Request
-    let sitter_req = match request {
+    let mut sitter_req = match request {
         Ok(valid_json) => valid_json.into_inner(),
         Err(e) => {
             println!("JSON error: {:?}", e);
@@ -23,6 +32,9 @@ pub async fn create_sitter(
         }
     }; 
 
+    // the password goes to 'credentials' (see services/auth.rs), never to the sitter document
+    let password = sitter_req.password.take();
+
     // Convert OwnerRequest to Owner and Validate Convertion
     let validated_sitter = match Sitter::try_from(sitter_req) {
         Ok(sitter) => sitter,
@@ -30,9 +42,16 @@ pub async fn create_sitter(
     };
 
 
-    match sitters::create_sitter(&db, validated_sitter).await
+    let actor = principal.map(|principal| principal.id.to_hex()).unwrap_or_else(|| ANONYMOUS.to_string());
+    match sitters::create_sitter(&db, validated_sitter, password, &actor).await
     {   // returns an SitterResponse
-        Ok(sitter) => JsonApiResponse::success(SitterResponse::from(sitter)),
+        Ok(sitter) => {
+            emails.send_verification_or_log(&db, &auth_config, Role::Sitter, sitter._id, &sitter.email).await;
+            let version = sitter.version;
+            conditional::with_etag(JsonApiResponse::success(SitterResponse::from(sitter)), version)
+        },
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(AppError::Conflict(message)) => ErrorJsonApiResponse::conflict(&message),
         Err(err) => ErrorJsonApiResponse::internal_server_error(&err.to_string()),
     }
 }
@@ -41,30 +60,52 @@ pub async fn create_sitter(
 // List ALL Sitters -> receive GET method on /sitters
 #[get("/sitters")]
 pub async fn list_sitters(
-    db: Data<AppDatabase>) -> HttpResponse {
+    db: Data<AppDatabase>, http_request: HttpRequest, query: Result<web::Query<ListQuery>, actix_web::Error>, principal: Principal) -> HttpResponse {
+
+    // Validate query string (filters and sort order, see ListQuery)
+    let list_query = match query {
+        Ok(valid_query) => valid_query.into_inner(),
+        Err(_) => return ErrorJsonApiResponse::bad_request("Invalid query string parameters."),
+    };
+
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Sitters, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
 
-    match sitters::read_sitters(&db).await {
+    match sitters::read_sitters(&db, &list_query, &scope).await {
         Ok(vec_sitter) => {
             // map the Vec<Sitter> received from the database handler 'read_sitters' into a vector of SitterResponse, to avoid exposing mongodb objects
-            let sitter_responses = vec_sitter.into_iter().map(|x| SitterResponse::from(x)).collect::<Vec<SitterResponse>>();
+            let sitter_responses = vec_sitter.into_iter().map(SitterResponse::from).collect::<Vec<SitterResponse>>();
             //HttpResponse::Ok().json(sitter_responses)
-            JsonApiResponse::success(sitter_responses)
+            // strong ETag from the content: 304 Not Modified while the list does not change
+            let validators = conditional::CacheValidators::for_content(&sitter_responses);
+            conditional::respond(&http_request, &validators, || JsonApiResponse::success(sitter_responses))
         },
         //Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+        Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
         Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
     }
 }
 
 // List specific Sitter -> receive GET method on /sitters/{id}
 #[get("/sitters/{id}")]
-pub async fn list_sitter(path: web::Path<String>, db: web::Data<AppDatabase>, ) -> HttpResponse {
+pub async fn list_sitter(path: web::Path<String>, db: web::Data<AppDatabase>, http_request: HttpRequest, principal: Principal) -> HttpResponse {
     // id received must be String because it is a Hexadecimal string
     let id_str = path.into_inner();
+    // what the principal may see (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Sitters, Access::Read).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
 
-    match sitters::read_sitter(&db, &id_str).await {
+    match sitters::read_sitter(&db, &id_str, &scope).await {
         Ok(sitter) =>  {
             //HttpResponse::Ok().json(SitterResponse::from(sitter))
-            JsonApiResponse::success(SitterResponse::from(sitter))
+            // ETag (version) + Last-Modified (updated_at): 304 Not Modified while the sitter does not change
+            let validators = conditional::CacheValidators::for_item(sitter.version, sitter.updated_at.or(sitter.created_at));
+            conditional::respond(&http_request, &validators, || JsonApiResponse::success(SitterResponse::from(sitter)))
         },
        //_ => HttpResponse::NotFound().body("{}"),
        Err(app_error) => ErrorJsonApiResponse::not_found(&app_error.to_string()),
@@ -74,9 +115,16 @@ pub async fn list_sitter(path: web::Path<String>, db: web::Data<AppDatabase>, )
 // UPDATES
 // Update specific Sitter -> receive PUT method on /sitters/{id} + a Json data representing a SitterUpdateRequest Object
 #[put("/sitters/{id}")]
+#[allow(clippy::too_many_arguments)]   // one extractor per piece of shared state
 pub async fn update_sitter(
     path: web::Path<String>, 
     db: web::Data<AppDatabase>, 
+    config: web::Data<AppConfig>, 
+    auth_config: web::Data<AuthConfig>, 
+    emails: web::Data<AccountEmails>, 
+    http_request: HttpRequest, 
+    principal: Principal, 
+    actor: Actor, 
     request: Result<Json<SitterUpdateRequest>, 
     actix_web::Error > ) -> HttpResponse {
 
@@ -100,24 +148,56 @@ pub async fn update_sitter(
     let sitter_id = path.into_inner();
     println!("Updating id {:?}", &sitter_id);
 
+    // Optimistic concurrency: the update only applies to the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Sitters, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
     // Invoking database layer
-    match sitters::update_sitter(&db, &sitter_id,sitter_update).await {
-        Ok(id) => JsonApiResponse::with_message(&format!("Sitter Update Sucessful: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    match sitters::update_sitter(&db, &sitter_id,sitter_update, &version_check, &scope, actor.as_str()).await {
+        Ok(updated_sitter) => {
+            // a new email is not verified yet: a link goes to it
+            if updated_sitter.email_verified_at.is_none() {
+                emails.send_verification_or_log(&db, &auth_config, Role::Sitter, updated_sitter._id, &updated_sitter.email).await;
+            }
+            conditional::with_etag(
+                JsonApiResponse::with_message(&format!("Sitter Update Sucessful: {}", updated_sitter._id.to_hex())),
+                updated_sitter.version)
+        },
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
     }
 }
 // -----------------------------------
 // DELETION
 // Delete specific Sitter -> receive DELETE method on /sitters/{id}
 #[delete("/sitters/{id}")]
-pub async fn delete_sitter(path: web::Path<String>, db: web::Data<AppDatabase>) -> HttpResponse {
+pub async fn delete_sitter(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor) -> HttpResponse {
 
     let sitter_id = path.into_inner();
     println!("Deleting id {:?}", sitter_id);
 
-    match sitters::delete_sitter(&db, &sitter_id).await {
+    // Optimistic concurrency: only delete the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // what the principal may change (see services/policy.rs)
+    let scope = match policy::scope(&db, &principal, Resource::Sitters, Access::Write).await {
+        Ok(scope) => scope,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match sitters::delete_sitter(&db, &sitter_id, &version_check, &scope, actor.as_str()).await {
         Ok(id) => JsonApiResponse::with_message(&format!("Sitter Deleted: {}", id)),
-        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
 
     }
 }
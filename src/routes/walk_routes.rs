@@ -0,0 +1,235 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use actix_ws::{Message, MessageStream, Session};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{app_errors::errors::AppError, config::{AppConfig, AuthConfig}, routes::{actor::{Actor, Principal}, conditional},
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::booking_model::{Booking, BookingResponse},
+            models::track_model::TrackPointResponse,
+            services::{db::AppDatabase, track_upload, walk_tracking::{self, WalkHub, WalkRole}}};
+
+// Browsers cannot set headers on a WebSocket: the access token may be sent in the query string instead
+// e.g. ws://localhost:8080/bookings/{id}/walk?access_token=eyJ0eXAiOiJKV1Qi...
+#[derive(Debug, Deserialize)]
+pub struct WalkQuery {
+    pub access_token: Option<String>,
+}
+
+const MAX_POSITION_MESSAGE: usize = 1024;   // bytes, a position is ~100
+
+// -----------------------------------
+// LIVE
+// Walk location sharing -> WebSocket on GET /bookings/{id}/walk
+// - the sitter of the booking (logged in as the sitter) sends positions: {"lat": .., "lon": .., "accuracy": .., "timestamp": ..}
+// - the owner of the booking (logged in as the owner) receives them: {"type": "position", ...}, starting with the last known one
+// - dispatchers and admins receive them too (see services/policy.rs)
+// - anybody else: 404 before the upgrade (bookings out of its scope)
+// The sitter gets {"type": "throttled", "retry_after_ms": ..} when sending faster than WALK_POSITION_MIN_INTERVAL_MS
+// and {"type": "error", "message": ..} for invalid positions.
+#[actix_web::get("/bookings/{id}/walk")]
+#[allow(clippy::too_many_arguments)]   // one extractor per piece of shared state
+pub async fn walk_socket(
+    path: web::Path<String>,
+    db: web::Data<AppDatabase>,
+    config: web::Data<AppConfig>,
+    hub: web::Data<WalkHub>,
+    http_request: HttpRequest,
+    body: web::Payload,
+    auth_config: web::Data<AuthConfig>,
+    principal: Option<Principal>,
+    query: web::Query<WalkQuery>) -> HttpResponse {
+
+    let booking_id = path.into_inner();
+    // Authorization header first, then the token of the query string
+    let principal = match (principal, query.into_inner().access_token) {
+        (Some(principal), _) => principal,
+        (None, Some(token)) => match Principal::from_token(&db, &auth_config, &token).await {
+            Ok(principal) => principal,
+            Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+        },
+        (None, None) => return ErrorJsonApiResponse::unauthorized("send an access token: Authorization header or ?access_token="),
+    };
+    let actor = principal.id.to_hex();
+
+    // Authorisation before the upgrade, so refused clients get a normal HTTP error
+    let (booking, role) = match walk_tracking::authorise(&db, &booking_id, &principal).await {
+        Ok(authorised) => authorised,
+        Err(AppError::InvalidId) => return ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    let (response, session, messages) = match actix_ws::handle(&http_request, body) {
+        Ok(upgrade) => upgrade,
+        Err(e) => return ErrorJsonApiResponse::bad_request(&format!("WebSocket upgrade failed: {}", e)),
+    };
+
+    match role {
+        WalkRole::Sitter => actix_web::rt::spawn(sitter_session(db, hub, booking, actor, session, messages, config.walk_position_interval)),
+        WalkRole::Owner | WalkRole::Staff => actix_web::rt::spawn(owner_session(db, hub, booking, session, messages)),
+    };
+    response
+}
+
+// Sitter: every accepted position is stored, then relayed to the owners following the walk
+async fn sitter_session(db: web::Data<AppDatabase>, hub: web::Data<WalkHub>, booking: Booking, actor: String, mut session: Session, mut messages: MessageStream, interval: Duration) {
+    let mut last_accepted: Option<Instant> = None;
+
+    while let Some(Ok(message)) = messages.next().await {
+        match message {
+            Message::Text(text) => {
+                // throttling: at most one position per interval
+                if let Some(elapsed) = last_accepted.map(|accepted| accepted.elapsed()).filter(|elapsed| *elapsed < interval) {
+                    let notice = json!({ "type": "throttled", "retry_after_ms": (interval - elapsed).as_millis() as u64 });
+                    if session.text(notice.to_string()).await.is_err() { return; }
+                    continue;
+                }
+                if text.len() > MAX_POSITION_MESSAGE {
+                    let notice = json!({ "type": "error", "message": "message too large" });
+                    if session.text(notice.to_string()).await.is_err() { return; }
+                    continue;
+                }
+
+                match walk_tracking::record_position(&db, &booking, &actor, &text).await {
+                    Ok(point) => {
+                        last_accepted = Some(Instant::now());
+                        hub.publish(booking._id, walk_tracking::position_frame(point));
+                    }
+                    Err(app_error) => {
+                        let notice = json!({ "type": "error", "message": app_error.to_string() });
+                        if session.text(notice.to_string()).await.is_err() { return; }
+                    }
+                }
+            }
+            Message::Ping(bytes) => {
+                let pong = session.pong(&bytes).await;
+                if pong.is_err() { return; }
+            }
+            Message::Close(reason) => {
+                let _ = session.close(reason).await;
+                return;
+            }
+            _ => {}
+        }
+    }
+    let _ = session.close(None).await;
+}
+
+// Owner: last known position first, then the live ones until either side closes
+async fn owner_session(db: web::Data<AppDatabase>, hub: web::Data<WalkHub>, booking: Booking, mut session: Session, mut messages: MessageStream) {
+    // subscribe before reading the last position, so that no position falls in between
+    let mut live_positions = hub.subscribe(booking._id);
+
+    if let Ok(Some(point)) = walk_tracking::last_position(&db, booking._id).await {
+        if session.text(walk_tracking::position_frame(point)).await.is_err() { return; }
+    }
+
+    loop {
+        tokio::select! {
+            position = live_positions.recv() => match position {
+                Ok(frame) => {
+                    if session.text(frame).await.is_err() { return; }
+                }
+                Err(RecvError::Lagged(_)) => continue,   // too slow: skip to the most recent positions
+                Err(RecvError::Closed) => break,
+            },
+            message = messages.next() => match message {
+                Some(Ok(Message::Ping(bytes))) => {
+                    let pong = session.pong(&bytes).await;
+                    if pong.is_err() { return; }
+                }
+                Some(Ok(Message::Close(reason))) => {
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                Some(Ok(_)) => {}   // owners only listen
+                _ => break,
+            },
+        }
+    }
+    let _ = session.close(None).await;
+}
+
+// -----------------------------------
+// READS
+// Track of a walk -> receive GET method on /bookings/{id}/track, positions in order
+// the uploaded track when there is one, the live positions otherwise
+// same authorisation as the WebSocket: the owner or the sitter of the booking, dispatchers and admins (logged in)
+#[actix_web::get("/bookings/{id}/track")]
+pub async fn booking_track(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    let booking_id = path.into_inner();
+
+    let (booking, _role) = match walk_tracking::authorise(&db, &booking_id, &principal).await {
+        Ok(authorised) => authorised,
+        Err(AppError::InvalidId) => return ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    match walk_tracking::read_track(&db, booking._id).await {
+        Ok(points) => {
+            let point_responses = points.into_iter().map(TrackPointResponse::from).collect::<Vec<TrackPointResponse>>();
+            JsonApiResponse::success(point_responses)
+        },
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// UPLOAD
+// Track of a walk, after the walk -> receive POST method on /bookings/{id}/track  + a GPX or GeoJSON body
+// (Content-Type: application/gpx+xml or application/geo+json, guessed from the body when generic)
+// only the sitter of the booking (logged in); stores the simplified track and records the walk stats on the booking
+// (distance, moving time, average pace), answers with the updated booking and its new version (ETag)
+#[actix_web::post("/bookings/{id}/track")]
+pub async fn upload_booking_track(path: web::Path<String>, db: web::Data<AppDatabase>, config: web::Data<AppConfig>, http_request: HttpRequest, principal: Principal, actor: Actor, mut body: web::Payload) -> HttpResponse {
+
+    let booking_id = path.into_inner();
+
+    let (booking, role) = match walk_tracking::authorise(&db, &booking_id, &principal).await {
+        Ok(authorised) => authorised,
+        Err(AppError::InvalidId) => return ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+    if role != WalkRole::Sitter {
+        return ErrorJsonApiResponse::forbidden(&AppError::Forbidden("only the sitter of the booking can upload its track".to_string()).to_string());
+    }
+
+    // Optimistic concurrency: the walk is recorded on the version the client has seen (If-Match)
+    let version_check = match conditional::if_match(&http_request, &config) {
+        Ok(version_check) => version_check,
+        Err(app_error) => return ErrorJsonApiResponse::from_write_error(&app_error),
+    };
+
+    // GPX files are larger than the default JSON payloads: read the body up to MAX_TRACK_UPLOAD
+    let mut track = web::BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return ErrorJsonApiResponse::bad_request(&format!("Failed to read the track: {}", e)),
+        };
+        if track.len() + chunk.len() > track_upload::MAX_TRACK_UPLOAD {
+            return ErrorJsonApiResponse::payload_too_large(&format!("The track is larger than {} bytes", track_upload::MAX_TRACK_UPLOAD));
+        }
+        track.extend_from_slice(&chunk);
+    }
+
+    let content_type = http_request.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let format = match track_upload::detect_format(content_type, &track) {
+        Ok(format) => format,
+        Err(app_error) => return ErrorJsonApiResponse::bad_request(&app_error.to_string()),
+    };
+
+    match track_upload::upload_track(&db, &booking, actor.as_str(), &version_check, format, &track).await {
+        Ok(updated_booking) => {
+            let version = updated_booking.version;
+            conditional::with_etag(JsonApiResponse::success(BookingResponse::from(updated_booking)), version)
+        },
+        Err(AppError::ParseError(message)) => ErrorJsonApiResponse::bad_request(&message),
+        Err(app_error) => ErrorJsonApiResponse::from_write_error(&app_error),
+    }
+}
@@ -0,0 +1,138 @@
+use actix_web::{web::{self, Json}, HttpResponse};
+use crate::{app_errors::errors::AppError, routes::actor::{Actor, Principal},
+            json_response::api_responses::{ErrorJsonApiResponse, JsonApiResponse},
+            models::webhook_model::{WebhookDeliveryResponse, WebhookRequest, WebhookSubscriptionResponse},
+            models::auth_model::Role,
+            services::{db::AppDatabase, webhooks, policy}};
+
+// -----------------------------------
+// CREATE 
+// Subscribe a webhook -> receive POST method on /webhooks + a Json WebhookRequest obj
+// { "url": "https://partner.example/hooks", "event_types": ["booking.created", "booking.cancelled"], "secret": "..." }
+#[actix_web::post("/webhooks")]
+pub async fn create_webhook(db: web::Data<AppDatabase>, principal: Principal, actor: Actor, request: Result<Json<WebhookRequest>, actix_web::Error>) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    // Validate Request
+    let webhook_req = match request {
+        Ok(valid_json) => valid_json.into_inner(),
+        Err(e) => {
+            println!("JSON error: {:?}", e);
+            return ErrorJsonApiResponse::bad_request("Invalid input. Missing required fields or wrong types.");
+        }
+    };
+
+    match webhooks::create_subscription(&db, webhook_req, actor.as_str()).await {
+        Ok(subscription) => JsonApiResponse::success(WebhookSubscriptionResponse::from(subscription)),
+        Err(AppError::ParseError(msg)) => ErrorJsonApiResponse::bad_request(&msg),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// READS
+// List ALL webhooks -> receive GET method on /webhooks
+#[actix_web::get("/webhooks")]
+pub async fn list_webhooks(db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    match webhooks::read_subscriptions(&db).await {
+        Ok(subscriptions) => {
+            let subscription_responses = subscriptions.into_iter().map(WebhookSubscriptionResponse::from).collect::<Vec<WebhookSubscriptionResponse>>();
+            JsonApiResponse::success(subscription_responses)
+        },
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// List specific webhook -> receive GET method on /webhooks/{id}
+#[actix_web::get("/webhooks/{id}")]
+pub async fn list_webhook(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let subscription_id = path.into_inner();
+
+    match webhooks::read_subscription(&db, &subscription_id).await {
+        Ok(subscription) => JsonApiResponse::success(WebhookSubscriptionResponse::from(subscription)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::not_found(&app_error.to_string()),
+    }
+}
+
+// Delivery attempts of a webhook -> receive GET method on /webhooks/{id}/deliveries
+// the 100 most recent deliveries, each with all its attempts (time, HTTP status, error, duration)
+#[actix_web::get("/webhooks/{id}/deliveries")]
+pub async fn list_webhook_deliveries(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let subscription_id = path.into_inner();
+
+    match webhooks::read_deliveries(&db, &subscription_id).await {
+        Ok(deliveries) => {
+            let delivery_responses = deliveries.into_iter().map(WebhookDeliveryResponse::from).collect::<Vec<WebhookDeliveryResponse>>();
+            JsonApiResponse::success(delivery_responses)
+        },
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&AppError::NotFound.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// UPDATES
+// Redeliver -> receive POST method on /webhooks/{id}/deliveries/{delivery_id}/redeliver
+// queues the delivery again (same Webhook-Id, new timestamp and signature)
+#[actix_web::post("/webhooks/{id}/deliveries/{delivery_id}/redeliver")]
+pub async fn redeliver_webhook(path: web::Path<(String, String)>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let (subscription_id, delivery_id) = path.into_inner();
+
+    match webhooks::redeliver(&db, &subscription_id, &delivery_id).await {
+        Ok(delivery) => JsonApiResponse::success(WebhookDeliveryResponse::from(delivery)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&AppError::NotFound.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
+
+// -----------------------------------
+// DELETION
+// Unsubscribe -> receive DELETE method on /webhooks/{id}, its pending deliveries are given up
+#[actix_web::delete("/webhooks/{id}")]
+pub async fn delete_webhook(path: web::Path<String>, db: web::Data<AppDatabase>, principal: Principal) -> HttpResponse {
+
+    // admins only (see services/policy.rs)
+    if let Err(app_error) = policy::require_role(&principal, &[Role::Admin]) {
+        return ErrorJsonApiResponse::from_write_error(&app_error);
+    }
+
+    let subscription_id = path.into_inner();
+
+    match webhooks::delete_subscription(&db, &subscription_id).await {
+        Ok(id) => JsonApiResponse::with_message(&format!("Webhook Deleted: {}", id)),
+        Err(AppError::InvalidId) => ErrorJsonApiResponse::bad_request(&AppError::InvalidId.to_string()),
+        Err(AppError::NotFound) => ErrorJsonApiResponse::not_found(&AppError::NotFound.to_string()),
+        Err(app_error) => ErrorJsonApiResponse::internal_server_error(&app_error.to_string()),
+    }
+}
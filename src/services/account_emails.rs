@@ -0,0 +1,236 @@
+use std::{sync::Arc, time::Duration};
+
+use bson::{doc, oid::ObjectId, DateTime};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use log::{error, info};
+use validator::Validate;
+
+use crate::{app_errors::errors::AppError,
+            config::{AuthConfig, MailConfig},
+            models::auth_model::{Principal, Role},
+            models::email_token_model::{EmailToken, EmailTokenClaims, PasswordResetConfirmRequest, PasswordResetRequest, TokenPurpose}};
+use crate::services::{auth, db::AppDatabase, owners, mailer::{Email, Mailer}};
+
+
+    // -------------------------------------------
+    // Account emails: email verification and password reset
+    // -------------------------------------------
+    //  - sign-up (POST /owners, POST /sitters) and email changes (PUT) send a link to the address:
+    //    GET /auth/verify-email?token=.. sets email_verified_at on the owner / sitter (email_verified in the responses)
+    //  - POST /auth/password-reset { email } sends a link to the page of PASSWORD_RESET_URL, which posts the token
+    //    and the new password to POST /auth/password-reset/confirm; every session of the account ends
+    // Tokens are signed, expire (EMAIL_VERIFICATION_TTL_S, PASSWORD_RESET_TTL_S) and are used once (see models/email_token_model.rs).
+    // The emails leave through the Mailer of MAIL_TRANSPORT (see services/mailer.rs).
+
+    const INVALID_TOKEN: &str = "invalid, expired or already used link";
+
+    // shared by the handlers (web::Data<AccountEmails>)
+    pub struct AccountEmails {
+        config: MailConfig,
+        mailer: Arc<dyn Mailer>,
+    }
+
+    impl AccountEmails {
+        pub fn new(config: MailConfig, mailer: Arc<dyn Mailer>) -> Self {
+            info!("Emails sent with the '{}' mailer", mailer.name());
+            AccountEmails { config, mailer }
+        }
+
+        // -----------------
+        // Email verification
+        // -----------------
+
+        // the link to verify 'email', for a new owner / sitter or a changed email
+        pub async fn send_verification(&self, db: &AppDatabase, auth_config: &AuthConfig, role: Role, principal_id: ObjectId, email: &str) -> Result<(), AppError> {
+            let token = issue_token(db, auth_config, TokenPurpose::EmailVerification, self.config.verification_ttl, role, principal_id, email).await?;
+            let link = format!("{}/auth/verify-email?token={}", self.config.public_url, token);
+            self.send(Email {
+                to: email.to_string(),
+                subject: "Confirm your email address".to_string(),
+                body: format!(
+                    "Hello,\n\nPlease confirm that {} is your email address by opening this link:\n\n{}\n\nThe link is valid for {} hours. If you did not create a Breizh Walker account, ignore this email.\n",
+                    email, link, self.config.verification_ttl.as_secs() / 3600,
+                ),
+            }).await
+        }
+
+        // sign-ups and email changes: the account is stored whatever happens to the email, a failure is only logged
+        // (the owner / sitter asks for a new link with POST /auth/verify-email/resend)
+        pub async fn send_verification_or_log(&self, db: &AppDatabase, auth_config: &AuthConfig, role: Role, principal_id: ObjectId, email: &str) {
+            if let Err(app_error) = self.send_verification(db, auth_config, role, principal_id, email).await {
+                error!("Could not send the verification email of {} {}: {}", role.as_str(), principal_id.to_hex(), app_error);
+            }
+        }
+
+        // POST /auth/verify-email/resend: a new link for the current email of the owner / sitter
+        pub async fn resend_verification(&self, db: &AppDatabase, auth_config: &AuthConfig, principal: &Principal) -> Result<(), AppError> {
+            let (email, verified) = match principal.role {
+                Role::Owner => db.get_owners_collection().find_one(doc! { "_id": principal.id }).await?
+                    .map(|owner| (owner.email, owner.email_verified_at.is_some())),
+                Role::Sitter => db.get_sitters_collection().find_one(doc! { "_id": principal.id }).await?
+                    .map(|sitter| (sitter.email, sitter.email_verified_at.is_some())),
+                _ => return Err(AppError::Forbidden("only owners and sitters verify their email".to_string())),
+            }
+            .ok_or(AppError::NotFound)?;
+            if verified {
+                return Err(AppError::Conflict("the email is already verified".to_string()));
+            }
+            self.send_verification(db, auth_config, principal.role, principal.id, &email).await
+        }
+
+        // -----------------
+        // Password reset
+        // -----------------
+
+        // POST /auth/password-reset: the same answer whether the email is known or not (no account discovery),
+        // the links sent before for the account stop working
+        pub async fn request_password_reset(&self, db: &AppDatabase, auth_config: &AuthConfig, request: PasswordResetRequest) -> Result<(), AppError> {
+            request.validate().map_err(|e| AppError::ParseError(e.to_string()))?;
+            let login = request.email.trim().to_lowercase();
+            let credential = match db.get_credentials_collection().find_one(doc! { "login": &login }).await? {
+                Some(credential) => credential,
+                None => {
+                    info!("Password reset asked for an unknown email");
+                    return Ok(());
+                },
+            };
+
+            db.get_email_tokens_collection()
+                .update_many(
+                    doc! { "principal_id": credential.principal_id, "purpose": TokenPurpose::PasswordReset.as_str(), "used_at": null },
+                    doc! { "$set": { "used_at": DateTime::now() } },
+                )
+                .await?;
+            let token = issue_token(db, auth_config, TokenPurpose::PasswordReset, self.config.password_reset_ttl, credential.role, credential.principal_id, &login).await?;
+            let link = format!("{}?token={}", self.config.password_reset_url, token);
+            let sent = self.send(Email {
+                to: login.clone(),
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Hello,\n\nSomebody (hopefully you) asked to reset the password of your Breizh Walker account. Choose a new password here:\n\n{}\n\nThe link is valid for {} minutes and works once. If you did not ask for it, ignore this email: your password does not change.\n",
+                    link, self.config.password_reset_ttl.as_secs() / 60,
+                ),
+            }).await;
+            if let Err(app_error) = sent {
+                error!("Could not send the password reset email of {} {}: {}", credential.role.as_str(), credential.principal_id.to_hex(), app_error);
+            }
+            Ok(())
+        }
+
+        async fn send(&self, email: Email) -> Result<(), AppError> {
+            self.mailer.send(&email).await.map_err(|e| AppError::BadGateway(format!("the email could not be sent: {}", e)))
+        }
+    }
+
+    // GET or POST /auth/verify-email: the email of the token becomes verified, if it is still the email of the account
+    pub async fn verify_email(db: &AppDatabase, auth_config: &AuthConfig, token: &str) -> Result<(Role, ObjectId), AppError> {
+        let claims = consume_token(db, auth_config, token, TokenPurpose::EmailVerification).await?;
+        let principal_id = parse_subject(&claims)?;
+        if !mark_verified(db, claims.role, principal_id, &claims.email).await? {
+            return Err(AppError::Conflict("the email of the account changed since this link was sent".to_string()));
+        }
+        info!("Email of {} {} verified", claims.role.as_str(), principal_id.to_hex());
+        Ok((claims.role, principal_id))
+    }
+
+    // POST /auth/password-reset/confirm: new password, every session ends (see auth::set_password)
+    // following the link also proves the email belongs to the owner / sitter
+    pub async fn confirm_password_reset(db: &AppDatabase, auth_config: &AuthConfig, request: PasswordResetConfirmRequest) -> Result<(), AppError> {
+        let claims = consume_token(db, auth_config, &request.token, TokenPurpose::PasswordReset).await?;
+        let principal_id = parse_subject(&claims)?;
+        let credential = db.get_credentials_collection()
+            .find_one(doc! { "principal_id": principal_id, "login": &claims.email })
+            .await?
+            .ok_or(AppError::Unauthorized(INVALID_TOKEN.to_string()))?;
+
+        auth::set_password(db, credential.role, principal_id, &request.password).await?;
+        mark_verified(db, credential.role, principal_id, &claims.email).await?;
+        info!("Password of {} {} reset", credential.role.as_str(), principal_id.to_hex());
+        Ok(())
+    }
+
+    // -----------------
+    // Tokens
+    // -----------------
+
+    async fn issue_token(db: &AppDatabase, auth_config: &AuthConfig, purpose: TokenPurpose, ttl: Duration, role: Role, principal_id: ObjectId, email: &str) -> Result<String, AppError> {
+        let now = DateTime::now();
+        let stored_token = EmailToken {
+            _id: ObjectId::new().to_hex(),
+            purpose,
+            principal_id,
+            role,
+            email: email.to_string(),
+            created_at: now,
+            expires_at: DateTime::from_millis(now.timestamp_millis() + ttl.as_millis() as i64),
+            used_at: None,
+        };
+        db.get_email_tokens_collection().insert_one(&stored_token).await?;
+
+        let claims = EmailTokenClaims {
+            sub: principal_id.to_hex(),
+            role,
+            email: stored_token.email,
+            jti: stored_token._id,
+            aud: purpose.as_str().to_string(),
+            iss: auth_config.issuer.clone(),
+            iat: now.timestamp_millis() / 1000,
+            exp: stored_token.expires_at.timestamp_millis() / 1000,
+        };
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(&auth_config.jwt_secret))
+            .map_err(|e| AppError::DatabaseError(format!("Failed to sign the {} token: {}", purpose.as_str(), e)))
+    }
+
+    // signature, issuer, purpose (audience) and expiry, then the token is marked as used (only the first request gets it)
+    async fn consume_token(db: &AppDatabase, auth_config: &AuthConfig, token: &str, purpose: TokenPurpose) -> Result<EmailTokenClaims, AppError> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[&auth_config.issuer]);
+        validation.set_audience(&[purpose.as_str()]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<EmailTokenClaims>(token.trim(), &DecodingKey::from_secret(&auth_config.jwt_secret), &validation)
+            .map_err(|_| AppError::Unauthorized(INVALID_TOKEN.to_string()))?
+            .claims;
+
+        let now = DateTime::now();
+        db.get_email_tokens_collection()
+            .find_one_and_update(
+                doc! { "_id": &claims.jti, "purpose": purpose.as_str(), "used_at": null, "expires_at": { "$gt": now } },
+                doc! { "$set": { "used_at": now } },
+            )
+            .await?
+            .ok_or(AppError::Unauthorized(INVALID_TOKEN.to_string()))?;
+        Ok(claims)
+    }
+
+    fn parse_subject(claims: &EmailTokenClaims) -> Result<ObjectId, AppError> {
+        ObjectId::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized(INVALID_TOKEN.to_string()))
+    }
+
+    // false when the account does not have this email any more (staff accounts have nothing to mark)
+    async fn mark_verified(db: &AppDatabase, role: Role, principal_id: ObjectId, email: &str) -> Result<bool, AppError> {
+        let mut filter = match role {
+            Role::Owner => owners::email_filter(email)?,   // encrypted: compared through its blind index
+            _ => doc! { "email": { "$regex": format!("^{}$", regex_escape(email)), "$options": "i" } },
+        };
+        filter.insert("_id", principal_id);
+        // a new version: cached copies (ETag) must not keep showing the email as unverified
+        let now = DateTime::now();
+        let update = doc! { "$set": { "email_verified_at": now, "updated_at": now }, "$inc": { "version": 1 } };
+        let matched = match role {
+            Role::Owner => db.get_owners_collection().update_one(filter, update).await?.matched_count,
+            Role::Sitter => db.get_sitters_collection().update_one(filter, update).await?.matched_count,
+            _ => return Ok(true),
+        };
+        Ok(matched > 0)
+    }
+
+    // for a $regex matching this exact text (see owners::email_filter)
+    pub fn regex_escape(text: &str) -> String {
+        text.chars().fold(String::new(), |mut escaped, character| {
+            if "\\^$.|?*+()[]{}".contains(character) {
+                escaped.push('\\');
+            }
+            escaped.push(character);
+            escaped
+        })
+    }
@@ -0,0 +1,307 @@
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::StreamExt;
+use log::{info, warn};
+use validator::Validate;
+
+use crate::{app_errors::errors::AppError,
+            models::api_key_model::{ApiKey, ApiKeyRequest, ApiKeyResponse},
+            models::auth_model::{Principal, Role}};
+use crate::services::{audit, auth, db::{self, AppDatabase}, policy, tls::ClientCertificate};
+
+
+    // -------------------------------------------
+    // API keys (partner integrations)
+    // -------------------------------------------
+    // A key is `bwk_<prefix>_<secret>`: the prefix (8 hex) finds the key, the SHA-256 of the whole key is compared
+    // to the stored hash. Admins manage the keys (routes/api_key_routes.rs), the middleware of
+    // routes/api_key_auth.rs turns `Authorization: ApiKey <key>` into an 'integration' Principal
+    // whose grants are the scopes of the key (see services/policy.rs).
+    // A key bound to a client certificate is refused unless the connection presents it (mutual TLS, see services/tls.rs).
+
+    const KEY_MARKER: &str = "bwk";
+    const PREFIX_BYTES: usize = 4;
+    const SECRET_BYTES: usize = 32;
+    const PREFIX_ATTEMPTS: usize = 3;              // a new prefix is drawn when one is already taken
+    const LAST_USED_RESOLUTION_S: i64 = 60;        // last_used_at is written at most once a minute per key
+    const INVALID_KEY: &str = "invalid, expired or revoked API key";
+    pub const AUDIT_RESOURCE: &str = "api_key";
+
+    // CREATE a key: returns it with the plain key, which is not stored and cannot be shown again
+    pub async fn create_key(db: &AppDatabase, key_request: ApiKeyRequest, actor: &str) -> Result<(ApiKey, String), AppError> {
+        key_request.validate().map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        let mut scopes = Vec::<String>::new();
+        for grant in &key_request.scopes {
+            policy::parse_grant(grant)?;
+            if !scopes.contains(grant) {
+                scopes.push(grant.clone());
+            }
+        }
+        let expires_at = match key_request.expires_at.as_deref() {
+            Some(expires_at) => match chrono::DateTime::parse_from_rfc3339(expires_at) {
+                Ok(date) if date.timestamp_millis() > DateTime::now().timestamp_millis() => Some(DateTime::from_millis(date.timestamp_millis())),
+                Ok(_) => return Err(AppError::ParseError("expires_at must be in the future".to_string())),
+                Err(_) => return Err(AppError::ParseError("expires_at must be an RFC3339 date, e.g. 2027-01-01T00:00:00Z".to_string())),
+            },
+            None => None,
+        };
+        let client_certificate_sha256 = key_request.client_certificate_sha256.as_deref().map(parse_fingerprint).transpose()?;
+
+        for _ in 0..PREFIX_ATTEMPTS {
+            let (prefix, key) = generate_key();
+            let api_key = ApiKey {
+                _id: ObjectId::new(),
+                name: key_request.name.clone(),
+                prefix,
+                key_hash: auth::hash_token(&key),
+                scopes: scopes.clone(),
+                expires_at,
+                last_used_at: None,
+                revoked_at: None,
+                client_certificate_sha256: client_certificate_sha256.clone(),
+                created_at: DateTime::now(),
+                created_by: actor.to_string(),
+            };
+            match db.get_api_keys_collection().insert_one(&api_key).await {
+                Ok(_) => {
+                    // the audit entry gets the public fields only, never the hash
+                    audit::record_create(db, AUDIT_RESOURCE, api_key._id, actor, &ApiKeyResponse::from(api_key.clone())).await;
+                    info!("API key {} ({}) created by {}", api_key.prefix, api_key.name, actor);
+                    return Ok((api_key, key));
+                },
+                Err(e) if db::is_duplicate_key(&e) => warn!("API key prefix {} already taken, drawing another one", api_key.prefix),
+                Err(e) => return Err(AppError::DatabaseError(format!("Failed to store the API key: {}", e))),
+            }
+        }
+        Err(AppError::DatabaseError("Could not find a free API key prefix".to_string()))
+    }
+
+    // READ keys, newest first (revoked and expired ones included)
+    pub async fn read_keys(db: &AppDatabase) -> Result<Vec<ApiKey>, AppError> {
+        let mut result_cursor = db.get_api_keys_collection()
+            .find(doc! {})
+            .sort(doc! { "created_at": -1, "_id": -1 })
+            .await?;
+
+        let mut vec_of_keys = Vec::<ApiKey>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(api_key) => vec_of_keys.push(api_key),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading API keys from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_keys)
+    }
+
+    // READ single key
+    pub async fn read_key(db: &AppDatabase, key_id: &str) -> Result<ApiKey, AppError> {
+        let obj_id = ObjectId::parse_str(key_id).map_err(|_| AppError::InvalidId)?;
+        match db.get_api_keys_collection().find_one(doc! { "_id": obj_id }).await? {
+            Some(api_key) => Ok(api_key),
+            None => Err(AppError::NotFound),
+        }
+    }
+
+    // REVOKE a key: refused from the next request on, kept for the audit trail (revoking twice keeps the first date)
+    pub async fn revoke_key(db: &AppDatabase, key_id: &str, actor: &str) -> Result<ApiKey, AppError> {
+        let api_key = read_key(db, key_id).await?;
+        if api_key.revoked_at.is_some() {
+            return Ok(api_key);
+        }
+
+        let update_fields = doc! { "revoked_at": DateTime::now() };
+        db.get_api_keys_collection()
+            .update_one(doc! { "_id": api_key._id, "revoked_at": null }, doc! { "$set": update_fields.clone() })
+            .await?;
+        audit::record_update(db, AUDIT_RESOURCE, api_key._id, actor, &ApiKeyResponse::from(api_key.clone()), &update_fields).await;
+        info!("API key {} ({}) revoked by {}", api_key.prefix, api_key.name, actor);
+        read_key(db, key_id).await
+    }
+
+    // The Principal of a key sent as `Authorization: ApiKey <key>` (Unauthorized when unknown, expired or revoked,
+    // or bound to a client certificate that the connection did not present)
+    pub async fn verify_key(db: &AppDatabase, key: &str, client_certificate: Option<&ClientCertificate>) -> Result<Principal, AppError> {
+        let prefix = key_prefix(key).ok_or(AppError::Unauthorized(INVALID_KEY.to_string()))?;
+        let api_key = db.get_api_keys_collection()
+            .find_one(doc! { "prefix": prefix })
+            .await?
+            .ok_or(AppError::Unauthorized(INVALID_KEY.to_string()))?;
+
+        let now = DateTime::now();
+        check_key(&api_key, key, client_certificate, now)?;
+
+        // last use, approximately: one write per key and per minute, not one per request
+        let recently_used = api_key.last_used_at
+            .is_some_and(|last_used_at| now.timestamp_millis() - last_used_at.timestamp_millis() < LAST_USED_RESOLUTION_S * 1000);
+        if !recently_used {
+            if let Err(e) = db.get_api_keys_collection().update_one(doc! { "_id": api_key._id }, doc! { "$set": { "last_used_at": now } }).await {
+                warn!("Could not record the use of API key {}: {}", api_key.prefix, e);
+            }
+        }
+
+        Ok(Principal {
+            id: api_key._id,
+            role: Role::Integration,
+            token_id: api_key.prefix,
+            expires_at: api_key.expires_at.map_or(i64::MAX, |expires_at| expires_at.timestamp_millis() / 1000),
+            grants: api_key.scopes,
+        })
+    }
+
+    // (prefix, key): `bwk_<prefix>_<secret>`
+    fn generate_key() -> (String, String) {
+        let prefix = auth::random_hex(PREFIX_BYTES);
+        let key = format!("{}_{}_{}", KEY_MARKER, prefix, auth::random_hex(SECRET_BYTES));
+        (prefix, key)
+    }
+
+    // the prefix finding the key, None when 'key' is not shaped like one
+    fn key_prefix(key: &str) -> Option<&str> {
+        key.strip_prefix(KEY_MARKER)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .and_then(|rest| rest.split_once('_'))
+            .map(|(prefix, _)| prefix)
+    }
+
+    // "AB:CD:.." as printed by openssl, or plain hex
+    fn parse_fingerprint(fingerprint: &str) -> Result<String, AppError> {
+        let fingerprint = fingerprint.replace(':', "").trim().to_lowercase();
+        if fingerprint.len() != 64 || !fingerprint.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(AppError::ParseError("client_certificate_sha256 must be the SHA-256 fingerprint of the certificate (64 hex characters)".to_string()));
+        }
+        Ok(fingerprint)
+    }
+
+    // the key found by its prefix: same hash, neither revoked nor expired, and sent with its client certificate when bound to one
+    fn check_key(api_key: &ApiKey, key: &str, client_certificate: Option<&ClientCertificate>, now: DateTime) -> Result<(), AppError> {
+        if !same_hash(&api_key.key_hash, &auth::hash_token(key))
+            || api_key.revoked_at.is_some()
+            || api_key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(AppError::Unauthorized(INVALID_KEY.to_string()));
+        }
+        if let Some(bound) = &api_key.client_certificate_sha256 {
+            if client_certificate.map(|certificate| &certificate.sha256) != Some(bound) {
+                warn!("API key {} used without its client certificate", api_key.prefix);
+                return Err(AppError::Unauthorized("this API key only works over mutual TLS with its client certificate".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // compares every character, whatever the first difference (no timing hint on the hash)
+    fn same_hash(expected: &str, actual: &str) -> bool {
+        expected.len() == actual.len()
+            && expected.bytes().zip(actual.bytes()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const CERTIFICATE_SHA256: &str = "5e8bd0f1a7c96d7a4b3d0c6e2f1a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f2a";
+
+        // a stored key and the key the integration sends
+        fn api_key() -> (ApiKey, String) {
+            let (prefix, key) = generate_key();
+            let api_key = ApiKey {
+                _id: ObjectId::new(),
+                name: "partner booking portal".to_string(),
+                prefix,
+                key_hash: auth::hash_token(&key),
+                scopes: vec!["bookings:read".to_string()],
+                expires_at: None,
+                last_used_at: None,
+                revoked_at: None,
+                client_certificate_sha256: None,
+                created_at: DateTime::now(),
+                created_by: "admin".to_string(),
+            };
+            (api_key, key)
+        }
+
+        fn seconds_from_now(seconds: i64) -> DateTime {
+            DateTime::from_millis(DateTime::now().timestamp_millis() + seconds * 1000)
+        }
+
+        #[test]
+        fn keys_start_with_their_prefix() {
+            let (prefix, key) = generate_key();
+            assert_eq!(prefix.len(), 2 * PREFIX_BYTES);
+            assert_eq!(key.len(), KEY_MARKER.len() + 2 + 2 * PREFIX_BYTES + 2 * SECRET_BYTES);
+            assert_eq!(key_prefix(&key), Some(prefix.as_str()));
+            assert_ne!(generate_key().1, key);
+
+            assert_eq!(key_prefix("bwk_0a1b2c3d_secret"), Some("0a1b2c3d"));
+            for not_a_key in ["", "bwk", "bwk_0a1b2c3d", "xyz_0a1b2c3d_secret", "bwk0a1b2c3d_secret", "Bearer bwk_0a1b2c3d_secret"] {
+                assert_eq!(key_prefix(not_a_key), None, "{}", not_a_key);
+            }
+        }
+
+        #[test]
+        fn the_whole_key_is_checked() {
+            let (api_key, key) = api_key();
+            assert!(check_key(&api_key, &key, None, DateTime::now()).is_ok());
+
+            // same prefix, another secret
+            let (_, other_key) = generate_key();
+            let forged = format!("{}_{}_{}", KEY_MARKER, api_key.prefix, other_key.rsplit('_').next().unwrap());
+            assert!(matches!(check_key(&api_key, &forged, None, DateTime::now()), Err(AppError::Unauthorized(_))));
+            assert!(check_key(&api_key, &key[..key.len() - 1], None, DateTime::now()).is_err());
+        }
+
+        #[test]
+        fn hashes_are_compared_in_full() {
+            let hash = auth::hash_token("bwk_0a1b2c3d_secret");
+            assert!(same_hash(&hash, &hash.clone()));
+            assert!(!same_hash(&hash, &auth::hash_token("bwk_0a1b2c3d_secreT")));
+            // a difference in the last character only
+            let last_differs = format!("{}{}", &hash[..63], if hash.ends_with('0') { '1' } else { '0' });
+            assert!(!same_hash(&hash, &last_differs));
+            assert!(!same_hash(&hash, &hash[..63]));
+            assert!(!same_hash(&hash, ""));
+        }
+
+        #[test]
+        fn expired_and_revoked_keys_are_refused() {
+            let (api_key, key) = api_key();
+
+            let expiring = ApiKey { expires_at: Some(seconds_from_now(60)), ..api_key.clone() };
+            assert!(check_key(&expiring, &key, None, DateTime::now()).is_ok());
+            assert!(matches!(check_key(&expiring, &key, None, seconds_from_now(60)), Err(AppError::Unauthorized(_))));
+            assert!(check_key(&expiring, &key, None, seconds_from_now(120)).is_err());
+
+            let revoked = ApiKey { revoked_at: Some(DateTime::now()), ..api_key };
+            assert!(matches!(check_key(&revoked, &key, None, DateTime::now()), Err(AppError::Unauthorized(_))));
+        }
+
+        #[test]
+        fn bound_keys_need_their_client_certificate() {
+            let (api_key, key) = api_key();
+            let bound = ApiKey { client_certificate_sha256: Some(CERTIFICATE_SHA256.to_string()), ..api_key.clone() };
+            let certificate = |sha256: &str| ClientCertificate { sha256: sha256.to_string() };
+
+            assert!(check_key(&bound, &key, Some(&certificate(CERTIFICATE_SHA256)), DateTime::now()).is_ok());
+            assert!(matches!(check_key(&bound, &key, None, DateTime::now()), Err(AppError::Unauthorized(_))));
+            let other_certificate = certificate(&CERTIFICATE_SHA256.replace('5', "6"));
+            assert!(matches!(check_key(&bound, &key, Some(&other_certificate), DateTime::now()), Err(AppError::Unauthorized(_))));
+
+            // a key that is not bound works with or without a certificate
+            assert!(check_key(&api_key, &key, Some(&certificate(CERTIFICATE_SHA256)), DateTime::now()).is_ok());
+        }
+
+        #[test]
+        fn fingerprints_are_normalized() {
+            let openssl = CERTIFICATE_SHA256
+                .to_uppercase()
+                .as_bytes()
+                .chunks(2)
+                .map(|pair| std::str::from_utf8(pair).unwrap())
+                .collect::<Vec<&str>>()
+                .join(":");
+            assert_eq!(parse_fingerprint(&openssl).unwrap(), CERTIFICATE_SHA256);
+            assert_eq!(parse_fingerprint(CERTIFICATE_SHA256).unwrap(), CERTIFICATE_SHA256);
+
+            assert!(matches!(parse_fingerprint(&CERTIFICATE_SHA256[..40]), Err(AppError::ParseError(_))));   // SHA-1
+            assert!(parse_fingerprint(&CERTIFICATE_SHA256.replace('a', "g")).is_err());
+        }
+    }
@@ -0,0 +1,365 @@
+use std::io::Cursor;
+
+use actix_web::web::Bytes;
+use bson::{doc, oid::ObjectId, Bson, DateTime};
+use futures::{AsyncReadExt, AsyncWriteExt, Stream, StreamExt};
+use image::{codecs::jpeg::JpegEncoder, ImageFormat, ImageReader, Limits};
+use log::warn;
+use sha2::{Digest, Sha256};
+
+use crate::{app_errors::errors::AppError,
+            models::attachment_model::Attachment};
+use crate::services::{audit, db::AppDatabase};
+
+
+    // -------------------------------------------
+    // Attachments (photos and documents) in GridFS
+    // -------------------------------------------
+    // An upload is checked completely before anything is stored:
+    //   1. type: sniffed from the first bytes (magic numbers), the Content-Type sent by the client is ignored
+    //   2. images: decoded (within MAX_IMAGE_DIMENSION) to read their size and generate a JPEG thumbnail
+    //   3. the file, then its thumbnail, are written to the GridFS bucket 'uploads', then the 'attachments' document
+    // Downloads read the chunks of the GridFS file directly, so a Range request only reads the chunks it needs.
+
+    const THUMBNAIL_SIZE: u32 = 320;             // pixels, bounding box of the thumbnails (aspect ratio kept)
+    const THUMBNAIL_QUALITY: u8 = 80;            // JPEG quality
+    const MAX_IMAGE_DIMENSION: u32 = 12_000;     // pixels, larger images are refused (decompression bombs)
+    const MAX_IMAGE_ALLOCATION: u64 = 512 * 1024 * 1024;
+    const MAX_FILENAME_LENGTH: usize = 255;
+
+    // What is uploaded, and the types accepted for it
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum AttachmentKind {
+        Photo,
+        Document,
+    }
+
+    impl AttachmentKind {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                AttachmentKind::Photo => "photo",
+                AttachmentKind::Document => "document",
+            }
+        }
+
+        pub fn accepted_types(&self) -> &'static [&'static str] {
+            match self {
+                AttachmentKind::Photo => &["image/jpeg", "image/png", "image/gif", "image/webp"],
+                AttachmentKind::Document => &["application/pdf", "image/jpeg", "image/png"],
+            }
+        }
+    }
+
+    // The resource the attachments belong to, e.g. the photos of dog 6816404642b784266124d516
+    #[derive(Debug, Clone, Copy)]
+    pub struct AttachmentTarget {
+        pub resource: &'static str,   // dogs | bookings | sitters
+        pub resource_id: ObjectId,
+        pub kind: AttachmentKind,
+    }
+
+    impl AttachmentTarget {
+        fn filter(&self) -> bson::Document {
+            doc! { "resource": self.resource, "resource_id": self.resource_id, "kind": self.kind.as_str() }
+        }
+    }
+
+    // A file read from the multipart request
+    pub struct UploadedFile {
+        pub filename: String,
+        pub bytes: Vec<u8>,
+    }
+
+    // A file ready to be stored: checked, with its thumbnail
+    struct PreparedFile {
+        filename: String,
+        content_type: &'static str,
+        bytes: Vec<u8>,
+        thumbnail: Option<Vec<u8>>,
+        dimensions: Option<(u32, u32)>,
+    }
+
+    // Content type from the magic numbers at the start of the file, None when unknown
+    pub fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+        if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some("image/jpeg")
+        } else if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+            Some("image/png")
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some("image/gif")
+        } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+            Some("image/webp")
+        } else if bytes.starts_with(b"%PDF-") {
+            Some("application/pdf")
+        } else {
+            None
+        }
+    }
+
+    // CREATE attachments: all the files are checked before the first one is stored
+    pub async fn store_attachments(db: &AppDatabase, target: AttachmentTarget, files: Vec<UploadedFile>, actor: &str) -> Result<Vec<Attachment>, AppError> {
+        if files.is_empty() {
+            return Err(AppError::ParseError("No file in the request, send multipart/form-data with at least one file".to_string()));
+        }
+
+        let mut prepared = Vec::with_capacity(files.len());
+        for file in files {
+            prepared.push(prepare(target.kind, file).await?);
+        }
+
+        let mut attachments = Vec::with_capacity(prepared.len());
+        for file in prepared {
+            let attachment = store(db, target, file, actor).await?;
+            audit::record_create(db, "attachments", attachment._id, actor, &attachment).await;
+            attachments.push(attachment);
+        }
+        Ok(attachments)
+    }
+
+    async fn prepare(kind: AttachmentKind, file: UploadedFile) -> Result<PreparedFile, AppError> {
+        let filename = sanitize_filename(&file.filename);
+        let content_type = match sniff_content_type(&file.bytes) {
+            Some(content_type) if kind.accepted_types().contains(&content_type) => content_type,
+            Some(content_type) => return Err(AppError::UnsupportedMediaType(format!("'{}' is {}, a {} must be one of {:?}", filename, content_type, kind.as_str(), kind.accepted_types()))),
+            None => return Err(AppError::UnsupportedMediaType(format!("'{}' is not a recognised file type, a {} must be one of {:?}", filename, kind.as_str(), kind.accepted_types()))),
+        };
+
+        let (thumbnail, dimensions) = match image_format(content_type) {
+            Some(format) => {
+                // decoding is CPU bound: off the async workers
+                let bytes = file.bytes.clone();
+                let (thumbnail, dimensions) = actix_web::rt::task::spawn_blocking(move || thumbnail(&bytes, format))
+                    .await
+                    .map_err(|_| AppError::InternalError)?
+                    .map_err(|e| AppError::UnsupportedMediaType(format!("'{}' could not be decoded as {}: {}", filename, content_type, e)))?;
+                (Some(thumbnail), Some(dimensions))
+            }
+            None => (None, None),
+        };
+
+        Ok(PreparedFile { filename, content_type, bytes: file.bytes, thumbnail, dimensions })
+    }
+
+    fn image_format(content_type: &str) -> Option<ImageFormat> {
+        match content_type {
+            "image/jpeg" => Some(ImageFormat::Jpeg),
+            "image/png" => Some(ImageFormat::Png),
+            "image/gif" => Some(ImageFormat::Gif),
+            "image/webp" => Some(ImageFormat::WebP),
+            _ => None,
+        }
+    }
+
+    // JPEG thumbnail (within THUMBNAIL_SIZE) and the size of the original image
+    fn thumbnail(bytes: &[u8], format: ImageFormat) -> Result<(Vec<u8>, (u32, u32)), image::ImageError> {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+        limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+        limits.max_alloc = Some(MAX_IMAGE_ALLOCATION);
+
+        let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+        reader.limits(limits);
+        let image = reader.decode()?;
+
+        let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8();   // JPEG has no alpha channel
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, THUMBNAIL_QUALITY).encode_image(&thumbnail)?;
+        Ok((encoded, (image.width(), image.height())))
+    }
+
+    // Last component of the client's filename, without control characters
+    fn sanitize_filename(filename: &str) -> String {
+        let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+        let name = name.chars().filter(|c| !c.is_control()).take(MAX_FILENAME_LENGTH).collect::<String>();
+        match name.trim() {
+            "" | "." | ".." => "upload".to_string(),
+            name => name.to_string(),
+        }
+    }
+
+    async fn store(db: &AppDatabase, target: AttachmentTarget, file: PreparedFile, actor: &str) -> Result<Attachment, AppError> {
+        let file_id = write_file(db, &file.filename, &file.bytes).await?;
+        let thumbnail_id = match &file.thumbnail {
+            Some(thumbnail) => match write_file(db, &format!("thumbnail-{}.jpg", file_id.to_hex()), thumbnail).await {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    delete_file(db, file_id).await;
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+
+        let attachment = Attachment {
+            _id: ObjectId::new(),
+            resource: target.resource.to_string(),
+            resource_id: target.resource_id,
+            kind: target.kind.as_str().to_string(),
+            filename: file.filename,
+            content_type: file.content_type.to_string(),
+            length: file.bytes.len() as i64,
+            sha256: hex(&Sha256::digest(&file.bytes)),
+            file_id,
+            thumbnail_id,
+            width: file.dimensions.map(|(width, _)| width as i64),
+            height: file.dimensions.map(|(_, height)| height as i64),
+            uploaded_at: DateTime::now(),
+            uploaded_by: actor.to_string(),
+        };
+
+        // the GridFS files are removed if the attachment cannot be stored: nothing would reference them
+        if let Err(e) = db.get_attachments_collection().insert_one(&attachment).await {
+            delete_file(db, file_id).await;
+            if let Some(thumbnail_id) = thumbnail_id {
+                delete_file(db, thumbnail_id).await;
+            }
+            return Err(AppError::DatabaseError(format!("Failed to store the attachment: {}", e)));
+        }
+        Ok(attachment)
+    }
+
+    // REF: GridFS -> https://www.mongodb.com/docs/drivers/rust/current/fundamentals/gridfs/
+    async fn write_file(db: &AppDatabase, filename: &str, bytes: &[u8]) -> Result<ObjectId, AppError> {
+        let file_id = ObjectId::new();
+        let mut upload = db.get_uploads_bucket()
+            .open_upload_stream(filename)
+            .id(Bson::ObjectId(file_id))
+            .await?;
+        upload.write_all(bytes).await?;
+        upload.close().await?;
+        Ok(file_id)
+    }
+
+    async fn delete_file(db: &AppDatabase, file_id: ObjectId) {
+        if let Err(e) = db.get_uploads_bucket().delete(Bson::ObjectId(file_id)).await {
+            warn!("Could not delete the GridFS file {}: {}", file_id.to_hex(), e);
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // READ the attachments of a resource, oldest first
+    pub async fn read_attachments(db: &AppDatabase, target: AttachmentTarget) -> Result<Vec<Attachment>, AppError> {
+        let mut result_cursor = db.get_attachments_collection()
+            .find(target.filter())
+            .sort(doc! { "uploaded_at": 1, "_id": 1 })
+            .await?;
+
+        let mut vec_of_attachments = Vec::<Attachment>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(attachment) => vec_of_attachments.push(attachment),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Attachment entries from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_attachments)
+    }
+
+    // READ one attachment of a resource
+    pub async fn read_attachment(db: &AppDatabase, target: AttachmentTarget, attachment_id: &str) -> Result<Attachment, AppError> {
+        let obj_id = match ObjectId::parse_str(attachment_id) {
+            Ok(id) => id,
+            Err(_) => return Err(AppError::InvalidId),
+        };
+        let mut filter = target.filter();
+        filter.insert("_id", obj_id);
+
+        match db.get_attachments_collection().find_one(filter).await? {
+            Some(attachment) => Ok(attachment),
+            None => Err(AppError::NotFound),
+        }
+    }
+
+    // DELETE an attachment and its GridFS files
+    pub async fn delete_attachment(db: &AppDatabase, target: AttachmentTarget, attachment_id: &str, actor: &str) -> Result<String, AppError> {
+        let attachment = read_attachment(db, target, attachment_id).await?;
+
+        let result = db.get_attachments_collection().delete_one(doc! { "_id": attachment._id }).await?;
+        if result.deleted_count == 0 {
+            return Err(AppError::NotFound);   // deleted in the meantime
+        }
+        delete_file(db, attachment.file_id).await;
+        if let Some(thumbnail_id) = attachment.thumbnail_id {
+            delete_file(db, thumbnail_id).await;
+        }
+
+        audit::record_delete(db, "attachments", attachment._id, actor, &attachment).await;
+        Ok(attachment._id.to_hex())
+    }
+
+    // DELETE every attachment (photos and documents) of a deleted dog or sitter, with their GridFS files
+    // the resource is already gone: failures are logged, like auth::delete_credentials
+    pub async fn delete_attachments_of(db: &AppDatabase, resource: &str, resource_id: ObjectId, actor: &str) {
+        let attachments = match db.get_attachments_collection().find(doc! { "resource": resource, "resource_id": resource_id }).await {
+            Ok(cursor) => cursor.collect::<Vec<_>>().await,
+            Err(e) => {
+                warn!("Could not read the attachments of {} {}: {}", resource, resource_id.to_hex(), e);
+                return;
+            }
+        };
+        for attachment in attachments {
+            let attachment = match attachment {
+                Ok(attachment) => attachment,
+                Err(e) => {
+                    warn!("Could not read an attachment of {} {}: {}", resource, resource_id.to_hex(), e);
+                    continue;
+                }
+            };
+            match db.get_attachments_collection().delete_one(doc! { "_id": attachment._id }).await {
+                Ok(result) if result.deleted_count > 0 => {
+                    delete_file(db, attachment.file_id).await;
+                    if let Some(thumbnail_id) = attachment.thumbnail_id {
+                        delete_file(db, thumbnail_id).await;
+                    }
+                    audit::record_delete(db, "attachments", attachment._id, actor, &attachment).await;
+                }
+                Ok(_) => {}   // deleted in the meantime
+                Err(e) => warn!("Could not delete the attachment {}: {}", attachment._id.to_hex(), e),
+            }
+        }
+    }
+
+    // Length of a GridFS file (thumbnails have no Attachment of their own)
+    pub async fn file_length(db: &AppDatabase, file_id: ObjectId) -> Result<u64, AppError> {
+        match db.get_uploads_bucket().find_one(doc! { "_id": file_id }).await? {
+            Some(file) => Ok(file.length),
+            None => Err(AppError::NotFound),
+        }
+    }
+
+    // Whole GridFS file in memory, for small files only (thumbnails)
+    pub async fn read_file(db: &AppDatabase, file_id: ObjectId) -> Result<Vec<u8>, AppError> {
+        let mut download = db.get_uploads_bucket().open_download_stream(Bson::ObjectId(file_id)).await?;
+        let mut bytes = Vec::new();
+        download.read_to_end(&mut bytes).await?;
+        Ok(bytes)
+    }
+
+    // Bytes 'first'..='last' of a GridFS file, streamed chunk by chunk
+    pub async fn read_file_range(db: &AppDatabase, file_id: ObjectId, first: u64, last: u64) -> Result<impl Stream<Item = Result<Bytes, actix_web::Error>> + 'static, AppError> {
+        let file = match db.get_uploads_bucket().find_one(doc! { "_id": file_id }).await? {
+            Some(file) => file,
+            None => return Err(AppError::NotFound),
+        };
+        let chunk_size = file.chunk_size_bytes as u64;
+
+        // only the chunks holding the range
+        let cursor = db.get_upload_chunks_collection()
+            .find(doc! { "files_id": file_id, "n": { "$gte": (first / chunk_size) as i64, "$lte": (last / chunk_size) as i64 } })
+            .sort(doc! { "n": 1 })
+            .await?;
+
+        Ok(cursor.map(move |result| {
+            let chunk = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            let n = chunk.get_i32("n").map_err(actix_web::error::ErrorInternalServerError)? as u64;
+            let data = chunk.get_binary_generic("data").map_err(actix_web::error::ErrorInternalServerError)?;
+
+            // part of this chunk inside the range
+            let chunk_start = n * chunk_size;
+            let from = first.saturating_sub(chunk_start) as usize;
+            let to = ((last + 1 - chunk_start) as usize).min(data.len());
+            Ok(Bytes::copy_from_slice(&data[from.min(to)..to]))
+        }))
+    }
@@ -0,0 +1,156 @@
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
+use futures::StreamExt;
+use log::error;
+use serde::Serialize;
+
+use crate::{app_errors::errors::AppError,
+            models::audit_model::{AuditEntry, AuditQuery}};
+use crate::services::{db::AppDatabase, listing};
+
+
+    // -------------------------------------------
+    // Audit log: who changed what and when
+    // -------------------------------------------
+    // Every create/update/delete of services::{owners, dogs, sitters, bookings} appends an AuditEntry,
+    // as do the exports and erasures of the personal data of an owner (services/privacy.rs).
+    // The entry is written right after the change; failing to write it is logged, it does not undo the change.
+
+    pub const RESOURCES: [&str; 4] = ["owners", "dogs", "sitters", "bookings"];
+
+    const DEFAULT_QUERY_LIMIT: i64 = 100;
+    const MAX_QUERY_LIMIT: i64 = 1000;
+
+    // bookkeeping fields are already in the entry itself (actor, timestamp), no need to repeat them in the diff
+    const IGNORED_FIELDS: [&str; 2] = ["updated_at", "updated_by"];
+
+    // create: every field goes from null to its value
+    pub async fn record_create<T: Serialize>(db: &AppDatabase, resource: &str, resource_id: ObjectId, actor: &str, created: &T) {
+        let changes = match bson::to_document(created) {
+            Ok(document) => diff(&Document::new(), &document),
+            Err(e) => return error!("Audit: cannot serialize created {} {}: {}", resource, resource_id, e),
+        };
+        record(db, resource, resource_id, "create", actor, changes).await;
+    }
+
+    // update: before/after of the fields written by the $set
+    pub async fn record_update<T: Serialize>(db: &AppDatabase, resource: &str, resource_id: ObjectId, actor: &str, before: &T, update_fields: &Document) {
+        let changes = match bson::to_document(before) {
+            Ok(document) => diff(&document, update_fields),
+            Err(e) => return error!("Audit: cannot serialize updated {} {}: {}", resource, resource_id, e),
+        };
+        record(db, resource, resource_id, "update", actor, changes).await;
+    }
+
+    // delete: every field goes from its value to null
+    pub async fn record_delete<T: Serialize>(db: &AppDatabase, resource: &str, resource_id: ObjectId, actor: &str, deleted: &T) {
+        let changes = match bson::to_document(deleted) {
+            Ok(document) => {
+                let removed: Document = document.keys().map(|key| (key.clone(), Bson::Null)).collect();
+                diff(&document, &removed)
+            }
+            Err(e) => return error!("Audit: cannot serialize deleted {} {}: {}", resource, resource_id, e),
+        };
+        record(db, resource, resource_id, "delete", actor, changes).await;
+    }
+
+    // export, erase: a request about the personal data of an owner (see services/privacy.rs), with what was done
+    pub async fn record_request(db: &AppDatabase, resource: &str, resource_id: ObjectId, action: &str, actor: &str, details: Document) {
+        record(db, resource, resource_id, action, actor, details).await;
+    }
+
+    // Erasure: the values of 'fields' are removed from the history of these documents, the entries themselves
+    // (who did what, when) stay. Returns the number of entries changed.
+    pub async fn redact(db: &AppDatabase, resource: &str, resource_ids: &[ObjectId], fields: &[&str]) -> Result<u64, AppError> {
+        let unset: Document = fields.iter().map(|field| (format!("changes.{}", field), Bson::String(String::new()))).collect();
+        let result = db.get_audit_collection()
+            .update_many(doc! { "resource": resource, "resource_id": { "$in": resource_ids } }, doc! { "$unset": unset })
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    // { field: { before, after } } for every field of 'after' (missing 'before' values are null)
+    fn diff(before: &Document, after: &Document) -> Document {
+        after
+            .iter()
+            .filter(|(field, _)| !IGNORED_FIELDS.contains(&field.as_str()))
+            .map(|(field, after_value)| {
+                let before_value = before.get(field).cloned().unwrap_or(Bson::Null);
+                (field.clone(), Bson::Document(doc! { "before": before_value, "after": after_value.clone() }))
+            })
+            .collect()
+    }
+
+    async fn record(db: &AppDatabase, resource: &str, resource_id: ObjectId, action: &str, actor: &str, changes: Document) {
+        let entry = AuditEntry {
+            _id: ObjectId::new(),
+            resource: resource.to_string(),
+            resource_id,
+            action: action.to_string(),
+            actor: actor.to_string(),
+            timestamp: DateTime::now(),
+            changes,
+        };
+        if let Err(e) = db.get_audit_collection().insert_one(&entry).await {
+            error!("Audit: failed to record {} of {} {} by {}: {}", action, resource, resource_id.to_hex(), actor, e);
+        }
+    }
+
+    // READ the history of one document, oldest change first
+    pub async fn read_history(db: &AppDatabase, resource: &str, resource_id: &str) -> Result<Vec<AuditEntry>, AppError> {
+        if !RESOURCES.contains(&resource) {
+            return Err(AppError::NotFound);
+        }
+        let obj_id = match ObjectId::parse_str(resource_id) {
+            Ok(id) => id,
+            Err(_) => return Err(AppError::InvalidId),
+        };
+
+        let filter = doc! { "resource": resource, "resource_id": obj_id };
+        find_entries(db, filter, doc! { "timestamp": 1, "_id": 1 }, None).await
+    }
+
+    // READ the history of several documents, e.g. an owner with its dogs and bookings, oldest change first
+    pub async fn read_histories(db: &AppDatabase, documents: &[(&str, &[ObjectId])]) -> Result<Vec<AuditEntry>, AppError> {
+        let any_of: Vec<Document> = documents
+            .iter()
+            .filter(|(_, resource_ids)| !resource_ids.is_empty())
+            .map(|(resource, resource_ids)| doc! { "resource": *resource, "resource_id": { "$in": *resource_ids } })
+            .collect();
+        if any_of.is_empty() {
+            return Ok(Vec::new());
+        }
+        find_entries(db, doc! { "$or": any_of }, doc! { "timestamp": 1, "_id": 1 }, None).await
+    }
+
+    // READ entries by actor / resource / action / time range, most recent first
+    pub async fn query_audit_log(db: &AppDatabase, audit_query: &AuditQuery) -> Result<Vec<AuditEntry>, AppError> {
+        let mut filter = doc! {};
+        if let Some(actor) = &audit_query.actor { filter.insert("actor", actor); }
+        if let Some(resource) = &audit_query.resource { filter.insert("resource", resource); }
+        if let Some(action) = &audit_query.action { filter.insert("action", action); }
+
+        let mut time_range = doc! {};
+        if let Some(from) = &audit_query.from { time_range.insert("$gte", listing::parse_datetime(from)?); }
+        if let Some(to) = &audit_query.to { time_range.insert("$lte", listing::parse_datetime(to)?); }
+        if !time_range.is_empty() { filter.insert("timestamp", time_range); }
+
+        let limit = audit_query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+        find_entries(db, filter, doc! { "timestamp": -1, "_id": -1 }, Some(limit)).await
+    }
+
+    async fn find_entries(db: &AppDatabase, filter: Document, sort: Document, limit: Option<i64>) -> Result<Vec<AuditEntry>, AppError> {
+        let mut find = db.get_audit_collection().find(filter).sort(sort);
+        if let Some(limit) = limit {
+            find = find.limit(limit);
+        }
+        let mut result_cursor = find.await?;
+
+        let mut vec_of_entries = Vec::<AuditEntry>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(entry) => vec_of_entries.push(entry),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Audit entries from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_entries)
+    }
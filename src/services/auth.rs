@@ -0,0 +1,545 @@
+use std::sync::OnceLock;
+
+use argon2::{password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString}, Argon2};
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use log::{error, warn};
+use mongodb::{options::ReturnDocument, ClientSession, Collection};
+use rand::{rngs::OsRng, RngCore};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use validator::Validate;
+
+use crate::{app_errors::errors::AppError,
+            config::AuthConfig,
+            models::auth_model::{AccessClaims, Credential, LoginRequest, LogoutRequest, Role, RefreshToken, RevokedToken, TokenResponse}};
+use crate::services::db::{self, AppDatabase};
+
+
+    // -------------------------------------------
+    // Authentication
+    // -------------------------------------------
+    // Owners and sitters get a credential when they sign up with a password (POST /owners, POST /sitters),
+    // accounts created before can be given one with `cargo run -- set-password <owner|sitter> <id>`.
+    // Staff accounts (dispatchers, admins) are created with `cargo run -- create-staff <dispatcher|admin> <email>`.
+    //  - POST /auth/login     email + password -> access token (JWT, ACCESS_TOKEN_TTL_S) + refresh token (REFRESH_TOKEN_TTL_S)
+    //  - POST /auth/refresh   refresh token -> new access token + new refresh token, the old one cannot be used again
+    //  - POST /auth/logout    revokes the access token and the session (or every session) of its refresh token
+    //  - GET /auth/oidc/login single sign-on with the company identity provider, same tokens (see services/oidc.rs)
+    //  - POST /auth/password-reset  a forgotten password is replaced through a link sent by email (see services/account_emails.rs)
+    // Handlers get the caller with the Principal / Actor extractors (see routes/actor.rs),
+    // what its role may read and change is decided in services/policy.rs.
+
+    const MIN_PASSWORD_LENGTH: usize = 12;
+    const MAX_PASSWORD_LENGTH: usize = 128;
+    const REFRESH_TOKEN_BYTES: usize = 32;
+    const INVALID_LOGIN: &str = "invalid email or password";
+    const INVALID_REFRESH_TOKEN: &str = "invalid or expired refresh token";
+
+    // -------------------------------------------
+    // Credentials
+    // -------------------------------------------
+
+    // CREATE the credential of a new owner or sitter (sign-up), the email must not be used by another account
+    pub async fn create_credential(db: &AppDatabase, role: Role, principal_id: ObjectId, email: &str, password: &str) -> Result<Credential, AppError> {
+        let login = normalize_login(email)?;
+        let password_hash = hash_password(password).await?;
+
+        let now = DateTime::now();
+        let credential = Credential { _id: ObjectId::new(), principal_id, role, login, password_hash, created_at: now, updated_at: now };
+        match db.get_credentials_collection().insert_one(&credential).await {
+            Ok(_) => Ok(credential),
+            Err(e) if db::is_duplicate_key(&e) => Err(AppError::Conflict("an account already uses this email".to_string())),
+            Err(e) => Err(AppError::DatabaseError(format!("Failed to store the credential: {}", e))),
+        }
+    }
+
+    // CREATE a staff account (dispatcher or admin): no owner or sitter behind it, the credential is the account
+    pub async fn create_staff(db: &AppDatabase, role: Role, email: &str, password: &str) -> Result<Credential, AppError> {
+        if !role.is_staff() {
+            return Err(AppError::ParseError("owners and sitters sign up with POST /owners and POST /sitters".to_string()));
+        }
+        create_credential(db, role, ObjectId::new(), email, password).await
+    }
+
+    // SET the password of an existing account (creates the credential, with its current email, when missing)
+    // every session of the account ends: its refresh tokens are revoked
+    pub async fn set_password(db: &AppDatabase, role: Role, principal_id: ObjectId, password: &str) -> Result<(), AppError> {
+        let email = match role {
+            Role::Owner => db.get_owners_collection().find_one(doc! { "_id": principal_id }).await?.map(|owner| owner.email),
+            Role::Sitter => db.get_sitters_collection().find_one(doc! { "_id": principal_id }).await?.map(|sitter| sitter.email),
+            // staff accounts only exist as credentials (see create_staff)
+            Role::Dispatcher | Role::Admin => db.get_credentials_collection()
+                .find_one(doc! { "principal_id": principal_id, "role": role.as_str() })
+                .await?
+                .map(|credential| credential.login),
+            Role::Integration => None,   // API keys, no password
+        };
+        let login = normalize_login(&email.ok_or(AppError::NotFound)?)?;
+        let password_hash = hash_password(password).await?;
+
+        let now = DateTime::now();
+        let result = db.get_credentials_collection()
+            .update_one(
+                doc! { "principal_id": principal_id },
+                doc! {
+                    "$set": { "password_hash": password_hash, "updated_at": now },
+                    "$setOnInsert": { "_id": ObjectId::new(), "role": role.as_str(), "login": login, "created_at": now },
+                },
+            )
+            .upsert(true)
+            .await;
+        match result {
+            Ok(_) => revoke_all_sessions(db, principal_id).await,
+            Err(e) if db::is_duplicate_key(&e) => Err(AppError::Conflict("another account already uses this email".to_string())),
+            Err(e) => Err(AppError::DatabaseError(format!("Failed to store the credential: {}", e))),
+        }
+    }
+
+    // DELETE the credential and the sessions of a deleted owner or sitter
+    // access tokens already issued stay valid until they expire (ACCESS_TOKEN_TTL_S)
+    pub async fn delete_credentials(db: &AppDatabase, principal_id: ObjectId) {
+        if let Err(e) = db.get_credentials_collection().delete_one(doc! { "principal_id": principal_id }).await {
+            error!("Could not delete the credential of {}: {}", principal_id.to_hex(), e);
+        }
+        if let Err(e) = db.get_refresh_tokens_collection().delete_many(doc! { "principal_id": principal_id }).await {
+            error!("Could not delete the refresh tokens of {}: {}", principal_id.to_hex(), e);
+        }
+    }
+
+    // UPDATE an owner or sitter (find_one_and_update, previous state returned) whose email may change:
+    // its login changes in the same write, so login and password reset follow the new address.
+    // With transactions both are written together; without, the login is changed first and put back when the
+    // update does not match (version conflict). A login taken by another account fails with a duplicate key.
+    pub async fn update_principal<T>(db: &AppDatabase, collection: &Collection<T>, principal_id: ObjectId, filter: Document, update: Document, new_email: Option<&str>) -> Result<Option<T>, mongodb::error::Error>
+    where T: DeserializeOwned + Send + Sync {
+        let new_login = match new_email {
+            Some(email) => email.trim().to_lowercase(),
+            None => return collection.find_one_and_update(filter, update).return_document(ReturnDocument::Before).await,
+        };
+
+        if db.transactions() {
+            let mut session = db.start_session().await?;
+            session.start_transaction().await?;
+            let result = match collection.find_one_and_update(filter, update).return_document(ReturnDocument::Before).session(&mut session).await {
+                Ok(Some(previous)) => change_login(db, principal_id, &new_login, Some(&mut session)).await.map(|_| Some(previous)),
+                other => other,
+            };
+            return match result {
+                Ok(Some(previous)) => session.commit_transaction().await.map(|_| Some(previous)),
+                other => {
+                    let _ = session.abort_transaction().await;   // may already be aborted by the server
+                    other
+                }
+            };
+        }
+
+        let previous_login = change_login(db, principal_id, &new_login, None).await?;
+        let result = collection.find_one_and_update(filter, update).return_document(ReturnDocument::Before).await;
+        if !matches!(result, Ok(Some(_))) {
+            if let Some(previous_login) = previous_login {
+                if let Err(e) = change_login(db, principal_id, &previous_login, None).await {
+                    error!("Could not put back the login of {} after a failed email change: {}", principal_id.to_hex(), e);
+                }
+            }
+        }
+        result
+    }
+
+    // returns the previous login (None: the account has no password login)
+    async fn change_login(db: &AppDatabase, principal_id: ObjectId, login: &str, session: Option<&mut ClientSession>) -> Result<Option<String>, mongodb::error::Error> {
+        let mut update = db.get_credentials_collection()
+            .find_one_and_update(doc! { "principal_id": principal_id }, doc! { "$set": { "login": login, "updated_at": DateTime::now() } })
+            .return_document(ReturnDocument::Before);
+        if let Some(session) = session {
+            update = update.session(session);
+        }
+        Ok(update.await?.map(|credential| credential.login))
+    }
+
+    // emails are compared case-insensitively
+    fn normalize_login(email: &str) -> Result<String, AppError> {
+        let login = email.trim().to_lowercase();
+        match login.contains('@') && login.len() >= 3 {
+            true => Ok(login),
+            false => Err(AppError::ParseError("a valid email is required to log in".to_string())),
+        }
+    }
+
+    // -------------------------------------------
+    // Passwords (Argon2id, default parameters of the argon2 crate)
+    // -------------------------------------------
+    // hashing takes tens of milliseconds of CPU on purpose: it runs on the blocking thread pool
+
+    pub async fn hash_password(password: &str) -> Result<String, AppError> {
+        let length = password.chars().count();
+        if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+            return Err(AppError::ParseError(format!("the password must have {} to {} characters", MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH)));
+        }
+        let password = password.to_string();
+        actix_web::rt::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default().hash_password(password.as_bytes(), &salt).map(|hash| hash.to_string())
+        })
+        .await
+        .map_err(|e| AppError::DatabaseError(format!("Password hashing failed: {}", e)))?
+        .map_err(|e| AppError::DatabaseError(format!("Password hashing failed: {}", e)))
+    }
+
+    async fn verify_password(password_hash: String, password: &str) -> bool {
+        let password = password.to_string();
+        actix_web::rt::task::spawn_blocking(move || {
+            match PasswordHash::new(&password_hash) {
+                Ok(parsed_hash) => Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok(),
+                Err(e) => {
+                    error!("Unreadable password hash: {}", e);
+                    false
+                }
+            }
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    // hash checked for unknown emails, so that they take as long as a wrong password
+    fn dummy_hash() -> &'static str {
+        static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+        DUMMY_HASH.get_or_init(|| {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(random_hex(16).as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .unwrap_or_default()
+        })
+    }
+
+    // -------------------------------------------
+    // Login, refresh, logout
+    // -------------------------------------------
+
+    pub async fn login(db: &AppDatabase, config: &AuthConfig, request: LoginRequest) -> Result<TokenResponse, AppError> {
+        request.validate().map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        let login = request.email.trim().to_lowercase();
+        let credential = db.get_credentials_collection().find_one(doc! { "login": &login }).await?;
+        let password_hash = match &credential {
+            Some(credential) => credential.password_hash.clone(),
+            None => dummy_hash().to_string(),
+        };
+        let verified = verify_password(password_hash, &request.password).await;
+
+        match credential {
+            Some(credential) if verified => issue_tokens(db, config, credential.principal_id, credential.role, ObjectId::new()).await,
+            _ => Err(AppError::Unauthorized(INVALID_LOGIN.to_string())),
+        }
+    }
+
+    // Rotation: a refresh token is used once. The same token seen twice means it was stolen (or the client
+    // retried with a stale one): the session is revoked and both parties have to log in again.
+    pub async fn refresh(db: &AppDatabase, config: &AuthConfig, refresh_token: &str) -> Result<TokenResponse, AppError> {
+        let token_hash = hash_token(refresh_token);
+        let now = DateTime::now();
+        let refresh_tokens = db.get_refresh_tokens_collection();
+
+        let rotated = refresh_tokens
+            .find_one_and_update(
+                doc! { "token_hash": &token_hash, "rotated_at": null, "revoked_at": null, "expires_at": { "$gt": now } },
+                doc! { "$set": { "rotated_at": now } },
+            )
+            .await?;
+        if let Some(token) = rotated {
+            return issue_tokens(db, config, token.principal_id, token.role, token.family_id).await;
+        }
+
+        if let Some(token) = refresh_tokens.find_one(doc! { "token_hash": &token_hash }).await? {
+            if token.rotated_at.is_some() && token.revoked_at.is_none() {
+                warn!("Refresh token of {} {} used twice, revoking its session {}", token.role.as_str(), token.principal_id.to_hex(), token.family_id.to_hex());
+                refresh_tokens
+                    .update_many(doc! { "family_id": token.family_id, "revoked_at": null }, doc! { "$set": { "revoked_at": now } })
+                    .await?;
+            }
+        }
+        Err(AppError::Unauthorized(INVALID_REFRESH_TOKEN.to_string()))
+    }
+
+    // the access token of the request is revoked until it expires, then the session of the refresh token
+    // (when it belongs to the same account), or every session with 'all'
+    // 'token_id' and 'token_expires_at' are the jti and exp claims of the access token (see routes/actor.rs)
+    pub async fn logout(db: &AppDatabase, principal_id: ObjectId, token_id: &str, token_expires_at: i64, request: LogoutRequest) -> Result<(), AppError> {
+        let revoked = RevokedToken { _id: token_id.to_string(), principal_id, expires_at: DateTime::from_millis(token_expires_at * 1000) };
+        if let Err(e) = db.get_revoked_tokens_collection().insert_one(&revoked).await {
+            if !db::is_duplicate_key(&e) {   // already revoked
+                return Err(AppError::DatabaseError(format!("Failed to revoke the access token: {}", e)));
+            }
+        }
+
+        if request.all {
+            return revoke_all_sessions(db, principal_id).await;
+        }
+        if let Some(refresh_token) = request.refresh_token {
+            let filter = doc! { "token_hash": hash_token(&refresh_token), "principal_id": principal_id };
+            if let Some(token) = db.get_refresh_tokens_collection().find_one(filter).await? {
+                db.get_refresh_tokens_collection()
+                    .update_many(doc! { "family_id": token.family_id, "revoked_at": null }, doc! { "$set": { "revoked_at": DateTime::now() } })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn revoke_all_sessions(db: &AppDatabase, principal_id: ObjectId) -> Result<(), AppError> {
+        db.get_refresh_tokens_collection()
+            .update_many(doc! { "principal_id": principal_id, "revoked_at": null }, doc! { "$set": { "revoked_at": DateTime::now() } })
+            .await?;
+        Ok(())
+    }
+
+    // -------------------------------------------
+    // Tokens
+    // -------------------------------------------
+
+    // also used by the OpenID Connect login (see services/oidc.rs)
+    pub async fn issue_tokens(db: &AppDatabase, config: &AuthConfig, principal_id: ObjectId, role: Role, family_id: ObjectId) -> Result<TokenResponse, AppError> {
+        let now = DateTime::now();
+        let access_ttl = config.access_token_ttl.as_secs() as i64;
+        let refresh_ttl = config.refresh_token_ttl.as_secs() as i64;
+
+        let claims = AccessClaims {
+            sub: principal_id.to_hex(),
+            role,
+            jti: ObjectId::new().to_hex(),
+            iss: config.issuer.clone(),
+            iat: now.timestamp_millis() / 1000,
+            exp: now.timestamp_millis() / 1000 + access_ttl,
+        };
+        let access_token = sign_access_token(config, &claims)?;
+
+        let refresh_token = random_hex(REFRESH_TOKEN_BYTES);
+        let stored_token = RefreshToken {
+            _id: ObjectId::new(),
+            token_hash: hash_token(&refresh_token),
+            family_id,
+            principal_id,
+            role,
+            issued_at: now,
+            expires_at: DateTime::from_millis(now.timestamp_millis() + refresh_ttl * 1000),
+            rotated_at: None,
+            revoked_at: None,
+        };
+        db.get_refresh_tokens_collection().insert_one(&stored_token).await?;
+
+        Ok(TokenResponse {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: access_ttl,
+            refresh_token,
+            refresh_expires_in: refresh_ttl,
+            principal_id: principal_id.to_hex(),
+            role,
+        })
+    }
+
+    fn sign_access_token(config: &AuthConfig, claims: &AccessClaims) -> Result<String, AppError> {
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &EncodingKey::from_secret(&config.jwt_secret))
+            .map_err(|e| AppError::DatabaseError(format!("Failed to sign the access token: {}", e)))
+    }
+
+    // Signature, issuer and expiry of the access token, then the revocation list (one lookup per request)
+    pub async fn verify_access_token(db: &AppDatabase, config: &AuthConfig, token: &str) -> Result<AccessClaims, AppError> {
+        let claims = decode_access_token(config, token)?;
+        if db.get_revoked_tokens_collection().find_one(doc! { "_id": &claims.jti }).await?.is_some() {
+            return Err(AppError::Unauthorized("the access token was revoked".to_string()));
+        }
+        Ok(claims)
+    }
+
+    // Signature, issuer and expiry only: who sent the request, without a database lookup (e.g. the rate limiter)
+    pub fn decode_access_token(config: &AuthConfig, token: &str) -> Result<AccessClaims, AppError> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[&config.issuer]);
+        validation.leeway = 5;
+
+        let claims = jsonwebtoken::decode::<AccessClaims>(token, &DecodingKey::from_secret(&config.jwt_secret), &validation)
+            .map_err(|_| AppError::Unauthorized("invalid or expired access token".to_string()))?
+            .claims;
+        if ObjectId::parse_str(&claims.sub).is_err() {
+            return Err(AppError::Unauthorized("invalid access token".to_string()));
+        }
+        Ok(claims)
+    }
+
+    // refresh tokens (and API keys) are stored as SHA-256: a leak of the collection does not give usable tokens
+    pub fn hash_token(token: &str) -> String {
+        Sha256::digest(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn random_hex(length: usize) -> String {
+        let mut bytes = vec![0u8; length];
+        OsRng.fill_bytes(&mut bytes);
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // Access tokens, passwords and hashes need nothing else; the login and the refresh rotation need MongoDB
+    // (see AppDatabase::init_test): `cargo test -- --ignored`
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL_NO_PAD, Engine};
+
+        use super::*;
+
+        fn auth_config() -> AuthConfig {
+            AuthConfig {
+                jwt_secret: vec![1u8; 32],
+                issuer: "breizh-app-walker-test".to_string(),
+                access_token_ttl: Duration::from_secs(900),
+                refresh_token_ttl: Duration::from_secs(3600),
+            }
+        }
+
+        // claims of an access token issued 'age_s' seconds ago, valid for 'ttl_s'
+        fn claims(age_s: i64, ttl_s: i64) -> AccessClaims {
+            let issued_at = DateTime::now().timestamp_millis() / 1000 - age_s;
+            AccessClaims {
+                sub: ObjectId::new().to_hex(),
+                role: Role::Owner,
+                jti: ObjectId::new().to_hex(),
+                iss: auth_config().issuer,
+                iat: issued_at,
+                exp: issued_at + ttl_s,
+            }
+        }
+
+        #[test]
+        fn access_token_is_decoded_with_its_claims() {
+            let config = auth_config();
+            let claims = claims(0, 900);
+            let token = sign_access_token(&config, &claims).unwrap();
+
+            let decoded = decode_access_token(&config, &token).unwrap();
+            assert_eq!((decoded.sub, decoded.jti, decoded.role), (claims.sub, claims.jti, Role::Owner));
+            assert_eq!(decoded.exp, claims.exp);
+        }
+
+        #[test]
+        fn expired_access_token_is_refused() {
+            let config = auth_config();
+            let expired = sign_access_token(&config, &claims(960, 900)).unwrap();
+            assert!(matches!(decode_access_token(&config, &expired), Err(AppError::Unauthorized(_))));
+            // within the 5 seconds of leeway (clock skew between servers)
+            let just_expired = sign_access_token(&config, &claims(902, 900)).unwrap();
+            assert!(decode_access_token(&config, &just_expired).is_ok());
+        }
+
+        #[test]
+        fn tampered_access_token_is_refused() {
+            let config = auth_config();
+            let token = sign_access_token(&config, &claims(0, 900)).unwrap();
+            let (signed, signature) = token.rsplit_once('.').unwrap();
+
+            // another signature
+            let flipped = if signature.starts_with('A') { "B" } else { "A" };
+            let tampered = format!("{}.{}{}", signed, flipped, &signature[1..]);
+            assert!(matches!(decode_access_token(&config, &tampered), Err(AppError::Unauthorized(_))));
+
+            // other claims (an admin role) under the original signature
+            let admin = AccessClaims { role: Role::Admin, ..claims(0, 900) };
+            let forged_payload = sign_access_token(&config, &admin).unwrap().split('.').nth(1).unwrap().to_string();
+            let header = signed.split('.').next().unwrap();
+            let forged = format!("{}.{}.{}", header, forged_payload, signature);
+            assert!(matches!(decode_access_token(&config, &forged), Err(AppError::Unauthorized(_))));
+
+            // signed with another secret, or for another issuer
+            let other_secret = AuthConfig { jwt_secret: vec![2u8; 32], ..auth_config() };
+            assert!(decode_access_token(&other_secret, &token).is_err());
+            let other_issuer = AuthConfig { issuer: "another-app".to_string(), ..auth_config() };
+            assert!(decode_access_token(&other_issuer, &token).is_err());
+
+            // HS256 only: an unsigned token is refused
+            let unsigned = format!("{}.{}.", BASE64_URL_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#), signed.split('.').nth(1).unwrap());
+            assert!(decode_access_token(&config, &unsigned).is_err());
+        }
+
+        #[test]
+        fn access_token_subject_must_be_an_id() {
+            let config = auth_config();
+            let token = sign_access_token(&config, &AccessClaims { sub: "admin".to_string(), ..claims(0, 900) }).unwrap();
+            assert!(matches!(decode_access_token(&config, &token), Err(AppError::Unauthorized(_))));
+        }
+
+        #[test]
+        fn tokens_are_stored_as_sha256() {
+            assert_eq!(hash_token("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+            let token = random_hex(REFRESH_TOKEN_BYTES);
+            assert_eq!(token.len(), 2 * REFRESH_TOKEN_BYTES);
+            assert_ne!(token, random_hex(REFRESH_TOKEN_BYTES));
+        }
+
+        #[test]
+        fn logins_are_case_insensitive_emails() {
+            assert_eq!(normalize_login("  Someone@Example.COM ").unwrap(), "someone@example.com");
+            assert!(matches!(normalize_login("someone"), Err(AppError::ParseError(_))));
+        }
+
+        #[actix_web::test]
+        async fn passwords_are_hashed_and_verified() {
+            assert!(matches!(hash_password("too short").await, Err(AppError::ParseError(_))));
+            assert!(matches!(hash_password(&"x".repeat(MAX_PASSWORD_LENGTH + 1)).await, Err(AppError::ParseError(_))));
+
+            let password_hash = hash_password("correct horse battery").await.unwrap();
+            assert!(password_hash.starts_with("$argon2id$"));
+            assert!(verify_password(password_hash.clone(), "correct horse battery").await);
+            assert!(!verify_password(password_hash, "correct horse battery!").await);
+            assert!(!verify_password("not a hash".to_string(), "correct horse battery").await);
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn login_checks_the_password() {
+            let db = AppDatabase::init_test().await;
+            let config = auth_config();
+            let email = format!("Login-{}@Example.com", ObjectId::new().to_hex());
+            let credential = create_staff(&db, Role::Dispatcher, &email, "correct horse battery").await.unwrap();
+
+            let login = |email: &str, password: &str| LoginRequest { email: email.to_string(), password: password.to_string() };
+            let tokens = super::login(&db, &config, login(&email.to_uppercase(), "correct horse battery")).await.unwrap();
+            assert_eq!((tokens.principal_id, tokens.role), (credential.principal_id.to_hex(), Role::Dispatcher));
+            assert!(verify_access_token(&db, &config, &tokens.access_token).await.is_ok());
+
+            assert!(matches!(super::login(&db, &config, login(&email, "wrong horse battery")).await, Err(AppError::Unauthorized(_))));
+            assert!(matches!(super::login(&db, &config, login("nobody@example.com", "correct horse battery")).await, Err(AppError::Unauthorized(_))));
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn reused_refresh_token_revokes_the_whole_session() {
+            let db = AppDatabase::init_test().await;
+            let config = auth_config();
+            let principal_id = ObjectId::new();
+            let first = issue_tokens(&db, &config, principal_id, Role::Owner, ObjectId::new()).await.unwrap();
+            let other_session = issue_tokens(&db, &config, principal_id, Role::Owner, ObjectId::new()).await.unwrap();
+
+            // rotation: the new refresh token works once, the old one not any more
+            let second = refresh(&db, &config, &first.refresh_token).await.unwrap();
+            assert_eq!((second.principal_id, second.role), (principal_id.to_hex(), Role::Owner));
+            assert_ne!(second.refresh_token, first.refresh_token);
+
+            // the rotated token seen again (stolen): every token of its session is revoked, the latest one too
+            assert!(matches!(refresh(&db, &config, &first.refresh_token).await, Err(AppError::Unauthorized(_))));
+            assert!(matches!(refresh(&db, &config, &second.refresh_token).await, Err(AppError::Unauthorized(_))));
+            // the other sessions of the account go on
+            assert!(refresh(&db, &config, &other_session.refresh_token).await.is_ok());
+
+            assert!(matches!(refresh(&db, &config, &random_hex(REFRESH_TOKEN_BYTES)).await, Err(AppError::Unauthorized(_))));
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn expired_refresh_token_is_refused() {
+            let db = AppDatabase::init_test().await;
+            let config = AuthConfig { refresh_token_ttl: Duration::ZERO, ..auth_config() };
+            let tokens = issue_tokens(&db, &config, ObjectId::new(), Role::Sitter, ObjectId::new()).await.unwrap();
+            assert!(matches!(refresh(&db, &config, &tokens.refresh_token).await, Err(AppError::Unauthorized(_))));
+        }
+    }
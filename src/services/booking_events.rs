@@ -0,0 +1,427 @@
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use futures::StreamExt;
+use log::warn;
+use mongodb::{error::TRANSIENT_TRANSACTION_ERROR, ClientSession};
+
+use crate::{app_errors::errors::AppError,
+            models::booking_event_model::{BookingEvent, BookingEventKind},
+            models::booking_model::{Booking, BookingResponse},
+            models::outbox_model::OutboxMessage};
+use crate::services::{booking_stream, db::{self, AppDatabase}, outbox, versioning::{self, VersionCheck}};
+
+
+    // -------------------------------------------
+    // Event-sourced bookings
+    // -------------------------------------------
+    // Writes to a booking never $set the 'booking' document directly:
+    //   1. decide: compare the request with the current state and turn it into events (Rescheduled, Cancelled, ...)
+    //   2. append: insert the events in 'booking_events', at the sequences following the current version
+    //   3. project: apply the events to the current state and store the result in 'booking'
+    //      (with the domain events for the outbox, in the same transaction, see services/outbox.rs)
+    // The 'booking' document can always be rebuilt from the stream (see commands/replay.rs).
+    //
+    // Concurrency: (booking_id, sequence) is a unique index, so two writers starting from the same version
+    // cannot both append: the second insert fails and is reported as 412 Precondition Failed.
+    // Same when the projection is no longer at the version the events were decided from: nothing is stored.
+
+    // Turn the requested changes into events, only for values that actually change
+    pub fn decide(current: &Booking, owner: Option<ObjectId>, start_time: Option<DateTime>, duration_minutes: Option<u8>, sitter: Option<ObjectId>, dogs: Option<Vec<ObjectId>>, cancelled: Option<bool>) -> Vec<BookingEventKind> {
+        let mut events = Vec::new();
+
+        if let Some(owner) = owner.filter(|owner| *owner != current.owner) {
+            events.push(BookingEventKind::OwnerChanged { owner });
+        }
+
+        // a new start time and/or duration is one reschedule
+        let new_start_time = start_time.unwrap_or(current.start_time);
+        let new_duration = duration_minutes.unwrap_or(current.duration_minutes);
+        if new_start_time != current.start_time || new_duration != current.duration_minutes {
+            events.push(BookingEventKind::Rescheduled { start_time: new_start_time, duration_minutes: new_duration });
+        }
+
+        if let Some(sitter) = sitter.filter(|sitter| current.sitter != Some(*sitter)) {
+            events.push(BookingEventKind::SitterAssigned { sitter });
+        }
+
+        if let Some(dogs) = dogs.filter(|dogs| *dogs != current.dogs) {
+            events.push(BookingEventKind::DogsChanged { dogs });
+        }
+
+        match cancelled {
+            Some(true) if !current.cancelled => events.push(BookingEventKind::Cancelled),
+            Some(false) if current.cancelled => events.push(BookingEventKind::Reinstated),
+            _ => {}
+        }
+        events
+    }
+
+    // Apply one event to the state of a booking (None: no booking yet, or deleted)
+    pub fn apply(state: Option<Booking>, event: &BookingEvent) -> Option<Booking> {
+        let mut booking = match (&event.event, state) {
+            (BookingEventKind::BookingRequested { owner, start_time, duration_minutes, sitter, dogs }, _) => Booking {
+                _id: event.booking_id,
+                owner: *owner,
+                start_time: *start_time,
+                duration_minutes: *duration_minutes,
+                cancelled: false,
+                sitter: *sitter,
+                dogs: dogs.clone(),
+                walk: None,
+                report_id: None,
+                version: 0,
+                created_at: Some(event.timestamp),
+                updated_at: None,
+                created_by: Some(event.actor.clone()),
+                updated_by: None,
+            },
+            (BookingEventKind::BookingImported { owner, start_time, duration_minutes, cancelled, sitter, dogs, created_at, created_by }, _) => Booking {
+                _id: event.booking_id,
+                owner: *owner,
+                start_time: *start_time,
+                duration_minutes: *duration_minutes,
+                cancelled: *cancelled,
+                sitter: *sitter,
+                dogs: dogs.clone(),
+                walk: None,
+                report_id: None,
+                version: 0,
+                created_at: *created_at,
+                updated_at: None,
+                created_by: created_by.clone(),
+                updated_by: None,
+            },
+            (BookingEventKind::Deleted, _) => return None,
+            // any other event needs an existing booking, a broken stream is skipped (and logged)
+            (_, None) => {
+                warn!("Booking event {} (sequence {}) of booking {} has no booking to apply to", event.event.name(), event.sequence, event.booking_id.to_hex());
+                return None;
+            }
+            (BookingEventKind::Rescheduled { start_time, duration_minutes }, Some(mut booking)) => {
+                booking.start_time = *start_time;
+                booking.duration_minutes = *duration_minutes;
+                booking
+            }
+            (BookingEventKind::OwnerChanged { owner }, Some(mut booking)) => { booking.owner = *owner; booking }
+            (BookingEventKind::SitterAssigned { sitter }, Some(mut booking)) => { booking.sitter = Some(*sitter); booking }
+            (BookingEventKind::DogsChanged { dogs }, Some(mut booking)) => { booking.dogs = dogs.clone(); booking }
+            (BookingEventKind::Cancelled, Some(mut booking)) => { booking.cancelled = true; booking }
+            (BookingEventKind::Reinstated, Some(mut booking)) => { booking.cancelled = false; booking }
+            (BookingEventKind::WalkRecorded { walk }, Some(mut booking)) => { booking.walk = Some(walk.clone()); booking }
+            (BookingEventKind::ReportSubmitted { report_id }, Some(mut booking)) => { booking.report_id = Some(*report_id); booking }
+        };
+
+        // the version of the projection is the sequence of its last event
+        booking.version = event.sequence;
+        booking.updated_at = Some(event.timestamp);
+        booking.updated_by = Some(event.actor.clone());
+        Some(booking)
+    }
+
+    // Current state of a booking from its whole stream
+    pub fn replay(events: &[BookingEvent]) -> Option<Booking> {
+        events.iter().fold(None, apply)
+    }
+
+    // Whole stream of a booking, in order
+    pub async fn load_stream(db: &AppDatabase, booking_id: ObjectId) -> Result<Vec<BookingEvent>, AppError> {
+        let mut cursor = db.get_booking_events_collection()
+            .find(doc! { "booking_id": booking_id })
+            .sort(doc! { "sequence": 1 })
+            .await?;
+
+        let mut events = Vec::new();
+        while let Some(result) = cursor.next().await {
+            events.push(result?);
+        }
+        Ok(events)
+    }
+
+    // Start the stream of a new booking with BookingRequested, then store its projection
+    pub async fn create(db: &AppDatabase, booking: &Booking, actor: &str) -> Result<Booking, AppError> {
+        let requested = BookingEventKind::BookingRequested {
+            owner: booking.owner,
+            start_time: booking.start_time,
+            duration_minutes: booking.duration_minutes,
+            sitter: booking.sitter,
+            dogs: booking.dogs.clone(),
+        };
+        let events = new_events(booking._id, 1, vec![requested], actor);
+
+        let (projection, outbox) = project(None, &events)?;
+        let projection = projection
+            .ok_or_else(|| AppError::DatabaseError("Failed to project new Booking".to_string()))?;
+        execute(db, BookingWrite { events, projection: ProjectionWrite::Insert(projection.clone()), outbox }).await?;
+        Ok(projection)
+    }
+
+    // Append events to the stream of an existing booking and update its projection
+    // 'current' is the projection the events were decided from; returns the new projection (None once Deleted)
+    pub async fn commit(db: &AppDatabase, current: &Booking, events: Vec<BookingEventKind>, actor: &str) -> Result<Option<Booking>, AppError> {
+        let mut stream = Vec::new();
+
+        // bookings written before event sourcing have no stream yet: it starts with a snapshot of the current state
+        let has_stream = db.get_booking_events_collection()
+            .count_documents(doc! { "booking_id": current._id })
+            .await? > 0;
+        if !has_stream {
+            stream.push(BookingEventKind::BookingImported {
+                owner: current.owner,
+                start_time: current.start_time,
+                duration_minutes: current.duration_minutes,
+                cancelled: current.cancelled,
+                sitter: current.sitter,
+                dogs: current.dogs.clone(),
+                created_at: current.created_at,
+                created_by: current.created_by.clone(),
+            });
+        }
+        stream.extend(events);
+
+        // the snapshot takes the current version, the new events follow it
+        let first_sequence = if has_stream { current.version + 1 } else { current.version };
+        let events = new_events(current._id, first_sequence, stream, actor);
+
+        let (projection, outbox) = project(Some(current), &events)?;
+        let filter = versioning::version_filter(current._id, &VersionCheck::OneOf(vec![current.version]));
+        let projection_write = match &projection {
+            Some(booking) => ProjectionWrite::Replace(filter, booking.clone()),
+            None => ProjectionWrite::Delete(filter),
+        };
+
+        execute(db, BookingWrite { events, projection: projection_write, outbox }).await?;
+        Ok(projection)
+    }
+
+    // Events at consecutive sequences, starting at 'first_sequence'
+    fn new_events(booking_id: ObjectId, first_sequence: i64, kinds: Vec<BookingEventKind>, actor: &str) -> Vec<BookingEvent> {
+        let timestamp = DateTime::now();
+        kinds
+            .into_iter()
+            .enumerate()
+            .map(|(position, event)| BookingEvent {
+                _id: ObjectId::new(),
+                booking_id,
+                sequence: first_sequence + position as i64,
+                event,
+                actor: actor.to_string(),
+                timestamp,
+            })
+            .collect()
+    }
+
+    // New projection after the events, and the outbox messages (domain events) they publish
+    // each message carries the booking as it is after its event (as it was before, for booking.deleted),
+    // and 'previous' { owner, sitter } when the event changed them: the parties that lost the booking hear of it too
+    fn project(current: Option<&Booking>, events: &[BookingEvent]) -> Result<(Option<Booking>, Vec<OutboxMessage>), AppError> {
+        let mut state = current.cloned();
+        let mut messages = Vec::new();
+
+        for event in events {
+            let previous = state.clone();
+            state = apply(state, event);
+
+            let event_type = match event.event.domain_event_type() {
+                Some(event_type) => event_type,
+                None => continue,   // internal event (BookingImported), not published
+            };
+            let parties_changed = match (&previous, &state) {
+                (Some(before), Some(after)) => before.owner != after.owner || before.sitter != after.sitter,
+                _ => false,
+            };
+            let previous_parties = previous.as_ref().filter(|_| parties_changed).map(|before| doc! {
+                "owner": before.owner.to_hex(),
+                "sitter": before.sitter.map(|sitter| sitter.to_hex()),
+            });
+            let booking = match state.clone().or(previous) {
+                Some(booking) => booking,
+                None => continue,
+            };
+            let mut payload = doc! {
+                "sequence": event.sequence,
+                "event": bson::to_bson(&event.event).map_err(|e| AppError::ParseError(e.to_string()))?,
+                "booking": bson::to_bson(&BookingResponse::from(booking)).map_err(|e| AppError::ParseError(e.to_string()))?,
+            };
+            if let Some(previous_parties) = previous_parties {
+                payload.insert("previous", previous_parties);
+            }
+            messages.push(outbox::new_message(event_type, "bookings", event.booking_id, payload, &event.actor));
+        }
+        Ok((state, messages))
+    }
+
+    // -------------------------------------------
+    // Writes of one booking change
+    // -------------------------------------------
+    // events + projection + outbox messages are written in ONE transaction when the deployment supports it
+    // (replica set), so a change is never published without being stored, nor stored without being published.
+
+    enum ProjectionWrite {
+        Insert(Booking),
+        Replace(Document, Booking),   // version filter, new state
+        Delete(Document),             // version filter
+    }
+
+    struct BookingWrite {
+        events: Vec<BookingEvent>,
+        projection: ProjectionWrite,
+        outbox: Vec<OutboxMessage>,
+    }
+
+    // a transaction aborted by a transient error (e.g. a write conflict) is run again, at most this many times
+    const MAX_TRANSACTION_ATTEMPTS: usize = 3;
+
+    // a projection that is not at the expected version any more (concurrent write) stores nothing: 412
+    async fn execute(db: &AppDatabase, write: BookingWrite) -> Result<(), AppError> {
+        let result = if db.transactions() {
+            execute_in_transaction(db, &write).await
+        } else {
+            execute_in_sequence(db, &write).await
+        };
+
+        // live subscribers (GET /bookings/stream): without change streams, they are notified from here
+        if matches!(result, Ok(affected) if affected > 0) && !db.change_streams() {
+            booking_stream::notify(db, &write.outbox);
+        }
+
+        // a sequence already taken means a concurrent write on the same booking (412)
+        match result {
+            Ok(0) => Err(AppError::PreconditionFailed),
+            Ok(_) => Ok(()),
+            Err(e) if db::is_duplicate_key(&e) => Err(AppError::PreconditionFailed),
+            Err(e) => Err(AppError::DatabaseError(format!("Failed to write Booking change: {}", e))),
+        }
+    }
+
+    async fn execute_in_transaction(db: &AppDatabase, write: &BookingWrite) -> Result<u64, mongodb::error::Error> {
+        // REF: transactions -> https://www.mongodb.com/docs/drivers/rust/current/fundamentals/transactions/
+        let mut session = db.start_session().await?;
+        let mut attempt = 1;
+        loop {
+            session.start_transaction().await?;
+            let result = match write_all(db, write, Some(&mut session)).await {
+                Ok(0) => session.abort_transaction().await.map(|_| 0),   // stale projection: the events are not kept
+                Ok(affected) => session.commit_transaction().await.map(|_| affected),
+                Err(e) => {
+                    let _ = session.abort_transaction().await;   // may already be aborted by the server
+                    Err(e)
+                }
+            };
+            match result {
+                Err(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR) && attempt < MAX_TRANSACTION_ATTEMPTS => attempt += 1,
+                other => return other,
+            }
+        }
+    }
+
+    // standalone server: the events of a stale projection are deleted again, before anything is published
+    async fn execute_in_sequence(db: &AppDatabase, write: &BookingWrite) -> Result<u64, mongodb::error::Error> {
+        let affected = write_all(db, write, None).await?;
+        if affected == 0 {
+            let event_ids: Vec<ObjectId> = write.events.iter().map(|event| event._id).collect();
+            db.get_booking_events_collection().delete_many(doc! { "_id": { "$in": event_ids } }).await?;
+        }
+        Ok(affected)
+    }
+
+    // returns the number of projection documents written, the outbox messages are only written when it is not 0
+    async fn write_all(db: &AppDatabase, write: &BookingWrite, mut session: Option<&mut ClientSession>) -> Result<u64, mongodb::error::Error> {
+        let mut insert_events = db.get_booking_events_collection().insert_many(&write.events);
+        if let Some(session) = session.as_deref_mut() {
+            insert_events = insert_events.session(session);
+        }
+        insert_events.await?;
+
+        let bookings = db.get_bookings_collection();
+        let affected = match &write.projection {
+            ProjectionWrite::Insert(booking) => {
+                let mut insert = bookings.insert_one(booking);
+                if let Some(session) = session.as_deref_mut() {
+                    insert = insert.session(session);
+                }
+                insert.await?;
+                1
+            }
+            ProjectionWrite::Replace(filter, booking) => {
+                let mut replace = bookings.replace_one(filter.clone(), booking);
+                if let Some(session) = session.as_deref_mut() {
+                    replace = replace.session(session);
+                }
+                replace.await?.matched_count
+            }
+            ProjectionWrite::Delete(filter) => {
+                let mut delete = bookings.delete_one(filter.clone());
+                if let Some(session) = session.as_deref_mut() {
+                    delete = delete.session(session);
+                }
+                delete.await?.deleted_count
+            }
+        };
+
+        if affected == 0 {
+            return Ok(0);
+        }
+        if !write.outbox.is_empty() {
+            let mut insert_messages = db.get_outbox_collection().insert_many(&write.outbox);
+            if let Some(session) = session {
+                insert_messages = insert_messages.session(session);
+            }
+            insert_messages.await?;
+        }
+        Ok(affected)
+    }
+
+    // Fields of the projection changed by a commit, in the { field: value } shape the audit log expects
+    pub fn changed_fields(before: &Booking, after: &Booking) -> Result<Document, AppError> {
+        let before = bson::to_document(before).map_err(|e| AppError::ParseError(e.to_string()))?;
+        let after = bson::to_document(after).map_err(|e| AppError::ParseError(e.to_string()))?;
+        Ok(after
+            .into_iter()
+            .filter(|(field, value)| before.get(field) != Some(value))
+            .collect())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn booking() -> Booking {
+            Booking {
+                _id: ObjectId::new(),
+                owner: ObjectId::new(),
+                start_time: DateTime::now(),
+                duration_minutes: 30,
+                cancelled: false,
+                sitter: Some(ObjectId::new()),
+                dogs: Vec::new(),
+                walk: None,
+                report_id: None,
+                version: 1,
+                created_at: None,
+                updated_at: None,
+                created_by: None,
+                updated_by: None,
+            }
+        }
+
+        #[test]
+        fn messages_name_the_parties_a_change_removed() {
+            let current = booking();
+            let (new_owner, new_sitter) = (ObjectId::new(), ObjectId::new());
+            let events = new_events(current._id, 2, vec![
+                BookingEventKind::OwnerChanged { owner: new_owner },
+                BookingEventKind::SitterAssigned { sitter: new_sitter },
+                BookingEventKind::Cancelled,
+            ], "dispatcher");
+
+            let (projection, messages) = project(Some(&current), &events).unwrap();
+            assert_eq!(projection.map(|booking| (booking.owner, booking.sitter, booking.version)), Some((new_owner, Some(new_sitter), 4)));
+            let previous = messages.iter().map(|message| message.payload.get_document("previous").ok()).collect::<Vec<Option<&Document>>>();
+
+            // each message: the parties just before its own event
+            let sitter = current.sitter.unwrap().to_hex();
+            assert_eq!(previous[0], Some(&doc! { "owner": current.owner.to_hex(), "sitter": &sitter }));
+            assert_eq!(previous[1], Some(&doc! { "owner": new_owner.to_hex(), "sitter": &sitter }));
+            assert_eq!(previous[2], None);   // same parties
+            assert_eq!(messages[2].payload.get_document("booking").unwrap().get_str("owner"), Ok(new_owner.to_hex().as_str()));
+        }
+    }
@@ -0,0 +1,287 @@
+use std::{collections::{HashSet, VecDeque}, sync::Arc, time::Duration};
+
+use actix_web::web::Bytes;
+use bson::{doc, oid::ObjectId, Document};
+use futures::{stream, Stream, StreamExt};
+use log::{info, warn};
+use mongodb::change_stream::event::ResumeToken;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{app_errors::errors::AppError,
+            models::outbox_model::OutboxMessage};
+use crate::services::{db::AppDatabase, outbox_sinks};
+
+
+    // -------------------------------------------
+    // Live booking changes (Server-Sent Events)
+    // -------------------------------------------
+    // Every booking change already writes its domain events in the 'outbox' collection (see services/booking_events.rs),
+    // so the stream is made of those messages:
+    //   - replica set: a change stream on 'outbox' feeds AppDatabase::get_booking_changes (run_change_stream_feed)
+    //   - standalone server: services::booking_events notifies it right after each write (notify)
+    // Each SSE event has the outbox message id as `id:`; a client reconnecting with Last-Event-ID first
+    // receives what it missed, read back from 'outbox', then the live changes.
+    // A stream for an owner (or a sitter) follows its bookings, and also gets the change that gave one of them
+    // to another owner (or sitter): the message names the previous parties (see booking_events::project).
+
+    const KEEP_ALIVE: Duration = Duration::from_secs(15);
+    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+    const RETRY_MS: u64 = 3000;           // reconnection delay suggested to the browser
+    const MAX_REPLAYED: i64 = 1000;       // missed changes sent on resume, the oldest are dropped beyond that
+    const RECENTLY_SENT: usize = 4096;    // ids remembered per client, to skip the changes it already received
+    const CATCH_UP_MARGIN_S: u32 = 60;    // a lagging client re-reads this far before its last change (see next_frame)
+
+    // In-process notification (standalone server, no change streams)
+    pub fn notify(db: &AppDatabase, messages: &[OutboxMessage]) {
+        for message in messages {
+            // no subscriber at the moment is not an error
+            let _ = db.get_booking_changes().send(message.clone());
+        }
+    }
+
+    // Change stream feed, runs for the whole life of the server when the deployment supports change streams
+    // REF: change streams -> https://www.mongodb.com/docs/drivers/rust/current/fundamentals/crud/read-operations/change-streams/
+    pub async fn run_change_stream_feed(db: Arc<AppDatabase>) {
+        if !db.change_streams() {
+            info!("Booking stream fed by in-process notifications (no change streams on this deployment)");
+            return;
+        }
+        info!("Booking stream fed by a change stream on '{}'", db.get_outbox_collection().name());
+
+        let mut resume_token: Option<ResumeToken> = None;
+        loop {
+            let pipeline = [doc! { "$match": { "operationType": "insert", "fullDocument.aggregate": "bookings" } }];
+            let mut watch = db.get_outbox_collection().watch().pipeline(pipeline);
+            if let Some(token) = resume_token.clone() {
+                watch = watch.resume_after(token);
+            }
+
+            match watch.await {
+                Ok(mut change_stream) => {
+                    while let Some(change) = change_stream.next().await {
+                        match change {
+                            Ok(change) => {
+                                if let Some(message) = change.full_document {
+                                    let _ = db.get_booking_changes().send(message);
+                                }
+                                resume_token = change_stream.resume_token();
+                            }
+                            Err(e) => {
+                                warn!("Booking stream: change stream interrupted, resuming: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => warn!("Booking stream: cannot open the change stream, retrying: {}", e),
+            }
+            actix_web::rt::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    // What a client asked to follow
+    #[derive(Debug, Clone)]
+    pub struct StreamFilter {
+        pub owner: Option<ObjectId>,            // only the bookings of this owner
+        pub sitter: Option<ObjectId>,           // only the bookings assigned to this sitter
+        pub last_event_id: Option<ObjectId>,    // resume after this event (Last-Event-ID)
+    }
+
+    struct StreamState {
+        db: Arc<AppDatabase>,
+        receiver: broadcast::Receiver<OutboxMessage>,
+        backlog: VecDeque<OutboxMessage>,       // missed changes, sent before the live ones
+        last_sent: Option<ObjectId>,
+        sent: SentIds,
+        owner: Option<String>,                  // hex, as in the payload
+        sitter: Option<String>,
+        started: bool,
+    }
+
+    // SSE body: `retry:` first, then the missed changes, then the live ones, with a comment every KEEP_ALIVE
+    pub async fn booking_stream(db: Arc<AppDatabase>, filter: StreamFilter) -> Result<impl Stream<Item = Result<Bytes, actix_web::Error>>, AppError> {
+        // subscribe BEFORE reading the backlog, so nothing falls between the two (duplicates are skipped, see SentIds)
+        let receiver = db.get_booking_changes().subscribe();
+        let owner = filter.owner.map(|owner| owner.to_hex());
+        let sitter = filter.sitter.map(|sitter| sitter.to_hex());
+
+        let backlog = match filter.last_event_id {
+            Some(last_event_id) => missed_changes(&db, last_event_id, owner.as_deref(), sitter.as_deref()).await?,
+            None => VecDeque::new(),
+        };
+
+        let state = StreamState { db, receiver, backlog, last_sent: filter.last_event_id, sent: SentIds::default(), owner, sitter, started: false };
+        Ok(stream::unfold(state, next_frame))
+    }
+
+    async fn next_frame(mut state: StreamState) -> Option<(Result<Bytes, actix_web::Error>, StreamState)> {
+        if !state.started {
+            state.started = true;
+            return Some((Ok(Bytes::from(format!("retry: {}\n\n", RETRY_MS))), state));
+        }
+
+        loop {
+            if let Some(message) = state.backlog.pop_front() {
+                if !state.sent.insert(message._id) {
+                    continue;
+                }
+                state.last_sent = Some(message._id);
+                return Some((Ok(Bytes::from(frame(&message))), state));
+            }
+
+            match actix_web::rt::time::timeout(KEEP_ALIVE, state.receiver.recv()).await {
+                Ok(Ok(message)) => {
+                    if !selected(&message, "owner", state.owner.as_deref()) || !selected(&message, "sitter", state.sitter.as_deref()) {
+                        continue;
+                    }
+                    if !state.sent.insert(message._id) {
+                        continue;   // already sent from the backlog
+                    }
+                    state.last_sent = Some(message._id);
+                    return Some((Ok(Bytes::from(frame(&message))), state));
+                }
+                // this client is too slow: catch up from the 'outbox' collection
+                // ObjectIds are only ordered per second and per process, and transactions may commit out of order:
+                // the catch up starts a little before the last change sent, what was already sent is skipped
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    warn!("Booking stream: subscriber lagged by {} change(s), catching up from the outbox", skipped);
+                    let since = match state.last_sent {
+                        Some(last_sent) => earlier_id(last_sent, CATCH_UP_MARGIN_S),
+                        None => continue,
+                    };
+                    match missed_changes(&state.db, since, state.owner.as_deref(), state.sitter.as_deref()).await {
+                        Ok(backlog) => state.backlog = backlog,
+                        Err(e) => warn!("Booking stream: catch up failed: {}", e),
+                    }
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(_) => return Some((Ok(Bytes::from_static(b": keep-alive\n\n")), state)),
+            }
+        }
+    }
+
+    // Booking messages written after 'last_event_id' (ObjectIds grow with time), oldest first
+    async fn missed_changes(db: &AppDatabase, last_event_id: ObjectId, owner: Option<&str>, sitter: Option<&str>) -> Result<VecDeque<OutboxMessage>, AppError> {
+        let mut filter = doc! { "aggregate": "bookings", "_id": { "$gt": last_event_id } };
+        let parties = [("owner", owner), ("sitter", sitter)]
+            .into_iter()
+            .filter_map(|(field, expected)| expected.map(|expected| doc! { "$or": [
+                { format!("payload.booking.{}", field): expected },
+                { format!("payload.previous.{}", field): expected },
+            ] }))
+            .collect::<Vec<Document>>();
+        if !parties.is_empty() {
+            filter.insert("$and", parties);
+        }
+
+        let mut result_cursor = db.get_outbox_collection()
+            .find(filter)
+            .sort(doc! { "_id": -1 })
+            .limit(MAX_REPLAYED)
+            .await?;
+
+        let mut missed = VecDeque::new();
+        while let Some(result) = result_cursor.next().await {
+            missed.push_front(result?);   // read newest first (limit), sent oldest first
+        }
+        Ok(missed)
+    }
+
+    // The ids of the changes last sent to a client, at most RECENTLY_SENT
+    #[derive(Default)]
+    struct SentIds {
+        ids: HashSet<ObjectId>,
+        order: VecDeque<ObjectId>,
+    }
+
+    impl SentIds {
+        // false when the id was already sent
+        fn insert(&mut self, id: ObjectId) -> bool {
+            if !self.ids.insert(id) {
+                return false;
+            }
+            self.order.push_back(id);
+            if self.order.len() > RECENTLY_SENT {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.ids.remove(&oldest);
+                }
+            }
+            true
+        }
+    }
+
+    // lowest ObjectId generated 'seconds' before 'id'
+    fn earlier_id(id: ObjectId, seconds: u32) -> ObjectId {
+        let timestamp = (id.timestamp().timestamp_millis() / 1000) as u32;
+        let mut bytes = [0u8; 12];
+        bytes[..4].copy_from_slice(&timestamp.saturating_sub(seconds).to_be_bytes());
+        ObjectId::from_bytes(bytes)
+    }
+
+    // 'field' of the booking in the message ("owner" or "sitter") is 'expected', or was before the change, when there is one
+    fn selected(message: &OutboxMessage, field: &str, expected: Option<&str>) -> bool {
+        match expected {
+            None => true,
+            Some(expected) => ["booking", "previous"]
+                .into_iter()
+                .any(|part| message.payload.get_document(part).and_then(|booking: &Document| booking.get_str(field)) == Ok(expected)),
+        }
+    }
+
+    // event: created | updated | deleted, data: the message as sent to the other sinks (see services/outbox_sinks.rs)
+    fn frame(message: &OutboxMessage) -> String {
+        let change = match message.event_type.as_str() {
+            "booking.created" => "created",
+            "booking.deleted" => "deleted",
+            _ => "updated",
+        };
+        format!("id: {}\nevent: {}\ndata: {}\n\n", message._id.to_hex(), change, outbox_sinks::message_body(message))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::services::outbox;
+
+        fn message(booking: Document, previous: Option<Document>) -> OutboxMessage {
+            let mut payload = doc! { "sequence": 2, "booking": booking };
+            if let Some(previous) = previous {
+                payload.insert("previous", previous);
+            }
+            outbox::new_message("booking.owner_changed", "bookings", ObjectId::new(), payload, "dispatcher")
+        }
+
+        #[test]
+        fn streams_follow_the_current_and_the_previous_parties() {
+            let changed = message(
+                doc! { "owner": "new-owner", "sitter": "new-sitter" },
+                Some(doc! { "owner": "old-owner", "sitter": "old-sitter" }),
+            );
+            for owner in [None, Some("new-owner"), Some("old-owner")] {
+                assert!(selected(&changed, "owner", owner), "{:?}", owner);
+            }
+            assert!(selected(&changed, "sitter", Some("old-sitter")));
+            assert!(!selected(&changed, "owner", Some("another-owner")));
+            // a field of the other party does not select
+            assert!(!selected(&changed, "owner", Some("old-sitter")));
+
+            let unchanged = message(doc! { "owner": "new-owner", "sitter": null }, None);
+            assert!(selected(&unchanged, "owner", Some("new-owner")));
+            assert!(!selected(&unchanged, "owner", Some("old-owner")));
+            assert!(!selected(&unchanged, "sitter", Some("old-sitter")));
+        }
+
+        #[test]
+        fn recently_sent_ids_are_bounded() {
+            let mut sent = SentIds::default();
+            let first = ObjectId::new();
+            assert!(sent.insert(first));
+            assert!(!sent.insert(first));
+            for _ in 0..RECENTLY_SENT {
+                sent.insert(ObjectId::new());
+            }
+            assert_eq!(sent.ids.len(), RECENTLY_SENT);
+            // forgotten once RECENTLY_SENT newer ids were sent
+            assert!(sent.insert(first));
+        }
+    }
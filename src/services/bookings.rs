@@ -2,48 +2,56 @@
 use futures::stream::StreamExt;
 use chrono::Utc;
 use std::time::SystemTime;
-use mongodb::bson::{Bson,doc, oid::ObjectId,DateTime};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
 use crate::{app_errors::errors::AppError, 
+            models::list_query_model::ListQuery,
+            models::booking_event_model::BookingEventKind,
             models::booking_model::{Booking, BookingUpdateRequest}};
 //use mongodb::Database; 
-use crate::services::db::AppDatabase;
+use crate::services::{audit, booking_events, db::AppDatabase, integrity, listing, policy, versioning::{self, VersionCheck}};
 
 
     // -----------------
     // CRUD FOR Booking
     // -----------------
+    // Bookings are event-sourced: writes append events to 'booking_events' and the 'booking'
+    // document is the projection of those events (see services/booking_events.rs). Reads use the projection.
+
     // CREATE for Booking
-    pub async fn create_booking(db: &AppDatabase, booking: Booking) -> Result<Booking, AppError> {
+    pub async fn create_booking(db: &AppDatabase, booking: Booking, actor: &str) -> Result<Booking, AppError> {
         // REF: mongodb insert_one() -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/insertOne/
     
-            // Execute operation in the DB
-            let booking_collection = db.get_bookings_collection();
+            // the stream starts with BookingRequested; timestamps and actors of the projection come from the event
+            let created_booking = booking_events::create(db, &booking, actor).await?;
 
-            let result = booking_collection
-                    .insert_one(&booking)
-                    .await?;
-                    //.ok()
-                    //.expect("Error creating owner");   
-    
-            //Ok(booking)
-            //  evaluate result and return 
-            match result.inserted_id {
-                Bson::ObjectId(_oid) =>  Ok(booking),  // some id received back 
-                other => Err(AppError::DatabaseError(format!("Failed to Create new Booking: {:?}", other))),
-            }
+            audit::record_create(db, "bookings", created_booking._id, actor, &created_booking).await;
+            Ok(created_booking)
         }
       
         // READ for Booking
-        pub async fn read_bookings(db: &AppDatabase) ->  Result<Vec<Booking>, AppError> {
+        pub async fn read_bookings(db: &AppDatabase, list_query: &ListQuery, scope: &Document) ->  Result<Vec<Booking>, AppError> {
             // REF: find multiple documents -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/find/
             // The find() method returns a Cursor type, which you can iterate through to retrieve individual documents/
             
+            // filters and sort order sent in the query string (see services/listing.rs)
+            let mut selection = listing::selection(list_query)?;
+            selection.filter = policy::restrict(selection.filter, scope);   // only what the caller may see (see services/policy.rs)
+
             // Execute operation in the DB
             let booking_collection = db.get_bookings_collection();
 
-            let mut result_cursor = booking_collection
-                .find(doc!{})
-                .await?;
+            // Lenient mode (LENIENT_READS=true): return the valid entries, skip and log the corrupt ones
+            if db.lenient_reads() {
+                let scan = integrity::scan_collection(booking_collection, selection).await?;
+                integrity::log_skipped("Booking", &scan.corrupt);
+                return Ok(scan.valid);
+            }
+
+            let mut find = booking_collection.find(selection.filter);
+            if let Some(sort) = selection.sort {
+                find = find.sort(sort);
+            }
+            let mut result_cursor = find.await?;
                 //.ok()
                 //.expect("Error while reading bookings from database.");
     
@@ -53,14 +61,14 @@ use crate::services::db::AppDatabase;
             while let Some(result) = result_cursor.next().await {
                 match result {
                     Ok(booking_entry) => vec_of_bookings.push(booking_entry),
-                    Err(e) => return Err(AppError::DatabaseError(format!("Error reading Booking entries from DB: {}" , e.to_string()))),
+                    Err(e) => return Err(AppError::DatabaseError(format!("Error reading Booking entries from DB: {}" , e))),
                 }
             }
             Ok(vec_of_bookings)
         }
     
         // READ single booking
-        pub async fn read_booking(db: &AppDatabase, booking_id: &str) -> Result<Booking , AppError > {
+        pub async fn read_booking(db: &AppDatabase, booking_id: &str, scope: &Document) -> Result<Booking , AppError > {
     
             // Verify/Parse received ID 
             // let obj_id = ObjectId::parse_str(booking_id).expect("Failed parsing Booking id.");
@@ -70,7 +78,7 @@ use crate::services::db::AppDatabase;
             };
     
             // Create query filter
-            let filter = doc! { "_id": obj_id};
+            let filter = policy::restrict(doc! { "_id": obj_id }, scope);
             
             // Execute operation in the DB
             let booking_collection = db.get_bookings_collection();
@@ -90,107 +98,102 @@ use crate::services::db::AppDatabase;
         }
     
         // UPDATE for Booking
-        pub async fn update_booking(db: &AppDatabase, booking_id: &str, booking_update: BookingUpdateRequest) ->   Result<String, AppError>{
+        pub async fn update_booking(db: &AppDatabase, booking_id: &str, booking_update: BookingUpdateRequest, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<Booking, AppError>{
     
-            //let booking_obj_id = ObjectId::parse_str(booking_id).expect("Update Booking: failed parsing Booking id.");
             // Verify/Parse received ID 
             let obj_id = match ObjectId::parse_str(booking_id) {
                 Ok(id) => id,
                 Err(_) => return Err(AppError::InvalidId),
             };
     
-            // Select fields sent in the UpdateRequest
-            let mut update_fields = doc! {};
-           
-            // Select fields sent in the BookingUpdateRequest
-            // REMEMBER : Validate the fields sent to update when required
-            if let Some(owner_str) =   booking_update.owner {
-                 // if some owner value was sent in the request, 
-                // we need to validate it before updating the received value
-               // let received_ownder_id = ObjectId::parse_str(owner_str).expect("Update Booking: failed parsing owner id.");
-               let received_owner  = match ObjectId::parse_str(owner_str) {  // here we validate the received owner id
-                    Ok(id) => id,
+            // Validate the fields sent in the BookingUpdateRequest
+            let owner = match booking_update.owner {
+                Some(owner_str) => match ObjectId::parse_str(owner_str) {  // here we validate the received owner id
+                    Ok(id) => Some(id),
                     Err(e) => return Err(AppError::DatabaseError(format!("Update Failed: invalid owner ID: {}", e ))),
-                };
-                update_fields.insert("owner", received_owner); 
-            }
-            // if received date, validate before inserting
-            if let Some(start_time) = booking_update.start_time {
-                //let chrono_datetime: SystemTime = 
-                //        chrono::DateTime::parse_from_rfc3339(&start_time)
-                //        .map_err(|err| format!("Failed to parse start time: {} ", err)).expect("Failed to parsing start time")
-                //        .with_timezone(&Utc).into();
-                let chrono_datetime: SystemTime = match chrono::DateTime::parse_from_rfc3339(&start_time) {
-                    Ok(dt) => dt.with_timezone(&Utc).into(),
-                    Err(err) if err.to_string().contains("expected date") => {
-                        return Err(AppError::ParseError("Start time must include a date".into()))
-                    }
-                    Err(err) => {
-                        return Err(AppError::ParseError(format!("Failed to parse start time: {}", err)))
-                    }
-                };
-                let bson_start_time = DateTime::from(chrono_datetime);
-    
-                update_fields.insert("start_time", bson_start_time);
+                },
+                None => None,
+            };
+            let sitter = match booking_update.sitter {
+                Some(sitter_str) => match ObjectId::parse_str(sitter_str) {
+                    Ok(id) => Some(id),
+                    Err(e) => return Err(AppError::DatabaseError(format!("Update Failed: invalid sitter ID: {}", e ))),
+                },
+                None => None,
+            };
+            let dogs = match booking_update.dogs {
+                Some(dog_strs) => match dog_strs.iter().map(ObjectId::parse_str).collect::<Result<Vec<ObjectId>, _>>() {
+                    Ok(ids) => Some(ids),
+                    Err(e) => return Err(AppError::DatabaseError(format!("Update Failed: invalid dog ID: {}", e ))),
+                },
+                None => None,
+            };
+            // if received date, validate it
+            let start_time = match booking_update.start_time {
+                Some(start_time) => {
+                    let chrono_datetime: SystemTime = match chrono::DateTime::parse_from_rfc3339(&start_time) {
+                        Ok(dt) => dt.with_timezone(&Utc).into(),
+                        Err(err) if err.to_string().contains("expected date") => {
+                            return Err(AppError::ParseError("Start time must include a date".into()))
+                        }
+                        Err(err) => {
+                            return Err(AppError::ParseError(format!("Failed to parse start time: {}", err)))
+                        }
+                    };
+                    Some(DateTime::from(chrono_datetime))
+                }
+                None => None,
+            };
+
+            // Current state (projection), only in the caller's scope and if it is the version the client has seen
+            let booking_collection = db.get_bookings_collection();
+            let current = match booking_collection.find_one(policy::restrict(doc! { "_id": obj_id }, scope)).await? {
+                Some(booking) => booking,
+                None => return Err(AppError::NotFound),
+            };
+            if !versioning::matches(version_check, current.version) {
+                return Err(AppError::PreconditionFailed);
             }
-    
-            if let Some(duration_minutes) = booking_update.duration_minutes{update_fields.insert("duration_minutes", duration_minutes as u32);}
-    
-            if let Some(cancelled) = booking_update.cancelled {update_fields.insert("cancelled", cancelled);}
-    
-            if update_fields.is_empty() { 
-                return Err(AppError::ParseError("No Fields provided to Updated".to_string()));  // Or return custom error, no fields 
+
+            // Turn the request into events (Rescheduled, SitterAssigned, DogsChanged, Cancelled, ...)
+            let events = booking_events::decide(&current, owner, start_time, booking_update.duration_minutes, sitter, dogs, booking_update.cancelled);
+            if events.is_empty() {
+                return Ok(current);   // nothing changes: no event, same version
             }
-    
-            // Prepare filter and update 
-            let filter = doc! { "_id": obj_id };
-            let update =  doc! { "$set": update_fields };
-           
-            // Execute operation in the DB
-            let booking_collection = db.get_bookings_collection();
 
-            let result = booking_collection
-                .update_one(filter, update)
-                .await;
-                //.ok()
-                //.expect("Error updating owners name.");
-    
-             // evaluate result and return, if update ok, return id of the updated doc
-             match result {
-                Ok(_result) => Ok(obj_id.to_hex()),   
-                Err(e) => Err(AppError::DatabaseError(format!("Failed to Update Booking: {}", e))),
+            // append the events and project them, if update ok, return the updated booking (with its new version)
+            match booking_events::commit(db, &current, events, actor).await? {
+                Some(updated_booking) => {
+                    let update_fields = booking_events::changed_fields(&current, &updated_booking)?;
+                    audit::record_update(db, "bookings", obj_id, actor, &current, &update_fields).await;
+                    Ok(updated_booking)
+                },
+                None => Err(AppError::DatabaseError("Failed to Update Booking: booking was deleted".to_string())),
             }
         }
     
         // DELETE for Booking
-        pub async fn delete_booking(db: &AppDatabase, booking_id: &str) -> Result<String, AppError>{
-            // REF: delete_one() -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/deleteOne/
+        pub async fn delete_booking(db: &AppDatabase, booking_id: &str, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<String, AppError>{
            
-            //let obj_id = ObjectId::parse_str(booking_id).expect("Failed to parse booking_id");   // parse ObjectId
             // Verify/Parse received ID 
             let obj_id = match ObjectId::parse_str(booking_id) {
                 Ok(id) => id,
                 Err(_) => return Err(AppError::InvalidId),
             };
     
-            // create query filter
-            let filter = doc! { "_id": obj_id, }; // query filter
-    
-            // Execute operation in the DB
+            // Current state (projection), only in the caller's scope and if it is the version the client has seen
             let booking_collection = db.get_bookings_collection();
-
-            let result = booking_collection
-                    .delete_one(filter)
-                    .await;
-                    //.ok()
-                    //.expect("Error deleting booking");
-    
-             // evaluate result and return, if delete ok, return id of the updated doc 
-            match result {
-                Ok(delete_result) if delete_result.deleted_count >= 1 => Ok(obj_id.to_hex()),
-                Ok(delete_result) if delete_result.deleted_count == 0 => Err(AppError::NotFound),
-                Ok(_) => Err(AppError::InternalError),
-                Err(db_error) => Err(AppError::DatabaseError(format!("Failed to Delete Booking: {}", db_error))),
+            let current = match booking_collection.find_one(policy::restrict(doc! { "_id": obj_id }, scope)).await? {
+                Some(booking) => booking,
+                None => return Err(AppError::NotFound),
+            };
+            if !versioning::matches(version_check, current.version) {
+                return Err(AppError::PreconditionFailed);
             }
+
+            // the Deleted event closes the stream (kept as history), the projection is removed
+            booking_events::commit(db, &current, vec![BookingEventKind::Deleted], actor).await?;
+            audit::record_delete(db, "bookings", obj_id, actor, &current).await;
+            Ok(obj_id.to_hex())
         }
-    
\ No newline at end of file
+    
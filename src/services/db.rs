@@ -1,11 +1,27 @@
-use crate::models::{booking_model::Booking, 
+use crate::models::{api_key_model::ApiKey,
+                     attachment_model::Attachment,
+                     auth_model::{Credential, RefreshToken, RevokedToken},
+                     audit_model::AuditEntry,
+                     booking_event_model::BookingEvent,
+                     booking_model::Booking, 
+                     email_token_model::EmailToken,
+                     oidc_model::{ExternalIdentity, OidcLogin},
+                     outbox_model::OutboxMessage,
+                     rate_limit_model::RateLimitBucket,
+                     track_model::TrackPoint,
+                     walk_report_model::WalkReport,
+                     webhook_model::{WebhookDelivery, WebhookSubscription},
                      dog_model::Dog, 
                      owner_model::Owner, 
+                     schema::CollectionSchema,
                      sitter_model::Sitter};
 
-use log::{info,error};
-use mongodb::{Client, Collection};
-use std::{env, process};
+use log::{info,error,warn};
+use mongodb::{bson::{doc, Document}, error::ErrorKind, gridfs::GridFsBucket, options::{GridFsBucketOptions, IndexOptions}, Client, ClientSession, Collection, Database, IndexModel};
+use std::{env, process, time::Duration};
+use crate::config::FieldEncryptionConfig;
+use crate::services::field_encryption;
+use tokio::sync::broadcast;
 
 
 
@@ -16,22 +32,56 @@ use std::{env, process};
 // - The routing layer handles interpreting DB errors and mapping them into proper HTTP responses (e.g., 404 Not Found, 500 Internal Server Error).
 // This separation of concerns makes the backend desing much clear. 
 
+const DEFAULT_MONGODB_URI: &str = "mongodb://localhost:27017/?directConnection=true";
+const DATABASE_NAME: &str = "dog_walking";
+const BOOKING_CHANGES_CAPACITY: usize = 1024;
+const UPLOADS_BUCKET: &str = "uploads";   // GridFS bucket of the attachments: 'uploads.files' + 'uploads.chunks'
+
 #[allow(dead_code)]
 pub struct AppDatabase {
+    client: Client,
     booking_collection: Collection<Booking>,
     dog_collection: Collection<Dog>,
     owner_collection: Collection<Owner>,
     sitter_collection: Collection<Sitter>,
+    booking_archive_collection: Collection<Document>,   // bookings archived by the doctor command (raw documents + archive reason)
+    audit_collection: Collection<AuditEntry>,           // append-only audit log (see services/audit.rs)
+    booking_events_collection: Collection<BookingEvent>, // event streams of the bookings, 'booking' is their projection (see services/booking_events.rs)
+    outbox_collection: Collection<OutboxMessage>,        // domain events waiting for the dispatcher (see services/outbox.rs)
+    webhook_subscriptions_collection: Collection<WebhookSubscription>,   // partner webhooks (see services/webhooks.rs)
+    webhook_deliveries_collection: Collection<WebhookDelivery>,
+    track_points_collection: Collection<TrackPoint>,    // walk positions (see services/walk_tracking.rs)
+    attachments_collection: Collection<Attachment>,     // photos and documents (see services/attachments.rs)
+    uploads_bucket: GridFsBucket,                       // their bytes
+    upload_chunks_collection: Collection<Document>,     // the chunks of the bucket, read directly for range downloads
+    walk_reports_collection: Collection<WalkReport>,    // one per booking (see services/walk_reports.rs)
+    credentials_collection: Collection<Credential>,     // login and password hash of the owners and sitters (see services/auth.rs)
+    refresh_tokens_collection: Collection<RefreshToken>,
+    revoked_tokens_collection: Collection<RevokedToken>,   // access tokens revoked before their expiry
+    api_keys_collection: Collection<ApiKey>,            // partner integrations (see services/api_keys.rs)
+    oidc_logins_collection: Collection<OidcLogin>,      // single sign-on logins in progress (see services/oidc.rs)
+    external_identities_collection: Collection<ExternalIdentity>,
+    email_tokens_collection: Collection<EmailToken>,    // email verification and password reset links (see services/account_emails.rs)
+    rate_limits_collection: Collection<RateLimitBucket>,   // rate limits shared by the instances (see services/rate_limiter.rs)
+    transactions: bool,       // multi-document transactions available (replica set or sharded cluster)
+    booking_changes: broadcast::Sender<OutboxMessage>,   // live booking changes for GET /bookings/stream (see services/booking_stream.rs)
+    lenient_reads: bool,      // skip (and report) corrupt documents in list reads instead of failing the whole request
 }
 
 impl AppDatabase {
 
     pub async fn init() -> Self {
 
+        // the keys of the encrypted owner fields, needed to read and write 'owner' (see services/field_encryption.rs)
+        if let Err(e) = field_encryption::init(&FieldEncryptionConfig::from_env()) {
+            error!("Field encryption initialization failed: {}", e);
+            process::exit(1)
+        }
+
         // set URI string to connect into the database
         let uri = match env::var("MONGODB_URI") {
             Ok(v) => v.to_string(),
-            Err(_) => "mongodb://localhost:27017/?directConnection=true".to_string(),
+            Err(_) => DEFAULT_MONGODB_URI.to_string(),
         };
         info!("Initializing database connection in : {} ...", uri);
 
@@ -44,24 +94,285 @@ impl AppDatabase {
                 process::exit(1) // Exit with error code
             }
         };
+        Self::open(client, DATABASE_NAME).await
+    }
+
+    // Test database: the tests that need MongoDB (TEST_MONGODB_URI, default: the local server) use 'dog_walking_test',
+    // with test keys for the owner fields. They are #[ignore]d, run them with `cargo test -- --ignored`:
+    // no server answering is a failure, never a pass
+    #[cfg(test)]
+    pub async fn init_test() -> Self {
+        field_encryption::init(&field_encryption::test_config()).expect("test field encryption keys");
+
+        let uri = env::var("TEST_MONGODB_URI").unwrap_or_else(|_| format!("{}&serverSelectionTimeoutMS=2000", DEFAULT_MONGODB_URI));
+        let client = Client::with_uri_str(&uri).await.unwrap_or_else(|e| panic!("invalid TEST_MONGODB_URI {}: {}", uri, e));
+        if let Err(e) = client.database("admin").run_command(doc! { "ping": 1 }).await {
+            panic!("no MongoDB at {} ({}), set TEST_MONGODB_URI", uri, e);
+        }
+        Self::open(client, "dog_walking_test").await
+    }
 
+    async fn open(client: Client, database_name: &str) -> Self {
         // Gets a handle to a database specified by name
         // we dont need to use match here This does not verify the DB exists.
 		// The database will only be created (or an error triggered) when you actually perform an operation, like inserting or querying.
-        let db = client.database("dog_walking");
+        let db = client.database(database_name);
+
+        // apply the $jsonSchema validators generated from our models (see models/schema.rs)
+        // a failure here (e.g. missing privileges) is logged but does not stop the server
+        apply_schema_validator::<Booking>(&db).await;
+        apply_schema_validator::<Owner>(&db).await;
+        apply_schema_validator::<Dog>(&db).await;
+        apply_schema_validator::<Sitter>(&db).await;
+        apply_schema_validator::<AuditEntry>(&db).await;
+        apply_schema_validator::<BookingEvent>(&db).await;
+        apply_schema_validator::<OutboxMessage>(&db).await;
+        apply_schema_validator::<WebhookSubscription>(&db).await;
+        apply_schema_validator::<WebhookDelivery>(&db).await;
+        apply_schema_validator::<TrackPoint>(&db).await;
+        apply_schema_validator::<Attachment>(&db).await;
+        apply_schema_validator::<WalkReport>(&db).await;
+        apply_schema_validator::<Credential>(&db).await;
+        apply_schema_validator::<RefreshToken>(&db).await;
+        apply_schema_validator::<RevokedToken>(&db).await;
+        apply_schema_validator::<ApiKey>(&db).await;
+        apply_schema_validator::<OidcLogin>(&db).await;
+        apply_schema_validator::<ExternalIdentity>(&db).await;
+        apply_schema_validator::<EmailToken>(&db).await;
+        apply_schema_validator::<RateLimitBucket>(&db).await;
 
         // set collections 
-        let booking_collection: Collection<Booking> = db.collection("booking");
-        let owner_collection: Collection<Owner> = db.collection("owner");
-        let dog_collection: Collection<Dog> = db.collection("dog");
-        let sitter_collection: Collection<Sitter> = db.collection("sitter");
+        let booking_collection: Collection<Booking> = db.collection(Booking::COLLECTION_NAME);
+        let owner_collection: Collection<Owner> = db.collection(Owner::COLLECTION_NAME);
+        // emails and phones are encrypted: owners are found (and kept unique by email) through their blind indexes
+        // (owners written before get theirs from `cargo run -- reencrypt-owners`, duplicates are fixed by `doctor --fix`)
+        let email_index = IndexModel::builder()
+            .keys(doc! { "email_index": 1 })
+            .options(IndexOptions::builder()
+                .unique(true)
+                .partial_filter_expression(doc! { "email_index": { "$type": "string" } })
+                .build())
+            .build();
+        let phone_index = IndexModel::builder()
+            .keys(doc! { "phone_index": 1 })
+            .build();
+        if let Err(e) = owner_collection.create_indexes([email_index, phone_index]).await {
+            warn!("Could not create the indexes of '{}': {}", Owner::COLLECTION_NAME, e);
+        }
+        let dog_collection: Collection<Dog> = db.collection(Dog::COLLECTION_NAME);
+        let sitter_collection: Collection<Sitter> = db.collection(Sitter::COLLECTION_NAME);
+        let booking_archive_collection: Collection<Document> = db.collection("booking_archive");
+        let audit_collection: Collection<AuditEntry> = db.collection(AuditEntry::COLLECTION_NAME);
+        let booking_events_collection: Collection<BookingEvent> = db.collection(BookingEvent::COLLECTION_NAME);
+
+        // one event per (booking, sequence): concurrent appends at the same version are rejected by MongoDB
+        let sequence_index = IndexModel::builder()
+            .keys(doc! { "booking_id": 1, "sequence": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = booking_events_collection.create_index(sequence_index).await {
+            warn!("Could not create the unique (booking_id, sequence) index on '{}': {}", BookingEvent::COLLECTION_NAME, e);
+        }
+
+        let outbox_collection: Collection<OutboxMessage> = db.collection(OutboxMessage::COLLECTION_NAME);
+        // the dispatcher looks for the oldest due pending message
+        let due_index = IndexModel::builder()
+            .keys(doc! { "status": 1, "next_attempt_at": 1 })
+            .build();
+        if let Err(e) = outbox_collection.create_index(due_index).await {
+            warn!("Could not create the (status, next_attempt_at) index on '{}': {}", OutboxMessage::COLLECTION_NAME, e);
+        }
 
+        let webhook_subscriptions_collection: Collection<WebhookSubscription> = db.collection(WebhookSubscription::COLLECTION_NAME);
+        let webhook_deliveries_collection: Collection<WebhookDelivery> = db.collection(WebhookDelivery::COLLECTION_NAME);
+        // one delivery per (subscription, outbox message), even if the message reaches the webhooks sink twice
+        let fan_out_index = IndexModel::builder()
+            .keys(doc! { "subscription_id": 1, "event_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let delivery_due_index = IndexModel::builder()
+            .keys(doc! { "status": 1, "next_attempt_at": 1 })
+            .build();
+        if let Err(e) = webhook_deliveries_collection.create_indexes([fan_out_index, delivery_due_index]).await {
+            warn!("Could not create the indexes of '{}': {}", WebhookDelivery::COLLECTION_NAME, e);
+        }
+
+        let track_points_collection: Collection<TrackPoint> = db.collection(TrackPoint::COLLECTION_NAME);
+        // a track is read in order, one booking at a time
+        let track_index = IndexModel::builder()
+            .keys(doc! { "booking_id": 1, "recorded_at": 1 })
+            .build();
+        if let Err(e) = track_points_collection.create_index(track_index).await {
+            warn!("Could not create the (booking_id, recorded_at) index on '{}': {}", TrackPoint::COLLECTION_NAME, e);
+        }
+
+        let attachments_collection: Collection<Attachment> = db.collection(Attachment::COLLECTION_NAME);
+        // attachments are listed per resource
+        let attachments_index = IndexModel::builder()
+            .keys(doc! { "resource": 1, "resource_id": 1, "kind": 1, "uploaded_at": 1 })
+            .build();
+        if let Err(e) = attachments_collection.create_index(attachments_index).await {
+            warn!("Could not create the (resource, resource_id, kind, uploaded_at) index on '{}': {}", Attachment::COLLECTION_NAME, e);
+        }
+        // GridFS creates the indexes of its collections on the first upload
+        let uploads_bucket = db.gridfs_bucket(GridFsBucketOptions::builder().bucket_name(UPLOADS_BUCKET.to_string()).build());
+        let upload_chunks_collection: Collection<Document> = db.collection(&format!("{}.chunks", UPLOADS_BUCKET));
+
+        let walk_reports_collection: Collection<WalkReport> = db.collection(WalkReport::COLLECTION_NAME);
+        // a booking has at most one report, found by its share token for the public page
+        let report_booking_index = IndexModel::builder()
+            .keys(doc! { "booking_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let report_token_index = IndexModel::builder()
+            .keys(doc! { "share_token": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = walk_reports_collection.create_indexes([report_booking_index, report_token_index]).await {
+            warn!("Could not create the indexes of '{}': {}", WalkReport::COLLECTION_NAME, e);
+        }
+
+        let credentials_collection: Collection<Credential> = db.collection(Credential::COLLECTION_NAME);
+        // one login per account: an email cannot be used by two accounts, an account has one credential
+        let login_index = IndexModel::builder()
+            .keys(doc! { "login": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let principal_index = IndexModel::builder()
+            .keys(doc! { "principal_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = credentials_collection.create_indexes([login_index, principal_index]).await {
+            warn!("Could not create the indexes of '{}': {}", Credential::COLLECTION_NAME, e);
+        }
+
+        let refresh_tokens_collection: Collection<RefreshToken> = db.collection(RefreshToken::COLLECTION_NAME);
+        // tokens are found by hash, revoked per family or per account, and removed by MongoDB once expired
+        let token_hash_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let family_index = IndexModel::builder()
+            .keys(doc! { "family_id": 1 })
+            .build();
+        let token_principal_index = IndexModel::builder()
+            .keys(doc! { "principal_id": 1 })
+            .build();
+        let refresh_expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        if let Err(e) = refresh_tokens_collection.create_indexes([token_hash_index, family_index, token_principal_index, refresh_expiry_index]).await {
+            warn!("Could not create the indexes of '{}': {}", RefreshToken::COLLECTION_NAME, e);
+        }
+
+        let revoked_tokens_collection: Collection<RevokedToken> = db.collection(RevokedToken::COLLECTION_NAME);
+        // a revoked access token is only kept until it would have expired anyway
+        let revoked_expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        if let Err(e) = revoked_tokens_collection.create_index(revoked_expiry_index).await {
+            warn!("Could not create the (expires_at) TTL index on '{}': {}", RevokedToken::COLLECTION_NAME, e);
+        }
+
+        let api_keys_collection: Collection<ApiKey> = db.collection(ApiKey::COLLECTION_NAME);
+        // keys are found by their prefix (the part before the secret)
+        let prefix_index = IndexModel::builder()
+            .keys(doc! { "prefix": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = api_keys_collection.create_index(prefix_index).await {
+            warn!("Could not create the (prefix) unique index on '{}': {}", ApiKey::COLLECTION_NAME, e);
+        }
+
+        let oidc_logins_collection: Collection<OidcLogin> = db.collection(OidcLogin::COLLECTION_NAME);
+        // a login not completed in time is removed by MongoDB
+        let login_expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        if let Err(e) = oidc_logins_collection.create_index(login_expiry_index).await {
+            warn!("Could not create the (expires_at) TTL index on '{}': {}", OidcLogin::COLLECTION_NAME, e);
+        }
+
+        let external_identities_collection: Collection<ExternalIdentity> = db.collection(ExternalIdentity::COLLECTION_NAME);
+        // one link per user of an identity provider
+        let subject_index = IndexModel::builder()
+            .keys(doc! { "issuer": 1, "subject": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = external_identities_collection.create_index(subject_index).await {
+            warn!("Could not create the (issuer, subject) unique index on '{}': {}", ExternalIdentity::COLLECTION_NAME, e);
+        }
+
+        let email_tokens_collection: Collection<EmailToken> = db.collection(EmailToken::COLLECTION_NAME);
+        // the links of an account are invalidated together, and removed by MongoDB once expired
+        let email_token_principal_index = IndexModel::builder()
+            .keys(doc! { "principal_id": 1, "purpose": 1 })
+            .build();
+        let email_token_expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        if let Err(e) = email_tokens_collection.create_indexes([email_token_principal_index, email_token_expiry_index]).await {
+            warn!("Could not create the indexes of '{}': {}", EmailToken::COLLECTION_NAME, e);
+        }
+
+        let rate_limits_collection: Collection<RateLimitBucket> = db.collection(RateLimitBucket::COLLECTION_NAME);
+        // a bucket that would be full again is the same as no bucket: MongoDB removes it
+        let rate_limit_expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        if let Err(e) = rate_limits_collection.create_index(rate_limit_expiry_index).await {
+            warn!("Could not create the TTL index on '{}': {}", RateLimitBucket::COLLECTION_NAME, e);
+        }
+
+        // transactions need a replica set (or mongos); a standalone server still works, without atomic outbox writes
+        let transactions = supports_transactions(&db).await;
+        if !transactions {
+            warn!("MongoDB deployment without transactions (standalone server): booking changes and their outbox messages are written one after the other");
+        }
+
+
+        // opt-in lenient mode for list reads (see services/integrity.rs)
+        let lenient_reads = matches!(env::var("LENIENT_READS").as_deref(), Ok("true") | Ok("1"));
+        if lenient_reads {
+            info!("Lenient reads enabled: corrupt documents will be skipped in list reads");
+        }
+
+        // subscribers that fall behind by more than this many changes catch up from the 'outbox' collection
+        let (booking_changes, _) = broadcast::channel(BOOKING_CHANGES_CAPACITY);
 
         AppDatabase {
+            client,
             booking_collection,
             dog_collection, 
             owner_collection,
             sitter_collection,
+            booking_archive_collection,
+            audit_collection,
+            booking_events_collection,
+            outbox_collection,
+            webhook_subscriptions_collection,
+            webhook_deliveries_collection,
+            track_points_collection,
+            attachments_collection,
+            uploads_bucket,
+            upload_chunks_collection,
+            walk_reports_collection,
+            credentials_collection,
+            refresh_tokens_collection,
+            revoked_tokens_collection,
+            api_keys_collection,
+            oidc_logins_collection,
+            external_identities_collection,
+            email_tokens_collection,
+            rate_limits_collection,
+            transactions,
+            booking_changes,
+            lenient_reads,
         }
     }
 
@@ -81,7 +392,162 @@ impl AppDatabase {
     pub fn get_sitters_collection(&self) -> &Collection<Sitter> {
         &self.sitter_collection
     }
+
+    pub fn get_booking_archive_collection(&self) -> &Collection<Document> {
+        &self.booking_archive_collection
+    }
+
+    pub fn get_audit_collection(&self) -> &Collection<AuditEntry> {
+        &self.audit_collection
+    }
+
+    pub fn get_booking_events_collection(&self) -> &Collection<BookingEvent> {
+        &self.booking_events_collection
+    }
+
+    pub fn get_outbox_collection(&self) -> &Collection<OutboxMessage> {
+        &self.outbox_collection
+    }
+
+    pub fn get_webhook_subscriptions_collection(&self) -> &Collection<WebhookSubscription> {
+        &self.webhook_subscriptions_collection
+    }
+
+    pub fn get_webhook_deliveries_collection(&self) -> &Collection<WebhookDelivery> {
+        &self.webhook_deliveries_collection
+    }
+
+    pub fn get_track_points_collection(&self) -> &Collection<TrackPoint> {
+        &self.track_points_collection
+    }
+
+    pub fn get_attachments_collection(&self) -> &Collection<Attachment> {
+        &self.attachments_collection
+    }
+
+    pub fn get_uploads_bucket(&self) -> &GridFsBucket {
+        &self.uploads_bucket
+    }
+
+    pub fn get_upload_chunks_collection(&self) -> &Collection<Document> {
+        &self.upload_chunks_collection
+    }
+
+    pub fn get_walk_reports_collection(&self) -> &Collection<WalkReport> {
+        &self.walk_reports_collection
+    }
+
+    pub fn get_credentials_collection(&self) -> &Collection<Credential> {
+        &self.credentials_collection
+    }
+
+    pub fn get_refresh_tokens_collection(&self) -> &Collection<RefreshToken> {
+        &self.refresh_tokens_collection
+    }
+
+    pub fn get_revoked_tokens_collection(&self) -> &Collection<RevokedToken> {
+        &self.revoked_tokens_collection
+    }
+
+    pub fn get_api_keys_collection(&self) -> &Collection<ApiKey> {
+        &self.api_keys_collection
+    }
+
+    pub fn get_oidc_logins_collection(&self) -> &Collection<OidcLogin> {
+        &self.oidc_logins_collection
+    }
+
+    pub fn get_external_identities_collection(&self) -> &Collection<ExternalIdentity> {
+        &self.external_identities_collection
+    }
+
+    pub fn get_email_tokens_collection(&self) -> &Collection<EmailToken> {
+        &self.email_tokens_collection
+    }
+
+    pub fn get_rate_limits_collection(&self) -> &Collection<RateLimitBucket> {
+        &self.rate_limits_collection
+    }
+
+    pub fn transactions(&self) -> bool {
+        self.transactions
+    }
+
+    // change streams have the same requirement as transactions (replica set or mongos)
+    pub fn change_streams(&self) -> bool {
+        self.transactions
+    }
+
+    pub fn get_booking_changes(&self) -> &broadcast::Sender<OutboxMessage> {
+        &self.booking_changes
+    }
+
+    pub async fn start_session(&self) -> Result<ClientSession, mongodb::error::Error> {
+        self.client.start_session().await
+    }
+
+    pub fn lenient_reads(&self) -> bool {
+        self.lenient_reads
+    }
     
 
 
 }
+
+// Apply (or refresh) the $jsonSchema validator of a collection
+// REF: collMod -> https://www.mongodb.com/docs/manual/reference/command/collMod/
+// - existing collection: collMod replaces its validator
+// - missing collection (NamespaceNotFound, code 26): create it with the validator
+// validationLevel "strict": inserts AND updates are checked; validationAction "error": invalid writes are rejected
+async fn apply_schema_validator<T: CollectionSchema>(db: &Database) {
+    let collection_name = T::COLLECTION_NAME;
+
+    let result = db
+        .run_command(doc! {
+            "collMod": collection_name,
+            "validator": T::validator(),
+            "validationLevel": "strict",
+            "validationAction": "error",
+        })
+        .await
+        .map(|_| ());
+
+    let result = match result {
+        Err(e) if matches!(*e.kind, ErrorKind::Command(ref command_error) if command_error.code == 26) => {
+            db.create_collection(collection_name)
+                .validator(T::validator())
+                .await
+        }
+        other => other,
+    };
+
+    match result {
+        Ok(_) => info!("Schema validator applied to collection '{}'", collection_name),
+        Err(e) => warn!("Could not apply schema validator to collection '{}': {}", collection_name, e),
+    }
+}
+
+// REF: hello -> https://www.mongodb.com/docs/manual/reference/command/hello/
+// replica set members answer with 'setName', mongos with msg "isdbgrid"
+async fn supports_transactions(db: &Database) -> bool {
+    match db.run_command(doc! { "hello": 1 }).await {
+        Ok(hello) => hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"),
+        Err(e) => {
+            warn!("Could not detect the MongoDB deployment type: {}", e);
+            false
+        }
+    }
+}
+
+// MongoDB duplicate key error (E11000): a unique index rejected the write
+pub fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    match *error.kind {
+        ErrorKind::InsertMany(ref failure) => failure
+            .write_errors
+            .as_ref()
+            .is_some_and(|write_errors| write_errors.iter().any(|write_error| write_error.code == DUPLICATE_KEY)),
+        ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref write_error)) => write_error.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
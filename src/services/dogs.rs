@@ -1,12 +1,14 @@
 
-use bson::{doc, oid::ObjectId, Bson};
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
 use futures::StreamExt;
 
 use crate::{app_errors::errors::AppError, 
-            models::dog_model::{Dog, DogUpdateRequest}};
+            models::list_query_model::ListQuery,
+            models::dog_model::{Dog, DogUpdateRequest, DogWalkStats}};
 
 //use mongodb::Database; 
-use crate::services::db::AppDatabase;
+use crate::services::{attachments, audit, db::AppDatabase, integrity, listing, policy, versioning::{self, VersionCheck}};
+use mongodb::options::ReturnDocument;
 
 
 
@@ -18,7 +20,14 @@ use crate::services::db::AppDatabase;
     // ---------------
 
     // CREATE for Dog
-    pub async fn create_dog(db: &AppDatabase, dog: Dog) -> Result<Dog, AppError> {
+    pub async fn create_dog(db: &AppDatabase, mut dog: Dog, actor: &str) -> Result<Dog, AppError> {
+
+        // bookkeeping: creation/modification timestamps and actors are managed here, never sent by clients
+        let now = DateTime::now();
+        dog.created_at = Some(now);
+        dog.updated_at = Some(now);
+        dog.created_by = Some(actor.to_string());
+        dog.updated_by = Some(actor.to_string());
 
          // Execute operation in the DB
         let dog_collection = db.get_dogs_collection();
@@ -29,34 +38,50 @@ use crate::services::db::AppDatabase;
 
          //  evaluate result and return 
         match result.inserted_id {
-            Bson::ObjectId(_oid) =>  Ok(dog),  
+            Bson::ObjectId(_oid) => {
+                audit::record_create(db, "dogs", dog._id, actor, &dog).await;
+                Ok(dog)
+            },
             other => Err(AppError::DatabaseError(format!("Failed to Create new Dog: {:?}", other))),
         }
     }
 
     // READ for Dog
-    pub async fn read_dogs(db: &AppDatabase) ->  Result<Vec<Dog>, AppError> {
+    pub async fn read_dogs(db: &AppDatabase, list_query: &ListQuery, scope: &Document) ->  Result<Vec<Dog>, AppError> {
+
+        // filters and sort order sent in the query string (see services/listing.rs)
+        let mut selection = listing::selection(list_query)?;
+        selection.filter = policy::restrict(selection.filter, scope);   // only what the caller may see (see services/policy.rs)
 
          // Execute operation in the DB
         let dog_collection = db.get_dogs_collection();
 
-        let mut result_cursor = dog_collection
-            .find(doc!{})
-            .await?;
+        // Lenient mode (LENIENT_READS=true): return the valid entries, skip and log the corrupt ones
+        if db.lenient_reads() {
+            let scan = integrity::scan_collection(dog_collection, selection).await?;
+            integrity::log_skipped("Dog", &scan.corrupt);
+            return Ok(scan.valid);
+        }
+
+        let mut find = dog_collection.find(selection.filter);
+        if let Some(sort) = selection.sort {
+            find = find.sort(sort);
+        }
+        let mut result_cursor = find.await?;
         
         let mut vec_of_dogs = Vec::<Dog>::new();
 
         while let Some(result) = result_cursor.next().await {
             match result {
                 Ok(dog_entry) => vec_of_dogs.push(dog_entry),
-                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Dog entries from DB: {}" , e.to_string()))),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Dog entries from DB: {}" , e))),
             }
         }
         Ok(vec_of_dogs)
     }
 
      // READ single dog
-     pub async fn read_dog(db: &AppDatabase, dog_id: &str) -> Result<Dog , AppError> {
+     pub async fn read_dog(db: &AppDatabase, dog_id: &str, scope: &Document) -> Result<Dog , AppError> {
 
         //let obj_id = ObjectId::parse_str(dog_id).expect("Failed parsing Dog id.");
         // Verify/Parse received ID 
@@ -65,7 +90,7 @@ use crate::services::db::AppDatabase;
             Err(_) => return Err(AppError::InvalidId),
         };
         // Create query filter
-        let filter = doc! { "_id": obj_id};
+        let filter = policy::restrict(doc! { "_id": obj_id }, scope);
 
          // Execute operation in the DB
         let dog_collection = db.get_dogs_collection();
@@ -88,8 +113,53 @@ use crate::services::db::AppDatabase;
 
     }
 
+    // READ walk totals of a Dog
+    // a booking walks the dogs it lists, or all the dogs of its owner when it lists none
+    // 'scope' is the caller's scope on dogs: only the stats of a dog it may see
+    pub async fn read_dog_stats(db: &AppDatabase, dog_id: &str, scope: &Document) -> Result<DogWalkStats, AppError> {
+
+        let dog = read_dog(db, dog_id, scope).await?;
+
+        let filter = doc! {
+            "cancelled": false,
+            "walk": { "$ne": null },
+            "$or": [
+                { "dogs": dog._id },
+                { "owner": dog.owner, "dogs": { "$in": [[], null] } },
+            ],
+        };
+        let mut result_cursor = db.get_bookings_collection().find(filter).await?;
+
+        let mut stats = DogWalkStats { dog_id: dog._id.to_hex(), ..DogWalkStats::default() };
+        let mut timed_distance_m = 0.0;
+        let mut last_walk_at: Option<DateTime> = None;
+        while let Some(result) = result_cursor.next().await {
+            let booking = match result {
+                Ok(booking) => booking,
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Booking entries from DB: {}" , e))),
+            };
+            let Some(walk) = booking.walk else { continue };
+
+            stats.walks += 1;
+            stats.distance_m += walk.distance_m;
+            if let Some(moving_time_s) = walk.moving_time_s {
+                stats.moving_time_s += moving_time_s;
+                timed_distance_m += walk.distance_m;
+            }
+            let walked_at = walk.started_at.unwrap_or(booking.start_time);
+            last_walk_at = last_walk_at.max(Some(walked_at));
+        }
+
+        stats.distance_m = (stats.distance_m * 10.0).round() / 10.0;
+        if stats.moving_time_s > 0 && timed_distance_m > 0.0 {
+            stats.average_pace_s_per_km = Some((stats.moving_time_s as f64 / (timed_distance_m / 1000.0) * 10.0).round() / 10.0);
+        }
+        stats.last_walk_at = last_walk_at.map(|date| date.to_chrono().to_rfc3339());
+        Ok(stats)
+    }
+
     // UPDATE for Dog:
-    pub async fn update_dog(db: &AppDatabase, dog_id: &str, dog_update: DogUpdateRequest) ->  Result<String, AppError>  {
+    pub async fn update_dog(db: &AppDatabase, dog_id: &str, dog_update: DogUpdateRequest, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<Dog, AppError>  {
 
         //let obj_id = ObjectId::parse_str(dog_id).expect("Update Dog: failed parsing Dog id.");
          // Verify/Parse received ID 
@@ -118,29 +188,36 @@ use crate::services::db::AppDatabase;
             //return Ok(false); 
             return Err(AppError::ParseError("No Fields provided to Updated".to_string())); 
         } // this empty field shouldnt happen anymore because I added the request validation in the router
+        update_fields.insert("updated_at", DateTime::now());   // bookkeeping, see create_dog
+        update_fields.insert("updated_by", actor);
     
         // Prepare filter and update 
-        let filter = doc! { "_id": obj_id };
-        let update =  doc! { "$set": update_fields };
+        let filter = policy::restrict(versioning::version_filter(obj_id, version_check), scope);  // only matches the version the client has seen
+        let update =  doc! { "$set": update_fields.clone(), "$inc": { "version": 1 } };
 
         // Execute operation in the DB
         let dog_collection = db.get_dogs_collection();
 
         let result = dog_collection
-            .update_one(filter, update)
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::Before)   // previous state, for the audit log
             .await;
             //.ok()
             //.expect("Error updating Dog.");
 
-        // evaluate result and return, if update ok, return id of the updated doc
+        // evaluate result and return, if update ok, return the updated doc (with its new version)
         match result {
-            Ok(_result) => Ok(obj_id.to_hex()),   
+            Ok(Some(previous_dog)) => {
+                audit::record_update(db, "dogs", obj_id, actor, &previous_dog, &update_fields).await;
+                versioning::apply_update(&previous_dog, &update_fields)
+            },
+            Ok(None) => Err(versioning::missing_or_conflict(dog_collection, obj_id, scope).await),
             Err(e) => Err(AppError::DatabaseError(format!("Failed to Update Dog: {}", e))),
         }
     }
 
     // DELETE for Dog
-    pub async fn delete_dog  (db: &AppDatabase, dog_id: &str) -> Result<String, AppError> {
+    pub async fn delete_dog(db: &AppDatabase, dog_id: &str, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<String, AppError> {
 
         // Verify/Parse received ID 
         //let obj_id = ObjectId::parse_str(dog_id).expect("Failed to parse booking_id"); 
@@ -150,13 +227,13 @@ use crate::services::db::AppDatabase;
         };
         
         // create query filter
-        let filter = doc! { "_id": obj_id, };
+        let filter = policy::restrict(versioning::version_filter(obj_id, version_check), scope);
 
         // Execute operation in the DB
         let dog_collection = db.get_dogs_collection();
         
         let result = dog_collection
-                .delete_one(filter)
+                .find_one_and_delete(filter)
                 .await;
                 //.ok()
                 //.expect("Error deleting Dog");
@@ -164,9 +241,12 @@ use crate::services::db::AppDatabase;
         // evaluate result and return, if delete ok, return id of the updated doc    
         //Ok(result.deleted_count>0)
         match result {
-            Ok(delete_result) if delete_result.deleted_count >= 1 => Ok(obj_id.to_hex()),
-            Ok(delete_result) if delete_result.deleted_count == 0 => Err(AppError::NotFound),
-            Ok(_) => Err(AppError::InternalError),
+            Ok(Some(deleted_dog)) => {
+                audit::record_delete(db, "dogs", obj_id, actor, &deleted_dog).await;
+                attachments::delete_attachments_of(db, "dogs", obj_id, actor).await;   // its photos
+                Ok(obj_id.to_hex())
+            },
+            Ok(None) => Err(versioning::missing_or_conflict(dog_collection, obj_id, scope).await),
             Err(db_error) => Err(AppError::DatabaseError(format!("Failed to Delete Dog: {}", db_error))),
         }
 
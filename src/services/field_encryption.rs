@@ -0,0 +1,363 @@
+use std::{collections::HashMap, fs, io::Write, sync::OnceLock};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use rand::RngCore;
+use ring::{aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN}, rand::{SecureRandom, SystemRandom}};
+use sha2::Sha256;
+
+use crate::config::FieldEncryptionConfig;
+
+
+    // -------------------------------------------
+    // Field encryption: personal data of the owners encrypted by the application (AES-256-GCM)
+    // -------------------------------------------
+    // The fields marked `#[serde(with = "field_encryption::encrypted")]` (models/owner_model.rs) are stored as
+    //   enc:<key id>:<base64 of nonce + ciphertext + tag>
+    // and decrypted when the document is read: MongoDB, its backups and the audit log only hold ciphertext.
+    // Values written before (plaintext) are still read; `cargo run -- reencrypt-owners` encrypts them.
+    //
+    // Keys (FIELD_ENCRYPTION_KEYS, else the keyring file FIELD_KEYRING_FILE, see config.rs):
+    //  - the first encryption key encrypts, every key of the keyring decrypts (the key id is stored with the value)
+    //  - rotation: put a new key first, restart, run `cargo run -- reencrypt-owners`, then remove the old key
+    //  - the blind index key never changes: the stored indexes would not match any more
+    //
+    // Blind index: an encrypted value cannot be searched, so email and phone also get an HMAC-SHA256 of their
+    // normalized value (email_index, phone_index). Lookups compute the HMAC of what they look for, and the unique
+    // index on email_index keeps one owner per email without MongoDB ever seeing the address.
+
+    const PREFIX: &str = "enc:";
+    const BLIND_INDEX_ID: &str = "blind-index";
+    const KEY_BYTES: usize = 32;
+
+    static KEYRING: OnceLock<Keyring> = OnceLock::new();
+
+    // (key id, key) in keyring order
+    type KeyEntries = Vec<(String, Vec<u8>)>;
+
+    struct Keyring {
+        active: String,                       // id of the key encrypting the new values
+        keys: HashMap<String, LessSafeKey>,   // every key that can decrypt, by id
+        blind_index_key: Vec<u8>,
+    }
+
+    // Loads the keys once, before the collections are used (called by AppDatabase::init)
+    pub fn init(config: &FieldEncryptionConfig) -> Result<(), String> {
+        if KEYRING.get().is_some() {
+            return Ok(());
+        }
+        let (entries, blind_index_key) = match &config.keys {
+            Some(keys) => {
+                let blind_index_key = config.blind_index_key.as_deref()
+                    .ok_or("FIELD_BLIND_INDEX_KEY is required with FIELD_ENCRYPTION_KEYS")?;
+                (parse_env_keys(keys)?, decode_key("FIELD_BLIND_INDEX_KEY", blind_index_key)?)
+            },
+            None => read_keyring_file(&config.keyring_file)?,
+        };
+
+        let active = entries.first().map(|(id, _)| id.clone()).ok_or("no field encryption key")?;
+        let mut keys = HashMap::new();
+        for (id, bytes) in entries {
+            let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| format!("invalid field encryption key '{}'", id))?;
+            if keys.insert(id.clone(), LessSafeKey::new(key)).is_some() {
+                return Err(format!("field encryption key '{}' given twice", id));
+            }
+        }
+        info!("Field encryption: {} key(s), new values encrypted with '{}'", keys.len(), active);
+        let _ = KEYRING.set(Keyring { active, keys, blind_index_key });
+        Ok(())
+    }
+
+    fn keyring() -> Result<&'static Keyring, String> {
+        KEYRING.get().ok_or_else(|| "field encryption is not initialized".to_string())
+    }
+
+    pub fn encrypt(plaintext: &str) -> Result<String, String> {
+        let keyring = keyring()?;
+        seal(keyring, &keyring.active, plaintext)
+    }
+
+    fn seal(keyring: &Keyring, key_id: &str, plaintext: &str) -> Result<String, String> {
+        let key = keyring.keys.get(key_id).ok_or_else(|| format!("unknown field encryption key '{}'", key_id))?;
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| "no random nonce available".to_string())?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        // the key id is authenticated too: a value cannot be passed off as sealed by another key
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(key_id.as_bytes()), &mut sealed)
+            .map_err(|_| "encryption failed".to_string())?;
+        Ok(format!("{}{}:{}", PREFIX, key_id, BASE64.encode([nonce.as_slice(), &sealed].concat())))
+    }
+
+    // plaintext values (written before the encryption) are returned as they are
+    pub fn decrypt(stored: &str) -> Result<String, String> {
+        let (key_id, payload) = match stored.strip_prefix(PREFIX).and_then(|sealed| sealed.split_once(':')) {
+            Some(parts) => parts,
+            None => return Ok(stored.to_string()),
+        };
+        let key = keyring()?.keys.get(key_id).ok_or_else(|| format!("unknown field encryption key '{}'", key_id))?;
+        let bytes = BASE64.decode(payload).map_err(|_| "invalid encrypted value".to_string())?;
+        if bytes.len() < NONCE_LEN {
+            return Err("invalid encrypted value".to_string());
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "invalid encrypted value".to_string())?;
+        let mut sealed = sealed.to_vec();
+        let plaintext = key.open_in_place(nonce, Aad::from(key_id.as_bytes()), &mut sealed)
+            .map_err(|_| format!("encrypted value not authentic for key '{}'", key_id))?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| "invalid encrypted value".to_string())
+    }
+
+    // plaintext, or encrypted with another key than the active one (see commands/reencrypt_owners.rs)
+    pub fn needs_reencryption(stored: &str) -> bool {
+        let active = match KEYRING.get() {
+            Some(keyring) => keyring.active.as_str(),
+            None => return false,
+        };
+        match stored.strip_prefix(PREFIX).and_then(|sealed| sealed.split_once(':')) {
+            Some((key_id, _)) => key_id != active,
+            None => true,
+        }
+    }
+
+    // Blind indexes: the field name is part of the HMAC, an email index never matches a phone index
+    pub fn email_index(email: &str) -> Result<String, String> {
+        blind_index("email", &email.trim().to_lowercase())
+    }
+
+    // digits only, with the leading '+' of international numbers: "+33 6 12-34-56-78" and "+33612345678" match
+    pub fn phone_index(phone: &str) -> Result<String, String> {
+        let phone = phone.trim();
+        let digits: String = phone.chars().filter(char::is_ascii_digit).collect();
+        let normalized = if phone.starts_with('+') { format!("+{}", digits) } else { digits };
+        blind_index("phone", &normalized)
+    }
+
+    fn blind_index(field: &str, normalized: &str) -> Result<String, String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&keyring()?.blind_index_key).map_err(|e| e.to_string())?;
+        mac.update(field.as_bytes());
+        mac.update(b"\0");
+        mac.update(normalized.as_bytes());
+        Ok(mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    // "<id>:<base64>,<id>:<base64>" (FIELD_ENCRYPTION_KEYS)
+    fn parse_env_keys(keys: &str) -> Result<KeyEntries, String> {
+        keys.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (id, key) = entry.split_once(':').ok_or("FIELD_ENCRYPTION_KEYS entries are <key id>:<base64 key>")?;
+                Ok((key_id(id)?, decode_key(id, key)?))
+            })
+            .collect()
+    }
+
+    // one "<id> <base64>" per line, '#' comments; the file is created with new keys when missing
+    fn read_keyring_file(path: &str) -> Result<(KeyEntries, Vec<u8>), String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => create_keyring_file(path)?,
+            Err(e) => return Err(format!("cannot read the keyring {}: {}", path, e)),
+        };
+
+        let (mut keys, mut blind_index_key) = (Vec::new(), None);
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (id, key) = line.split_once(char::is_whitespace).ok_or_else(|| format!("invalid line in the keyring {}: <key id> <base64 key>", path))?;
+            let key = decode_key(id, key.trim())?;
+            if id == BLIND_INDEX_ID {
+                blind_index_key = Some(key);
+            } else {
+                keys.push((key_id(id)?, key));
+            }
+        }
+        let blind_index_key = blind_index_key.ok_or_else(|| format!("no '{}' key in the keyring {}", BLIND_INDEX_ID, path))?;
+        Ok((keys, blind_index_key))
+    }
+
+    fn create_keyring_file(path: &str) -> Result<String, String> {
+        let new_key = || {
+            let mut key = vec![0u8; KEY_BYTES];
+            rand::thread_rng().fill_bytes(&mut key);
+            BASE64.encode(key)
+        };
+        let content = format!(
+            "# Field encryption keyring (see services/field_encryption.rs): <key id> <base64 of 32 bytes>\n\
+             # The first key encrypts, every key decrypts. The '{}' key must never change.\n\
+             {} {}\n\
+             key-1 {}\n",
+            BLIND_INDEX_ID, BLIND_INDEX_ID, new_key(), new_key());
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);   // readable by the server only
+        options.open(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("cannot create the keyring {}: {}", path, e))?;
+        warn!("No field encryption keyring: new keys written to {}, back it up (without it the owner data cannot be read)", path);
+        Ok(content)
+    }
+
+    fn key_id(id: &str) -> Result<String, String> {
+        let valid = !id.is_empty() && id != BLIND_INDEX_ID && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!("invalid field encryption key id '{}' (letters, digits, '-' and '_')", id));
+        }
+        Ok(id.to_string())
+    }
+
+    fn decode_key(id: &str, key: &str) -> Result<Vec<u8>, String> {
+        match BASE64.decode(key) {
+            Ok(bytes) if bytes.len() == KEY_BYTES => Ok(bytes),
+            _ => Err(format!("field encryption key '{}' must be {} bytes in base64", id, KEY_BYTES)),
+        }
+    }
+
+    // serde adapters of the encrypted fields: #[serde(with = "field_encryption::encrypted")]
+    pub mod encrypted {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&super::encrypt(value).map_err(serde::ser::Error::custom)?)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+            super::decrypt(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+        }
+    }
+
+    // same for optional fields: #[serde(default, with = "field_encryption::encrypted_option")]
+    pub mod encrypted_option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::encrypted::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|stored| super::decrypt(&stored))
+                .transpose()
+                .map_err(serde::de::Error::custom)
+        }
+    }
+
+    // The keys of the tests (the keyring is loaded once per process, AppDatabase::init_test uses the same):
+    // 'test' encrypts, 'previous' is a key rotated away, 'alias' has the bytes of 'test' under another id
+    #[cfg(test)]
+    pub fn test_config() -> FieldEncryptionConfig {
+        let key = |byte: u8| BASE64.encode([byte; KEY_BYTES]);
+        FieldEncryptionConfig {
+            keys: Some(format!("test:{},previous:{},alias:{}", key(7), key(8), key(7))),
+            blind_index_key: Some(key(9)),
+            keyring_file: String::new(),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn test_keyring() -> &'static Keyring {
+            init(&test_config()).unwrap();
+            keyring().unwrap()
+        }
+
+        // the stored value, its nonce + ciphertext + tag changed by 'change'
+        fn with_payload(stored: &str, change: impl FnOnce(&mut Vec<u8>)) -> String {
+            let (key_id, payload) = stored.strip_prefix(PREFIX).and_then(|sealed| sealed.split_once(':')).unwrap();
+            let mut bytes = BASE64.decode(payload).unwrap();
+            change(&mut bytes);
+            format!("{}{}:{}", PREFIX, key_id, BASE64.encode(bytes))
+        }
+
+        #[test]
+        fn values_are_encrypted_and_decrypted() {
+            test_keyring();
+            let stored = encrypt("jane.doe@example.com").unwrap();
+            assert!(stored.starts_with("enc:test:"));
+            assert!(!stored.contains("jane"));
+            assert_eq!(decrypt(&stored).unwrap(), "jane.doe@example.com");
+            // a new nonce every time: equal values do not give equal ciphertexts
+            assert_ne!(encrypt("jane.doe@example.com").unwrap(), stored);
+
+            assert_eq!(decrypt(&encrypt("").unwrap()).unwrap(), "");
+            // written before the encryption
+            assert_eq!(decrypt("06 12 34 56 78").unwrap(), "06 12 34 56 78");
+        }
+
+        #[test]
+        fn values_of_a_previous_key_are_still_read() {
+            let keyring = test_keyring();
+            let stored = seal(keyring, "previous", "1 rue de Brest").unwrap();
+            assert!(stored.starts_with("enc:previous:"));
+            assert_eq!(decrypt(&stored).unwrap(), "1 rue de Brest");
+
+            assert!(decrypt(&stored.replacen("enc:previous:", "enc:removed:", 1)).unwrap_err().contains("unknown field encryption key"));
+        }
+
+        #[test]
+        fn tampered_values_are_refused() {
+            test_keyring();
+            let stored = encrypt("jane.doe@example.com").unwrap();
+
+            let flipped = with_payload(&stored, |bytes| bytes[NONCE_LEN] ^= 1);
+            assert!(decrypt(&flipped).unwrap_err().contains("not authentic"));
+            let other_nonce = with_payload(&stored, |bytes| bytes[0] ^= 1);
+            assert!(decrypt(&other_nonce).is_err());
+            let truncated = with_payload(&stored, |bytes| bytes.truncate(NONCE_LEN + 4));
+            assert!(decrypt(&truncated).is_err());
+            assert!(decrypt("enc:test:not base64!").is_err());
+        }
+
+        #[test]
+        fn key_id_is_authenticated() {
+            test_keyring();
+            let stored = encrypt("jane.doe@example.com").unwrap();
+            // same key bytes under another id: refused, the id is part of the authenticated data
+            let swapped = stored.replacen("enc:test:", "enc:alias:", 1);
+            assert!(decrypt(&swapped).unwrap_err().contains("not authentic for key 'alias'"));
+            let swapped = stored.replacen("enc:test:", "enc:previous:", 1);
+            assert!(decrypt(&swapped).is_err());
+        }
+
+        #[test]
+        fn values_not_sealed_by_the_active_key_need_reencryption() {
+            let keyring = test_keyring();
+            assert!(!needs_reencryption(&encrypt("jane.doe@example.com").unwrap()));
+            assert!(needs_reencryption(&seal(keyring, "previous", "jane.doe@example.com").unwrap()));
+            assert!(needs_reencryption(&seal(keyring, "alias", "jane.doe@example.com").unwrap()));
+            assert!(needs_reencryption("jane.doe@example.com"));
+        }
+
+        #[test]
+        fn blind_indexes_match_normalized_values() {
+            test_keyring();
+            assert_eq!(phone_index("+33 6 12-34-56-78").unwrap(), phone_index("+33612345678").unwrap());
+            assert_eq!(phone_index(" 06.12.34.56.78 ").unwrap(), phone_index("0612345678").unwrap());
+            assert_ne!(phone_index("+33612345678").unwrap(), phone_index("33612345678").unwrap());
+            assert_ne!(phone_index("0612345678").unwrap(), phone_index("0612345679").unwrap());
+
+            assert_eq!(email_index(" Jane.Doe@Example.COM ").unwrap(), email_index("jane.doe@example.com").unwrap());
+            assert_ne!(email_index("jane.doe@example.com").unwrap(), email_index("john.doe@example.com").unwrap());
+            // an HMAC per field: the same text is not the same index
+            assert_ne!(email_index("0612345678").unwrap(), phone_index("0612345678").unwrap());
+            assert_eq!(email_index("jane.doe@example.com").unwrap().len(), 64);
+        }
+
+        #[test]
+        fn environment_keys_are_checked() {
+            let key = BASE64.encode([1u8; KEY_BYTES]);
+            let entries = parse_env_keys(&format!("new:{}, old:{}", key, key)).unwrap();
+            assert_eq!(entries.iter().map(|(id, _)| id.as_str()).collect::<Vec<&str>>(), ["new", "old"]);
+
+            assert!(parse_env_keys(&key).is_err());                                         // no id
+            assert!(parse_env_keys(&format!("short:{}", BASE64.encode([1u8; 16]))).is_err());   // AES-256 only
+            assert!(parse_env_keys(&format!("{}:{}", BLIND_INDEX_ID, key)).is_err());
+            assert!(parse_env_keys(&format!("a b:{}", key)).is_err());
+        }
+    }
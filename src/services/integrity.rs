@@ -0,0 +1,97 @@
+use bson::{Bson, Document};
+use futures::StreamExt;
+use log::warn;
+use mongodb::Collection;
+use serde::de::DeserializeOwned;
+
+use crate::{app_errors::errors::AppError,
+            models::{integrity_model::{CorruptDocument, IntegrityReport},
+                     schema::CollectionSchema}};
+use crate::services::{db::AppDatabase, listing::ListSelection};
+
+
+    // -------------------------------------------
+    // Document integrity (lenient reads + report)
+    // -------------------------------------------
+    // A typed cursor (Cursor<Booking>) fails as soon as one document does not match the struct,
+    // so here we read the raw BSON documents (Collection<Document>) and deserialise them one by one.
+    // That way a single corrupt document costs us one entry instead of the whole list.
+
+    // Result of a scan: what could be deserialised and what could not
+    pub struct ScanResult<T> {
+        pub valid: Vec<T>,
+        pub corrupt: Vec<CorruptDocument>,
+    }
+
+    // Scan all the documents selected (filter + sort), splitting them into valid entries and corrupt ones.
+    // Errors coming from the database itself (connection, invalid query, ...) are still returned as DatabaseError.
+    pub async fn scan_collection<T>(collection: &Collection<T>, selection: ListSelection) -> Result<ScanResult<T>, AppError>
+    where
+        T: DeserializeOwned + Send + Sync,
+    {
+        let raw_collection = collection.clone_with_type::<Document>();
+        let mut find = raw_collection.find(selection.filter);
+        if let Some(sort) = selection.sort {
+            find = find.sort(sort);
+        }
+        let mut result_cursor = find.await?;
+
+        let mut scan = ScanResult { valid: Vec::new(), corrupt: Vec::new() };
+
+        while let Some(result) = result_cursor.next().await {
+            let raw_document = result?;
+            let id = document_id(&raw_document);
+
+            match bson::from_document::<T>(raw_document) {
+                Ok(entry) => scan.valid.push(entry),
+                Err(e) => scan.corrupt.push(CorruptDocument { _id: id, error: e.to_string() }),
+            }
+        }
+        Ok(scan)
+    }
+
+    // Lenient reads: log every skipped document, then a summary count
+    pub fn log_skipped(resource: &str, corrupt: &[CorruptDocument]) {
+        if corrupt.is_empty() {
+            return;
+        }
+        for entry in corrupt {
+            warn!("Skipping corrupt {} entry {}: {}", resource, entry._id, entry.error);
+        }
+        warn!("Lenient read of {} entries: {} corrupt document(s) skipped (see GET /admin/integrity)", resource, corrupt.len());
+    }
+
+    // Build the integrity report of one resource (owners, dogs, sitters, bookings)
+    pub async fn integrity_report(db: &AppDatabase, resource: &str) -> Result<IntegrityReport, AppError> {
+        match resource {
+            "owners" => build_report(resource, db.get_owners_collection()).await,
+            "dogs" => build_report(resource, db.get_dogs_collection()).await,
+            "sitters" => build_report(resource, db.get_sitters_collection()).await,
+            "bookings" => build_report(resource, db.get_bookings_collection()).await,
+            _ => Err(AppError::NotFound),
+        }
+    }
+
+    async fn build_report<T>(resource: &str, collection: &Collection<T>) -> Result<IntegrityReport, AppError>
+    where
+        T: CollectionSchema + DeserializeOwned + Send + Sync,
+    {
+        let scan = scan_collection(collection, ListSelection::all()).await?;
+
+        Ok(IntegrityReport {
+            resource: resource.to_string(),
+            collection: T::COLLECTION_NAME.to_string(),
+            scanned: scan.valid.len() + scan.corrupt.len(),
+            valid: scan.valid.len(),
+            corrupt: scan.corrupt,
+        })
+    }
+
+    // _id of a raw document, as a string we can report back
+    fn document_id(raw_document: &Document) -> String {
+        match raw_document.get("_id") {
+            Some(Bson::ObjectId(oid)) => oid.to_hex(),
+            Some(other) => other.to_string(),
+            None => "<missing _id>".to_string(),
+        }
+    }
@@ -0,0 +1,71 @@
+use bson::{doc, DateTime, Document};
+use chrono::Utc;
+
+use crate::{app_errors::errors::AppError, models::list_query_model::ListQuery};
+
+    // -------------------------------------------
+    // List endpoints: filtering and sorting
+    // -------------------------------------------
+    // Translates a ListQuery (query string) into the MongoDB filter and sort documents
+    // used by read_owners / read_dogs / read_sitters / read_bookings.
+    // Only the server-managed metadata (created_*/updated_*) can be used for now.
+
+    const SORTABLE_FIELDS: [&str; 4] = ["created_at", "updated_at", "created_by", "updated_by"];
+
+    // What a list read selects from a collection
+    #[derive(Debug, Default)]
+    pub struct ListSelection {
+        pub filter: Document,
+        pub sort: Option<Document>,
+    }
+
+    impl ListSelection {
+        // every document, natural order (used by the doctor and the integrity report)
+        pub fn all() -> Self {
+            ListSelection::default()
+        }
+    }
+
+    pub fn selection(list_query: &ListQuery) -> Result<ListSelection, AppError> {
+        let mut filter = doc! {};
+
+        if let Some(created_by) = &list_query.created_by { filter.insert("created_by", created_by); }
+        if let Some(updated_by) = &list_query.updated_by { filter.insert("updated_by", updated_by); }
+
+        if let Some(range) = date_range(&list_query.created_after, &list_query.created_before)? { filter.insert("created_at", range); }
+        if let Some(range) = date_range(&list_query.updated_after, &list_query.updated_before)? { filter.insert("updated_at", range); }
+
+        let sort = match &list_query.sort {
+            Some(sort) => Some(sort_document(sort)?),
+            None => None,
+        };
+
+        Ok(ListSelection { filter, sort })
+    }
+
+    // "updated_at" -> { updated_at: 1 }, "-updated_at" -> { updated_at: -1 } (+ _id to keep a stable order)
+    fn sort_document(sort: &str) -> Result<Document, AppError> {
+        let (field, direction) = match sort.strip_prefix('-') {
+            Some(field) => (field, -1),
+            None => (sort, 1),
+        };
+        if !SORTABLE_FIELDS.contains(&field) {
+            return Err(AppError::ParseError(format!("Cannot sort by '{}', expected one of: {}", field, SORTABLE_FIELDS.join(", "))));
+        }
+        Ok(doc! { field: direction, "_id": direction })
+    }
+
+    // { $gte: after, $lte: before } with the bounds that were sent
+    fn date_range(after: &Option<String>, before: &Option<String>) -> Result<Option<Document>, AppError> {
+        let mut range = doc! {};
+        if let Some(after) = after { range.insert("$gte", parse_datetime(after)?); }
+        if let Some(before) = before { range.insert("$lte", parse_datetime(before)?); }
+        Ok(if range.is_empty() { None } else { Some(range) })
+    }
+
+    pub fn parse_datetime(value: &str) -> Result<DateTime, AppError> {
+        match chrono::DateTime::parse_from_rfc3339(value) {
+            Ok(datetime) => Ok(DateTime::from_chrono(datetime.with_timezone(&Utc))),
+            Err(err) => Err(AppError::ParseError(format!("Invalid date '{}' (expected RFC3339): {}", value, err))),
+        }
+    }
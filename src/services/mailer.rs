@@ -0,0 +1,217 @@
+use std::{path::PathBuf, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::future::BoxFuture;
+use log::{info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_rustls::{rustls::{self, pki_types::ServerName}, TlsConnector};
+
+use crate::config::MailConfig;
+
+
+    // -------------------------------------------
+    // Mailer: how the emails of the server leave it
+    // -------------------------------------------
+    // Selected with MAIL_TRANSPORT (see config.rs):
+    //   log   the email is written to the application log (local development, default)
+    //   file  one .eml file per email in MAIL_DIR, opens in any mail client (local development, tests)
+    //   smtp  sent through SMTP_HOST (STARTTLS or implicit TLS, AUTH PLAIN)
+    // A mailer returns Ok once the email is accepted (written, or queued by the SMTP server).
+
+    pub trait Mailer: Send + Sync {
+        fn name(&self) -> &'static str;
+        fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>>;
+    }
+
+    // a plain text email to one recipient
+    #[derive(Debug, Clone)]
+    pub struct Email {
+        pub to: String,
+        pub subject: String,
+        pub body: String,
+    }
+
+    pub fn build_mailer(config: &MailConfig) -> Arc<dyn Mailer> {
+        match config.transport.as_str() {
+            "file" => Arc::new(FileMailer { from: config.from.clone(), dir: PathBuf::from(&config.dir) }),
+            "smtp" => Arc::new(SmtpMailer { config: config.clone() }),
+            "log" => Arc::new(LogMailer),
+            other => {
+                warn!("Unknown MAIL_TRANSPORT '{}', emails are written to the log", other);
+                Arc::new(LogMailer)
+            },
+        }
+    }
+
+    // --- log: writes the email in the application log (never fails) ---
+    pub struct LogMailer;
+
+    impl Mailer for LogMailer {
+        fn name(&self) -> &'static str {
+            "log"
+        }
+
+        fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                info!("Email to {} \"{}\":\n{}", email.to, email.subject, email.body);
+                Ok(())
+            })
+        }
+    }
+
+    // --- file: one RFC 5322 message (.eml) per email in a directory ---
+    pub struct FileMailer {
+        from: String,
+        dir: PathBuf,
+    }
+
+    impl Mailer for FileMailer {
+        fn name(&self) -> &'static str {
+            "file"
+        }
+
+        fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                let message = format_message(&self.from, email);
+                let path = self.dir.join(format!("{}-{}.eml", chrono::Utc::now().format("%Y%m%dT%H%M%S%.3f"), crate::services::auth::random_hex(4)));
+                let dir = self.dir.clone();
+                let written = path.clone();
+                actix_web::rt::task::spawn_blocking(move || {
+                    std::fs::create_dir_all(&dir)?;
+                    std::fs::write(&written, message)
+                })
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+                info!("Email to {} \"{}\" written to {}", email.to, email.subject, path.display());
+                Ok(())
+            })
+        }
+    }
+
+    // --- smtp: one connection per email (a few emails an hour: no pool) ---
+    pub struct SmtpMailer {
+        config: MailConfig,
+    }
+
+    impl Mailer for SmtpMailer {
+        fn name(&self) -> &'static str {
+            "smtp"
+        }
+
+        fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                match actix_web::rt::time::timeout(self.config.smtp_timeout, smtp_send(&self.config, email)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(format!("SMTP server {}:{} timed out", self.config.smtp_host, self.config.smtp_port)),
+                }
+            })
+        }
+    }
+
+    trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+    impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+    async fn smtp_send(config: &MailConfig, email: &Email) -> Result<(), String> {
+        let host = config.smtp_host.as_str();
+        let tcp = actix_web::rt::net::TcpStream::connect((host, config.smtp_port)).await
+            .map_err(|e| format!("cannot connect to {}:{}: {}", host, config.smtp_port, e))?;
+        let (mut stream, mut encrypted): (Box<dyn SmtpStream>, bool) = match config.smtp_tls.as_str() {
+            "tls" => (Box::new(tls_connect(host, tcp).await?), true),
+            _ => (Box::new(tcp), false),
+        };
+
+        let mut reader = BufReader::new(&mut stream);
+        expect_reply(&mut reader, 220).await?;
+        command(&mut reader, "EHLO breizh-walker\r\n", 250).await?;
+        drop(reader);
+        if config.smtp_tls == "starttls" {
+            let mut reader = BufReader::new(&mut stream);
+            command(&mut reader, "STARTTLS\r\n", 220).await?;
+            drop(reader);
+            stream = Box::new(tls_connect(host, stream).await?);
+            encrypted = true;
+            command(&mut BufReader::new(&mut stream), "EHLO breizh-walker\r\n", 250).await?;
+        }
+
+        let mut reader = BufReader::new(&mut stream);
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            if !encrypted {
+                return Err("SMTP credentials are only sent over TLS (SMTP_TLS=starttls or tls)".to_string());
+            }
+            let credentials = BASE64.encode(format!("\0{}\0{}", username, password));
+            command(&mut reader, &format!("AUTH PLAIN {}\r\n", credentials), 235).await?;
+        }
+        command(&mut reader, &format!("MAIL FROM:<{}>\r\n", address(&config.from)), 250).await?;
+        command(&mut reader, &format!("RCPT TO:<{}>\r\n", address(&email.to)), 250).await?;
+        command(&mut reader, "DATA\r\n", 354).await?;
+        // dot-stuffing is not needed: the body is base64, no line starts with '.'
+        command(&mut reader, &format!("{}\r\n.\r\n", format_message(&config.from, email)), 250).await?;
+        let _ = reader.get_mut().write_all(b"QUIT\r\n").await;
+        info!("Email to {} \"{}\" sent through {}", email.to, email.subject, host);
+        Ok(())
+    }
+
+    async fn tls_connect<S: AsyncRead + AsyncWrite + Unpin>(host: &str, stream: S) -> Result<tokio_rustls::client::TlsStream<S>, String> {
+        let roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(host.to_string()).map_err(|e| format!("invalid SMTP host {}: {}", host, e))?;
+        TlsConnector::from(Arc::new(tls_config))
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| format!("TLS with {} failed: {}", host, e))
+    }
+
+    async fn command<S: AsyncRead + AsyncWrite + Unpin>(reader: &mut BufReader<S>, line: &str, expected: u16) -> Result<(), String> {
+        reader.get_mut().write_all(line.as_bytes()).await.map_err(|e| e.to_string())?;
+        reader.get_mut().flush().await.map_err(|e| e.to_string())?;
+        expect_reply(reader, expected).await
+    }
+
+    // a reply is one or more lines "250-..." ending with "250 ..."
+    async fn expect_reply<S: AsyncRead + Unpin>(reader: &mut BufReader<S>, expected: u16) -> Result<(), String> {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.map_err(|e| e.to_string())? == 0 {
+                return Err("the SMTP server closed the connection".to_string());
+            }
+            let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+            if code != Some(expected) && !(expected == 250 && code == Some(251)) {
+                return Err(format!("SMTP server answered: {}", line.trim_end()));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    // "Name <user@host>" -> "user@host"
+    fn address(mailbox: &str) -> &str {
+        match (mailbox.rfind('<'), mailbox.rfind('>')) {
+            (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+            _ => mailbox.trim(),
+        }
+    }
+
+    // RFC 5322 message: UTF-8 text, base64 body (no line length or '.' issues), RFC 2047 subject
+    fn format_message(from: &str, email: &Email) -> String {
+        let body = BASE64.encode(email.body.as_bytes())
+            .as_bytes()
+            .chunks(76)
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect::<Vec<String>>()
+            .join("\r\n");
+        format!(
+            "From: {}\r\nTo: {}\r\nSubject: =?UTF-8?B?{}?=\r\nDate: {}\r\nMessage-ID: <{}@breizh-walker>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+            from,
+            email.to,
+            BASE64.encode(email.subject.as_bytes()),
+            chrono::Utc::now().to_rfc2822(),
+            crate::services::auth::random_hex(16),
+            body,
+        )
+    }
@@ -2,4 +2,27 @@ pub mod db;
 pub mod sitters;
 pub mod owners;
 pub mod dogs;
-pub mod bookings;
\ No newline at end of file
+pub mod bookings;
+pub mod integrity;
+pub mod versioning;
+pub mod listing;
+pub mod audit;
+pub mod booking_events;
+pub mod outbox;
+pub mod outbox_sinks;
+pub mod webhooks;
+pub mod booking_stream;
+pub mod walk_tracking;
+pub mod track_upload;
+pub mod attachments;
+pub mod walk_reports;
+pub mod auth;
+pub mod policy;
+pub mod api_keys;
+pub mod oidc;
+pub mod mailer;
+pub mod account_emails;
+pub mod rate_limiter;
+pub mod tls;
+pub mod field_encryption;
+pub mod privacy;
@@ -0,0 +1,564 @@
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use bson::{doc, oid::ObjectId, DateTime};
+use jsonwebtoken::{jwk::{Jwk, JwkSet}, Algorithm, DecodingKey, Validation};
+use log::{info, warn};
+use mongodb::options::{Collation, CollationStrength};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::{app_errors::errors::AppError,
+            config::{AuthConfig, OidcConfig},
+            models::auth_model::{Role, TokenResponse},
+            models::oidc_model::{IdTokenClaims, OidcCallbackQuery, OidcLogin, ProviderMetadata, TokenEndpointResponse}};
+use crate::services::{auth, db::AppDatabase, owners};
+
+
+    // -------------------------------------------
+    // OpenID Connect login (single sign-on)
+    // -------------------------------------------
+    // Authorization code flow with PKCE (S256) against the identity provider of OIDC_ISSUER (see config.rs):
+    //  - the discovery document and the signing keys (JWKS) are fetched once and cached for OIDC_METADATA_TTL_S,
+    //    an ID token signed with an unknown key ('kid') refreshes the keys (at most every JWKS_MIN_REFRESH)
+    //  - the state, nonce and code verifier of each login are kept in 'oidc_logins' until the callback (used once)
+    //  - roles: the values of the OIDC_ROLES_CLAIM claim mapped by OIDC_ROLE_MAPPING give dispatcher / admin,
+    //    anybody else must have a verified email that is the email of an owner or a sitter
+    //  - the link (issuer, subject) -> account is kept in 'external_identities'
+    // The result is the usual access + refresh tokens (see services/auth.rs): nothing changes for the other routes.
+    // Try it locally with the mock identity provider: `cargo run -- mock-idp` (see commands/mock_idp.rs).
+
+    const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+    const STATE_BYTES: usize = 32;
+    const CODE_VERIFIER_BYTES: usize = 32;      // 64 hex characters (RFC 7636: 43 to 128)
+    const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+    const INVALID_LOGIN: &str = "invalid or expired OpenID Connect login, start again from GET /auth/oidc/login";
+
+    // asymmetric signatures only: an HMAC key in a JWKS would be a shared secret anybody can read
+    const ACCEPTED_ALGORITHMS: [Algorithm; 9] = [
+        Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+        Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+        Algorithm::ES256, Algorithm::ES384, Algorithm::EdDSA,
+    ];
+
+    struct ProviderCache {
+        metadata: ProviderMetadata,
+        keys: JwkSet,
+        fetched_at: Instant,
+        keys_fetched_at: Instant,
+    }
+
+    // shared by the handlers (web::Data<OidcProvider>), disabled when OIDC_* is not configured
+    pub struct OidcProvider {
+        config: Option<OidcConfig>,
+        http: reqwest::Client,
+        cache: Mutex<Option<ProviderCache>>,
+    }
+
+    impl OidcProvider {
+        pub fn new(config: Option<OidcConfig>) -> Self {
+            let http = reqwest::Client::builder()
+                .timeout(HTTP_TIMEOUT)
+                .build()
+                .unwrap_or_default();
+            OidcProvider { config, http, cache: Mutex::new(None) }
+        }
+
+        fn config(&self) -> Result<&OidcConfig, AppError> {
+            self.config.as_ref().ok_or(AppError::NotFound)
+        }
+
+        // Step 1: the URL of the identity provider to send the browser to
+        pub async fn start_login(&self, db: &AppDatabase) -> Result<String, AppError> {
+            let config = self.config()?;
+            let metadata = self.metadata().await?;
+
+            let login = OidcLogin {
+                _id: auth::random_hex(STATE_BYTES),
+                nonce: auth::random_hex(STATE_BYTES),
+                code_verifier: auth::random_hex(CODE_VERIFIER_BYTES),
+                expires_at: DateTime::from_millis(DateTime::now().timestamp_millis() + config.login_ttl.as_millis() as i64),
+            };
+            db.get_oidc_logins_collection().insert_one(&login).await?;
+
+            let code_challenge = BASE64_URL.encode(Sha256::digest(login.code_verifier.as_bytes()));
+            let url = Url::parse_with_params(&metadata.authorization_endpoint, &[
+                ("response_type", "code"),
+                ("client_id", config.client_id.as_str()),
+                ("redirect_uri", config.redirect_uri.as_str()),
+                ("scope", config.scopes.as_str()),
+                ("state", login._id.as_str()),
+                ("nonce", login.nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ]).map_err(|e| AppError::BadGateway(format!("invalid authorization_endpoint: {}", e)))?;
+            Ok(url.to_string())
+        }
+
+        // Step 3: the callback of the identity provider -> tokens of the linked account
+        pub async fn complete_login(&self, db: &AppDatabase, auth_config: &AuthConfig, query: OidcCallbackQuery) -> Result<TokenResponse, AppError> {
+            let config = self.config()?;
+            if let Some(error) = query.error {
+                return Err(AppError::Unauthorized(format!("the identity provider refused the login: {} {}", error, query.error_description.unwrap_or_default())));
+            }
+            let (code, state) = match (query.code, query.state) {
+                (Some(code), Some(state)) => (code, state),
+                _ => return Err(AppError::ParseError("code and state are required".to_string())),
+            };
+
+            // the state is used once, whatever happens next
+            let login = db.get_oidc_logins_collection()
+                .find_one_and_delete(doc! { "_id": &state, "expires_at": { "$gt": DateTime::now() } })
+                .await?
+                .ok_or(AppError::Unauthorized(INVALID_LOGIN.to_string()))?;
+
+            let metadata = self.metadata().await?;
+            let mut form = vec![
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", config.redirect_uri.as_str()),
+                ("client_id", config.client_id.as_str()),
+                ("code_verifier", login.code_verifier.as_str()),
+            ];
+            if let Some(client_secret) = &config.client_secret {
+                form.push(("client_secret", client_secret.as_str()));
+            }
+            let response = self.http.post(&metadata.token_endpoint).form(&form).send().await
+                .map_err(|e| AppError::BadGateway(format!("token endpoint unreachable: {}", e)))?;
+            if response.status().is_client_error() {
+                return Err(AppError::Unauthorized(format!("the identity provider refused the code ({})", response.status())));
+            }
+            if !response.status().is_success() {
+                return Err(AppError::BadGateway(format!("token endpoint answered {}", response.status())));
+            }
+            let tokens: TokenEndpointResponse = response.json().await
+                .map_err(|e| AppError::BadGateway(format!("unexpected answer of the token endpoint: {}", e)))?;
+
+            let claims = self.verify_id_token(config, &metadata, &tokens.id_token).await?;
+            if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+                return Err(AppError::Unauthorized("the ID token does not belong to this login (nonce)".to_string()));
+            }
+
+            let (principal_id, role) = link_account(db, config, &metadata.issuer, &claims).await?;
+            info!("OpenID Connect login of {} {} ({})", role.as_str(), principal_id.to_hex(), claims.sub);
+            auth::issue_tokens(db, auth_config, principal_id, role, ObjectId::new()).await
+        }
+
+        // Signature (key of the JWKS named by 'kid'), issuer, audience and expiry of the ID token
+        async fn verify_id_token(&self, config: &OidcConfig, metadata: &ProviderMetadata, id_token: &str) -> Result<IdTokenClaims, AppError> {
+            let invalid = |reason: String| AppError::Unauthorized(format!("invalid ID token: {}", reason));
+            let header = jsonwebtoken::decode_header(id_token).map_err(|e| invalid(e.to_string()))?;
+            if !ACCEPTED_ALGORITHMS.contains(&header.alg) {
+                return Err(invalid(format!("{:?} signatures are not accepted", header.alg)));
+            }
+            let jwk = self.signing_key(header.kid.as_deref()).await?;
+            let key = DecodingKey::from_jwk(&jwk).map_err(|e| invalid(e.to_string()))?;
+
+            let mut validation = Validation::new(header.alg);
+            validation.set_issuer(&[&metadata.issuer]);
+            validation.set_audience(&[&config.client_id]);
+            validation.leeway = 30;
+            jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+                .map(|token| token.claims)
+                .map_err(|e| invalid(e.to_string()))
+        }
+
+        // -----------------
+        // Discovery and keys (cached)
+        // -----------------
+
+        async fn metadata(&self) -> Result<ProviderMetadata, AppError> {
+            let config = self.config()?;
+            let mut cache = self.cache.lock().await;
+            if let Some(cached) = cache.as_ref().filter(|cached| cached.fetched_at.elapsed() < config.metadata_ttl) {
+                return Ok(cached.metadata.clone());
+            }
+
+            let discovery_url = format!("{}/.well-known/openid-configuration", config.issuer);
+            let metadata: ProviderMetadata = self.fetch_json(&discovery_url).await?;
+            if metadata.issuer.trim_end_matches('/') != config.issuer {
+                return Err(AppError::BadGateway(format!("the discovery document is for issuer {}, expected {}", metadata.issuer, config.issuer)));
+            }
+            let keys: JwkSet = self.fetch_json(&metadata.jwks_uri).await?;
+            info!("OpenID Connect provider {}: {} signing key(s)", metadata.issuer, keys.keys.len());
+
+            let now = Instant::now();
+            *cache = Some(ProviderCache { metadata: metadata.clone(), keys, fetched_at: now, keys_fetched_at: now });
+            Ok(metadata)
+        }
+
+        // the key named 'kid' (the only key when the token names none), the keys are fetched again
+        // when it is unknown: the identity provider rotated its keys
+        async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, AppError> {
+            let mut cache = self.cache.lock().await;
+            let cached = cache.as_mut().ok_or(AppError::InternalError)?;
+            if let Some(jwk) = find_key(&cached.keys, kid) {
+                return Ok(jwk);
+            }
+            if cached.keys_fetched_at.elapsed() >= JWKS_MIN_REFRESH {
+                warn!("ID token signed with unknown key {:?}, fetching the keys of {} again", kid, cached.metadata.issuer);
+                cached.keys = self.fetch_json(&cached.metadata.jwks_uri).await?;
+                cached.keys_fetched_at = Instant::now();
+                if let Some(jwk) = find_key(&cached.keys, kid) {
+                    return Ok(jwk);
+                }
+            }
+            Err(AppError::Unauthorized(format!("invalid ID token: unknown signing key {:?}", kid)))
+        }
+
+        async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+            let response = self.http.get(url).send().await
+                .map_err(|e| AppError::BadGateway(format!("{} unreachable: {}", url, e)))?;
+            if !response.status().is_success() {
+                return Err(AppError::BadGateway(format!("{} answered {}", url, response.status())));
+            }
+            response.json().await.map_err(|e| AppError::BadGateway(format!("unexpected answer of {}: {}", url, e)))
+        }
+    }
+
+    fn find_key(keys: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+        match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        }
+    }
+
+    // -----------------
+    // Accounts
+    // -----------------
+
+    // The account of the user: staff from the roles claim, otherwise the owner or sitter with the same verified email
+    // (or the one linked at a previous login); the link is recorded for the next logins
+    async fn link_account(db: &AppDatabase, config: &OidcConfig, issuer: &str, claims: &IdTokenClaims) -> Result<(ObjectId, Role), AppError> {
+        let email = claims.email.as_deref().map(str::trim).map(str::to_lowercase).unwrap_or_default();
+        let identities = db.get_external_identities_collection();
+        let linked = identities.find_one(doc! { "issuer": issuer, "subject": &claims.sub }).await?;
+
+        let (principal_id, role) = match staff_role(config, claims) {
+            Some(role) => {
+                // the same account as the previous SSO logins, or as the password login of that email (create-staff)
+                let principal_id = match linked.as_ref().filter(|identity| identity.role.is_staff()) {
+                    Some(identity) => identity.principal_id,
+                    None => db.get_credentials_collection()
+                        .find_one(doc! { "login": &email, "role": { "$in": ["dispatcher", "admin"] } })
+                        .await?
+                        .map_or_else(ObjectId::new, |credential| credential.principal_id),
+                };
+                (principal_id, role)
+            },
+            None => match linked.as_ref().filter(|identity| !identity.role.is_staff()) {
+                Some(identity) => (identity.principal_id, identity.role),
+                None => account_by_email(db, claims, &email).await?,
+            },
+        };
+
+        let now = DateTime::now();
+        identities
+            .update_one(
+                doc! { "issuer": issuer, "subject": &claims.sub },
+                doc! {
+                    "$set": { "email": &email, "principal_id": principal_id, "role": role.as_str(), "last_login_at": now },
+                    "$setOnInsert": { "_id": ObjectId::new(), "created_at": now },
+                },
+            )
+            .upsert(true)
+            .await?;
+        Ok((principal_id, role))
+    }
+
+    // highest staff role given by the roles claim (a string or an array of strings)
+    fn staff_role(config: &OidcConfig, claims: &IdTokenClaims) -> Option<Role> {
+        let values: Vec<&str> = match claims.other.get(&config.roles_claim) {
+            Some(serde_json::Value::String(value)) => vec![value.as_str()],
+            Some(serde_json::Value::Array(values)) => values.iter().filter_map(serde_json::Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let roles: Vec<&str> = config.role_mapping.iter()
+            .filter(|(value, _)| values.contains(&value.as_str()))
+            .map(|(_, role)| role.as_str())
+            .collect();
+        if roles.contains(&"admin") {
+            Some(Role::Admin)
+        } else if roles.contains(&"dispatcher") {
+            Some(Role::Dispatcher)
+        } else {
+            None
+        }
+    }
+
+    // owners and sitters are found by email, case-insensitively; the email must be verified by the identity provider
+    async fn account_by_email(db: &AppDatabase, claims: &IdTokenClaims, email: &str) -> Result<(ObjectId, Role), AppError> {
+        if email.is_empty() || !claims.email_verified {
+            return Err(AppError::Forbidden("a verified email is required to log in with single sign-on".to_string()));
+        }
+        let case_insensitive = Collation::builder().locale("en").strength(CollationStrength::Secondary).build();
+        let owner = db.get_owners_collection().find_one(owners::email_filter(email)?).await?;   // encrypted, see services/field_encryption.rs
+        let sitter = db.get_sitters_collection().find_one(doc! { "email": email }).collation(case_insensitive).await?;
+        match (owner, sitter) {
+            (Some(owner), None) => Ok((owner._id, Role::Owner)),
+            (None, Some(sitter)) => Ok((sitter._id, Role::Sitter)),
+            (Some(_), Some(_)) => Err(AppError::Conflict(format!("{} is the email of an owner and of a sitter", email))),
+            (None, None) => Err(AppError::Forbidden(format!("no owner or sitter account uses {}", email))),
+        }
+    }
+
+    // The whole flow against the mock identity provider (commands/mock_idp.rs), started in-process:
+    // discovery, keys, PKCE and ID tokens need nothing else; the logins (state, account links) need MongoDB
+    // (see AppDatabase::init_test): `cargo test -- --ignored`
+    #[cfg(test)]
+    mod tests {
+        use std::net::TcpListener;
+
+        use reqwest::{header, redirect, StatusCode};
+        use serde_json::json;
+
+        use super::*;
+        use crate::{commands::mock_idp,
+                    models::owner_model::{Owner, OwnerRequest},
+                    models::sitter_model::{Sitter, SitterRequest},
+                    services::sitters};
+
+        const CLIENT_ID: &str = "walker";
+        const REDIRECT_URI: &str = "http://localhost:8080/auth/oidc/callback";
+
+        // issuer URL of a new mock identity provider
+        fn start_mock_idp() -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+            actix_web::rt::spawn(mock_idp::serve(listener, issuer.clone()).unwrap());
+            issuer
+        }
+
+        fn oidc_config(issuer: &str) -> OidcConfig {
+            OidcConfig {
+                issuer: issuer.to_string(),
+                client_id: CLIENT_ID.to_string(),
+                client_secret: None,
+                redirect_uri: REDIRECT_URI.to_string(),
+                scopes: "openid email profile".to_string(),
+                roles_claim: "roles".to_string(),
+                role_mapping: vec![("walker-admins".to_string(), "admin".to_string()), ("walker-dispatch".to_string(), "dispatcher".to_string())],
+                metadata_ttl: Duration::from_secs(3600),
+                login_ttl: Duration::from_secs(600),
+            }
+        }
+
+        fn auth_config() -> AuthConfig {
+            AuthConfig {
+                jwt_secret: vec![1u8; 32],
+                issuer: "breizh-app-walker-test".to_string(),
+                access_token_ttl: Duration::from_secs(900),
+                refresh_token_ttl: Duration::from_secs(3600),
+            }
+        }
+
+        // the user logs in at the identity provider: its redirect to the callback carries the code and the state
+        async fn authorize(authorization_url: &str, email: &str, roles: &str) -> (String, String) {
+            let browser = reqwest::Client::builder().redirect(redirect::Policy::none()).build().unwrap();
+            let mut url = Url::parse(authorization_url).unwrap();
+            url.query_pairs_mut().append_pair("login_hint", email).append_pair("roles", roles);
+            let response = browser.get(url).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::FOUND);
+
+            let location = Url::parse(response.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+            assert!(location.as_str().starts_with(REDIRECT_URI));
+            let parameter = |name: &str| location.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.to_string()).unwrap();
+            (parameter("code"), parameter("state"))
+        }
+
+        fn claims(value: serde_json::Value) -> IdTokenClaims {
+            serde_json::from_value(value).unwrap()
+        }
+
+        fn unique_email(name: &str) -> String {
+            format!("{}-{}@example.com", name, ObjectId::new().to_hex())
+        }
+
+        #[actix_web::test]
+        async fn discovery_document_and_signing_keys_are_fetched() {
+            let issuer = start_mock_idp();
+            let provider = OidcProvider::new(Some(oidc_config(&issuer)));
+
+            let metadata = provider.metadata().await.unwrap();
+            assert_eq!(metadata.issuer, issuer);
+            assert_eq!(metadata.authorization_endpoint, format!("{}/authorize", issuer));
+            assert_eq!(metadata.token_endpoint, format!("{}/token", issuer));
+
+            let kid = provider.cache.lock().await.as_ref().map(|cached| cached.keys.keys[0].common.key_id.clone()).unwrap();
+            assert!(provider.signing_key(kid.as_deref()).await.is_ok());
+            // the only key also verifies a token without 'kid'
+            assert!(provider.signing_key(None).await.is_ok());
+        }
+
+        #[actix_web::test]
+        async fn discovery_document_of_another_issuer_is_refused() {
+            let issuer = start_mock_idp().replace("127.0.0.1", "localhost");
+            let provider = OidcProvider::new(Some(oidc_config(&issuer)));
+            assert!(matches!(provider.metadata().await, Err(AppError::BadGateway(_))));
+        }
+
+        #[actix_web::test]
+        async fn unknown_signing_key_is_refused() {
+            let provider = OidcProvider::new(Some(oidc_config(&start_mock_idp())));
+            provider.metadata().await.unwrap();
+            // the keys were just fetched (JWKS_MIN_REFRESH): not fetched again, the key stays unknown
+            assert!(matches!(provider.signing_key(Some("rotated-away")).await, Err(AppError::Unauthorized(_))));
+        }
+
+        #[actix_web::test]
+        async fn pkce_code_exchange_gives_a_verified_id_token() {
+            let issuer = start_mock_idp();
+            let config = oidc_config(&issuer);
+            let provider = OidcProvider::new(Some(config.clone()));
+            let metadata = provider.metadata().await.unwrap();
+
+            let code_verifier = auth::random_hex(CODE_VERIFIER_BYTES);
+            let code_challenge = BASE64_URL.encode(Sha256::digest(code_verifier.as_bytes()));
+            let authorization_url = Url::parse_with_params(&metadata.authorization_endpoint, &[
+                ("response_type", "code"), ("client_id", CLIENT_ID), ("redirect_uri", REDIRECT_URI), ("scope", "openid email"),
+                ("state", "state-1"), ("nonce", "nonce-1"), ("code_challenge", code_challenge.as_str()), ("code_challenge_method", "S256"),
+            ]).unwrap();
+            let exchange = |code: String, code_verifier: String| {
+                let http = provider.http.clone();
+                let token_endpoint = metadata.token_endpoint.clone();
+                async move {
+                    http.post(token_endpoint)
+                        .form(&[("grant_type", "authorization_code"), ("code", code.as_str()), ("redirect_uri", REDIRECT_URI),
+                                ("client_id", CLIENT_ID), ("code_verifier", code_verifier.as_str())])
+                        .send()
+                        .await
+                        .unwrap()
+                }
+            };
+
+            // another verifier than the one of the challenge: refused (and the code is spent)
+            let (code, state) = authorize(authorization_url.as_str(), "Someone@Example.com", "walker-dispatch").await;
+            assert_eq!(state, "state-1");
+            assert_eq!(exchange(code, auth::random_hex(CODE_VERIFIER_BYTES)).await.status(), StatusCode::BAD_REQUEST);
+
+            let (code, _) = authorize(authorization_url.as_str(), "Someone@Example.com", "walker-dispatch").await;
+            let response = exchange(code.clone(), code_verifier.clone()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let tokens: TokenEndpointResponse = response.json().await.unwrap();
+            // a code is used once
+            assert_eq!(exchange(code, code_verifier).await.status(), StatusCode::BAD_REQUEST);
+
+            let claims = provider.verify_id_token(&config, &metadata, &tokens.id_token).await.unwrap();
+            assert_eq!(claims.nonce.as_deref(), Some("nonce-1"));
+            assert_eq!(claims.email.as_deref(), Some("Someone@Example.com"));
+            assert!(claims.email_verified);
+            assert_eq!(staff_role(&config, &claims), Some(Role::Dispatcher));
+
+            // an ID token for another client (audience) is refused
+            let other_client = OidcConfig { client_id: "another-client".to_string(), ..config };
+            assert!(matches!(provider.verify_id_token(&other_client, &metadata, &tokens.id_token).await, Err(AppError::Unauthorized(_))));
+        }
+
+        #[test]
+        fn roles_claim_maps_to_the_highest_staff_role() {
+            let config = oidc_config("http://localhost:9000");
+            let with_roles = |roles: serde_json::Value| claims(json!({ "sub": "user-1", "roles": roles }));
+
+            assert_eq!(staff_role(&config, &with_roles(json!(["walker-dispatch"]))), Some(Role::Dispatcher));
+            assert_eq!(staff_role(&config, &with_roles(json!(["walker-dispatch", "walker-admins"]))), Some(Role::Admin));
+            assert_eq!(staff_role(&config, &with_roles(json!("walker-admins"))), Some(Role::Admin));
+            assert_eq!(staff_role(&config, &with_roles(json!(["admin", "walkers"]))), None);   // values, not role names
+            assert_eq!(staff_role(&config, &claims(json!({ "sub": "user-1" }))), None);
+
+            let groups_claim = OidcConfig { roles_claim: "groups".to_string(), ..config.clone() };
+            assert_eq!(staff_role(&groups_claim, &claims(json!({ "sub": "user-1", "groups": ["walker-admins"] }))), Some(Role::Admin));
+            assert_eq!(staff_role(&groups_claim, &with_roles(json!(["walker-admins"]))), None);
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn login_links_the_owner_with_the_same_email() {
+            let db = AppDatabase::init_test().await;
+            let provider = OidcProvider::new(Some(oidc_config(&start_mock_idp())));
+            let email = unique_email("owner");
+            let owner = Owner::try_from(OwnerRequest {
+                name: "Oidc Owner".to_string(),
+                email: email.clone(),
+                phone: "0601020304".to_string(),
+                address: "1 rue de Brest".to_string(),
+                home_access: None,
+                password: None,
+            }).unwrap();
+            let owner = owners::create_owner(&db, owner, None, "test").await.unwrap();
+
+            // the login in progress keeps the PKCE verifier of the challenge sent to the identity provider
+            let authorization_url = provider.start_login(&db).await.unwrap();
+            let (code, state) = authorize(&authorization_url, &email.to_uppercase(), "").await;
+            let login = db.get_oidc_logins_collection().find_one(doc! { "_id": &state }).await.unwrap().unwrap();
+            let challenge = Url::parse(&authorization_url).unwrap().query_pairs().find(|(key, _)| key == "code_challenge").map(|(_, value)| value.to_string());
+            assert_eq!(challenge, Some(BASE64_URL.encode(Sha256::digest(login.code_verifier.as_bytes()))));
+
+            let callback = OidcCallbackQuery { code: Some(code.clone()), state: Some(state.clone()), error: None, error_description: None };
+            let tokens = provider.complete_login(&db, &auth_config(), callback).await.unwrap();
+            assert_eq!(tokens.role, Role::Owner);
+            assert_eq!(tokens.principal_id, owner._id.to_hex());
+
+            // the state is used once
+            let replayed = OidcCallbackQuery { code: Some(code), state: Some(state), error: None, error_description: None };
+            assert!(matches!(provider.complete_login(&db, &auth_config(), replayed).await, Err(AppError::Unauthorized(_))));
+
+            // the link is recorded for the next logins
+            let identity = db.get_external_identities_collection()
+                .find_one(doc! { "subject": format!("mock|{}", email) })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(identity.principal_id, owner._id);
+            assert_eq!(identity.role, Role::Owner);
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn login_links_sitters_by_email_and_staff_by_role() {
+            let db = AppDatabase::init_test().await;
+            let provider = OidcProvider::new(Some(oidc_config(&start_mock_idp())));
+            let sitter_email = unique_email("sitter");
+            let sitter = Sitter::try_from(SitterRequest {
+                firstname: "Oidc".to_string(),
+                lastname: "Sitter".to_string(),
+                gender: "F".to_string(),
+                email: sitter_email.clone(),
+                phone: "0601020304".to_string(),
+                address: "2 rue de Brest".to_string(),
+                password: None,
+            }).unwrap();
+            let sitter = sitters::create_sitter(&db, sitter, None, "test").await.unwrap();
+
+            let log_in = |email: String, roles: &'static str| {
+                let (provider, db) = (&provider, &db);
+                async move {
+                    let (code, state) = authorize(&provider.start_login(db).await.unwrap(), &email, roles).await;
+                    provider.complete_login(db, &auth_config(), OidcCallbackQuery { code: Some(code), state: Some(state), error: None, error_description: None }).await
+                }
+            };
+
+            let tokens = log_in(sitter_email, "").await.unwrap();
+            assert_eq!((tokens.role, tokens.principal_id), (Role::Sitter, sitter._id.to_hex()));
+
+            // staff from the roles claim, with no owner or sitter behind the email; the same account on the next login
+            let staff_email = unique_email("admin");
+            let first = log_in(staff_email.clone(), "walker-admins").await.unwrap();
+            assert_eq!(first.role, Role::Admin);
+            let second = log_in(staff_email, "walker-admins,walker-dispatch").await.unwrap();
+            assert_eq!((second.role, second.principal_id), (Role::Admin, first.principal_id));
+
+            // nobody uses this email
+            assert!(matches!(log_in(unique_email("stranger"), "").await, Err(AppError::Forbidden(_))));
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn unknown_state_is_refused() {
+            let db = AppDatabase::init_test().await;
+            let provider = OidcProvider::new(Some(oidc_config(&start_mock_idp())));
+            let callback = OidcCallbackQuery { code: Some("code".to_string()), state: Some(auth::random_hex(STATE_BYTES)), error: None, error_description: None };
+            assert!(matches!(provider.complete_login(&db, &auth_config(), callback).await, Err(AppError::Unauthorized(_))));
+        }
+    }
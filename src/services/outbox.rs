@@ -0,0 +1,193 @@
+use std::{sync::Arc, time::Duration};
+
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use futures::StreamExt;
+use log::{error, info, warn};
+use mongodb::options::ReturnDocument;
+
+use crate::{app_errors::errors::AppError,
+            config::DispatcherConfig,
+            models::outbox_model::{OutboxMessage, OutboxQuery, OutboxStatus}};
+use crate::services::{db::AppDatabase, outbox_sinks::{InProcessSink, LogSink, OutboxSink, WebhookSink, WebhookSubscriptionsSink}};
+
+
+    // -------------------------------------------
+    // Outbox dispatcher
+    // -------------------------------------------
+    // Domain events are written to the 'outbox' collection together with the change they describe
+    // (same transaction, see services/booking_events.rs). This task reads them back and delivers them:
+    //   1. claim the oldest due message (locked for LEASE, so several instances never deliver it at the same time)
+    //   2. call every sink that has not received it yet
+    //   3. delivered, or retried after an exponential backoff, or dead-lettered after max_attempts
+    // A crash between 2 and 3 only means the message is delivered again once the lease expires (at least once).
+
+    pub const LEASE: Duration = Duration::from_secs(30);
+    const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+    const DEFAULT_QUERY_LIMIT: i64 = 100;
+    const MAX_QUERY_LIMIT: i64 = 1000;
+
+    // New pending message, to be inserted with the change it describes
+    pub fn new_message(event_type: &str, aggregate: &str, aggregate_id: ObjectId, payload: Document, actor: &str) -> OutboxMessage {
+        let now = DateTime::now();
+        OutboxMessage {
+            _id: ObjectId::new(),
+            event_type: event_type.to_string(),
+            aggregate: aggregate.to_string(),
+            aggregate_id,
+            payload,
+            actor: actor.to_string(),
+            created_at: now,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            locked_until: None,
+            delivered_to: Vec::new(),
+            last_error: None,
+            delivered_at: None,
+        }
+    }
+
+    // Sinks listed in OUTBOX_SINKS; the in-process sink is always created so that subscribers can be attached to it
+    pub fn build_sinks(db: Arc<AppDatabase>, config: &DispatcherConfig, in_process: Arc<InProcessSink>) -> Vec<Arc<dyn OutboxSink>> {
+        let mut sinks: Vec<Arc<dyn OutboxSink>> = Vec::new();
+        for name in &config.sinks {
+            match (name.as_str(), &config.webhook_url) {
+                ("log", _) => sinks.push(Arc::new(LogSink)),
+                ("in_process", _) => sinks.push(in_process.clone()),
+                ("webhook", Some(url)) => sinks.push(Arc::new(WebhookSink::new(url.clone()))),
+                ("webhook_subscriptions", _) => sinks.push(Arc::new(WebhookSubscriptionsSink::new(db.clone()))),
+                ("webhook", None) => warn!("Outbox: 'webhook' sink ignored, OUTBOX_WEBHOOK_URL is not set"),
+                (unknown, _) => warn!("Outbox: unknown sink '{}' ignored", unknown),
+            }
+        }
+        sinks
+    }
+
+    // Dispatcher loop, runs for the whole life of the server
+    pub async fn run_dispatcher(db: Arc<AppDatabase>, sinks: Vec<Arc<dyn OutboxSink>>, config: DispatcherConfig) {
+        info!("Outbox dispatcher started with sink(s): {:?}", sinks.iter().map(|sink| sink.name()).collect::<Vec<&str>>());
+        loop {
+            match claim_next(&db).await {
+                Ok(Some(message)) => {
+                    if let Err(e) = deliver(&db, &sinks, message, &config).await {
+                        error!("Outbox: failed to record a delivery: {}", e);
+                    }
+                }
+                Ok(None) => actix_web::rt::time::sleep(config.poll_interval).await,
+                Err(e) => {
+                    error!("Outbox: failed to read pending messages: {}", e);
+                    actix_web::rt::time::sleep(config.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    // Oldest pending message that is due and not claimed by another dispatcher, claimed for LEASE
+    async fn claim_next(db: &AppDatabase) -> Result<Option<OutboxMessage>, AppError> {
+        let now = DateTime::now();
+        let filter = doc! {
+            "status": OutboxStatus::Pending.as_str(),
+            "next_attempt_at": { "$lte": now },
+            "$or": [ { "locked_until": null }, { "locked_until": { "$lte": now } } ],
+        };
+        let claimed = db.get_outbox_collection()
+            .find_one_and_update(filter, doc! { "$set": { "locked_until": after(now, LEASE) } })
+            .sort(doc! { "next_attempt_at": 1, "_id": 1 })
+            .return_document(ReturnDocument::After)
+            .await?;
+        Ok(claimed)
+    }
+
+    async fn deliver(db: &AppDatabase, sinks: &[Arc<dyn OutboxSink>], message: OutboxMessage, config: &DispatcherConfig) -> Result<(), AppError> {
+        let mut delivered_to = Vec::new();
+        let mut errors = Vec::new();
+
+        for sink in sinks.iter().filter(|sink| !message.delivered_to.iter().any(|name| name == sink.name())) {
+            match sink.deliver(&message).await {
+                Ok(()) => delivered_to.push(sink.name()),
+                Err(e) => errors.push(format!("{}: {}", sink.name(), e)),
+            }
+        }
+
+        let now = DateTime::now();
+        let update = if errors.is_empty() {
+            doc! {
+                "$set": { "status": OutboxStatus::Delivered.as_str(), "delivered_at": now, "locked_until": null, "last_error": null },
+                "$addToSet": { "delivered_to": { "$each": &delivered_to } },
+            }
+        } else {
+            let attempts = message.attempts + 1;
+            let last_error = errors.join("; ");
+            let (status, next_attempt_at) = if attempts >= config.max_attempts {
+                error!("Outbox: message {} ({}) dead-lettered after {} attempts: {}", message._id.to_hex(), message.event_type, attempts, last_error);
+                (OutboxStatus::DeadLetter, now)
+            } else {
+                warn!("Outbox: message {} ({}) attempt {} failed, retrying: {}", message._id.to_hex(), message.event_type, attempts, last_error);
+                (OutboxStatus::Pending, after(now, retry_delay(config.retry_base, attempts)))
+            };
+            doc! {
+                "$set": { "status": status.as_str(), "attempts": attempts, "next_attempt_at": next_attempt_at, "locked_until": null, "last_error": last_error },
+                "$addToSet": { "delivered_to": { "$each": &delivered_to } },
+            }
+        };
+
+        db.get_outbox_collection()
+            .update_one(doc! { "_id": message._id }, update)
+            .await?;
+        Ok(())
+    }
+
+    // base, 2 x base, 4 x base, ... at most MAX_RETRY_DELAY (also used by services/webhooks.rs)
+    pub fn retry_delay(base: Duration, attempts: i32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1).max(0) as u32);
+        base.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+
+    pub fn after(time: DateTime, delay: Duration) -> DateTime {
+        DateTime::from_millis(time.timestamp_millis() + delay.as_millis() as i64)
+    }
+
+    // READ messages (admin), most recent first
+    pub async fn read_messages(db: &AppDatabase, outbox_query: &OutboxQuery) -> Result<Vec<OutboxMessage>, AppError> {
+        let mut filter = doc! {};
+        if let Some(status) = &outbox_query.status { filter.insert("status", status.as_str()); }
+
+        let limit = outbox_query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+        let mut result_cursor = db.get_outbox_collection()
+            .find(filter)
+            .sort(doc! { "created_at": -1, "_id": -1 })
+            .limit(limit)
+            .await?;
+
+        let mut vec_of_messages = Vec::<OutboxMessage>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(message) => vec_of_messages.push(message),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Outbox messages from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_messages)
+    }
+
+    // Put a dead-lettered message back in the queue, with a fresh attempt counter
+    pub async fn requeue(db: &AppDatabase, message_id: &str) -> Result<OutboxMessage, AppError> {
+        let obj_id = match ObjectId::parse_str(message_id) {
+            Ok(id) => id,
+            Err(_) => return Err(AppError::InvalidId),
+        };
+
+        let result = db.get_outbox_collection()
+            .find_one_and_update(
+                doc! { "_id": obj_id, "status": OutboxStatus::DeadLetter.as_str() },
+                doc! { "$set": { "status": OutboxStatus::Pending.as_str(), "attempts": 0, "next_attempt_at": DateTime::now(), "locked_until": null } },
+            )
+            .return_document(ReturnDocument::After)
+            .await?;
+
+        match result {
+            Some(message) => Ok(message),
+            // unknown id, or a message that is not dead-lettered
+            None => Err(AppError::NotFound),
+        }
+    }
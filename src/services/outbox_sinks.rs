@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use log::info;
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use crate::models::outbox_model::OutboxMessage;
+use crate::services::{db::AppDatabase, webhooks};
+
+
+    // -------------------------------------------
+    // Outbox sinks: where the dispatcher delivers the messages
+    // -------------------------------------------
+    // A sink returns Ok once the message is accepted; an Err is retried later by the dispatcher (see services/outbox.rs).
+    // Sinks are selected with OUTBOX_SINKS (see config.rs).
+
+    pub trait OutboxSink: Send + Sync {
+        // stored in OutboxMessage::delivered_to, must stay stable across restarts
+        fn name(&self) -> &'static str;
+        fn deliver<'a>(&'a self, message: &'a OutboxMessage) -> BoxFuture<'a, Result<(), String>>;
+    }
+
+    // JSON body sent to external systems: the message without its delivery bookkeeping
+    pub fn message_body(message: &OutboxMessage) -> serde_json::Value {
+        json!({
+            "event_id": message._id.to_hex(),
+            "event_type": message.event_type,
+            "aggregate": message.aggregate,
+            "aggregate_id": message.aggregate_id.to_hex(),
+            "actor": message.actor,
+            "occurred_at": message.created_at.to_chrono().to_rfc3339(),
+            "data": mongodb::bson::Bson::Document(message.payload.clone()).into_relaxed_extjson(),
+        })
+    }
+
+    // --- log: writes the message in the application log (never fails) ---
+    pub struct LogSink;
+
+    impl OutboxSink for LogSink {
+        fn name(&self) -> &'static str {
+            "log"
+        }
+
+        fn deliver<'a>(&'a self, message: &'a OutboxMessage) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                info!("Outbox event {} {} for {} {}: {}", message._id.to_hex(), message.event_type, message.aggregate, message.aggregate_id.to_hex(), message_body(message));
+                Ok(())
+            })
+        }
+    }
+
+    // --- webhook: POSTs the message body to a fixed URL, any non 2xx answer is a failure ---
+    pub struct WebhookSink {
+        url: String,
+        client: reqwest::Client,
+    }
+
+    impl WebhookSink {
+        pub fn new(url: String) -> Self {
+            WebhookSink { url, client: reqwest::Client::new() }
+        }
+    }
+
+    impl OutboxSink for WebhookSink {
+        fn name(&self) -> &'static str {
+            "webhook"
+        }
+
+        fn deliver<'a>(&'a self, message: &'a OutboxMessage) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                let response = self.client
+                    .post(&self.url)
+                    .json(&message_body(message))
+                    .send()
+                    .await
+                    .map_err(|e| format!("webhook {}: {}", self.url, e))?;
+                match response.status() {
+                    status if status.is_success() => Ok(()),
+                    status => Err(format!("webhook {} answered {}", self.url, status)),
+                }
+            })
+        }
+    }
+
+    // --- in_process: broadcasts the message to the subscribers living in this process ---
+    // e.g. let mut receiver = in_process_sink.subscribe(); while let Ok(message) = receiver.recv().await { .. }
+    // Subscribers that fall behind by more than 'capacity' messages lose the oldest ones (RecvError::Lagged).
+    pub struct InProcessSink {
+        sender: broadcast::Sender<OutboxMessage>,
+    }
+
+    impl InProcessSink {
+        pub fn new(capacity: usize) -> Self {
+            let (sender, _) = broadcast::channel(capacity);
+            InProcessSink { sender }
+        }
+
+        #[allow(dead_code)]
+        pub fn subscribe(&self) -> broadcast::Receiver<OutboxMessage> {
+            self.sender.subscribe()
+        }
+    }
+
+    impl OutboxSink for InProcessSink {
+        fn name(&self) -> &'static str {
+            "in_process"
+        }
+
+        fn deliver<'a>(&'a self, message: &'a OutboxMessage) -> BoxFuture<'a, Result<(), String>> {
+            // no subscriber at the moment is not an error: there is nobody to deliver to
+            let _ = self.sender.send(message.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    // --- webhook_subscriptions: one signed delivery per matching subscription (see services/webhooks.rs) ---
+    // the sink only records the deliveries, the webhook delivery worker sends them (with its own retries)
+    pub struct WebhookSubscriptionsSink {
+        db: Arc<AppDatabase>,
+    }
+
+    impl WebhookSubscriptionsSink {
+        pub fn new(db: Arc<AppDatabase>) -> Self {
+            WebhookSubscriptionsSink { db }
+        }
+    }
+
+    impl OutboxSink for WebhookSubscriptionsSink {
+        fn name(&self) -> &'static str {
+            "webhook_subscriptions"
+        }
+
+        fn deliver<'a>(&'a self, message: &'a OutboxMessage) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                webhooks::fan_out(&self.db, message).await.map_err(|e| e.to_string())
+            })
+        }
+    }
@@ -1,11 +1,13 @@
 
-use bson::{doc, oid::ObjectId, Bson};
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
 use futures::StreamExt;
 use crate::{app_errors::errors::AppError, 
-            models::owner_model::{Owner, OwnerUpdateRequest}};
+            models::auth_model::Role,
+            models::list_query_model::ListQuery,
+            models::owner_model::{Owner, OwnerLookupQuery, OwnerUpdateRequest}};
 
 //use mongodb::Database; 
-use crate::services::db::AppDatabase;
+use crate::services::{account_emails, audit, auth, db::{self, AppDatabase}, field_encryption, integrity, listing, policy, versioning::{self, VersionCheck}};
 
 
     
@@ -14,20 +16,44 @@ use crate::services::db::AppDatabase;
     // ----------------
 
     // CREATE for Owner: In mongodb, you can insert a document into a collection by calling the insert_one() method on a Collection instance.
-    pub async fn create_owner(db: &AppDatabase, owner: Owner) -> Result<Owner, AppError> {
+    // with a password (sign-up), the credential is created first: an email already used by another account is refused (Conflict)
+    // before the owner is stored (see services/auth.rs), and the unique email_index refuses an email of another owner
+    pub async fn create_owner(db: &AppDatabase, mut owner: Owner, password: Option<String>, actor: &str) -> Result<Owner, AppError> {
         // REF: mongodb insert_one() -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/insertOne/
         // https://docs.rs/mongodb/3.2.3/mongodb/results/struct.InsertOneResult.html
 
+        // bookkeeping: creation/modification timestamps and actors are managed here, never sent by clients
+        let now = DateTime::now();
+        owner.created_at = Some(now);
+        owner.updated_at = Some(now);
+        owner.created_by = Some(actor.to_string());
+        owner.updated_by = Some(actor.to_string());
+
         // Execute operation in the DB
         let owner_collection =  db.get_owners_collection();
 
-        let result = owner_collection
-            .insert_one(&owner)
-            .await?;
+        if let Some(password) = &password {
+            auth::create_credential(db, Role::Owner, owner._id, &owner.email, password).await?;
+        }
+        let result = match owner_collection.insert_one(&owner).await {
+            Ok(result) => result,
+            Err(e) => {
+                if password.is_some() {
+                    auth::delete_credentials(db, owner._id).await;
+                }
+                if db::is_duplicate_key(&e) {
+                    return Err(AppError::Conflict("another owner already uses this email".to_string()));
+                }
+                return Err(e.into());
+            }
+        };
 
         //  evaluate result and return 
         match result.inserted_id {
-            Bson::ObjectId(_oid) =>  Ok(owner),  // some id received back 
+            Bson::ObjectId(_oid) => {
+                audit::record_create(db, "owners", owner._id, actor, &owner).await;
+                Ok(owner)
+            },  // some id received back 
             other => Err(AppError::DatabaseError(format!("Failed to Create new Owner: {:?}", other))),
         }
     }
@@ -35,16 +61,36 @@ use crate::services::db::AppDatabase;
     // READ for Owner: 
     // In mongodb, you can query for multiple documents in a collection by calling the 'find()' method on a Collection instance.
     // 1) READ ALL: 
-    pub async fn read_owners(db: &AppDatabase) ->  Result<Vec<Owner>, AppError> {
+    pub async fn read_owners(db: &AppDatabase, list_query: &ListQuery, lookup: &OwnerLookupQuery, scope: &Document) ->  Result<Vec<Owner>, AppError> {
         // REF: find multiple documents -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/find/
         // The find() method returns a Cursor type, which you can iterate through to retrieve individual documents/
 
+         // filters and sort order sent in the query string (see services/listing.rs)
+         let mut selection = listing::selection(list_query)?;
+         // email and phone are encrypted: they are matched by their blind index (see services/field_encryption.rs)
+         if let Some(email) = &lookup.email {
+             selection.filter.insert("email_index", field_encryption::email_index(email).map_err(AppError::ParseError)?);
+         }
+         if let Some(phone) = &lookup.phone {
+             selection.filter.insert("phone_index", field_encryption::phone_index(phone).map_err(AppError::ParseError)?);
+         }
+         selection.filter = policy::restrict(selection.filter, scope);   // only what the caller may see (see services/policy.rs)
+
          // Execute operation in the DB
          let owner_collection = db.get_owners_collection();
 
-        let mut result_cursor = owner_collection
-            .find(doc!{})
-            .await?;
+        // Lenient mode (LENIENT_READS=true): return the valid entries, skip and log the corrupt ones
+        if db.lenient_reads() {
+            let scan = integrity::scan_collection(owner_collection, selection).await?;
+            integrity::log_skipped("Owner", &scan.corrupt);
+            return Ok(scan.valid);
+        }
+
+        let mut find = owner_collection.find(selection.filter);
+        if let Some(sort) = selection.sort {
+            find = find.sort(sort);
+        }
+        let mut result_cursor = find.await?;
             //.ok()
             //.expect("Error while reading owners from database.");
         
@@ -54,7 +100,7 @@ use crate::services::db::AppDatabase;
         while let Some(result) = result_cursor.next().await {
             match result {
                 Ok(owner_entry) => vec_of_owners.push(owner_entry),
-                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Owner entries from DB: {}" , e.to_string()))),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Owner entries from DB: {}" , e))),
             }
         }
         Ok(vec_of_owners)
@@ -63,7 +109,7 @@ use crate::services::db::AppDatabase;
     // READ single owner
     // find_one: If a document matches the filter criteria, the method returns a Result<Option<T>> type with a value of Some. 
     //           If no documents match the filter criteria, find_one() returns a Result<Option<T>> type with a value of None.
-    pub async fn read_owner(db: &AppDatabase, owner_id: &str) -> Result<Owner , AppError > {
+    pub async fn read_owner(db: &AppDatabase, owner_id: &str, scope: &Document) -> Result<Owner , AppError > {
 
         // Verify/Parse received ID 
         //let obj_id = ObjectId::parse_str(owner_id).expect("Failed parsing owner id.");
@@ -74,7 +120,7 @@ use crate::services::db::AppDatabase;
         };
 
         // Create query filter
-        let filter = doc! { "_id": obj_id};
+        let filter = policy::restrict(doc! { "_id": obj_id }, scope);
 
         // Execute operation in the DB
         let owner_collection = db.get_owners_collection();
@@ -93,8 +139,19 @@ use crate::services::db::AppDatabase;
 
     }
 
+    // Filter matching the owners with this email, case-insensitively, through the blind index
+    // (owners written before the encryption have no index yet, their plaintext email is compared instead)
+    pub fn email_filter(email: &str) -> Result<Document, AppError> {
+        let email_index = field_encryption::email_index(email).map_err(AppError::DatabaseError)?;
+        let legacy_email = format!("^{}$", account_emails::regex_escape(email.trim()));
+        Ok(doc! { "$or": [
+            { "email_index": email_index },
+            { "email_index": null, "email": { "$regex": legacy_email, "$options": "i" } },
+        ] })
+    }
+
     // UPDATE for Owner:
-    pub async fn update_owner(db: &AppDatabase, owner_id: &str, owner_update: OwnerUpdateRequest) ->  Result<String, AppError> {
+    pub async fn update_owner(db: &AppDatabase, owner_id: &str, owner_update: OwnerUpdateRequest, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<Owner, AppError> {
 
         // Verify/Parse received ID 
         let obj_id = match ObjectId::parse_str(owner_id) {
@@ -103,29 +160,46 @@ use crate::services::db::AppDatabase;
         };
         
         // Select fields sent in the UpdateRequest
+        // the personal data is stored encrypted, with the blind indexes of email and phone (see services/field_encryption.rs)
+        let encrypt = |value: &str| field_encryption::encrypt(value).map_err(|e| AppError::DatabaseError(format!("Failed to encrypt the Owner: {}", e)));
         let mut update_fields = doc! {};
+        let new_email = owner_update.email.clone();   // the login of the account follows (see auth::update_principal)
         if let Some(name) = owner_update.name {update_fields.insert("name", name); }
-        if let Some(email) = owner_update.email {update_fields.insert("email", email);}
-        if let Some(phone) = owner_update.phone {update_fields.insert("phone", phone);}
-        if let Some(address) = owner_update.address {update_fields.insert("address", address);}
+        if let Some(email) = owner_update.email {
+            update_fields.insert("email", encrypt(&email)?);
+            update_fields.insert("email_index", field_encryption::email_index(&email).map_err(AppError::DatabaseError)?);
+            update_fields.insert("email_verified_at", Bson::Null);   // the new address has to be verified (see services/account_emails.rs)
+        }
+        if let Some(phone) = owner_update.phone {
+            update_fields.insert("phone", encrypt(&phone)?);
+            update_fields.insert("phone_index", field_encryption::phone_index(&phone).map_err(AppError::DatabaseError)?);
+        }
+        if let Some(address) = owner_update.address {update_fields.insert("address", encrypt(&address)?);}
+        if let Some(home_access) = owner_update.home_access {update_fields.insert("home_access", encrypt(&home_access)?);}
         // Check for empty request
         if update_fields.is_empty() { 
             return Err(AppError::ParseError("No Fields provided to Updated".to_string())); 
         } // this empty field shouldnt happen anymore because I added the request validation in the router
+        update_fields.insert("updated_at", DateTime::now());   // bookkeeping, see create_owner
+        update_fields.insert("updated_by", actor);
     
         // Prepare filter and update 
-        let filter = doc! { "_id": obj_id };
-        let update =  doc! { "$set": update_fields };
+        let filter = policy::restrict(versioning::version_filter(obj_id, version_check), scope);  // only matches the version the client has seen
+        let update =  doc! { "$set": update_fields.clone(), "$inc": { "version": 1 } };
 
         // Execute operation in the DB
+        // previous state returned, for the audit log
         let owner_collection = db.get_owners_collection();
-        let result = owner_collection
-            .update_one(filter, update)
-            .await;
+        let result = auth::update_principal(db, owner_collection, obj_id, filter, update, new_email.as_deref()).await;
 
-        // evaluate result and return, if update ok, return id of the updated doc
+        // evaluate result and return, if update ok, return the updated doc (with its new version)
         match result {
-            Ok(_result) => Ok(obj_id.to_hex()),   
+            Ok(Some(previous_owner)) => {
+                audit::record_update(db, "owners", obj_id, actor, &previous_owner, &update_fields).await;
+                versioning::apply_update(&previous_owner, &update_fields)
+            },
+            Ok(None) => Err(versioning::missing_or_conflict(owner_collection, obj_id, scope).await),
+            Err(e) if db::is_duplicate_key(&e) => Err(AppError::Conflict("another account already uses this email".to_string())),
             Err(e) => Err(AppError::DatabaseError(format!("Failed to Update Owner: {}", e))),
         }
 
@@ -133,7 +207,7 @@ use crate::services::db::AppDatabase;
 
     // DELETE for Owner: 
     // In mongodb, you can delete a document from a collection by calling the delete_one() method on a Collection instance.
-    pub async fn delete_owner(db: &AppDatabase, owner_id: &str) -> Result<String, AppError> {
+    pub async fn delete_owner(db: &AppDatabase, owner_id: &str, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<String, AppError> {
         // REF: delete_one() -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/deleteOne/
        
         // Verify/Parse received ID 
@@ -144,12 +218,12 @@ use crate::services::db::AppDatabase;
         };
 
         // create query filter
-        let filter = doc! { "_id": obj_id, };
+        let filter = policy::restrict(versioning::version_filter(obj_id, version_check), scope);
 
         // Execute operation at DB
         let owner_collection = db.get_owners_collection();
         let result = owner_collection
-                .delete_one(filter)
+                .find_one_and_delete(filter)
                 .await;
                 //.ok()
                 //.expect("Error deleting booking");
@@ -157,9 +231,12 @@ use crate::services::db::AppDatabase;
         //Ok(result.deleted_count>0)
         // evaluate result and return, if delete ok, return id of the updated doc
         match result {
-            Ok(delete_result) if delete_result.deleted_count >= 1 => Ok(obj_id.to_hex()),
-            Ok(delete_result) if delete_result.deleted_count == 0 => Err(AppError::NotFound),
-            Ok(_) => Err(AppError::InternalError),
+            Ok(Some(deleted_owner)) => {
+                audit::record_delete(db, "owners", obj_id, actor, &deleted_owner).await;
+                auth::delete_credentials(db, obj_id).await;   // the account cannot log in any more
+                Ok(obj_id.to_hex())
+            },
+            Ok(None) => Err(versioning::missing_or_conflict(owner_collection, obj_id, scope).await),
             Err(db_error) => Err(AppError::DatabaseError(format!("Failed to Delete Owner: {}", db_error))),
         }
     }
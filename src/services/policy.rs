@@ -0,0 +1,415 @@
+use bson::{doc, oid::ObjectId, Bson, Document};
+
+use crate::{app_errors::errors::AppError,
+            models::auth_model::{Principal, Role}};
+use crate::services::db::AppDatabase;
+
+
+    // -------------------------------------------
+    // Authorisation: roles and per-resource scopes
+    // -------------------------------------------
+    // What a principal may reach is a *scope*: a MongoDB filter the services add to their queries
+    // (list, read, update, delete). A document out of scope is never loaded, it is "not found" (404)
+    // like a document that does not exist. A role without any access to a resource gets Forbidden (403).
+    //
+    //   resource           read                                      update / delete
+    //   owners             admin, dispatcher: all                    admin: all
+    //                      owner: itself                             owner: itself
+    //                      sitter: the owners of its bookings
+    //   dogs               admin, dispatcher: all                    admin: all
+    //                      owner: its dogs                           owner: its dogs
+    //                      sitter: the dogs of those owners
+    //   bookings           admin, dispatcher: all                    admin, dispatcher: all
+    //                      owner: its bookings                       owner: its bookings
+    //                      sitter: the bookings assigned to it       (walks, tracks and reports: see walk_tracking::authorise)
+    //   sitters            everybody: all (directory)                admin: all, sitter: itself
+    //   sitter documents   admin, dispatcher: all, sitter: its own   admin: all, sitter: its own
+    //
+    // Creations are checked on the new document (check_sign_up, check_dog_owner, check_booking_fields),
+    // the routes for staff only (audit, outbox, webhooks, integrity, API keys) use require_role.
+    //
+    // Integrations (API keys, see services/api_keys.rs) reach all the documents of the resources their key
+    // grants: "bookings:read", "bookings:write" (write includes read), ... and nothing else.
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Resource {
+        Owners,
+        Sitters,
+        Dogs,
+        Bookings,
+        SitterDocuments,
+    }
+
+    impl Resource {
+        pub const ALL: [Resource; 5] = [Resource::Owners, Resource::Sitters, Resource::Dogs, Resource::Bookings, Resource::SitterDocuments];
+
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Resource::Owners => "owners",
+                Resource::Sitters => "sitters",
+                Resource::Dogs => "dogs",
+                Resource::Bookings => "bookings",
+                Resource::SitterDocuments => "sitter documents",
+            }
+        }
+
+        // name of the resource in the grants of an API key
+        pub fn key(&self) -> &'static str {
+            match self {
+                Resource::SitterDocuments => "sitter_documents",
+                _ => self.as_str(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Access {
+        Read,
+        Write,   // update and delete
+    }
+
+    impl Access {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Access::Read => "read",
+                Access::Write => "write",
+            }
+        }
+    }
+
+    // "<resource>:<read|write>", e.g. "bookings:write" (the grants of an API key)
+    pub fn parse_grant(grant: &str) -> Result<(Resource, Access), AppError> {
+        let (resource, access) = grant.split_once(':').unwrap_or((grant, ""));
+        let resource = Resource::ALL.into_iter().find(|candidate| candidate.key() == resource);
+        let access = [Access::Read, Access::Write].into_iter().find(|candidate| candidate.as_str() == access);
+        match (resource, access) {
+            (Some(resource), Some(access)) => Ok((resource, access)),
+            _ => Err(AppError::ParseError(format!(
+                "Unknown grant '{}', expected <resource>:<read|write> with a resource in {:?}",
+                grant,
+                Resource::ALL.iter().map(Resource::key).collect::<Vec<&str>>(),
+            ))),
+        }
+    }
+
+    // an API key granting write access also grants read access
+    fn granted(principal: &Principal, resource: Resource, access: Access) -> bool {
+        principal.grants.iter().any(|grant| match parse_grant(grant) {
+            Ok((granted_resource, granted_access)) => granted_resource == resource && (granted_access == access || granted_access == Access::Write),
+            Err(_) => false,
+        })
+    }
+
+    // The filter selecting what 'principal' may read or change in 'resource' ({} for everything)
+    pub async fn scope(db: &AppDatabase, principal: &Principal, resource: Resource, access: Access) -> Result<Document, AppError> {
+        let scope = match rule(principal, resource, access)? {
+            Rule::Filter(filter) => filter,
+            Rule::BookedOwners(field) => doc! { field: { "$in": booked_owners(db, principal.id).await? } },
+        };
+        Ok(scope)
+    }
+
+    // The table above, before the owners booked by a sitter are looked up
+    enum Rule {
+        Filter(Document),
+        BookedOwners(&'static str),   // the field holding the owner: one of the owners of the sitter's bookings
+    }
+
+    fn rule(principal: &Principal, resource: Resource, access: Access) -> Result<Rule, AppError> {
+        let id = principal.id;
+        let filter = match (principal.role, resource, access) {
+            (Role::Admin, _, _) => doc! {},
+            (Role::Dispatcher, _, Access::Read) => doc! {},
+            (Role::Dispatcher, Resource::Bookings, Access::Write) => doc! {},
+
+            (Role::Integration, _, _) if granted(principal, resource, access) => doc! {},
+            (Role::Integration, _, _) => return Err(forbidden(principal, resource, access)),
+
+            (_, Resource::Sitters, Access::Read) => doc! {},
+
+            (Role::Owner, Resource::Owners, _) => doc! { "_id": id },
+            (Role::Owner, Resource::Dogs | Resource::Bookings, _) => doc! { "owner": id },
+
+            (Role::Sitter, Resource::Sitters | Resource::SitterDocuments, _) => doc! { "_id": id },
+            (Role::Sitter, Resource::Bookings, Access::Read) => doc! { "sitter": id },
+            (Role::Sitter, Resource::Owners, Access::Read) => return Ok(Rule::BookedOwners("_id")),
+            (Role::Sitter, Resource::Dogs, Access::Read) => return Ok(Rule::BookedOwners("owner")),
+
+            _ => return Err(forbidden(principal, resource, access)),
+        };
+        Ok(Rule::Filter(filter))
+    }
+
+    // 'filter' AND 'scope' (a plain merge would let the scope overwrite an `_id` of the filter)
+    pub fn restrict(filter: Document, scope: &Document) -> Document {
+        match scope.is_empty() {
+            true => filter,
+            false => doc! { "$and": [filter, scope.clone()] },
+        }
+    }
+
+    // Routes reserved to some roles (e.g. the admin routes)
+    pub fn require_role(principal: &Principal, roles: &[Role]) -> Result<(), AppError> {
+        match roles.contains(&principal.role) {
+            true => Ok(()),
+            false => Err(AppError::Forbidden(format!(
+                "reserved to: {}",
+                roles.iter().map(Role::as_str).collect::<Vec<&str>>().join(", "),
+            ))),
+        }
+    }
+
+    // POST /owners, POST /sitters: a sign-up (no token), an admin creating the account,
+    // or an integration allowed to write 'resource'
+    pub fn check_sign_up(principal: Option<&Principal>, resource: Resource) -> Result<(), AppError> {
+        match principal {
+            None => Ok(()),
+            Some(principal) if principal.role == Role::Integration && granted(principal, resource, Access::Write) => Ok(()),
+            Some(principal) => require_role(principal, &[Role::Admin]),
+        }
+    }
+
+    // POST /dogs and PUT /dogs/{id}: owners only register their own dogs (and cannot give them away)
+    pub fn check_dog_owner(principal: &Principal, owner: Option<ObjectId>) -> Result<(), AppError> {
+        match principal.role {
+            Role::Admin => Ok(()),
+            Role::Integration if granted(principal, Resource::Dogs, Access::Write) => Ok(()),
+            Role::Owner if owner.is_none_or(|owner| owner == principal.id) => Ok(()),
+            Role::Owner => Err(AppError::Forbidden("an owner can only register its own dogs".to_string())),
+            _ => Err(forbidden(principal, Resource::Dogs, Access::Write)),
+        }
+    }
+
+    // POST /bookings and PUT /bookings/{id}: owners book for themselves and their own dogs,
+    // the sitter is assigned by a dispatcher (or an admin)
+    pub async fn check_booking_fields(db: &AppDatabase, principal: &Principal, owner: Option<ObjectId>, sitter: Option<ObjectId>, dogs: &[ObjectId]) -> Result<(), AppError> {
+        if !check_booking_parties(principal, owner, sitter)? {
+            return Ok(());
+        }
+        let mut dogs = dogs.to_vec();
+        dogs.sort();
+        dogs.dedup();
+        let expected = dogs.len() as u64;
+        let own_dogs = db.get_dogs_collection()
+            .count_documents(doc! { "_id": { "$in": dogs }, "owner": principal.id })
+            .await?;
+        match own_dogs == expected {
+            true => Ok(()),
+            false => Err(AppError::Forbidden("an owner can only book walks for its own dogs".to_string())),
+        }
+    }
+
+    // the owner and sitter of the booking; true: the dogs must be the principal's own too (an owner)
+    fn check_booking_parties(principal: &Principal, owner: Option<ObjectId>, sitter: Option<ObjectId>) -> Result<bool, AppError> {
+        match principal.role {
+            Role::Admin | Role::Dispatcher => Ok(false),
+            Role::Integration if granted(principal, Resource::Bookings, Access::Write) => Ok(false),
+            Role::Owner => {
+                if owner.is_some_and(|owner| owner != principal.id) {
+                    return Err(AppError::Forbidden("an owner can only book for itself".to_string()));
+                }
+                if sitter.is_some() {
+                    return Err(AppError::Forbidden("the sitter of a booking is assigned by a dispatcher".to_string()));
+                }
+                Ok(true)
+            },
+            Role::Sitter | Role::Integration => Err(forbidden(principal, Resource::Bookings, Access::Write)),
+        }
+    }
+
+    // the owners of the bookings a sitter is (or was) assigned to
+    async fn booked_owners(db: &AppDatabase, sitter_id: ObjectId) -> Result<Vec<Bson>, AppError> {
+        Ok(db.get_bookings_collection().distinct("owner", doc! { "sitter": sitter_id }).await?)
+    }
+
+    fn forbidden(principal: &Principal, resource: Resource, access: Access) -> AppError {
+        if principal.role == Role::Integration {
+            return AppError::Forbidden(format!("the API key does not grant {}:{}", resource.key(), access.as_str()));
+        }
+        let access = match access {
+            Access::Read => "read",
+            Access::Write => "change",
+        };
+        AppError::Forbidden(format!("{} accounts cannot {} {}", principal.role.as_str(), access, resource.as_str()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // what a rule gives, relative to the principal
+        #[derive(Debug, PartialEq)]
+        enum Expected {
+            All,
+            Itself,          // { _id: principal }
+            OwnedBy,         // { owner: principal }
+            AssignedTo,      // { sitter: principal }
+            BookedOwners,    // { _id: { $in: owners of the sitter's bookings } }
+            OfBookedOwners,  // { owner: { $in: owners of the sitter's bookings } }
+            Forbidden,
+        }
+        use Expected::*;
+
+        fn principal(role: Role, grants: &[&str]) -> Principal {
+            Principal {
+                id: ObjectId::new(),
+                role,
+                token_id: "test".to_string(),
+                expires_at: i64::MAX,
+                grants: grants.iter().map(|grant| grant.to_string()).collect(),
+            }
+        }
+
+        fn expected(principal: &Principal, resource: Resource, access: Access) -> Expected {
+            match rule(principal, resource, access) {
+                Ok(Rule::Filter(filter)) if filter.is_empty() => All,
+                Ok(Rule::Filter(filter)) if filter == doc! { "_id": principal.id } => Itself,
+                Ok(Rule::Filter(filter)) if filter == doc! { "owner": principal.id } => OwnedBy,
+                Ok(Rule::Filter(filter)) if filter == doc! { "sitter": principal.id } => AssignedTo,
+                Ok(Rule::Filter(filter)) => panic!("unexpected scope {} for {:?} {:?} {:?}", filter, principal.role, resource, access),
+                Ok(Rule::BookedOwners("_id")) => BookedOwners,
+                Ok(Rule::BookedOwners("owner")) => OfBookedOwners,
+                Ok(Rule::BookedOwners(field)) => panic!("unexpected booked owners field {}", field),
+                Err(AppError::Forbidden(_)) => Forbidden,
+                Err(app_error) => panic!("unexpected error {:?}", app_error),
+            }
+        }
+
+        // a resource, then what a role gets to read it and to change it
+        type Row = (Resource, Expected, Expected);
+
+        #[test]
+        fn scopes_follow_the_table() {
+            use Resource::*;
+            // (resource, read, write) for each role, the integration key grants "bookings:read" and "dogs:write"
+            let table: [(Role, [Row; 5]); 5] = [
+                (Role::Owner, [
+                    (Owners, Itself, Itself),
+                    (Sitters, All, Forbidden),
+                    (Dogs, OwnedBy, OwnedBy),
+                    (Bookings, OwnedBy, OwnedBy),
+                    (SitterDocuments, Forbidden, Forbidden),
+                ]),
+                (Role::Sitter, [
+                    (Owners, BookedOwners, Forbidden),
+                    (Sitters, All, Itself),
+                    (Dogs, OfBookedOwners, Forbidden),
+                    (Bookings, AssignedTo, Forbidden),
+                    (SitterDocuments, Itself, Itself),
+                ]),
+                (Role::Dispatcher, [
+                    (Owners, All, Forbidden),
+                    (Sitters, All, Forbidden),
+                    (Dogs, All, Forbidden),
+                    (Bookings, All, All),
+                    (SitterDocuments, All, Forbidden),
+                ]),
+                (Role::Admin, [
+                    (Owners, All, All),
+                    (Sitters, All, All),
+                    (Dogs, All, All),
+                    (Bookings, All, All),
+                    (SitterDocuments, All, All),
+                ]),
+                (Role::Integration, [
+                    (Owners, Forbidden, Forbidden),
+                    (Sitters, Forbidden, Forbidden),
+                    (Dogs, All, All),
+                    (Bookings, All, Forbidden),
+                    (SitterDocuments, Forbidden, Forbidden),
+                ]),
+            ];
+
+            for (role, rows) in table {
+                let principal = principal(role, &["bookings:read", "dogs:write"]);
+                for (resource, read, write) in rows {
+                    assert_eq!(expected(&principal, resource, Access::Read), read, "{:?} reading {:?}", role, resource);
+                    assert_eq!(expected(&principal, resource, Access::Write), write, "{:?} changing {:?}", role, resource);
+                }
+            }
+        }
+
+        #[test]
+        fn integrations_without_grants_reach_nothing() {
+            let principal = principal(Role::Integration, &[]);
+            for resource in Resource::ALL {
+                for access in [Access::Read, Access::Write] {
+                    assert_eq!(expected(&principal, resource, access), Forbidden, "{:?} {:?}", resource, access);
+                }
+            }
+            // unknown grants give nothing either
+            let principal = self::principal(Role::Integration, &["bookings", "bookings:admin", "*:write"]);
+            assert_eq!(expected(&principal, Resource::Bookings, Access::Read), Forbidden);
+        }
+
+        #[test]
+        fn grants_are_parsed() {
+            assert_eq!(parse_grant("sitter_documents:write").unwrap(), (Resource::SitterDocuments, Access::Write));
+            assert_eq!(parse_grant("bookings:read").unwrap(), (Resource::Bookings, Access::Read));
+            for invalid in ["bookings", "bookings:", "sitter documents:read", "walks:read", ""] {
+                assert!(matches!(parse_grant(invalid), Err(AppError::ParseError(_))), "{}", invalid);
+            }
+        }
+
+        #[test]
+        fn restrict_keeps_the_filter_of_the_caller() {
+            let requested = ObjectId::new();
+            let itself = ObjectId::new();
+
+            // no scope: the filter as it is
+            assert_eq!(restrict(doc! { "_id": requested }, &doc! {}), doc! { "_id": requested });
+            // both must match: another owner's document is not found, instead of the scope replacing its _id
+            assert_eq!(
+                restrict(doc! { "_id": requested }, &doc! { "_id": itself }),
+                doc! { "$and": [{ "_id": requested }, { "_id": itself }] },
+            );
+            assert_eq!(
+                restrict(doc! { "_id": requested, "cancelled": false }, &doc! { "owner": itself }),
+                doc! { "$and": [{ "_id": requested, "cancelled": false }, { "owner": itself }] },
+            );
+        }
+
+        #[test]
+        fn owners_book_for_themselves_without_choosing_the_sitter() {
+            let owner = principal(Role::Owner, &[]);
+            let other = ObjectId::new();
+
+            // the dogs are then checked against the owner's (database)
+            assert!(check_booking_parties(&owner, None, None).unwrap());
+            assert!(check_booking_parties(&owner, Some(owner.id), None).unwrap());
+            assert!(matches!(check_booking_parties(&owner, Some(other), None), Err(AppError::Forbidden(_))));
+            assert!(matches!(check_booking_parties(&owner, None, Some(other)), Err(AppError::Forbidden(_))));
+
+            for staff in [Role::Admin, Role::Dispatcher] {
+                assert!(!check_booking_parties(&principal(staff, &[]), Some(other), Some(other)).unwrap());
+            }
+            assert!(!check_booking_parties(&principal(Role::Integration, &["bookings:write"]), Some(other), Some(other)).unwrap());
+            assert!(matches!(check_booking_parties(&principal(Role::Integration, &["bookings:read"]), None, None), Err(AppError::Forbidden(_))));
+            assert!(matches!(check_booking_parties(&principal(Role::Sitter, &[]), None, None), Err(AppError::Forbidden(_))));
+        }
+
+        #[test]
+        fn owners_only_register_their_own_dogs() {
+            let owner = principal(Role::Owner, &[]);
+            assert!(check_dog_owner(&owner, None).is_ok());
+            assert!(check_dog_owner(&owner, Some(owner.id)).is_ok());
+            assert!(matches!(check_dog_owner(&owner, Some(ObjectId::new())), Err(AppError::Forbidden(_))));
+
+            assert!(check_dog_owner(&principal(Role::Admin, &[]), Some(ObjectId::new())).is_ok());
+            assert!(check_dog_owner(&principal(Role::Integration, &["dogs:write"]), Some(ObjectId::new())).is_ok());
+            for role in [Role::Sitter, Role::Dispatcher] {
+                assert!(matches!(check_dog_owner(&principal(role, &[]), None), Err(AppError::Forbidden(_))), "{:?}", role);
+            }
+        }
+
+        #[test]
+        fn accounts_are_created_by_sign_up_admins_and_granted_integrations() {
+            assert!(check_sign_up(None, Resource::Owners).is_ok());
+            assert!(check_sign_up(Some(&principal(Role::Admin, &[])), Resource::Sitters).is_ok());
+            assert!(check_sign_up(Some(&principal(Role::Integration, &["owners:write"])), Resource::Owners).is_ok());
+            assert!(check_sign_up(Some(&principal(Role::Integration, &["owners:write"])), Resource::Sitters).is_err());
+            for role in [Role::Owner, Role::Sitter, Role::Dispatcher] {
+                assert!(matches!(check_sign_up(Some(&principal(role, &[])), Resource::Owners), Err(AppError::Forbidden(_))), "{:?}", role);
+            }
+        }
+    }
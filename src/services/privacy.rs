@@ -0,0 +1,259 @@
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
+use futures::StreamExt;
+use mongodb::Collection;
+use serde::de::DeserializeOwned;
+use validator::Validate;
+
+use crate::{app_errors::errors::AppError,
+            models::attachment_model::AttachmentResponse,
+            models::audit_model::AuditEntryResponse,
+            models::booking_model::BookingResponse,
+            models::dog_model::DogResponse,
+            models::owner_model::{Owner, OwnerResponse},
+            models::privacy_model::{AccountExport, BookingExport, DogExport, EraseRequest, ErasureResponse, ExternalIdentityExport, OwnerExport, EXPORT_FORMAT},
+            models::track_model::TrackPointResponse,
+            models::walk_report_model::WalkReportResponse};
+use crate::services::{attachments::{self, AttachmentKind, AttachmentTarget}, audit, auth, db::AppDatabase, owners, walk_reports};
+
+
+    // -------------------------------------------
+    // Personal data requests of the owners (GDPR)
+    // -------------------------------------------
+    // Export (subject access): the owner, its account, dogs, bookings with their reports, photos and tracks,
+    // and the audit log about them, in one JSON document (models/privacy_model.rs).
+    //
+    // Erasure (right to be forgotten), the bookings are kept for the statistics, without anything personal:
+    //   owner          name, email, phone, address, home access replaced by placeholders, erased_at set
+    //                  (the document stays: the bookings still point to it, and `doctor` would archive them otherwise)
+    //   account        password login, sessions, single sign-on links and email links deleted
+    //   dogs           name replaced, photos deleted (breed and age are kept)
+    //   bookings       kept: dates, durations, sitters, walk statistics
+    //                  track points and photos deleted, walk reports lose their notes, photos and shared link
+    //   audit log      the personal values are removed from the entries of the owner, its dogs, reports and photos
+    // Both requests are recorded in the audit log (action export / erase) with who made them.
+    // An erasure that fails half way can be run again: the owner is only marked as erased at the end.
+
+    const ERASED_NAME: &str = "Erased owner";
+    const ERASED_PHONE: &str = "0000000";
+    const ERASED_ADDRESS: &str = "erased";
+    const ERASED_DOG_NAME: &str = "Erased dog";
+
+    // the fields of the audit entries holding personal values
+    const OWNER_PERSONAL_FIELDS: [&str; 7] = ["name", "email", "email_index", "phone", "phone_index", "address", "home_access"];
+    const DOG_PERSONAL_FIELDS: [&str; 1] = ["name"];
+    const REPORT_PERSONAL_FIELDS: [&str; 3] = ["notes", "photos", "share_token"];
+    const ATTACHMENT_PERSONAL_FIELDS: [&str; 1] = ["filename"];
+
+    // EXPORT everything stored about an owner (GET /owners/{id}/export)
+    pub async fn export_owner(db: &AppDatabase, owner_id: &str, scope: &Document, actor: &str) -> Result<OwnerExport, AppError> {
+        let owner = owners::read_owner(db, owner_id, scope).await?;
+        let dogs = find_all(db.get_dogs_collection(), doc! { "owner": owner._id }).await?;
+        let bookings = find_all(db.get_bookings_collection(), doc! { "owner": owner._id }).await?;
+        let dog_ids: Vec<ObjectId> = dogs.iter().map(|dog| dog._id).collect();
+        let booking_ids: Vec<ObjectId> = bookings.iter().map(|booking| booking._id).collect();
+
+        let account = export_account(db, owner._id).await?;
+
+        let mut dog_exports = Vec::with_capacity(dogs.len());
+        for dog in dogs {
+            let photos = attachments::read_attachments(db, photos_of("dogs", dog._id)).await?;
+            dog_exports.push(DogExport {
+                dog: DogResponse::from(dog),
+                photos: photos.into_iter().map(AttachmentResponse::from).collect(),
+            });
+        }
+
+        let mut booking_exports = Vec::with_capacity(bookings.len());
+        for booking in bookings {
+            let report = db.get_walk_reports_collection().find_one(doc! { "booking_id": booking._id }).await?;
+            let photos = attachments::read_attachments(db, photos_of("bookings", booking._id)).await?;
+            let track = find_all(db.get_track_points_collection(), doc! { "booking_id": booking._id }).await?;
+            booking_exports.push(BookingExport {
+                booking: BookingResponse::from(booking),
+                report: report.map(WalkReportResponse::from),
+                photos: photos.into_iter().map(AttachmentResponse::from).collect(),
+                track: track.into_iter().map(TrackPointResponse::from).collect(),
+            });
+        }
+
+        let archived_bookings = find_all(db.get_booking_archive_collection(), doc! { "owner": owner._id })
+            .await?
+            .into_iter()
+            .map(|archived_booking| Bson::Document(archived_booking).into_relaxed_extjson())
+            .collect();
+
+        let history = audit::read_histories(db, &[("owners", &[owner._id]), ("dogs", &dog_ids), ("bookings", &booking_ids)]).await?;
+
+        audit::record_request(db, "owners", owner._id, "export", actor, doc! {
+            "dogs": dog_ids.len() as i64,
+            "bookings": booking_ids.len() as i64,
+        }).await;
+
+        Ok(OwnerExport {
+            format: EXPORT_FORMAT.to_string(),
+            exported_at: DateTime::now().to_chrono().to_rfc3339(),
+            owner: OwnerResponse::from(owner),
+            account,
+            dogs: dog_exports,
+            bookings: booking_exports,
+            archived_bookings,
+            history: history.into_iter().map(AuditEntryResponse::from).collect(),
+        })
+    }
+
+    async fn export_account(db: &AppDatabase, owner_id: ObjectId) -> Result<AccountExport, AppError> {
+        let credential = db.get_credentials_collection()
+            .find_one(doc! { "principal_id": owner_id })
+            .await?;
+        let identities = find_all(db.get_external_identities_collection(), doc! { "principal_id": owner_id }).await?;
+
+        Ok(AccountExport {
+            login: credential.as_ref().map(|credential| credential.login.clone()),
+            login_created_at: credential.map(|credential| credential.created_at.to_chrono().to_rfc3339()),
+            external_identities: identities
+                .into_iter()
+                .map(|identity| ExternalIdentityExport {
+                    issuer: identity.issuer,
+                    subject: identity.subject,
+                    email: identity.email,
+                    created_at: identity.created_at.to_chrono().to_rfc3339(),
+                    last_login_at: identity.last_login_at.to_chrono().to_rfc3339(),
+                })
+                .collect(),
+        })
+    }
+
+    // ERASE the personal data of an owner (POST /owners/{id}/erase), see the header for what is kept
+    pub async fn erase_owner(db: &AppDatabase, owner_id: &str, request: EraseRequest, actor: &str) -> Result<ErasureResponse, AppError> {
+        let reason = request.reason.trim();
+        if reason.is_empty() {
+            return Err(AppError::ParseError("the reason of the erasure is required".to_string()));
+        }
+        request.validate().map_err(|e| AppError::ParseError(e.to_string()))?;
+        let obj_id = match ObjectId::parse_str(owner_id) {
+            Ok(id) => id,
+            Err(_) => return Err(AppError::InvalidId),
+        };
+        let owner = db.get_owners_collection().find_one(doc! { "_id": obj_id }).await?.ok_or(AppError::NotFound)?;
+        if owner.erased_at.is_some() {
+            return Err(AppError::Conflict("the personal data of this owner was already erased".to_string()));
+        }
+        let mut erasure = ErasureResponse { owner_id: obj_id.to_hex(), ..Default::default() };
+
+        // the account: no more logins, sessions nor links sent by email
+        auth::delete_credentials(db, obj_id).await;
+        erasure.external_identities_deleted = db.get_external_identities_collection()
+            .delete_many(doc! { "principal_id": obj_id })
+            .await?
+            .deleted_count;
+        db.get_email_tokens_collection().delete_many(doc! { "principal_id": obj_id }).await?;
+
+        // the dogs: photos deleted, name replaced
+        let now = DateTime::now();
+        let dogs = find_all(db.get_dogs_collection(), doc! { "owner": obj_id }).await?;
+        let dog_ids: Vec<ObjectId> = dogs.iter().map(|dog| dog._id).collect();
+        let mut attachment_ids = Vec::new();
+        for dog_id in &dog_ids {
+            attachment_ids.extend(delete_photos(db, photos_of("dogs", *dog_id), actor).await?);
+        }
+        erasure.dogs_anonymised = db.get_dogs_collection()
+            .update_many(
+                doc! { "owner": obj_id },
+                doc! { "$set": { "name": ERASED_DOG_NAME, "updated_at": now, "updated_by": actor }, "$inc": { "version": 1 } },
+            )
+            .await?
+            .modified_count;
+
+        // the bookings stay, without their track, photos and the personal parts of their report
+        let bookings = find_all(db.get_bookings_collection(), doc! { "owner": obj_id }).await?;
+        let booking_ids: Vec<ObjectId> = bookings.iter().map(|booking| booking._id).collect();
+        erasure.bookings_kept = booking_ids.len() as u64;
+        for booking_id in &booking_ids {
+            attachment_ids.extend(delete_photos(db, photos_of("bookings", *booking_id), actor).await?);
+        }
+        erasure.attachments_deleted = attachment_ids.len() as u64;
+        erasure.track_points_deleted = db.get_track_points_collection()
+            .delete_many(doc! { "booking_id": { "$in": &booking_ids } })
+            .await?
+            .deleted_count;
+        let reports = find_all(db.get_walk_reports_collection(), doc! { "booking_id": { "$in": &booking_ids } }).await?;
+        let report_ids: Vec<ObjectId> = reports.iter().map(|report| report._id).collect();
+        for report_id in &report_ids {
+            // a new share token: the links already sent stop working
+            db.get_walk_reports_collection()
+                .update_one(doc! { "_id": report_id }, doc! { "$set": { "notes": Bson::Null, "photos": [], "share_token": walk_reports::share_token() } })
+                .await?;
+        }
+        erasure.walk_reports_cleared = report_ids.len() as u64;
+
+        // the history keeps who did what and when, not the values
+        erasure.audit_entries_redacted = audit::redact(db, "owners", &[obj_id], &OWNER_PERSONAL_FIELDS).await?
+            + audit::redact(db, "dogs", &dog_ids, &DOG_PERSONAL_FIELDS).await?
+            + audit::redact(db, "walk_reports", &report_ids, &REPORT_PERSONAL_FIELDS).await?
+            + audit::redact(db, "attachments", &attachment_ids, &ATTACHMENT_PERSONAL_FIELDS).await?;
+
+        // the owner last: until then the erasure can be run again
+        let erased_owner = Owner {
+            name: ERASED_NAME.to_string(),
+            email: format!("erased-{}@invalid", obj_id.to_hex()),
+            email_index: None,
+            phone: ERASED_PHONE.to_string(),
+            phone_index: None,
+            address: ERASED_ADDRESS.to_string(),
+            home_access: None,
+            email_verified_at: None,
+            erased_at: Some(now),
+            version: owner.version + 1,
+            updated_at: Some(now),
+            updated_by: Some(actor.to_string()),
+            ..owner
+        };
+        db.get_owners_collection().replace_one(doc! { "_id": obj_id }, &erased_owner).await?;
+        erasure.erased_at = now.to_chrono().to_rfc3339();
+
+        audit::record_request(db, "owners", obj_id, "erase", actor, doc! {
+            "reason": reason,
+            "dogs_anonymised": erasure.dogs_anonymised as i64,
+            "bookings_kept": erasure.bookings_kept as i64,
+            "walk_reports_cleared": erasure.walk_reports_cleared as i64,
+            "track_points_deleted": erasure.track_points_deleted as i64,
+            "attachments_deleted": erasure.attachments_deleted as i64,
+            "external_identities_deleted": erasure.external_identities_deleted as i64,
+            "audit_entries_redacted": erasure.audit_entries_redacted as i64,
+        }).await;
+        Ok(erasure)
+    }
+
+    // the photos of a dog or a booking
+    fn photos_of(resource: &'static str, resource_id: ObjectId) -> AttachmentTarget {
+        AttachmentTarget { resource, resource_id, kind: AttachmentKind::Photo }
+    }
+
+    // deletes the photos (and their files), returns their IDs
+    async fn delete_photos(db: &AppDatabase, target: AttachmentTarget, actor: &str) -> Result<Vec<ObjectId>, AppError> {
+        let mut deleted = Vec::new();
+        for photo in attachments::read_attachments(db, target).await? {
+            match attachments::delete_attachment(db, target, &photo._id.to_hex(), actor).await {
+                Ok(_) | Err(AppError::NotFound) => deleted.push(photo._id),   // or deleted in the meantime
+                Err(app_error) => return Err(app_error),
+            }
+        }
+        Ok(deleted)
+    }
+
+    // every matching document, in insertion order
+    async fn find_all<T>(collection: &Collection<T>, filter: Document) -> Result<Vec<T>, AppError>
+    where
+        T: DeserializeOwned + Send + Sync,
+    {
+        let mut result_cursor = collection.find(filter).sort(doc! { "_id": 1 }).await?;
+
+        let mut vec_of_documents = Vec::<T>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(document) => vec_of_documents.push(document),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading {} entries from DB: {}", collection.name(), e))),
+            }
+        }
+        Ok(vec_of_documents)
+    }
@@ -0,0 +1,289 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use futures::future::BoxFuture;
+use log::{info, warn};
+use mongodb::{bson::doc, options::ReturnDocument};
+
+use crate::config::{RateLimit, RateLimitConfig};
+use crate::services::db::AppDatabase;
+
+
+    // -------------------------------------------
+    // Rate limiter: token buckets per client (see routes/rate_limit.rs for the middleware)
+    // -------------------------------------------
+    // A client is its API key, else the account of its access token, else its IP address.
+    // Each client has one bucket for the routes of RATE_LIMIT_ROUTES it calls, and one shared by every other route
+    // (RATE_LIMIT_DEFAULT). A bucket holds 'requests' tokens, each request takes one, and it refills continuously
+    // at requests / period: bursts are allowed up to the limit, then the client gets 429 until a token is back.
+    // RATE_LIMIT_STORE selects where the buckets live:
+    //   memory   in this instance (default): with N instances behind a load balancer a client gets up to N times the limit
+    //   mongodb  in the 'rate_limits' collection, shared by every instance (one update per request)
+
+    // buckets kept by the memory store before the full ones are dropped (a full bucket is the same as none)
+    const MEMORY_BUCKETS_SWEEP: usize = 10_000;
+
+    // the outcome of a request for the RateLimit-* headers
+    #[derive(Debug, Clone, Copy)]
+    pub struct Decision {
+        pub allowed: bool,
+        pub remaining: u32,          // requests left right now
+        pub reset: Duration,         // until the bucket is full again
+        pub retry_after: Duration,   // until the next request is allowed (zero when allowed)
+    }
+
+    impl Decision {
+        // from the tokens left once the request took its token (or not)
+        fn from_tokens(allowed: bool, tokens: f64, limit: &RateLimit) -> Self {
+            let per_second = limit.requests as f64 / limit.period.as_secs_f64();
+            let tokens = tokens.clamp(0.0, limit.requests as f64);
+            Decision {
+                allowed,
+                remaining: tokens.floor() as u32,
+                reset: Duration::from_secs_f64((limit.requests as f64 - tokens) / per_second),
+                retry_after: if allowed { Duration::ZERO } else { Duration::from_secs_f64((1.0 - tokens).max(0.0) / per_second) },
+            }
+        }
+    }
+
+    pub trait RateLimitStore: Send + Sync {
+        fn name(&self) -> &'static str;
+        // takes a token from the bucket 'key' if there is one
+        fn take<'a>(&'a self, key: &'a str, limit: &'a RateLimit) -> BoxFuture<'a, Result<Decision, String>>;
+    }
+
+    // shared by the middleware (web::Data<RateLimiter>)
+    pub struct RateLimiter {
+        config: RateLimitConfig,
+        store: Arc<dyn RateLimitStore>,
+    }
+
+    impl RateLimiter {
+        pub fn new(config: RateLimitConfig, db: Arc<AppDatabase>) -> Self {
+            let store: Arc<dyn RateLimitStore> = match config.store.as_str() {
+                "mongodb" => Arc::new(MongoStore { db }),
+                "memory" => Arc::new(MemoryStore::default()),
+                other => {
+                    warn!("Unknown RATE_LIMIT_STORE '{}', the rate limits are kept in memory", other);
+                    Arc::new(MemoryStore::default())
+                },
+            };
+            if config.enabled {
+                info!("Rate limits kept in the '{}' store", store.name());
+            } else {
+                info!("Rate limiting disabled (RATE_LIMIT_ENABLED=false)");
+            }
+            RateLimiter { config, store }
+        }
+
+        pub fn enabled(&self) -> bool {
+            self.config.enabled
+        }
+
+        pub fn trust_proxy(&self) -> bool {
+            self.config.trust_proxy
+        }
+
+        // the bucket of the route (its own, or the default one) and its limit
+        pub fn limit_for(&self, method: &str, route: &str) -> (String, RateLimit) {
+            self.config.routes
+                .iter()
+                .find(|route_limit| route_limit.route == route && (route_limit.method == "*" || route_limit.method == method))
+                .map(|route_limit| (format!("{} {}", route_limit.method, route_limit.route), route_limit.limit))
+                .unwrap_or_else(|| ("*".to_string(), self.config.default_limit))
+        }
+
+        // None when the store failed: the request goes through (a broken store must not take the API down)
+        pub async fn check(&self, client: &str, method: &str, route: &str) -> Option<(RateLimit, Decision)> {
+            let (bucket, limit) = self.limit_for(method, route);
+            let key = format!("{}|{}", client, bucket);
+            match self.store.take(&key, &limit).await {
+                Ok(decision) => Some((limit, decision)),
+                Err(e) => {
+                    warn!("Rate limit of {} not checked, the '{}' store failed: {}", key, self.store.name(), e);
+                    None
+                },
+            }
+        }
+    }
+
+    // --- memory: the buckets of this instance ---
+    #[derive(Default)]
+    pub struct MemoryStore {
+        buckets: Mutex<HashMap<String, MemoryBucket>>,
+    }
+
+    struct MemoryBucket {
+        tokens: f64,
+        refilled_at: Instant,
+    }
+
+    impl RateLimitStore for MemoryStore {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        fn take<'a>(&'a self, key: &'a str, limit: &'a RateLimit) -> BoxFuture<'a, Result<Decision, String>> {
+            Box::pin(async move { Ok(self.take_at(key, limit, Instant::now())) })
+        }
+    }
+
+    impl MemoryStore {
+        // the bucket as it is at 'now'
+        fn take_at(&self, key: &str, limit: &RateLimit, now: Instant) -> Decision {
+            let capacity = limit.requests as f64;
+            let per_second = capacity / limit.period.as_secs_f64();
+            let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if buckets.len() >= MEMORY_BUCKETS_SWEEP && !buckets.contains_key(key) {
+                buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.refilled_at).as_secs_f64() * per_second < capacity);
+            }
+
+            let bucket = buckets.entry(key.to_string()).or_insert(MemoryBucket { tokens: capacity, refilled_at: now });
+            bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled_at).as_secs_f64() * per_second).min(capacity);
+            bucket.refilled_at = now;
+            let allowed = bucket.tokens >= 1.0;
+            if allowed {
+                bucket.tokens -= 1.0;
+            }
+            Decision::from_tokens(allowed, bucket.tokens, limit)
+        }
+    }
+
+    // --- mongodb: the buckets of every instance, one atomic update per request ---
+    pub struct MongoStore {
+        db: Arc<AppDatabase>,
+    }
+
+    impl RateLimitStore for MongoStore {
+        fn name(&self) -> &'static str {
+            "mongodb"
+        }
+
+        fn take<'a>(&'a self, key: &'a str, limit: &'a RateLimit) -> BoxFuture<'a, Result<Decision, String>> {
+            Box::pin(async move {
+                let capacity = limit.requests as f64;
+                let period_ms = limit.period.as_millis() as i64;
+                let per_ms = capacity / period_ms as f64;
+                // same arithmetic as the memory store, on the clock of MongoDB ($$NOW); a missing bucket is a full one
+                let refill = vec![
+                    doc! { "$set": {
+                        "tokens": { "$min": [capacity, { "$add": [
+                            { "$ifNull": ["$tokens", capacity] },
+                            { "$multiply": [{ "$subtract": ["$$NOW", { "$ifNull": ["$refilled_at", "$$NOW"] }] }, per_ms] },
+                        ] }] },
+                        "refilled_at": "$$NOW",
+                    } },
+                    doc! { "$set": { "allowed": { "$gte": ["$tokens", 1.0] } } },
+                    doc! { "$set": {
+                        "tokens": { "$cond": ["$allowed", { "$subtract": ["$tokens", 1.0] }, "$tokens"] },
+                        "expires_at": { "$add": ["$$NOW", period_ms] },
+                    } },
+                ];
+                let bucket = self.db.get_rate_limits_collection()
+                    .find_one_and_update(doc! { "_id": key }, refill)
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| "the bucket was not written".to_string())?;
+                Ok(Decision::from_tokens(bucket.allowed, bucket.tokens, limit))
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::config::RouteLimit;
+
+        // 10 requests per minute: a token every 6 seconds
+        const LIMIT: RateLimit = RateLimit { requests: 10, period: Duration::from_secs(60) };
+
+        fn seconds(seconds: f64) -> Duration {
+            Duration::from_secs_f64(seconds)
+        }
+
+        // the decisions of 'count' requests at 'now'
+        fn burst(store: &MemoryStore, key: &str, count: usize, now: Instant) -> Vec<Decision> {
+            (0..count).map(|_| store.take_at(key, &LIMIT, now)).collect()
+        }
+
+        #[test]
+        fn bursts_are_allowed_up_to_the_limit() {
+            let (store, start) = (MemoryStore::default(), Instant::now());
+            let decisions = burst(&store, "client", 11, start);
+
+            assert!(decisions[..10].iter().all(|decision| decision.allowed));
+            assert_eq!(decisions.iter().map(|decision| decision.remaining).collect::<Vec<u32>>(), [9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0]);
+            assert!(!decisions[10].allowed);
+            assert_eq!(decisions[0].retry_after, Duration::ZERO);
+            // the other clients have their own bucket
+            assert!(store.take_at("other client", &LIMIT, start).allowed);
+        }
+
+        #[test]
+        fn tokens_come_back_at_requests_per_period() {
+            let (store, start) = (MemoryStore::default(), Instant::now());
+            burst(&store, "client", 10, start);
+
+            // a whole token after 6 seconds, not before
+            let refused = store.take_at("client", &LIMIT, start + seconds(3.0));
+            assert!(!refused.allowed);
+            assert_eq!(refused.retry_after, seconds(3.0));
+            assert_eq!(refused.reset, seconds(57.0));
+
+            let allowed = store.take_at("client", &LIMIT, start + seconds(6.0));
+            assert!(allowed.allowed);
+            assert_eq!(allowed.remaining, 0);
+            assert!(!store.take_at("client", &LIMIT, start + seconds(6.0)).allowed);
+
+            // 30 seconds later: 5 tokens
+            let decisions = burst(&store, "client", 6, start + seconds(36.0));
+            assert_eq!(decisions.iter().filter(|decision| decision.allowed).count(), 5);
+        }
+
+        #[test]
+        fn buckets_do_not_fill_over_the_limit() {
+            let (store, start) = (MemoryStore::default(), Instant::now());
+            store.take_at("client", &LIMIT, start);
+
+            // an hour idle is a full bucket, not 600 tokens
+            let decisions = burst(&store, "client", 11, start + seconds(3600.0));
+            assert_eq!(decisions[0].remaining, 9);
+            assert_eq!(decisions[0].reset, seconds(6.0));
+            assert_eq!(decisions.iter().filter(|decision| decision.allowed).count(), 10);
+        }
+
+        #[test]
+        fn retry_after_is_the_time_to_the_next_token() {
+            let limit = RateLimit { requests: 5, period: Duration::from_secs(300) };   // a token every minute
+            assert_eq!(Decision::from_tokens(false, 0.0, &limit).retry_after, seconds(60.0));
+            assert_eq!(Decision::from_tokens(false, 0.75, &limit).retry_after, seconds(15.0));
+            assert_eq!(Decision::from_tokens(true, 0.75, &limit).retry_after, Duration::ZERO);
+            // the MongoDB store may return slightly negative tokens
+            let decision = Decision::from_tokens(false, -0.000001, &limit);
+            assert_eq!((decision.remaining, decision.retry_after, decision.reset), (0, seconds(60.0), seconds(300.0)));
+        }
+
+        #[test]
+        fn routes_with_their_own_limit_have_their_own_bucket() {
+            let login = RateLimit { requests: 10, period: Duration::from_secs(60) };
+            let limiter = RateLimiter {
+                config: RateLimitConfig {
+                    enabled: true,
+                    default_limit: RateLimit { requests: 300, period: Duration::from_secs(60) },
+                    routes: vec![
+                        RouteLimit { method: "POST".to_string(), route: "/auth/login".to_string(), limit: login },
+                        RouteLimit { method: "*".to_string(), route: "/bookings/{id}".to_string(), limit: LIMIT },
+                    ],
+                    store: "memory".to_string(),
+                    trust_proxy: false,
+                },
+                store: Arc::new(MemoryStore::default()),
+            };
+
+            assert_eq!(limiter.limit_for("POST", "/auth/login"), ("POST /auth/login".to_string(), login));
+            assert_eq!(limiter.limit_for("GET", "/auth/login").0, "*");
+            assert_eq!(limiter.limit_for("DELETE", "/bookings/{id}"), ("* /bookings/{id}".to_string(), LIMIT));
+            assert_eq!(limiter.limit_for("GET", "/owners"), ("*".to_string(), limiter.config.default_limit));
+        }
+    }
@@ -1,9 +1,11 @@
-use bson::{doc, oid::ObjectId, Bson};
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
 use futures::StreamExt;
 use crate::{app_errors::errors::AppError, 
+            models::auth_model::Role,
+            models::list_query_model::ListQuery,
             models::sitter_model::{Sitter, SitterUpdateRequest}};
 //use mongodb::Database; 
-use crate::services::db::AppDatabase;
+use crate::services::{attachments, audit, auth, db::{self, AppDatabase}, integrity, listing, policy, versioning::{self, VersionCheck}};
 
 
     // ----------------
@@ -12,21 +14,42 @@ use crate::services::db::AppDatabase;
 
 
     // CREATE for Sitter: In mongodb, you can insert a document into a collection by calling the insert_one() method on a Collection instance.
-    pub async fn create_sitter(db: &AppDatabase, sitter: Sitter) -> Result<Sitter, AppError> {
+    // with a password (sign-up), the credential is created first: an email already used by another account is refused (Conflict)
+    // before the sitter is stored (see services/auth.rs)
+    pub async fn create_sitter(db: &AppDatabase, mut sitter: Sitter, password: Option<String>, actor: &str) -> Result<Sitter, AppError> {
         // REF: mongodb insert_one() -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/insertOne/
  
+        // bookkeeping: creation/modification timestamps and actors are managed here, never sent by clients
+        let now = DateTime::now();
+        sitter.created_at = Some(now);
+        sitter.updated_at = Some(now);
+        sitter.created_by = Some(actor.to_string());
+        sitter.updated_by = Some(actor.to_string());
+
         // Execute operation in the DB
         let sitter_collection = db.get_sitters_collection();
 
-        let result = sitter_collection
-        .insert_one(&sitter)
-        .await?;
+        if let Some(password) = &password {
+            auth::create_credential(db, Role::Sitter, sitter._id, &sitter.email, password).await?;
+        }
+        let result = match sitter_collection.insert_one(&sitter).await {
+            Ok(result) => result,
+            Err(e) => {
+                if password.is_some() {
+                    auth::delete_credentials(db, sitter._id).await;
+                }
+                return Err(e.into());
+            }
+        };
         //.ok()
         //.expect("Error creating sitter in the database");   
 
         //  evaluate result and return 
         match result.inserted_id {
-            Bson::ObjectId(_oid) =>  Ok(sitter),  // some id received back 
+            Bson::ObjectId(_oid) => {
+                audit::record_create(db, "sitters", sitter._id, actor, &sitter).await;
+                Ok(sitter)
+            },  // some id received back 
             other => Err(AppError::DatabaseError(format!("Failed to Create new Owner: {:?}", other))),
         }
  
@@ -35,16 +58,29 @@ use crate::services::db::AppDatabase;
      // READ for Sitter: 
      // In mongodb, you can query for multiple documents in a collection by calling the 'find()' method on a Collection instance.
      // 1) READ ALL: 
-     pub async fn read_sitters(db: &AppDatabase) ->  Result<Vec<Sitter>, AppError> {
+     pub async fn read_sitters(db: &AppDatabase, list_query: &ListQuery, scope: &Document) ->  Result<Vec<Sitter>, AppError> {
          // REF: find multiple documents -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/find/
          // The find() method returns a Cursor type, which you can iterate through to retrieve individual documents/
         
+        // filters and sort order sent in the query string (see services/listing.rs)
+        let mut selection = listing::selection(list_query)?;
+        selection.filter = policy::restrict(selection.filter, scope);   // only what the caller may see (see services/policy.rs)
+
         // Execute operation in the DB
         let sitter_collection = db.get_sitters_collection();
 
-        let mut result_cursor = sitter_collection
-            .find(doc!{})
-            .await?;
+        // Lenient mode (LENIENT_READS=true): return the valid entries, skip and log the corrupt ones
+        if db.lenient_reads() {
+            let scan = integrity::scan_collection(sitter_collection, selection).await?;
+            integrity::log_skipped("Sitter", &scan.corrupt);
+            return Ok(scan.valid);
+        }
+
+        let mut find = sitter_collection.find(selection.filter);
+        if let Some(sort) = selection.sort {
+            find = find.sort(sort);
+        }
+        let mut result_cursor = find.await?;
             //.ok()
             //.expect("Error while reading sitters from database.");
         
@@ -55,13 +91,13 @@ use crate::services::db::AppDatabase;
          while let Some(result) = result_cursor.next().await {
              match result {
                  Ok(sitter_entry) => vec_of_sitters.push(sitter_entry),
-                 Err(e) => return Err(AppError::DatabaseError(format!("Error reading Owner entries from DB: {}" , e.to_string()))),
+                 Err(e) => return Err(AppError::DatabaseError(format!("Error reading Owner entries from DB: {}" , e))),
              }
          }
          Ok(vec_of_sitters)
      }
      // READ single sitter
-     pub async fn read_sitter(db: &AppDatabase, sitter_id: &str) -> Result<Sitter , AppError > {
+     pub async fn read_sitter(db: &AppDatabase, sitter_id: &str, scope: &Document) -> Result<Sitter , AppError > {
  
         //let obj_id = ObjectId::parse_str(sitter_id).expect("Failed parsing sitter id.");
         // Verify/Parse received ID 
@@ -71,7 +107,7 @@ use crate::services::db::AppDatabase;
         };
 
         // Create query filter
-        let filter = doc! { "_id": obj_id};
+        let filter = policy::restrict(doc! { "_id": obj_id }, scope);
 
         // Execute operation in the DB
         let sitter_collection = db.get_sitters_collection();
@@ -91,7 +127,7 @@ use crate::services::db::AppDatabase;
      }
  
      // UPDATE for Sitter:
-    pub async fn update_sitter(db: &AppDatabase, sitter_id: &str, sitter_update: SitterUpdateRequest) ->  Result<String, AppError> {
+    pub async fn update_sitter(db: &AppDatabase, sitter_id: &str, sitter_update: SitterUpdateRequest, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<Sitter, AppError> {
  
          //let obj_id = ObjectId::parse_str(sitter_id).expect("Update sitter: failed parsing sitter id.");
         // Verify/Parse received ID 
@@ -103,34 +139,44 @@ use crate::services::db::AppDatabase;
         
         // Select fields sent in the UpdateRequest
         let mut update_fields = doc! {};
+        let new_email = sitter_update.email.clone();   // the login of the account follows (see auth::update_principal)
     
         if let Some(firstname) = sitter_update.firstname {update_fields.insert("firstname", firstname); }
         if let Some(lastname)  = sitter_update.lastname  {update_fields.insert("lastname", lastname); }
         if let Some(gender) = sitter_update.gender {update_fields.insert("gender", gender);}
-        if let Some(email) = sitter_update.email {update_fields.insert("email", email);}
+        if let Some(email) = sitter_update.email {
+            update_fields.insert("email", email);
+            update_fields.insert("email_verified_at", Bson::Null);   // the new address has to be verified (see services/account_emails.rs)
+        }
         if let Some(phone) = sitter_update.phone {update_fields.insert("phone", phone);}
         if let Some(address) = sitter_update.address {update_fields.insert("address", address);}
  
         if update_fields.is_empty() { 
             return Err(AppError::ParseError("No Fields provided to Updated".to_string())); 
         } // this empty field shouldnt happen anymore because I added the request validation in the router 
+        update_fields.insert("updated_at", DateTime::now());   // bookkeeping, see create_sitter
+        update_fields.insert("updated_by", actor);
         
         // Prepare filter and update 
-        let filter = doc! { "_id": obj_id };
-        let update =  doc! { "$set": update_fields };
+        let filter = policy::restrict(versioning::version_filter(obj_id, version_check), scope);  // only matches the version the client has seen
+        let update =  doc! { "$set": update_fields.clone(), "$inc": { "version": 1 } };
         
         // Execute operation in the DB
         let sitter_collection = db.get_sitters_collection();
 
-        let result = sitter_collection
-            .update_one(filter, update)
-            .await;
+        // previous state returned, for the audit log
+        let result = auth::update_principal(db, sitter_collection, obj_id, filter, update, new_email.as_deref()).await;
             //.ok()
             //.expect("Error updating sitters name.");
  
         //Ok(result.matched_count == 1)
         match result {
-            Ok(_result) => Ok(obj_id.to_hex()),   
+            Ok(Some(previous_sitter)) => {
+                audit::record_update(db, "sitters", obj_id, actor, &previous_sitter, &update_fields).await;
+                versioning::apply_update(&previous_sitter, &update_fields)
+            },
+            Ok(None) => Err(versioning::missing_or_conflict(sitter_collection, obj_id, scope).await),
+            Err(e) if db::is_duplicate_key(&e) => Err(AppError::Conflict("another account already uses this email".to_string())),
             Err(e) => Err(AppError::DatabaseError(format!("Failed to Update Sitter: {}", e))),
         }
 
@@ -139,7 +185,7 @@ use crate::services::db::AppDatabase;
  
      // DELETE for Sitter: 
      // In mongodb, you can delete a document from a collection by calling the delete_one() method on a Collection instance.
-    pub async fn delete_sitter(db: &AppDatabase, sitter_id: &str) -> Result<String, AppError> {
+    pub async fn delete_sitter(db: &AppDatabase, sitter_id: &str, version_check: &VersionCheck, scope: &Document, actor: &str) -> Result<String, AppError> {
          // REF: delete_one() -> https://www.mongodb.com/docs/drivers/rust/current/usage-examples/deleteOne/
         
         // parse ObjectId
@@ -152,13 +198,13 @@ use crate::services::db::AppDatabase;
         };
 
          // Create query filter
-         let filter = doc! { "_id": obj_id, };
+         let filter = policy::restrict(versioning::version_filter(obj_id, version_check), scope);
  
         // Execute operation at DB
         let sitter_collection = db.get_sitters_collection();
         
         let result = sitter_collection
-                .delete_one(filter)
+                .find_one_and_delete(filter)
                 .await;
                 //.ok()
                 //.expect("Error deleting booking");
@@ -167,9 +213,13 @@ use crate::services::db::AppDatabase;
         //Ok(result.deleted_count>0)
         // evaluate result and return, if delete ok, return id of the updated doc
         match result {
-            Ok(delete_result) if delete_result.deleted_count >= 1 => Ok(obj_id.to_hex()),
-            Ok(delete_result) if delete_result.deleted_count == 0 => Err(AppError::NotFound),
-            Ok(_) => Err(AppError::InternalError),
+            Ok(Some(deleted_sitter)) => {
+                audit::record_delete(db, "sitters", obj_id, actor, &deleted_sitter).await;
+                auth::delete_credentials(db, obj_id).await;   // the account cannot log in any more
+                attachments::delete_attachments_of(db, "sitters", obj_id, actor).await;   // its photos and documents
+                Ok(obj_id.to_hex())
+            },
+            Ok(None) => Err(versioning::missing_or_conflict(sitter_collection, obj_id, scope).await),
             Err(db_error) => Err(AppError::DatabaseError(format!("Failed to Delete Sitter: {}", db_error))),
         }
     }
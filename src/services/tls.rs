@@ -0,0 +1,246 @@
+use std::{any::Any, fs, sync::{Arc, RwLock}, time::SystemTime};
+
+use actix_web::dev::Extensions;
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use tokio_rustls::rustls::{self, client::danger::HandshakeSignatureValid, crypto::CryptoProvider, pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, UnixTime},
+                           server::{danger::{ClientCertVerified, ClientCertVerifier}, ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+                           sign::CertifiedKey, DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig, SignatureScheme};
+
+use crate::config::TlsConfig;
+
+
+    // -------------------------------------------
+    // HTTPS: TLS termination in the server (rustls), with certificate reload
+    // -------------------------------------------
+    // Enabled by TLS_CERT_PATH / TLS_KEY_PATH (see config.rs). The certificate, its key and the client CA are read again
+    //  - on SIGHUP (`kill -HUP <pid>`, e.g. from the renewal hook of certbot)
+    //  - when one of the files changes (checked every TLS_RELOAD_INTERVAL_S)
+    // New connections get the new certificate, open connections keep theirs: nothing is dropped.
+    // Invalid new files (unreadable, key not matching the certificate, ...) are logged and the previous ones stay in use.
+    //
+    // Mutual TLS (TLS_CLIENT_CA_PATH): partner systems present a client certificate signed by that CA.
+    // Its SHA-256 is kept with the connection (ClientCertificate, see on_connect below) and an API key can be bound
+    // to it: the key then only works over a connection with that certificate (see services/api_keys.rs).
+    // The server sends no list of accepted authorities in its certificate request: clients send their certificate anyway.
+
+    // the client certificate of the connection (mutual TLS), read with `request.conn_data::<ClientCertificate>()`
+    #[derive(Debug, Clone)]
+    pub struct ClientCertificate {
+        pub sha256: String,     // of the DER certificate, lowercase hex, as `openssl x509 -noout -fingerprint -sha256` without ':'
+    }
+
+    // the certificate, key and client CA in use, swapped in place by the reloads
+    pub struct TlsState {
+        config: TlsConfig,
+        provider: Arc<CryptoProvider>,
+        certified_key: RwLock<Arc<CertifiedKey>>,
+        client_verifier: RwLock<Arc<dyn ClientCertVerifier>>,
+        modified: RwLock<Vec<Option<SystemTime>>>,    // of the files, to notice changes
+    }
+
+    impl TlsState {
+        // reads the files once: the server does not start with invalid ones
+        pub fn load(config: TlsConfig) -> Result<Arc<Self>, String> {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let certified_key = load_certified_key(&config, &provider)?;
+            let client_verifier = load_client_verifier(&config, &provider)?;
+            let modified = modification_times(&config);
+            info!("HTTPS certificate loaded from {}", config.cert_path);
+            Ok(Arc::new(TlsState {
+                config,
+                provider,
+                certified_key: RwLock::new(Arc::new(certified_key)),
+                client_verifier: RwLock::new(client_verifier),
+                modified: RwLock::new(modified),
+            }))
+        }
+
+        pub fn port(&self) -> u16 {
+            self.config.port
+        }
+
+        pub fn keep_http(&self) -> bool {
+            self.config.keep_http
+        }
+
+        // the rustls configuration of the listener: it asks this state for the certificate of each new connection
+        pub fn server_config(self: &Arc<Self>) -> ServerConfig {
+            let mut server_config = ServerConfig::builder_with_provider(self.provider.clone())
+                .with_safe_default_protocol_versions()
+                .expect("the ring provider supports the default TLS versions")
+                .with_client_cert_verifier(Arc::new(ReloadingClientVerifier(self.clone())))
+                .with_cert_resolver(Arc::new(ReloadingCertResolver(self.clone())));
+            server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+            server_config
+        }
+
+        // reads the files again, keeps the previous certificate when they are not valid
+        // (tried again at the next change: a renewal writing the certificate then the key is picked up once both are there)
+        pub fn reload(&self) {
+            *self.modified.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = modification_times(&self.config);
+            let certified_key = match load_certified_key(&self.config, &self.provider) {
+                Ok(certified_key) => certified_key,
+                Err(e) => return error!("HTTPS certificate not reloaded, the previous one stays in use: {}", e),
+            };
+            let client_verifier = match load_client_verifier(&self.config, &self.provider) {
+                Ok(client_verifier) => client_verifier,
+                Err(e) => return error!("HTTPS client CA not reloaded, the previous one stays in use: {}", e),
+            };
+            *self.certified_key.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(certified_key);
+            *self.client_verifier.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = client_verifier;
+            info!("HTTPS certificate reloaded from {}", self.config.cert_path);
+        }
+
+        fn files_changed(&self) -> bool {
+            *self.modified.read().unwrap_or_else(|poisoned| poisoned.into_inner()) != modification_times(&self.config)
+        }
+
+        fn client_verifier(&self) -> Arc<dyn ClientCertVerifier> {
+            self.client_verifier.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+        }
+    }
+
+    // Reload loop (spawned by main.rs): SIGHUP, or a change of the files seen by the periodic check
+    pub async fn run_reloader(state: Arc<TlsState>) {
+        let interval = state.config.reload_interval;
+        #[cfg(unix)]
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                warn!("SIGHUP not available, the HTTPS certificate is only reloaded when its files change: {}", e);
+                None
+            },
+        };
+
+        loop {
+            #[cfg(unix)]
+            let signaled = async {
+                match hangup.as_mut() {
+                    Some(hangup) => { hangup.recv().await; },
+                    None => std::future::pending::<()>().await,
+                }
+            };
+            #[cfg(not(unix))]
+            let signaled = std::future::pending::<()>();
+            let tick = async {
+                if interval.is_zero() {
+                    std::future::pending::<()>().await
+                } else {
+                    actix_web::rt::time::sleep(interval).await
+                }
+            };
+
+            tokio::select! {
+                _ = signaled => {
+                    info!("SIGHUP received, reloading the HTTPS certificate");
+                    state.reload();
+                },
+                _ = tick => {
+                    if state.files_changed() {
+                        info!("HTTPS certificate files changed, reloading them");
+                        state.reload();
+                    }
+                },
+            }
+        }
+    }
+
+    // HttpServer::on_connect: keeps the client certificate of a TLS connection for the requests sent over it
+    pub fn on_connect(connection: &dyn Any, extensions: &mut Extensions) {
+        let certificate = connection
+            .downcast_ref::<actix_tls::accept::rustls_0_23::TlsStream<actix_web::rt::net::TcpStream>>()
+            .and_then(|stream| stream.get_ref().1.peer_certificates())
+            .and_then(|certificates| certificates.first());
+        if let Some(certificate) = certificate {
+            let sha256 = Sha256::digest(certificate.as_ref()).iter().map(|byte| format!("{:02x}", byte)).collect();
+            extensions.insert(ClientCertificate { sha256 });
+        }
+    }
+
+    fn load_certified_key(config: &TlsConfig, provider: &CryptoProvider) -> Result<CertifiedKey, String> {
+        let certificates = CertificateDer::pem_file_iter(&config.cert_path)
+            .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("cannot read the certificates of {}: {}", config.cert_path, e))?;
+        if certificates.is_empty() {
+            return Err(format!("no certificate in {}", config.cert_path));
+        }
+        let key = PrivateKeyDer::from_pem_file(&config.key_path)
+            .map_err(|e| format!("cannot read the private key of {}: {}", config.key_path, e))?;
+        CertifiedKey::from_der(certificates, key, provider)
+            .map_err(|e| format!("invalid key {} for the certificate {}: {}", config.key_path, config.cert_path, e))
+    }
+
+    fn load_client_verifier(config: &TlsConfig, provider: &Arc<CryptoProvider>) -> Result<Arc<dyn ClientCertVerifier>, String> {
+        let client_ca_path = match &config.client_ca_path {
+            Some(client_ca_path) => client_ca_path,
+            None => return Ok(Arc::new(rustls::server::NoClientAuth)),
+        };
+        let mut roots = RootCertStore::empty();
+        for certificate in CertificateDer::pem_file_iter(client_ca_path).map_err(|e| format!("cannot read {}: {}", client_ca_path, e))? {
+            let certificate = certificate.map_err(|e| format!("cannot read {}: {}", client_ca_path, e))?;
+            roots.add(certificate).map_err(|e| format!("invalid CA certificate in {}: {}", client_ca_path, e))?;
+        }
+        let builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).clear_root_hint_subjects();
+        let builder = if config.client_auth_required { builder } else { builder.allow_unauthenticated() };
+        builder.build().map_err(|e| format!("invalid client CA {}: {}", client_ca_path, e))
+    }
+
+    fn modification_times(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+        [Some(&config.cert_path), Some(&config.key_path), config.client_ca_path.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+            .collect()
+    }
+
+    // --- rustls hooks reading the current state on every handshake ---
+
+    #[derive(Debug)]
+    struct ReloadingCertResolver(Arc<TlsState>);
+
+    impl ResolvesServerCert for ReloadingCertResolver {
+        fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+            Some(self.0.certified_key.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone())
+        }
+    }
+
+    #[derive(Debug)]
+    struct ReloadingClientVerifier(Arc<TlsState>);
+
+    impl ClientCertVerifier for ReloadingClientVerifier {
+        fn offer_client_auth(&self) -> bool {
+            self.0.client_verifier().offer_client_auth()
+        }
+
+        fn client_auth_mandatory(&self) -> bool {
+            self.0.client_verifier().client_auth_mandatory()
+        }
+
+        // no hints (see the header): the verifier in use can change, this answer cannot
+        fn root_hint_subjects(&self) -> &[DistinguishedName] {
+            &[]
+        }
+
+        fn verify_client_cert(&self, end_entity: &CertificateDer<'_>, intermediates: &[CertificateDer<'_>], now: UnixTime) -> Result<ClientCertVerified, rustls::Error> {
+            self.0.client_verifier().verify_client_cert(end_entity, intermediates, now)
+        }
+
+        fn verify_tls12_signature(&self, message: &[u8], certificate: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.0.client_verifier().verify_tls12_signature(message, certificate, dss)
+        }
+
+        fn verify_tls13_signature(&self, message: &[u8], certificate: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.0.client_verifier().verify_tls13_signature(message, certificate, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.client_verifier().supported_verify_schemes()
+        }
+    }
+
+    impl std::fmt::Debug for TlsState {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("TlsState").field("config", &self.config).finish()
+        }
+    }
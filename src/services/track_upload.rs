@@ -0,0 +1,414 @@
+use bson::{doc, oid::ObjectId, DateTime};
+use quick_xml::{events::{BytesStart, Event}, Reader};
+use serde_json::Value;
+
+use crate::{app_errors::errors::AppError,
+            models::booking_event_model::BookingEventKind,
+            models::booking_model::Booking,
+            models::track_model::{TrackPoint, WalkStats}};
+use crate::services::{audit, booking_events, db::AppDatabase, listing, versioning::{self, VersionCheck}};
+
+
+    // -------------------------------------------
+    // Uploaded walk tracks (GPX / GeoJSON)
+    // -------------------------------------------
+    // After the walk, the sitter uploads the track recorded by their app (POST /bookings/{id}/track):
+    //   1. parse: GPX <trkseg>/<trkpt>, or GeoJSON LineString / MultiLineString (also in a Feature or FeatureCollection)
+    //   2. stats: distance, moving time and average pace, computed from all the points
+    //   3. simplify: Douglas-Peucker, the stored track stays within SIMPLIFY_TOLERANCE_M of the uploaded one
+    //   4. store: the simplified points are added to 'track_points' under a new upload id (source "upload"),
+    //      then the stats are recorded on the booking with a WalkRecorded event (see services/booking_events.rs),
+    //      and only then the previously uploaded points are deleted
+    //      (the booking changed meanwhile: the new points are deleted instead, 412)
+    // Uploading again replaces both: the app may send a corrected track.
+
+    pub const MAX_TRACK_UPLOAD: usize = 10 * 1024 * 1024;   // bytes, a 2 hours walk at 1 point/s is ~1 MB of GPX
+    const MAX_TRACK_POINTS: usize = 200_000;
+    const SIMPLIFY_TOLERANCE_M: f64 = 3.0;     // about the accuracy of a phone GPS
+    const MOVING_SPEED_MPS: f64 = 0.5;         // slower is a stop (sniffing a tree), not counted in the moving time
+    const MAX_POINT_GAP_S: f64 = 300.0;        // longer without a position: GPS lost or recording paused
+    const EARTH_RADIUS_M: f64 = 6_371_008.8;   // mean radius
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TrackFormat {
+        Gpx,
+        GeoJson,
+    }
+
+    impl TrackFormat {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                TrackFormat::Gpx => "gpx",
+                TrackFormat::GeoJson => "geojson",
+            }
+        }
+    }
+
+    // Format of the upload, from its Content-Type, or from its first character when the type is generic
+    pub fn detect_format(content_type: Option<&str>, body: &[u8]) -> Result<TrackFormat, AppError> {
+        let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+        if content_type.contains("gpx") || content_type.ends_with("/xml") {
+            return Ok(TrackFormat::Gpx);
+        }
+        if content_type.contains("json") {
+            return Ok(TrackFormat::GeoJson);
+        }
+        match body.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'<') => Ok(TrackFormat::Gpx),
+            Some(b'{') => Ok(TrackFormat::GeoJson),
+            _ => Err(AppError::ParseError("Unsupported track format, expected GPX (application/gpx+xml) or GeoJSON (application/geo+json)".to_string())),
+        }
+    }
+
+    // One position of the uploaded track
+    #[derive(Debug, Clone, Copy)]
+    struct RawPoint {
+        lat: f64,
+        lon: f64,
+        time: Option<DateTime>,
+    }
+
+    // A track is a list of segments (GPX <trkseg>, lines of a MultiLineString): the gap between two segments
+    // (recording paused) counts neither in the distance nor in the moving time
+    type Segments = Vec<Vec<RawPoint>>;
+
+    // UPLOAD the track of a booking's walk, returns the booking with its new walk stats (and version)
+    pub async fn upload_track(db: &AppDatabase, booking: &Booking, actor: &str, version_check: &VersionCheck, format: TrackFormat, body: &[u8]) -> Result<Booking, AppError> {
+        if booking.cancelled {
+            return Err(AppError::Forbidden("the booking is cancelled".to_string()));
+        }
+        if !versioning::matches(version_check, booking.version) {
+            return Err(AppError::PreconditionFailed);
+        }
+
+        let segments = match format {
+            TrackFormat::Gpx => parse_gpx(body)?,
+            TrackFormat::GeoJson => parse_geojson(body)?,
+        };
+        let segments = validate(segments)?;
+
+        let simplified = segments.iter().map(|segment| simplify(segment)).collect::<Segments>();
+        let mut stats = compute_stats(&segments, format);
+        stats.points_stored = simplified.iter().map(|segment| segment.len() as i64).sum();
+
+        // points without a timestamp are dated at the start of the booking
+        let upload_id = ObjectId::new();
+        let received_at = DateTime::now();
+        let points = simplified
+            .iter()
+            .flatten()
+            .map(|point| TrackPoint {
+                _id: ObjectId::new(),   // ids follow the order of the track, used to sort points of the same time
+                booking_id: booking._id,
+                lat: point.lat,
+                lon: point.lon,
+                accuracy_m: None,
+                recorded_at: point.time.unwrap_or(booking.start_time),
+                received_at,
+                source: "upload".to_string(),
+                recorded_by: actor.to_string(),
+                upload_id: Some(upload_id),
+            })
+            .collect::<Vec<TrackPoint>>();
+        let track_points = db.get_track_points_collection();
+        track_points.insert_many(&points).await?;
+
+        // the stats are part of the booking: one more event in its stream
+        let events = vec![BookingEventKind::WalkRecorded { walk: stats }];
+        let committed = match booking_events::commit(db, booking, events, actor).await {
+            Ok(Some(updated_booking)) => Ok(updated_booking),
+            Ok(None) => Err(AppError::DatabaseError("Failed to record the walk: booking was deleted".to_string())),
+            Err(app_error) => Err(app_error),
+        };
+
+        // the upload that was not recorded on the booking is not its track: only one upload is kept
+        let replaced = match &committed {
+            Ok(_) => doc! { "booking_id": booking._id, "source": "upload", "upload_id": { "$ne": upload_id } },
+            Err(_) => doc! { "booking_id": booking._id, "upload_id": upload_id },
+        };
+        track_points.delete_many(replaced).await?;
+
+        let updated_booking = committed?;
+        let update_fields = booking_events::changed_fields(booking, &updated_booking)?;
+        audit::record_update(db, "bookings", booking._id, actor, booking, &update_fields).await;
+        Ok(updated_booking)
+    }
+
+    // -------------------------------------------
+    // Parsing
+    // -------------------------------------------
+
+    // GPX 1.0 / 1.1: <gpx><trk><trkseg><trkpt lat=".." lon=".."><time>..</time></trkpt>...
+    // routes (<rte>) and waypoints (<wpt>) are planned, not recorded: ignored
+    fn parse_gpx(body: &[u8]) -> Result<Segments, AppError> {
+        let mut reader = Reader::from_reader(body);
+        reader.config_mut().trim_text(true);
+
+        let mut segments = Segments::new();
+        let mut current: Option<RawPoint> = None;   // inside a <trkpt>
+        let mut in_time = false;                     // inside the <time> of a <trkpt>
+        let mut buffer = Vec::new();
+        loop {
+            let event = reader.read_event_into(&mut buffer)
+                .map_err(|e| AppError::ParseError(format!("Invalid GPX at position {}: {}", reader.error_position(), e)))?;
+            match event {
+                Event::Eof => break,
+                Event::Start(element) => match element.local_name().as_ref() {
+                    b"trkseg" => segments.push(Vec::new()),
+                    b"trkpt" => current = Some(gpx_point(&element)?),
+                    b"time" if current.is_some() => in_time = true,
+                    _ => {}
+                },
+                Event::Empty(element) if element.local_name().as_ref() == b"trkpt" => push_point(&mut segments, gpx_point(&element)?),
+                Event::Text(text) if in_time => {
+                    let text = text.unescape().map_err(|e| AppError::ParseError(format!("Invalid GPX time: {}", e)))?;
+                    if let Some(point) = current.as_mut() {
+                        point.time = Some(listing::parse_datetime(&text)?);
+                    }
+                }
+                Event::End(element) => match element.local_name().as_ref() {
+                    b"time" => in_time = false,
+                    b"trkpt" => {
+                        if let Some(point) = current.take() {
+                            push_point(&mut segments, point);
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+            buffer.clear();
+        }
+        Ok(segments)
+    }
+
+    // lat and lon attributes of a <trkpt>
+    fn gpx_point(element: &BytesStart) -> Result<RawPoint, AppError> {
+        let mut lat = None;
+        let mut lon = None;
+        for attribute in element.attributes() {
+            let attribute = attribute.map_err(|e| AppError::ParseError(format!("Invalid GPX attribute: {}", e)))?;
+            let value = std::str::from_utf8(&attribute.value).ok().and_then(|value| value.trim().parse::<f64>().ok());
+            match attribute.key.local_name().as_ref() {
+                b"lat" => lat = value,
+                b"lon" => lon = value,
+                _ => {}
+            }
+        }
+        match (lat, lon) {
+            (Some(lat), Some(lon)) => Ok(RawPoint { lat, lon, time: None }),
+            _ => Err(AppError::ParseError("Invalid GPX: every <trkpt> needs numeric lat and lon attributes".to_string())),
+        }
+    }
+
+    // a <trkpt> outside of any <trkseg> starts one
+    fn push_point(segments: &mut Segments, point: RawPoint) {
+        match segments.last_mut() {
+            Some(segment) => segment.push(point),
+            None => segments.push(vec![point]),
+        }
+    }
+
+    // GeoJSON (RFC 7946): coordinates are [lon, lat] or [lon, lat, elevation]
+    // timestamps are not part of the standard, the ones of common exporters are read from the Feature properties:
+    // "coordTimes" (togeojson, one list per line for a MultiLineString) or "coordinateProperties": { "times" }
+    fn parse_geojson(body: &[u8]) -> Result<Segments, AppError> {
+        let value: Value = serde_json::from_slice(body).map_err(|e| AppError::ParseError(format!("Invalid GeoJSON: {}", e)))?;
+        let mut segments = Segments::new();
+        collect_geojson(&value, None, &mut segments)?;
+        Ok(segments)
+    }
+
+    fn collect_geojson(value: &Value, times: Option<&Value>, segments: &mut Segments) -> Result<(), AppError> {
+        match value.get("type").and_then(Value::as_str) {
+            Some("FeatureCollection") => {
+                for feature in value.get("features").and_then(Value::as_array).into_iter().flatten() {
+                    collect_geojson(feature, None, segments)?;
+                }
+            }
+            Some("Feature") => {
+                let properties = value.get("properties");
+                let times = properties
+                    .and_then(|properties| properties.get("coordTimes"))
+                    .or_else(|| properties.and_then(|properties| properties.pointer("/coordinateProperties/times")));
+                if let Some(geometry) = value.get("geometry").filter(|geometry| !geometry.is_null()) {
+                    collect_geojson(geometry, times, segments)?;
+                }
+            }
+            Some("LineString") => segments.push(geojson_line(value.get("coordinates"), times)?),
+            Some("MultiLineString") => {
+                let lines = value.get("coordinates").and_then(Value::as_array)
+                    .ok_or_else(|| AppError::ParseError("Invalid GeoJSON: a MultiLineString needs coordinates".to_string()))?;
+                for (index, line) in lines.iter().enumerate() {
+                    segments.push(geojson_line(Some(line), times.and_then(|times| times.get(index)))?);
+                }
+            }
+            Some("GeometryCollection") => {
+                for geometry in value.get("geometries").and_then(Value::as_array).into_iter().flatten() {
+                    collect_geojson(geometry, None, segments)?;
+                }
+            }
+            Some(_) => {}   // Point, Polygon, ...: not part of a track
+            None => return Err(AppError::ParseError("Invalid GeoJSON: missing \"type\"".to_string())),
+        }
+        Ok(())
+    }
+
+    fn geojson_line(coordinates: Option<&Value>, times: Option<&Value>) -> Result<Vec<RawPoint>, AppError> {
+        let coordinates = coordinates.and_then(Value::as_array)
+            .ok_or_else(|| AppError::ParseError("Invalid GeoJSON: a LineString needs coordinates".to_string()))?;
+
+        let mut line = Vec::with_capacity(coordinates.len());
+        for (index, position) in coordinates.iter().enumerate() {
+            let (lon, lat) = match (position.get(0).and_then(Value::as_f64), position.get(1).and_then(Value::as_f64)) {
+                (Some(lon), Some(lat)) => (lon, lat),
+                _ => return Err(AppError::ParseError("Invalid GeoJSON: positions must be [lon, lat]".to_string())),
+            };
+            let time = match times.and_then(|times| times.get(index)).and_then(Value::as_str) {
+                Some(time) => Some(listing::parse_datetime(time)?),
+                None => None,
+            };
+            line.push(RawPoint { lat, lon, time });
+        }
+        Ok(line)
+    }
+
+    // Coordinates in range, enough points and not too many; empty segments are dropped
+    fn validate(segments: Segments) -> Result<Segments, AppError> {
+        let segments = segments.into_iter().filter(|segment| !segment.is_empty()).collect::<Segments>();
+
+        let count = segments.iter().map(Vec::len).sum::<usize>();
+        if count < 2 {
+            return Err(AppError::ParseError("The track needs at least 2 points".to_string()));
+        }
+        if count > MAX_TRACK_POINTS {
+            return Err(AppError::ParseError(format!("The track has {} points, at most {} are accepted", count, MAX_TRACK_POINTS)));
+        }
+        let out_of_range = segments.iter().flatten().any(|point| {
+            !(-90.0..=90.0).contains(&point.lat) || !(-180.0..=180.0).contains(&point.lon)
+        });
+        if out_of_range {
+            return Err(AppError::ParseError("lat must be within -90..90 and lon within -180..180".to_string()));
+        }
+        Ok(segments)
+    }
+
+    // -------------------------------------------
+    // Stats
+    // -------------------------------------------
+
+    // distance: sum of the great-circle distances between consecutive points of each segment
+    // moving time: time between consecutive timestamped points faster than MOVING_SPEED_MPS (and less than MAX_POINT_GAP_S apart)
+    // average pace: moving time per kilometer, over the distance covered between timestamped points
+    fn compute_stats(segments: &Segments, format: TrackFormat) -> WalkStats {
+        let mut distance_m = 0.0;
+        let mut timed_distance_m = 0.0;
+        let mut moving_time_s = 0.0;
+        let mut timed = false;
+
+        for segment in segments {
+            for pair in segment.windows(2) {
+                let step_m = haversine(&pair[0], &pair[1]);
+                distance_m += step_m;
+
+                if let (Some(from), Some(to)) = (pair[0].time, pair[1].time) {
+                    timed = true;
+                    timed_distance_m += step_m;
+                    let step_s = (to.timestamp_millis() - from.timestamp_millis()) as f64 / 1000.0;
+                    if step_s > 0.0 && step_s <= MAX_POINT_GAP_S && step_m / step_s >= MOVING_SPEED_MPS {
+                        moving_time_s += step_s;
+                    }
+                }
+            }
+        }
+
+        let times = segments.iter().flatten().filter_map(|point| point.time);
+        let started_at = times.clone().min();
+        let ended_at = times.max();
+
+        let moving_time_s = if timed { Some(moving_time_s.round() as i64) } else { None };
+        let average_pace_s_per_km = match moving_time_s {
+            Some(moving_time_s) if moving_time_s > 0 && timed_distance_m > 0.0 => Some(round_to_tenth(moving_time_s as f64 / (timed_distance_m / 1000.0))),
+            _ => None,
+        };
+
+        WalkStats {
+            distance_m: round_to_tenth(distance_m),
+            moving_time_s,
+            average_pace_s_per_km,
+            started_at,
+            ended_at,
+            points_received: segments.iter().map(|segment| segment.len() as i64).sum(),
+            points_stored: 0,   // set once simplified
+            format: format.as_str().to_string(),
+        }
+    }
+
+    fn round_to_tenth(value: f64) -> f64 {
+        (value * 10.0).round() / 10.0
+    }
+
+    // Great-circle distance in meters
+    fn haversine(from: &RawPoint, to: &RawPoint) -> f64 {
+        let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+        let delta_lat = lat2 - lat1;
+        let delta_lon = (to.lon - from.lon).to_radians();
+        let a = (delta_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (delta_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+
+    // -------------------------------------------
+    // Simplification
+    // -------------------------------------------
+    // Douglas-Peucker: keep the first and last points, then recursively the point furthest from the kept line
+    // while it is further than SIMPLIFY_TOLERANCE_M. Distances use a local flat projection (walks are a few km).
+
+    fn simplify(segment: &[RawPoint]) -> Vec<RawPoint> {
+        if segment.len() < 3 {
+            return segment.to_vec();
+        }
+        let origin = segment[0];
+        let projected = segment.iter().map(|point| project(&origin, point)).collect::<Vec<(f64, f64)>>();
+
+        let mut keep = vec![false; segment.len()];
+        keep[0] = true;
+        keep[segment.len() - 1] = true;
+
+        // ranges still to examine (no recursion: tracks can have many thousands of points)
+        let mut ranges = vec![(0, segment.len() - 1)];
+        while let Some((first, last)) = ranges.pop() {
+            let mut furthest = (0, 0.0);
+            for index in first + 1..last {
+                let distance = distance_to_segment(projected[index], projected[first], projected[last]);
+                if distance > furthest.1 {
+                    furthest = (index, distance);
+                }
+            }
+            if furthest.1 > SIMPLIFY_TOLERANCE_M {
+                keep[furthest.0] = true;
+                ranges.push((first, furthest.0));
+                ranges.push((furthest.0, last));
+            }
+        }
+
+        segment.iter().zip(keep).filter(|(_, keep)| *keep).map(|(point, _)| *point).collect()
+    }
+
+    // meters east / north of 'origin' (equirectangular projection)
+    fn project(origin: &RawPoint, point: &RawPoint) -> (f64, f64) {
+        let x = (point.lon - origin.lon).to_radians() * EARTH_RADIUS_M * origin.lat.to_radians().cos();
+        let y = (point.lat - origin.lat).to_radians() * EARTH_RADIUS_M;
+        (x, y)
+    }
+
+    fn distance_to_segment(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length_squared = dx * dx + dy * dy;
+        let t = if length_squared == 0.0 {
+            0.0
+        } else {
+            (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_squared).clamp(0.0, 1.0)
+        };
+        let (closest_x, closest_y) = (start.0 + t * dx, start.1 + t * dy);
+        ((point.0 - closest_x).powi(2) + (point.1 - closest_y).powi(2)).sqrt()
+    }
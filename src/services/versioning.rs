@@ -0,0 +1,70 @@
+use bson::{doc, oid::ObjectId, Bson, Document};
+use mongodb::Collection;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::app_errors::errors::AppError;
+use crate::services::policy;
+
+    // -------------------------------------------
+    // Optimistic concurrency control (versions)
+    // -------------------------------------------
+    // Every document carries a `version` number, incremented ($inc) by each update.
+    // The client sends back the version it read (HTTP If-Match, see routes/conditional.rs) and the
+    // update/delete only matches the document if it is still at that version: no blind overwrites.
+    // Documents written before versions existed have no `version` field, they are read as version 0.
+
+    // What the caller expects the current version to be
+    #[derive(Debug, Clone)]
+    pub enum VersionCheck {
+        Any,                 // no check (If-Match: * or If-Match not required and not sent)
+        OneOf(Vec<i64>),     // one of the versions listed in If-Match
+    }
+
+    // Filter matching the document by id AND by expected version
+    pub fn version_filter(obj_id: ObjectId, version_check: &VersionCheck) -> Document {
+        match version_check {
+            VersionCheck::Any => doc! { "_id": obj_id },
+            VersionCheck::OneOf(versions) => {
+                let mut version_clauses = vec![
+                    doc! { "version": { "$in": versions.iter().map(|version| Bson::Int64(*version)).collect::<Vec<Bson>>() } },
+                ];
+                // legacy documents without version are at version 0
+                if versions.contains(&0) {
+                    version_clauses.push(doc! { "version": { "$exists": false } });
+                }
+                doc! { "_id": obj_id, "$or": version_clauses }
+            }
+        }
+    }
+
+    // Same check as version_filter, on a document already read (e.g. before appending booking events)
+    pub fn matches(version_check: &VersionCheck, version: i64) -> bool {
+        match version_check {
+            VersionCheck::Any => true,
+            VersionCheck::OneOf(versions) => versions.contains(&version),
+        }
+    }
+
+    // The version-filtered operation matched nothing: either the document does not exist (404)
+    // or it exists at another version (412); out of the caller's scope (see services/policy.rs) it does not exist
+    pub async fn missing_or_conflict<T: Send + Sync>(collection: &Collection<T>, obj_id: ObjectId, scope: &Document) -> AppError {
+        match collection.count_documents(policy::restrict(doc! { "_id": obj_id }, scope)).await {
+            Ok(0) => AppError::NotFound,
+            Ok(_) => AppError::PreconditionFailed,
+            Err(e) => AppError::from(e),
+        }
+    }
+
+    // The document as it is after a versioned update, computed from its previous state
+    // (find_one_and_update with ReturnDocument::Before, so the audit log gets the real 'before' values)
+    pub fn apply_update<T: Serialize + DeserializeOwned>(before: &T, update_fields: &Document) -> Result<T, AppError> {
+        let mut document = bson::to_document(before).map_err(|e| AppError::ParseError(e.to_string()))?;
+        let version = document.get_i64("version").unwrap_or(0);
+
+        for (field, value) in update_fields {
+            document.insert(field.clone(), value.clone());
+        }
+        document.insert("version", version + 1);
+
+        bson::from_document(document).map_err(|e| AppError::ParseError(e.to_string()))
+    }
@@ -0,0 +1,274 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::StreamExt;
+use log::warn;
+use rand::RngCore;
+use validator::Validate;
+
+use crate::{app_errors::errors::AppError,
+            models::attachment_model::Attachment,
+            models::booking_event_model::BookingEventKind,
+            models::booking_model::Booking,
+            models::walk_report_model::{WalkReport, WalkReportRequest}};
+use crate::services::{attachments, audit, booking_events, db::{self, AppDatabase}};
+
+
+    // -------------------------------------------
+    // Walk reports
+    // -------------------------------------------
+    // After the walk, the sitter of the booking submits the report once (POST /bookings/{id}/report):
+    // pee / poo / water given, mood, notes and some of the photos uploaded to the booking.
+    // The report is stored in 'walk_reports' and announced with a ReportSubmitted event on the booking
+    // (booking.report_submitted for the outbox sinks, SSE and webhooks).
+    // The owner reads it as JSON, or shares /reports/{share_token}: a self-contained HTML page (thumbnails inlined).
+
+    const SHARE_TOKEN_BYTES: usize = 32;
+
+    // CREATE the report of a booking, the walk must be over and the report not submitted yet
+    pub async fn submit_report(db: &AppDatabase, booking: &Booking, request: WalkReportRequest, actor: &str) -> Result<WalkReport, AppError> {
+        request.validate().map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        if booking.cancelled {
+            return Err(AppError::Conflict("the booking is cancelled".to_string()));
+        }
+        if booking.report_id.is_some() {
+            return Err(AppError::Conflict("the report of this walk was already submitted".to_string()));
+        }
+        let walk_end = booking.start_time.timestamp_millis() + booking.duration_minutes as i64 * 60_000;
+        if DateTime::now().timestamp_millis() < walk_end {
+            let walk_end = DateTime::from_millis(walk_end).to_chrono().to_rfc3339();
+            return Err(AppError::Conflict(format!("the walk is not over yet, the report can be submitted from {}", walk_end)));
+        }
+
+        let photos = booking_photos(db, booking, &request.photos).await?;
+        let report = WalkReport {
+            _id: ObjectId::new(),
+            booking_id: booking._id,
+            pee: request.pee,
+            poo: request.poo,
+            water_given: request.water_given,
+            mood: request.mood,
+            notes: request.notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty()),
+            photos,
+            share_token: share_token(),
+            submitted_at: DateTime::now(),
+            submitted_by: actor.to_string(),
+        };
+
+        // the unique booking_id index settles two submissions at the same time
+        let reports = db.get_walk_reports_collection();
+        if let Err(e) = reports.insert_one(&report).await {
+            return Err(match db::is_duplicate_key(&e) {
+                true => AppError::Conflict("the report of this walk was already submitted".to_string()),
+                false => AppError::DatabaseError(format!("Failed to store the Walk report: {}", e)),
+            });
+        }
+
+        // the booking points to its report; if it cannot, the report is removed so that it can be submitted again
+        let events = vec![BookingEventKind::ReportSubmitted { report_id: report._id }];
+        if let Err(app_error) = booking_events::commit(db, booking, events, actor).await {
+            if let Err(e) = reports.delete_one(doc! { "_id": report._id }).await {
+                warn!("Walk report {} of booking {} is stored but not linked: {}", report._id.to_hex(), booking._id.to_hex(), e);
+            }
+            return Err(app_error);
+        }
+
+        audit::record_create(db, "walk_reports", report._id, actor, &report).await;
+        Ok(report)
+    }
+
+    // Photos chosen for the report: each one must be a photo of this booking (duplicates are ignored)
+    async fn booking_photos(db: &AppDatabase, booking: &Booking, photo_ids: &[String]) -> Result<Vec<ObjectId>, AppError> {
+        let mut photos = Vec::<ObjectId>::new();
+        for photo_id in photo_ids {
+            let photo = ObjectId::parse_str(photo_id).map_err(|_| AppError::ParseError(format!("Invalid photo ID '{}'", photo_id)))?;
+            if !photos.contains(&photo) {
+                photos.push(photo);
+            }
+        }
+        if photos.is_empty() {
+            return Ok(photos);
+        }
+
+        let found = db.get_attachments_collection()
+            .count_documents(doc! { "_id": { "$in": &photos }, "resource": "bookings", "resource_id": booking._id, "kind": "photo" })
+            .await?;
+        if found != photos.len() as u64 {
+            return Err(AppError::ParseError("photos must be photos of this booking, upload them first (POST /bookings/{id}/photos)".to_string()));
+        }
+        Ok(photos)
+    }
+
+    // capability of the public page, a new one also revokes the links already shared (see services/privacy.rs)
+    pub fn share_token() -> String {
+        let mut bytes = [0u8; SHARE_TOKEN_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    // READ the report of a booking
+    pub async fn read_report(db: &AppDatabase, booking: &Booking) -> Result<WalkReport, AppError> {
+        match db.get_walk_reports_collection().find_one(doc! { "booking_id": booking._id }).await? {
+            Some(report) => Ok(report),
+            None => Err(AppError::NotFound),
+        }
+    }
+
+    // READ a shared report and its booking, by share token
+    pub async fn read_shared_report(db: &AppDatabase, share_token: &str) -> Result<(WalkReport, Booking), AppError> {
+        // tokens are hex strings of a fixed length: anything else is not looked up
+        if share_token.len() != SHARE_TOKEN_BYTES * 2 || !share_token.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(AppError::NotFound);
+        }
+        let report = match db.get_walk_reports_collection().find_one(doc! { "share_token": share_token }).await? {
+            Some(report) => report,
+            None => return Err(AppError::NotFound),
+        };
+        match db.get_bookings_collection().find_one(doc! { "_id": report.booking_id }).await? {
+            Some(booking) => Ok((report, booking)),
+            None => Err(AppError::NotFound),   // booking deleted: the page is not shared any more
+        }
+    }
+
+    // -------------------------------------------
+    // Shareable HTML page
+    // -------------------------------------------
+
+    // The report as a standalone HTML page: no external resources, photos inlined as data: URLs (thumbnails)
+    pub async fn render_report_page(db: &AppDatabase, report: &WalkReport, booking: &Booking) -> Result<String, AppError> {
+        let dog_names = dog_names(db, booking).await?;
+        let photos = photo_thumbnails(db, report).await?;
+
+        let start = booking.start_time.to_chrono();
+        let title = match dog_names.is_empty() {
+            true => "Walk report".to_string(),
+            false => format!("Walk report: {}", dog_names.join(", ")),
+        };
+        let check = |done: bool| if done { "&#10004; yes" } else { "&#10008; no" };
+
+        let mut walk = String::new();
+        if let Some(stats) = &booking.walk {
+            walk.push_str(&format!("<li>Distance: {:.2} km</li>", stats.distance_m / 1000.0));
+            if let Some(moving_time_s) = stats.moving_time_s {
+                walk.push_str(&format!("<li>Moving time: {} min</li>", (moving_time_s + 30) / 60));
+            }
+            if let Some(pace) = stats.average_pace_s_per_km {
+                let pace = pace.round() as i64;
+                walk.push_str(&format!("<li>Average pace: {}:{:02} min/km</li>", pace / 60, pace % 60));
+            }
+        }
+
+        let notes = match &report.notes {
+            Some(notes) => format!("<h2>Notes</h2><p class=\"notes\">{}</p>", escape_html(notes)),
+            None => String::new(),
+        };
+        let photos = match photos.is_empty() {
+            true => String::new(),
+            false => format!(
+                "<h2>Photos</h2><div class=\"photos\">{}</div>",
+                photos.iter().map(|data| format!("<img src=\"data:image/jpeg;base64,{}\" alt=\"Walk photo\">", data)).collect::<String>(),
+            ),
+        };
+
+        Ok(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 40rem; margin: 2rem auto; padding: 0 1rem; color: #222; }}
+h1 {{ font-size: 1.5rem; }} h2 {{ font-size: 1.1rem; margin-top: 1.5rem; }}
+ul {{ padding-left: 1.2rem; }} .notes {{ white-space: pre-wrap; }}
+.photos img {{ max-width: 100%; border-radius: 0.5rem; margin: 0 0.5rem 0.5rem 0; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{date}, {duration} min</p>
+<ul>{walk}</ul>
+<h2>Checklist</h2>
+<ul>
+<li>Pee: {pee}</li>
+<li>Poo: {poo}</li>
+<li>Water given: {water}</li>
+<li>Mood: {mood}</li>
+</ul>
+{notes}
+{photos}
+<p><small>Submitted {submitted}</small></p>
+</body>
+</html>
+"#,
+            title = escape_html(&title),
+            date = start.format("%A %-d %B %Y, %H:%M UTC"),
+            duration = booking.duration_minutes,
+            walk = walk,
+            pee = check(report.pee),
+            poo = check(report.poo),
+            water = check(report.water_given),
+            mood = report.mood.as_str(),
+            notes = notes,
+            photos = photos,
+            submitted = report.submitted_at.to_chrono().format("%-d %B %Y, %H:%M UTC"),
+        ))
+    }
+
+    // Names of the dogs walked: the dogs of the booking, all the dogs of the owner when it lists none
+    async fn dog_names(db: &AppDatabase, booking: &Booking) -> Result<Vec<String>, AppError> {
+        let filter = match booking.dogs.is_empty() {
+            true => doc! { "owner": booking.owner },
+            false => doc! { "_id": { "$in": &booking.dogs } },
+        };
+        let mut result_cursor = db.get_dogs_collection().find(filter).sort(doc! { "name": 1 }).await?;
+
+        let mut names = Vec::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(dog) => names.push(dog.name),
+                Err(e) => warn!("Skipping an unreadable dog of booking {}: {}", booking._id.to_hex(), e),
+            }
+        }
+        Ok(names)
+    }
+
+    // Base64 thumbnails of the photos of the report, in its order (photos deleted since are left out)
+    async fn photo_thumbnails(db: &AppDatabase, report: &WalkReport) -> Result<Vec<String>, AppError> {
+        let mut result_cursor = db.get_attachments_collection()
+            .find(doc! { "_id": { "$in": &report.photos }, "resource": "bookings", "resource_id": report.booking_id })
+            .await?;
+        let mut found = Vec::<Attachment>::new();
+        while let Some(result) = result_cursor.next().await {
+            found.push(result?);
+        }
+
+        let mut thumbnails = Vec::new();
+        for photo_id in &report.photos {
+            let thumbnail_id = match found.iter().find(|photo| photo._id == *photo_id).and_then(|photo| photo.thumbnail_id) {
+                Some(thumbnail_id) => thumbnail_id,
+                None => continue,
+            };
+            match attachments::read_file(db, thumbnail_id).await {
+                Ok(bytes) => thumbnails.push(BASE64.encode(bytes)),
+                Err(app_error) => warn!("Could not read thumbnail {} of walk report {}: {}", thumbnail_id.to_hex(), report._id.to_hex(), app_error),
+            }
+        }
+        Ok(thumbnails)
+    }
+
+    fn escape_html(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
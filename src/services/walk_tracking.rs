@@ -0,0 +1,195 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::StreamExt;
+use tokio::sync::broadcast;
+
+use crate::{app_errors::errors::AppError,
+            models::auth_model::{Principal, Role},
+            models::booking_model::Booking,
+            models::track_model::{PositionMessage, TrackPoint, TrackPointResponse}};
+use crate::services::{db::AppDatabase, listing, policy::{self, Access, Resource}};
+
+
+    // -------------------------------------------
+    // Live walk tracking
+    // -------------------------------------------
+    // The sitter of a booking publishes GPS positions, the owner of the booking follows them live
+    // (WebSocket GET /bookings/{id}/walk, see routes/walk_routes.rs). Every accepted position is stored
+    // in 'track_points' before being relayed, so the track can be displayed after the walk.
+
+    const LIVE_CHANNEL_CAPACITY: usize = 64;
+    const MAX_CLOCK_SKEW_MS: i64 = 60_000;    // device timestamps further in the future are replaced by the reception time
+
+    // Who is on the other end of the socket
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum WalkRole {
+        Sitter,   // publishes positions
+        Owner,    // receives them
+        Staff,    // dispatcher, admin or integration: receives them too, never writes
+    }
+
+    // Live positions of the walks in progress, one channel per booking (in this process only)
+    // the frames are serialized once, by the publisher
+    pub struct WalkHub {
+        channels: Mutex<HashMap<ObjectId, broadcast::Sender<String>>>,
+    }
+
+    impl WalkHub {
+        pub fn new() -> Self {
+            WalkHub { channels: Mutex::new(HashMap::new()) }
+        }
+
+        // the channel of the booking goes away with its last subscription (see WalkSubscription)
+        pub fn subscribe(&self, booking_id: ObjectId) -> WalkSubscription<'_> {
+            let mut channels = self.channels.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let receiver = channels
+                .entry(booking_id)
+                .or_insert_with(|| broadcast::channel(LIVE_CHANNEL_CAPACITY).0)
+                .subscribe();
+            WalkSubscription { hub: self, booking_id, receiver: Some(receiver) }
+        }
+
+        pub fn publish(&self, booking_id: ObjectId, frame: String) {
+            let mut channels = self.channels.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(sender) = channels.get(&booking_id) {
+                // nobody listens any more: forget the channel
+                if sender.send(frame).is_err() {
+                    channels.remove(&booking_id);
+                }
+            }
+        }
+    }
+
+    // A watcher of the live positions of a booking, for as long as its socket is open
+    pub struct WalkSubscription<'a> {
+        hub: &'a WalkHub,
+        booking_id: ObjectId,
+        receiver: Option<broadcast::Receiver<String>>,   // only None while dropped
+    }
+
+    impl WalkSubscription<'_> {
+        pub async fn recv(&mut self) -> Result<String, broadcast::error::RecvError> {
+            match self.receiver.as_mut() {
+                Some(receiver) => receiver.recv().await,
+                None => Err(broadcast::error::RecvError::Closed),
+            }
+        }
+    }
+
+    // the last watcher of a booking removes its channel, even when nobody ever published to it
+    impl Drop for WalkSubscription<'_> {
+        fn drop(&mut self) {
+            drop(self.receiver.take());
+            let mut channels = self.hub.channels.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if channels.get(&self.booking_id).is_some_and(|sender| sender.receiver_count() == 0) {
+                channels.remove(&self.booking_id);
+            }
+        }
+    }
+
+    // The booking, and the role of 'principal' in it: its sitter, its owner, or staff (see services/policy.rs)
+    // a booking out of the principal's scope is not found
+    pub async fn authorise(db: &AppDatabase, booking_id: &str, principal: &Principal) -> Result<(Booking, WalkRole), AppError> {
+        let obj_id = match ObjectId::parse_str(booking_id) {
+            Ok(id) => id,
+            Err(_) => return Err(AppError::InvalidId),
+        };
+        let scope = policy::scope(db, principal, Resource::Bookings, Access::Read).await?;
+        let booking = match db.get_bookings_collection().find_one(policy::restrict(doc! { "_id": obj_id }, &scope)).await? {
+            Some(booking) => booking,
+            None => return Err(AppError::NotFound),
+        };
+
+        let role = if booking.sitter == Some(principal.id) {
+            WalkRole::Sitter
+        } else if booking.owner == principal.id {
+            WalkRole::Owner
+        } else if principal.role.is_staff() || principal.role == Role::Integration {
+            WalkRole::Staff
+        } else {
+            return Err(AppError::Forbidden("only the owner and the sitter of the booking can follow its walk".to_string()));
+        };
+        Ok((booking, role))
+    }
+
+    // Validate and store a position sent by the sitter (a JSON PositionMessage)
+    pub async fn record_position(db: &AppDatabase, booking: &Booking, actor: &str, text: &str) -> Result<TrackPoint, AppError> {
+        if booking.cancelled {
+            return Err(AppError::Forbidden("the booking is cancelled".to_string()));
+        }
+        let position: PositionMessage = serde_json::from_str(text)
+            .map_err(|e| AppError::ParseError(format!("expected {{\"lat\", \"lon\", \"accuracy\"?, \"timestamp\"?}}: {}", e)))?;
+
+        if !(-90.0..=90.0).contains(&position.lat) || !(-180.0..=180.0).contains(&position.lon) {
+            return Err(AppError::ParseError("lat must be within -90..90 and lon within -180..180".to_string()));
+        }
+        if position.accuracy.is_some_and(|accuracy| !accuracy.is_finite() || accuracy < 0.0) {
+            return Err(AppError::ParseError("accuracy must be a positive number of meters".to_string()));
+        }
+
+        let received_at = DateTime::now();
+        let recorded_at = match &position.timestamp {
+            Some(timestamp) => {
+                let recorded_at = listing::parse_datetime(timestamp)?;
+                if recorded_at.timestamp_millis() > received_at.timestamp_millis() + MAX_CLOCK_SKEW_MS { received_at } else { recorded_at }
+            }
+            None => received_at,
+        };
+
+        let point = TrackPoint {
+            _id: ObjectId::new(),
+            booking_id: booking._id,
+            lat: position.lat,
+            lon: position.lon,
+            accuracy_m: position.accuracy,
+            recorded_at,
+            received_at,
+            source: "live".to_string(),
+            recorded_by: actor.to_string(),
+            upload_id: None,
+        };
+        db.get_track_points_collection().insert_one(&point).await?;
+        Ok(point)
+    }
+
+    // READ the track of a booking, in order
+    // the track uploaded after the walk (see services/track_upload.rs) replaces the live positions
+    pub async fn read_track(db: &AppDatabase, booking_id: ObjectId) -> Result<Vec<TrackPoint>, AppError> {
+        let track_points = db.get_track_points_collection();
+        let uploaded = track_points.find_one(doc! { "booking_id": booking_id, "source": "upload" }).await?.is_some();
+        let filter = match uploaded {
+            true => doc! { "booking_id": booking_id, "source": "upload" },
+            false => doc! { "booking_id": booking_id },
+        };
+
+        let mut result_cursor = track_points
+            .find(filter)
+            .sort(doc! { "recorded_at": 1, "_id": 1 })
+            .await?;
+
+        let mut vec_of_points = Vec::<TrackPoint>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(point) => vec_of_points.push(point),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Track points from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_points)
+    }
+
+    // Last known position, sent to an owner as soon as they connect
+    pub async fn last_position(db: &AppDatabase, booking_id: ObjectId) -> Result<Option<TrackPoint>, AppError> {
+        let point = db.get_track_points_collection()
+            .find_one(doc! { "booking_id": booking_id })
+            .sort(doc! { "recorded_at": -1, "_id": -1 })
+            .await?;
+        Ok(point)
+    }
+
+    // WebSocket frame for a position: { "type": "position", "booking_id", "lat", "lon", "accuracy", "recorded_at" }
+    pub fn position_frame(point: TrackPoint) -> String {
+        let mut frame = serde_json::to_value(TrackPointResponse::from(point)).unwrap_or_default();
+        frame["type"] = serde_json::Value::from("position");
+        frame.to_string()
+    }
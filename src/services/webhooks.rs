@@ -0,0 +1,538 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use mongodb::options::ReturnDocument;
+use sha2::Sha256;
+use validator::Validate;
+
+use crate::{app_errors::errors::AppError,
+            config::DispatcherConfig,
+            models::booking_event_model::DOMAIN_EVENT_TYPES,
+            models::outbox_model::OutboxMessage,
+            models::webhook_model::{DeliveryAttempt, DeliveryStatus, WebhookDelivery, WebhookRequest, WebhookSubscription, ALL_EVENTS}};
+use crate::services::{db::{self, AppDatabase}, outbox, outbox_sinks};
+
+
+    // -------------------------------------------
+    // Outbound webhooks
+    // -------------------------------------------
+    // 1. fan-out: the 'webhook_subscriptions' outbox sink turns each message into one WebhookDelivery
+    //    per subscription wanting its event type
+    // 2. delivery: run_delivery_worker POSTs the deliveries, signed, and retries them with exponential backoff
+    //
+    // Every request carries:
+    //   Webhook-Id:        delivery id (the same on retries, receivers can deduplicate with it)
+    //   Webhook-Event:     event type, e.g. booking.cancelled
+    //   Webhook-Timestamp: unix time (seconds) of the attempt, receivers should reject old ones (replays)
+    //   Webhook-Signature: sha256=<hex HMAC-SHA256(secret, "{timestamp}.{body}")>
+
+    pub const ID_HEADER: &str = "Webhook-Id";
+    pub const EVENT_HEADER: &str = "Webhook-Event";
+    pub const TIMESTAMP_HEADER: &str = "Webhook-Timestamp";
+    pub const SIGNATURE_HEADER: &str = "Webhook-Signature";
+
+    const MAX_DELIVERIES_LISTED: i64 = 100;
+    const MAX_ERROR_LENGTH: usize = 500;
+    const MAX_ATTEMPTS_KEPT: i32 = 20;   // attempts listed with a delivery, the oldest are dropped
+
+    // -----------------
+    // Subscriptions
+    // -----------------
+
+    // CREATE subscription
+    pub async fn create_subscription(db: &AppDatabase, webhook_request: WebhookRequest, actor: &str) -> Result<WebhookSubscription, AppError> {
+        webhook_request.validate().map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        if !(webhook_request.url.starts_with("http://") || webhook_request.url.starts_with("https://")) {
+            return Err(AppError::ParseError("Webhook url must be an http(s) URL".to_string()));
+        }
+        if let Some(unknown) = webhook_request.event_types.iter().find(|event_type| event_type.as_str() != ALL_EVENTS && !DOMAIN_EVENT_TYPES.contains(&event_type.as_str())) {
+            return Err(AppError::ParseError(format!("Unknown event type '{}', expected one of {:?} or \"{}\"", unknown, DOMAIN_EVENT_TYPES, ALL_EVENTS)));
+        }
+
+        let subscription = WebhookSubscription {
+            _id: ObjectId::new(),
+            url: webhook_request.url,
+            event_types: webhook_request.event_types,
+            secret: webhook_request.secret,
+            active: true,
+            created_at: DateTime::now(),
+            created_by: actor.to_string(),
+        };
+        db.get_webhook_subscriptions_collection().insert_one(&subscription).await?;
+        Ok(subscription)
+    }
+
+    // READ subscriptions
+    pub async fn read_subscriptions(db: &AppDatabase) -> Result<Vec<WebhookSubscription>, AppError> {
+        let mut result_cursor = db.get_webhook_subscriptions_collection()
+            .find(doc! {})
+            .sort(doc! { "created_at": 1, "_id": 1 })
+            .await?;
+
+        let mut vec_of_subscriptions = Vec::<WebhookSubscription>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(subscription) => vec_of_subscriptions.push(subscription),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Webhook subscriptions from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_subscriptions)
+    }
+
+    // READ single subscription
+    pub async fn read_subscription(db: &AppDatabase, subscription_id: &str) -> Result<WebhookSubscription, AppError> {
+        let obj_id = parse_id(subscription_id)?;
+        match db.get_webhook_subscriptions_collection().find_one(doc! { "_id": obj_id }).await? {
+            Some(subscription) => Ok(subscription),
+            None => Err(AppError::NotFound),
+        }
+    }
+
+    // DELETE subscription: its pending deliveries are given up
+    pub async fn delete_subscription(db: &AppDatabase, subscription_id: &str) -> Result<String, AppError> {
+        let obj_id = parse_id(subscription_id)?;
+        let result = db.get_webhook_subscriptions_collection().delete_one(doc! { "_id": obj_id }).await?;
+        if result.deleted_count == 0 {
+            return Err(AppError::NotFound);
+        }
+        db.get_webhook_deliveries_collection()
+            .update_many(
+                doc! { "subscription_id": obj_id, "status": DeliveryStatus::Pending.as_str() },
+                doc! { "$set": { "status": DeliveryStatus::Failed.as_str(), "locked_until": null } },
+            )
+            .await?;
+        Ok(obj_id.to_hex())
+    }
+
+    // -----------------
+    // Deliveries
+    // -----------------
+
+    // READ the latest deliveries of a subscription, most recent first
+    pub async fn read_deliveries(db: &AppDatabase, subscription_id: &str) -> Result<Vec<WebhookDelivery>, AppError> {
+        let subscription = read_subscription(db, subscription_id).await?;
+
+        let mut result_cursor = db.get_webhook_deliveries_collection()
+            .find(doc! { "subscription_id": subscription._id })
+            .sort(doc! { "created_at": -1, "_id": -1 })
+            .limit(MAX_DELIVERIES_LISTED)
+            .await?;
+
+        let mut vec_of_deliveries = Vec::<WebhookDelivery>::new();
+        while let Some(result) = result_cursor.next().await {
+            match result {
+                Ok(delivery) => vec_of_deliveries.push(delivery),
+                Err(e) => return Err(AppError::DatabaseError(format!("Error reading Webhook deliveries from DB: {}", e))),
+            }
+        }
+        Ok(vec_of_deliveries)
+    }
+
+    // Send a delivery again (whatever its status), as soon as possible, with a fresh backoff
+    pub async fn redeliver(db: &AppDatabase, subscription_id: &str, delivery_id: &str) -> Result<WebhookDelivery, AppError> {
+        let subscription = read_subscription(db, subscription_id).await?;
+        let delivery_obj_id = parse_id(delivery_id)?;
+
+        let result = db.get_webhook_deliveries_collection()
+            .find_one_and_update(doc! { "_id": delivery_obj_id, "subscription_id": subscription._id }, redelivery(DateTime::now()))
+            .return_document(ReturnDocument::After)
+            .await?;
+
+        match result {
+            Some(delivery) => Ok(delivery),
+            None => Err(AppError::NotFound),
+        }
+    }
+
+    // pending again, due now, with the backoff starting over (failed_attempts drives it, see after_failure)
+    fn redelivery(now: DateTime) -> bson::Document {
+        doc! { "$set": { "status": DeliveryStatus::Pending.as_str(), "failed_attempts": 0, "next_attempt_at": now, "locked_until": null } }
+    }
+
+    // Fan-out of an outbox message (called by the webhook_subscriptions sink, see services/outbox_sinks.rs)
+    // safe to call twice for the same message: the unique (subscription_id, event_id) index keeps one delivery
+    pub async fn fan_out(db: &AppDatabase, message: &OutboxMessage) -> Result<(), AppError> {
+        let filter = doc! { "active": true, "event_types": { "$in": [&message.event_type, ALL_EVENTS] } };
+        let mut result_cursor = db.get_webhook_subscriptions_collection().find(filter).await?;
+
+        let body = outbox_sinks::message_body(message).to_string();
+        let now = DateTime::now();
+        while let Some(subscription) = result_cursor.next().await {
+            let delivery = WebhookDelivery {
+                _id: ObjectId::new(),
+                subscription_id: subscription?._id,
+                event_id: message._id,
+                event_type: message.event_type.clone(),
+                body: body.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: Vec::new(),
+                failed_attempts: 0,
+                next_attempt_at: now,
+                locked_until: None,
+                created_at: now,
+                delivered_at: None,
+            };
+            match db.get_webhook_deliveries_collection().insert_one(&delivery).await {
+                Ok(_) => {}
+                Err(e) if db::is_duplicate_key(&e) => {}   // already fanned out
+                Err(e) => return Err(AppError::from(e)),
+            }
+        }
+        Ok(())
+    }
+
+    // hex HMAC-SHA256 of "{timestamp}.{body}" with the subscription secret
+    pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body.as_bytes());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    // Delivery loop, runs for the whole life of the server (same claim/lease pattern as services/outbox.rs)
+    pub async fn run_delivery_worker(db: Arc<AppDatabase>, config: DispatcherConfig) {
+        let client = match delivery_client(config.webhook_timeout) {
+            Ok(client) => client,
+            Err(e) => return error!("Webhooks: cannot build the HTTP client, deliveries disabled: {}", e),
+        };
+        info!("Webhook delivery worker started");
+        loop {
+            match claim_next(&db).await {
+                Ok(Some(delivery)) => {
+                    if let Err(e) = attempt(&db, &client, delivery, &config).await {
+                        error!("Webhooks: failed to record a delivery attempt: {}", e);
+                    }
+                }
+                Ok(None) => actix_web::rt::time::sleep(config.poll_interval).await,
+                Err(e) => {
+                    error!("Webhooks: failed to read pending deliveries: {}", e);
+                    actix_web::rt::time::sleep(config.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    // redirects are not followed: a subscriber URL must not bounce the deliveries to another (e.g. internal) host,
+    // a 3xx answer is a failed attempt like any other non-2xx
+    fn delivery_client(timeout: Duration) -> reqwest::Result<reqwest::Client> {
+        reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+    }
+
+    async fn claim_next(db: &AppDatabase) -> Result<Option<WebhookDelivery>, AppError> {
+        let now = DateTime::now();
+        let filter = doc! {
+            "status": DeliveryStatus::Pending.as_str(),
+            "next_attempt_at": { "$lte": now },
+            "$or": [ { "locked_until": null }, { "locked_until": { "$lte": now } } ],
+        };
+        let claimed = db.get_webhook_deliveries_collection()
+            .find_one_and_update(filter, doc! { "$set": { "locked_until": outbox::after(now, outbox::LEASE) } })
+            .sort(doc! { "next_attempt_at": 1, "_id": 1 })
+            .return_document(ReturnDocument::After)
+            .await?;
+        Ok(claimed)
+    }
+
+    // One signed POST, then: delivered, retried later, or failed
+    async fn attempt(db: &AppDatabase, client: &reqwest::Client, delivery: WebhookDelivery, config: &DispatcherConfig) -> Result<(), AppError> {
+        let deliveries = db.get_webhook_deliveries_collection();
+
+        let subscription = match db.get_webhook_subscriptions_collection().find_one(doc! { "_id": delivery.subscription_id }).await? {
+            Some(subscription) if subscription.active => subscription,
+            _ => {
+                // deleted (or deactivated) since the fan-out: nobody to deliver to
+                deliveries.update_one(doc! { "_id": delivery._id }, doc! { "$set": { "status": DeliveryStatus::Failed.as_str(), "locked_until": null } }).await?;
+                return Ok(());
+            }
+        };
+
+        let attempt = send(client, &subscription.url, &subscription.secret, &delivery).await;
+        let attempt_bson = bson::to_bson(&attempt).map_err(|e| AppError::ParseError(e.to_string()))?;
+        // only the last MAX_ATTEMPTS_KEPT attempts are kept: a receiver down for days would grow the document forever
+        let push_attempt = doc! { "attempts": { "$each": [attempt_bson], "$slice": -MAX_ATTEMPTS_KEPT } };
+
+        let now = DateTime::now();
+        let update = match &attempt.error {
+            None => doc! {
+                "$set": { "status": DeliveryStatus::Delivered.as_str(), "delivered_at": now, "locked_until": null },
+                "$push": push_attempt,
+            },
+            Some(error) => {
+                let failed_attempts = delivery.failed_attempts + 1;
+                let (status, next_attempt_at) = after_failure(failed_attempts, config, now);
+                match status {
+                    DeliveryStatus::Failed => error!("Webhooks: delivery {} of {} to {} failed {} times, giving up: {}", delivery._id.to_hex(), delivery.event_type, subscription.url, failed_attempts, error),
+                    _ => warn!("Webhooks: delivery {} of {} to {} failed (attempt {}), retrying: {}", delivery._id.to_hex(), delivery.event_type, subscription.url, failed_attempts, error),
+                }
+                doc! {
+                    "$set": { "status": status.as_str(), "failed_attempts": failed_attempts, "next_attempt_at": next_attempt_at, "locked_until": null },
+                    "$push": push_attempt,
+                }
+            }
+        };
+        deliveries.update_one(doc! { "_id": delivery._id }, update).await?;
+        Ok(())
+    }
+
+    // One signed POST of the delivery body to 'url'
+    async fn send(client: &reqwest::Client, url: &str, secret: &str, delivery: &WebhookDelivery) -> DeliveryAttempt {
+        let timestamp = chrono::Utc::now().timestamp();
+        let started = Instant::now();
+        let result = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(ID_HEADER, delivery._id.to_hex())
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, format!("sha256={}", signature(secret, timestamp, &delivery.body)))
+            .body(delivery.body.clone())
+            .send()
+            .await;
+
+        let mut attempt = DeliveryAttempt {
+            at: DateTime::now(),
+            status_code: None,
+            error: None,
+            duration_ms: started.elapsed().as_millis() as i64,
+        };
+        match &result {
+            Ok(response) => {
+                attempt.status_code = Some(i32::from(response.status().as_u16()));
+                if !response.status().is_success() {
+                    attempt.error = Some(format!("receiver answered {}", response.status()));
+                }
+            }
+            Err(e) => attempt.error = Some(e.to_string().chars().take(MAX_ERROR_LENGTH).collect()),
+        }
+        attempt
+    }
+
+    // after the failed attempt number 'failed_attempts': retried with exponential backoff, failed after max_attempts
+    fn after_failure(failed_attempts: i32, config: &DispatcherConfig, now: DateTime) -> (DeliveryStatus, DateTime) {
+        if failed_attempts >= config.max_attempts {
+            (DeliveryStatus::Failed, now)
+        } else {
+            (DeliveryStatus::Pending, outbox::after(now, outbox::retry_delay(config.retry_base, failed_attempts)))
+        }
+    }
+
+    fn parse_id(id: &str) -> Result<ObjectId, AppError> {
+        ObjectId::parse_str(id).map_err(|_| AppError::InvalidId)
+    }
+
+    // Deliveries against a local HTTP receiver; the redelivery test also needs MongoDB (see AppDatabase::init_test, `cargo test -- --ignored`)
+    #[cfg(test)]
+    mod tests {
+        use std::sync::Mutex;
+
+        use actix_web::{http::header, web, App, HttpRequest, HttpResponse, HttpServer};
+
+        use super::*;
+
+        const SECRET: &str = "whsec_test_0123456789";
+
+        // what the receiver got: path, Webhook-* headers, body
+        #[derive(Debug, Clone)]
+        struct Received {
+            path: String,
+            id: String,
+            event: String,
+            timestamp: i64,
+            signature: String,
+            body: String,
+        }
+
+        type Inbox = web::Data<Mutex<Vec<Received>>>;
+
+        // /hook answers 200, /broken 500, /moved redirects to /hook
+        async fn receiver() -> (String, Inbox) {
+            let inbox: Inbox = web::Data::new(Mutex::new(Vec::new()));
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let base_url = format!("http://{}", listener.local_addr().unwrap());
+            let app_inbox = inbox.clone();
+            let server = HttpServer::new(move || App::new()
+                .app_data(app_inbox.clone())
+                .route("/hook", web::post().to(record))
+                .route("/broken", web::post().to(|| async { HttpResponse::InternalServerError().finish() }))
+                .route("/moved", web::post().to(|| async { HttpResponse::TemporaryRedirect().insert_header((header::LOCATION, "/hook")).finish() }))
+                )
+                .workers(1)
+                .listen(listener)
+                .unwrap()
+                .run();
+            actix_web::rt::spawn(server);
+            (base_url, inbox)
+        }
+
+        async fn record(request: HttpRequest, body: String, inbox: Inbox) -> HttpResponse {
+            let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+            inbox.lock().unwrap().push(Received {
+                path: request.path().to_string(),
+                id: header(ID_HEADER),
+                event: header(EVENT_HEADER),
+                timestamp: header(TIMESTAMP_HEADER).parse().unwrap_or_default(),
+                signature: header(SIGNATURE_HEADER),
+                body,
+            });
+            HttpResponse::NoContent().finish()
+        }
+
+        fn dispatcher_config(max_attempts: i32) -> DispatcherConfig {
+            DispatcherConfig {
+                sinks: Vec::new(),
+                webhook_url: None,
+                poll_interval: Duration::from_millis(100),
+                max_attempts,
+                retry_base: Duration::from_secs(1),
+                webhook_timeout: Duration::from_secs(5),
+            }
+        }
+
+        fn delivery(subscription_id: ObjectId, failed_attempts: i32, attempts: Vec<DeliveryAttempt>) -> WebhookDelivery {
+            WebhookDelivery {
+                _id: ObjectId::new(),
+                subscription_id,
+                event_id: ObjectId::new(),
+                event_type: "booking.created".to_string(),
+                body: r#"{"event":"booking.created"}"#.to_string(),
+                status: DeliveryStatus::Pending,
+                attempts,
+                failed_attempts,
+                next_attempt_at: DateTime::now(),
+                locked_until: None,
+                created_at: DateTime::now(),
+                delivered_at: None,
+            }
+        }
+
+        #[test]
+        fn signature_is_the_hex_hmac_of_timestamp_dot_body() {
+            // HMAC-SHA256("whsec_test", "1700000000.{"event":"booking.created"}"), computed independently
+            assert_eq!(
+                signature("whsec_test", 1_700_000_000, r#"{"event":"booking.created"}"#),
+                "612003e72749f743f6381c0dbfd68c80a4636ee4e67201b170529734fb032ab2",
+            );
+        }
+
+        #[test]
+        fn failed_attempts_back_off_exponentially_then_fail() {
+            let now = DateTime::from_millis(1_700_000_000_000);
+            let config = dispatcher_config(5);
+            let delays: Vec<i64> = (1..5)
+                .map(|failed_attempts| after_failure(failed_attempts, &config, now))
+                .map(|(status, next_attempt_at)| {
+                    assert_eq!(status, DeliveryStatus::Pending);
+                    next_attempt_at.timestamp_millis() - now.timestamp_millis()
+                })
+                .collect();
+            assert_eq!(delays, [1_000, 2_000, 4_000, 8_000]);
+            assert_eq!(after_failure(5, &config, now), (DeliveryStatus::Failed, now));
+
+            // at most one hour between two attempts
+            let slow = DispatcherConfig { retry_base: Duration::from_secs(1_000), ..dispatcher_config(10) };
+            assert_eq!(after_failure(4, &slow, now).1.timestamp_millis() - now.timestamp_millis(), 3_600_000);
+        }
+
+        #[test]
+        fn redelivery_restarts_the_backoff() {
+            let now = DateTime::now();
+            let update = redelivery(now);
+            let set = update.get_document("$set").unwrap();
+            assert_eq!(set.get_str("status"), Ok(DeliveryStatus::Pending.as_str()));
+            assert_eq!(set.get_i32("failed_attempts"), Ok(0));
+            assert_eq!(set.get_datetime("next_attempt_at"), Ok(&now));
+            // the next failure waits the first delay again
+            let (_, next_attempt_at) = after_failure(set.get_i32("failed_attempts").unwrap() + 1, &dispatcher_config(5), now);
+            assert_eq!(next_attempt_at.timestamp_millis() - now.timestamp_millis(), 1_000);
+        }
+
+        #[actix_web::test]
+        async fn receiver_gets_a_signed_delivery() {
+            let (base_url, inbox) = receiver().await;
+            let client = delivery_client(Duration::from_secs(5)).unwrap();
+            let delivery = delivery(ObjectId::new(), 0, Vec::new());
+
+            let attempt = send(&client, &format!("{}/hook", base_url), SECRET, &delivery).await;
+            assert_eq!(attempt.status_code, Some(204));
+            assert_eq!(attempt.error, None);
+
+            let received = inbox.lock().unwrap().clone();
+            assert_eq!(received.len(), 1);
+            let received = &received[0];
+            assert_eq!(received.id, delivery._id.to_hex());
+            assert_eq!(received.event, "booking.created");
+            assert_eq!(received.body, delivery.body);
+            assert!((received.timestamp - chrono::Utc::now().timestamp()).abs() < 60);
+            let expected = format!("sha256={}", signature(SECRET, received.timestamp, &received.body));
+            assert_eq!(received.signature, expected);
+            assert_eq!(received.signature.len(), "sha256=".len() + 64);
+        }
+
+        #[actix_web::test]
+        async fn receiver_error_is_a_failed_attempt() {
+            let (base_url, _inbox) = receiver().await;
+            let client = delivery_client(Duration::from_secs(5)).unwrap();
+
+            let attempt = send(&client, &format!("{}/broken", base_url), SECRET, &delivery(ObjectId::new(), 0, Vec::new())).await;
+            assert_eq!(attempt.status_code, Some(500));
+            assert!(attempt.error.is_some());
+
+            // nobody listening
+            let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/hook", closed.local_addr().unwrap());
+            drop(closed);
+            let attempt = send(&client, &url, SECRET, &delivery(ObjectId::new(), 0, Vec::new())).await;
+            assert_eq!(attempt.status_code, None);
+            assert!(attempt.error.is_some());
+        }
+
+        #[actix_web::test]
+        async fn redirects_are_not_followed() {
+            let (base_url, inbox) = receiver().await;
+            let client = delivery_client(Duration::from_secs(5)).unwrap();
+
+            let attempt = send(&client, &format!("{}/moved", base_url), SECRET, &delivery(ObjectId::new(), 0, Vec::new())).await;
+            assert_eq!(attempt.status_code, Some(307));
+            assert!(attempt.error.is_some());
+            assert!(inbox.lock().unwrap().iter().all(|received| received.path != "/hook"));
+        }
+
+        #[actix_web::test]
+        #[ignore = "needs MongoDB (TEST_MONGODB_URI)"]
+        async fn redelivered_delivery_is_sent_and_keeps_the_last_attempts() {
+            let db = AppDatabase::init_test().await;
+            let (base_url, inbox) = receiver().await;
+            let subscription = create_subscription(&db, WebhookRequest {
+                url: format!("{}/hook", base_url),
+                event_types: vec![ALL_EVENTS.to_string()],
+                secret: SECRET.to_string(),
+            }, "test").await.unwrap();
+
+            // failed for good, with a full attempt history
+            let old_attempt = DeliveryAttempt { at: DateTime::now(), status_code: Some(500), error: Some("receiver answered 500".to_string()), duration_ms: 1 };
+            let mut failed = delivery(subscription._id, 10, vec![old_attempt; MAX_ATTEMPTS_KEPT as usize]);
+            failed.status = DeliveryStatus::Failed;
+            db.get_webhook_deliveries_collection().insert_one(&failed).await.unwrap();
+
+            let pending = redeliver(&db, &subscription._id.to_hex(), &failed._id.to_hex()).await.unwrap();
+            assert_eq!(pending.status, DeliveryStatus::Pending);
+            assert_eq!(pending.failed_attempts, 0);
+
+            attempt(&db, &delivery_client(Duration::from_secs(5)).unwrap(), pending, &dispatcher_config(5)).await.unwrap();
+            let delivered = db.get_webhook_deliveries_collection().find_one(doc! { "_id": failed._id }).await.unwrap().unwrap();
+            assert_eq!(delivered.status, DeliveryStatus::Delivered);
+            assert_eq!(delivered.attempts.len(), MAX_ATTEMPTS_KEPT as usize);
+            assert_eq!(delivered.attempts.last().and_then(|attempt| attempt.status_code), Some(204));
+            assert_eq!(inbox.lock().unwrap().len(), 1);
+
+            delete_subscription(&db, &subscription._id.to_hex()).await.unwrap();
+        }
+    }